@@ -1,4 +1,6 @@
-use crate::token::NumericConstant;
+use std::path::{ Path, PathBuf };
+
+use crate::token::{ NumericConstant, Span };
 use crate::typechecker::TypeID;
 
 #[derive(Clone)]
@@ -12,28 +14,39 @@ pub enum ComplexType {
 #[derive(Debug, Clone)]
 pub enum ParsedType {
     CheckedType(TypeID),
-    Name(Vec<String>, String),
+    /// A type referred to by name, before the typechecker has
+    /// resolved it. The span is only used for diagnostics, so it
+    /// doesn't take part in equality.
+    Name(Vec<String>, String, Span),
+    /// `[N]T`, an array of `N` elements of type `T`. The size has to be
+    /// a constant integer expression.
     Array(Box<ParsedType>, ParsedExpression),
+    /// `*T`, a pointer to a `T`. `*nothing` points at memory of any type.
+    Pointer(Box<ParsedType>),
 }
 
 impl PartialEq for ParsedType {
     fn eq(&self, other: &Self) -> bool {
-        match &self {
-            ParsedType::CheckedType(id) => {
-                if let ParsedType::CheckedType(o_id) = other {
-                    return id == o_id
-                }
+        match (self, other) {
+            (ParsedType::CheckedType(id), ParsedType::CheckedType(other_id)) => id == other_id,
+            (ParsedType::CheckedType(_), _) | (_, ParsedType::CheckedType(_)) => {
                 panic!("Can only check quality of checked types against unchecked types");
             },
-            ParsedType::Name(module_path, name) => {
-                if let ParsedType::Name(other_module_path, other_name) = other {
-                    return (module_path == other_module_path) && (name == other_name);
-                }
-                todo!("Implement arrays");
+            (ParsedType::Name(module_path, name, _span), ParsedType::Name(other_module_path, other_name, _other_span)) => {
+                (module_path == other_module_path) && (name == other_name)
             },
-            ParsedType::Array(_elem_type, _size) => {
-                todo!("Implement arrays");
-            }
+            // Sizes that aren't literals have to be evaluated before they can
+            // be compared, which only the typechecker can do
+            (ParsedType::Array(elem_type, size), ParsedType::Array(other_elem_type, other_size)) => {
+                let same_size = match (size, other_size) {
+                    (ParsedExpression::NumericConstant(size, _), ParsedExpression::NumericConstant(other_size, _)) => size == other_size,
+                    _ => false,
+                };
+
+                same_size && elem_type == other_elem_type
+            },
+            (ParsedType::Pointer(pointee), ParsedType::Pointer(other_pointee)) => pointee == other_pointee,
+            _ => false,
         }
     }
 }
@@ -45,6 +58,7 @@ pub enum UnaryOperator {
     PostIncrement,
     PreDecrement,
     PostDecrement,
+    Negate,
     LogicalNot,
     BitwiseNot,
     AddressOf,
@@ -54,6 +68,7 @@ pub enum UnaryOperator {
 
 /// An operator that has two operands.
 #[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum BinaryOperator {
     Invalid,
 
@@ -98,19 +113,87 @@ pub enum BinaryOperator {
 
 impl BinaryOperator {
     pub fn is_assignment(&self) -> bool {
+        matches!(self, BinaryOperator::Assign          |
+                       BinaryOperator::AddAssign       |
+                       BinaryOperator::SubtractAssign  |
+                       BinaryOperator::MultiplyAssign  |
+                       BinaryOperator::DivideAssign    |
+                       BinaryOperator::ModuloAssign    |
+                       BinaryOperator::AndAssign       |
+                       BinaryOperator::OrAssign        |
+                       BinaryOperator::XORAssign       |
+                       BinaryOperator::LeftShiftAssign |
+                       BinaryOperator::RightShiftAssign)
+    }
+
+    pub fn as_str(&self) -> &str {
         match self {
-            BinaryOperator::Assign          |
-            BinaryOperator::AddAssign       |
-            BinaryOperator::SubtractAssign  |
-            BinaryOperator::MultiplyAssign  |
-            BinaryOperator::DivideAssign    |
-            BinaryOperator::ModuloAssign    |
-            BinaryOperator::AndAssign       |
-            BinaryOperator::OrAssign        |
-            BinaryOperator::XORAssign       |
-            BinaryOperator::LeftShiftAssign |
-            BinaryOperator::RightShiftAssign => true,
-            _ => false
+            BinaryOperator::Invalid => "<invalid>",
+
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Modulo => "%",
+
+            BinaryOperator::LogicalAnd => "&&",
+            BinaryOperator::LogicalOr => "||",
+            BinaryOperator::LogicalXOR => "^^",
+
+            BinaryOperator::NEQ => "!=",
+            BinaryOperator::EQ => "==",
+            BinaryOperator::LT => "<",
+            BinaryOperator::GT => ">",
+            BinaryOperator::LEQ => "<=",
+            BinaryOperator::GEQ => ">=",
+
+            BinaryOperator::BitwiseAnd => "&",
+            BinaryOperator::BitwiseOr => "|",
+            BinaryOperator::BitwiseXOR => "^",
+            BinaryOperator::BitwiseLeftShift => "<<",
+            BinaryOperator::BitwiseRightShift => ">>",
+
+            BinaryOperator::Assign => "=",
+
+            BinaryOperator::AddAssign => "+=",
+            BinaryOperator::SubtractAssign => "-=",
+            BinaryOperator::MultiplyAssign => "*=",
+            BinaryOperator::DivideAssign => "/=",
+            BinaryOperator::ModuloAssign => "%=",
+
+            BinaryOperator::AndAssign => "&=",
+            BinaryOperator::OrAssign => "|=",
+            BinaryOperator::XORAssign => "^=",
+
+            BinaryOperator::LeftShiftAssign => "<<=",
+            BinaryOperator::RightShiftAssign => ">>=",
+        }
+    }
+
+    /// The operator an assignment applies before storing, e.g.
+    /// `Add` for `+=`. Plain `=` has none.
+    /// Whether it's `+`, `-`, `*`, `/` or `%`.
+    pub fn is_arithmetic(&self) -> bool {
+        matches!(self, BinaryOperator::Add      |
+                       BinaryOperator::Subtract |
+                       BinaryOperator::Multiply |
+                       BinaryOperator::Divide   |
+                       BinaryOperator::Modulo)
+    }
+
+    pub fn without_assignment(&self) -> Option<BinaryOperator> {
+        match self {
+            BinaryOperator::AddAssign => Some(BinaryOperator::Add),
+            BinaryOperator::SubtractAssign => Some(BinaryOperator::Subtract),
+            BinaryOperator::MultiplyAssign => Some(BinaryOperator::Multiply),
+            BinaryOperator::DivideAssign => Some(BinaryOperator::Divide),
+            BinaryOperator::ModuloAssign => Some(BinaryOperator::Modulo),
+            BinaryOperator::AndAssign => Some(BinaryOperator::BitwiseAnd),
+            BinaryOperator::OrAssign => Some(BinaryOperator::BitwiseOr),
+            BinaryOperator::XORAssign => Some(BinaryOperator::BitwiseXOR),
+            BinaryOperator::LeftShiftAssign => Some(BinaryOperator::BitwiseLeftShift),
+            BinaryOperator::RightShiftAssign => Some(BinaryOperator::BitwiseRightShift),
+            _ => None
         }
     }
 }
@@ -121,24 +204,92 @@ impl BinaryOperator {
 pub struct ParsedVarDecl {
     pub parsed_type: ParsedType,
     pub name: String,
+    pub span: Span,
     pub defualt_value: ParsedExpression,
 }
 
 /// A declaration of a procedure, otherwise known as a
-/// function. These could either be methods of a struct
-/// simple functions on their own.
+/// function. The ones declared inside of a struct are
+/// named after it, like `Vec3::length`, and can be
+/// called as methods if they take the struct first.
 #[derive(Debug, Clone)]
 pub struct ParsedProcDecl {
     pub name: String,
+    pub span: Span,
     pub parameters: Vec<ParsedVarDecl>,
+    /// Where the parameters end with `..`, which takes any number of
+    /// extra arguments like C's `...`
+    pub variadic: Option<Span>,
     pub parsed_return_type: ParsedType,
-    pub body: ParsedBlock,
+    /// The body, which `#foreign` and `#intrinsic` procs don't have since
+    /// they're defined outside of the program
+    pub body: Option<ParsedBlock>,
+    pub attributes: ProcAttributes,
+    /// The text of the `///` comments in front of it
+    #[allow(dead_code)] // only printed by `--emit=ast` so far
+    pub doc: Option<String>,
+}
+
+/// Directives put in front of a proc's `decl`.
+#[derive(Debug, Clone, Default)]
+pub struct ProcAttributes {
+    /// `#inline`, which asks for every call to the proc to be inlined
+    pub inline: Option<Span>,
+    /// `#foreign`, optionally followed by the library the proc is in
+    pub foreign: Option<(Option<String>, Span)>,
+    /// `#intrinsic`, which says the compiler implements the proc itself
+    pub intrinsic: Option<Span>,
+}
+
+/// What a directive that runs at compile time does with its expression.
+#[derive(Debug, Clone)]
+pub enum DirectiveKind {
+    /// `#assert(cond)` or `#assert(cond, "message")`, which stops the
+    /// compilation if `cond` is false
+    Assert(Option<String>),
+    /// `#run expr`, which is evaluated for its side effects
+    Run,
+}
+
+impl DirectiveKind {
+    pub fn name(&self) -> &str {
+        match self {
+            DirectiveKind::Assert(_) => "#assert",
+            DirectiveKind::Run => "#run",
+        }
+    }
+}
+
+/// A directive at the top level of a module whose expression is run
+/// while compiling.
+#[derive(Debug, Clone)]
+pub struct ParsedDirective {
+    pub kind: DirectiveKind,
+    pub value: ParsedExpression,
+    pub span: Span,
+}
+
+/// A constant declared at the top level of a module, like
+/// `decl SOME_CONST: i32 = 0x2a;`. Its value is worked out at
+/// compile time, so it may call procedures.
+#[derive(Debug, Clone)]
+pub struct ParsedConstDecl {
+    pub name: String,
+    pub span: Span,
+    pub parsed_type: ParsedType,
+    pub value: ParsedExpression,
+    /// The text of the `///` comments in front of it
+    #[allow(dead_code)] // only printed by `--emit=ast` so far
+    pub doc: Option<String>,
 }
 
-/// A call to a procedure
+/// A call to a procedure. Calls through a namespace, like
+/// `Event::KeyPressed(key)`, may also build an enum variant.
 #[derive(Debug, Clone)]
 pub struct ParsedProcCall {
+    pub namespace: Vec<String>,
     pub name: String,
+    pub span: Span,
     pub passed_parameters: Vec<ParsedVarDecl>,
 }
 
@@ -151,8 +302,8 @@ pub enum RangeExprBound {
     Exclusive,
 }
 
-/// The body of a `match` expression. It can either
-/// consist of a single expression, or a block that
+/// The body of a `match` arm, or a branch of an `if` that's used as a
+/// value. It can either consist of a single expression, or a block that
 /// has a return expression of the same type.
 #[derive(Debug, Clone)]
 pub enum MatchExprBody {
@@ -160,13 +311,35 @@ pub enum MatchExprBody {
     Block(ParsedBlock),
 }
 
+/// A name that a `match` arm gives to one field of a variant's
+/// payload. `label` is only filled in for `label: name`; a lone
+/// name binds the field at its position, or the field with that
+/// name if the payload is labeled. `_` binds nothing.
+#[derive(Debug, Clone)]
+pub struct MatchBinding {
+    pub label: String,
+    pub name: String,
+    pub span: Span,
+}
+
+/// An arm that matches one variant of an enum, like
+/// `Event::Moved(x, y)`. `bindings` is `None` when the arm
+/// doesn't look at the payload at all.
+#[derive(Debug, Clone)]
+pub struct ParsedVariantPattern {
+    pub namespace: Vec<String>,
+    pub name: String,
+    pub bindings: Option<Vec<MatchBinding>>,
+    pub span: Span,
+}
+
 /// The case of a `match` expression that is to be matched
 /// against.
 #[derive(Debug, Clone)]
 pub enum MatchExprCase {
     Expr(ParsedExpression, MatchExprBody),
-    EnumVariant(String, MatchExprBody),
-    Fallback(MatchExprBody),
+    EnumVariant(ParsedVariantPattern, MatchExprBody),
+    Fallback(Span, MatchExprBody),
 }
 
 /// An abstract representation of an expression, which is
@@ -174,25 +347,73 @@ pub enum MatchExprCase {
 /// general that are used to express some meaningful value.
 #[derive(Debug, Clone)]
 pub enum ParsedExpression {
-    Bool(bool),
-    NumericConstant(NumericConstant),
-    StringLiteral(String),
-    CharLiteral(u8),
-    Var(String),
-    NamespacedVar(Vec<String>, String),
-    Range(RangeExprBound, Box<ParsedExpression>, Box<ParsedExpression>, RangeExprBound),
-    Match(Box<ParsedExpression>, Vec<MatchExprCase>),
-    Operator(BinaryOperator),
-    UnaryOperation(Box<ParsedExpression>, UnaryOperator),
-    BinaryOperation(Box<ParsedExpression>, BinaryOperator, Box<ParsedExpression>),
+    Bool(bool, Span),
+    /// `null`, which is a pointer of whatever type the surrounding code
+    /// expects
+    Null(Span),
+    NumericConstant(NumericConstant, Span),
+    StringLiteral(String, Span),
+    CharLiteral(char, Span),
+    Var(String, Span),
+    NamespacedVar(Vec<String>, String, Span),
+    Range(RangeExprBound, Box<ParsedExpression>, Box<ParsedExpression>, RangeExprBound, Span),
+    /// The type is what every arm yields, which the typechecker fills in
+    Match(Box<ParsedExpression>, Vec<MatchExprCase>, Box<ParsedType>, Span),
+    /// An `if` that's used as a value, given as the condition and the
+    /// branches. An `else if` is an `If` in the `else` branch. The type is
+    /// filled in like a `Match`'s.
+    If(Box<ParsedExpression>, Box<MatchExprBody>, Box<MatchExprBody>, Box<ParsedType>, Span),
+    /// `[a, b, c]`. The type is the array's, which the typechecker fills in
+    ArrayLiteral(Vec<ParsedExpression>, Box<ParsedType>, Span),
+    /// `[value; N]`, an array of `N` copies of the value. The type is
+    /// filled in by the typechecker like an `ArrayLiteral`'s.
+    ArrayRepeat(Box<ParsedExpression>, Box<ParsedExpression>, Box<ParsedType>, Span),
+    /// `array[index]`
+    Index(Box<ParsedExpression>, Box<ParsedExpression>, Span),
+    /// `Name{member: value, ...}`. The typechecker resolves the type.
+    StructLiteral(Box<ParsedType>, Vec<ParsedVarDecl>, Span),
+    /// `value.member`, where the value is a struct or a pointer to one
+    Member(Box<ParsedExpression>, String, Span),
+    /// `value.method(...)`. The typechecker turns these into a `ProcCall`
+    /// to the proc in the struct's namespace, with the value passed first.
+    MethodCall(Box<ParsedExpression>, ParsedProcCall),
+    Operator(BinaryOperator, Span),
+    UnaryOperation(Box<ParsedExpression>, UnaryOperator, Span),
+    BinaryOperation(Box<ParsedExpression>, BinaryOperator, Box<ParsedExpression>, Span),
     ProcCall(ParsedProcCall),
     Invalid,
 }
 
 impl ParsedExpression {
+    pub fn span(&self) -> Span {
+        match self {
+            ParsedExpression::Bool(_, span)                  |
+            ParsedExpression::Null(span)                     |
+            ParsedExpression::NumericConstant(_, span)       |
+            ParsedExpression::StringLiteral(_, span)         |
+            ParsedExpression::CharLiteral(_, span)           |
+            ParsedExpression::Var(_, span)                   |
+            ParsedExpression::NamespacedVar(_, _, span)      |
+            ParsedExpression::Range(_, _, _, _, span)        |
+            ParsedExpression::Match(_, _, _, span)           |
+            ParsedExpression::If(_, _, _, _, span)           |
+            ParsedExpression::ArrayLiteral(_, _, span)       |
+            ParsedExpression::ArrayRepeat(_, _, _, span)     |
+            ParsedExpression::Index(_, _, span)              |
+            ParsedExpression::StructLiteral(_, _, span)      |
+            ParsedExpression::Member(_, _, span)             |
+            ParsedExpression::Operator(_, span)              |
+            ParsedExpression::UnaryOperation(_, _, span)     |
+            ParsedExpression::BinaryOperation(_, _, _, span) => *span,
+            ParsedExpression::ProcCall(call)        |
+            ParsedExpression::MethodCall(_, call) => call.span,
+            ParsedExpression::Invalid => Span::default(),
+        }
+    }
+
     pub fn priority(&self) -> i32 {
         match self {
-            ParsedExpression::Operator(op) => {
+            ParsedExpression::Operator(op, _span) => {
                 match op {
                     BinaryOperator::Invalid => -1,
 
@@ -239,14 +460,28 @@ impl ParsedExpression {
         }
     }
 
-    pub fn is_assignable(&self) -> bool {
+    /// Number literals don't have a type of their own, so they take the
+    /// type of whatever they're combined with.
+    pub fn is_untyped_literal(&self) -> bool {
         match self {
-            ParsedExpression::Var(_)              |
-            ParsedExpression::NamespacedVar(_, _) |
-            ParsedExpression::UnaryOperation(_, _) => true,
+            ParsedExpression::NumericConstant(constant, _) => constant.suffix().is_none(),
+            ParsedExpression::Null(_) => true,
+            ParsedExpression::UnaryOperation(operand, UnaryOperator::Negate, _) => operand.is_untyped_literal(),
+            ParsedExpression::If(_, then_body, else_body, _, _) => {
+                [then_body, else_body].iter().all(|body| matches!(&***body, MatchExprBody::Expr(value) if value.is_untyped_literal()))
+            },
             _ => false
         }
     }
+
+    /// Whether the expression names somewhere a value is stored, so it can
+    /// be assigned to or have its members reached without a copy.
+    pub fn is_assignable(&self) -> bool {
+        matches!(self, ParsedExpression::Var(_, _)        |
+                       ParsedExpression::Index(_, _, _)   |
+                       ParsedExpression::Member(_, _, _)  |
+                       ParsedExpression::UnaryOperation(_, UnaryOperator::Dereference, _))
+    }
 }
 
 /// A statement that is to be acted upon, typically
@@ -255,27 +490,50 @@ impl ParsedExpression {
 pub enum ParsedStatement {
     Expr(ParsedExpression),
     VarDecl(ParsedVarDecl),
-    VarAssign(String, BinaryOperator, ParsedExpression),
+    /// An assignment straight to a variable, like `x += 1`, given with the
+    /// span of the variable's name
+    VarAssign(String, BinaryOperator, ParsedExpression, Span),
     If(ParsedExpression, ParsedBlock, Option<Box<ParsedStatement>>),
     Block(ParsedBlock),
     ForLoop(ParsedVarDecl, ParsedExpression, ParsedBlock),
     WhileLoop(ParsedExpression, ParsedBlock),
     InfiniteLoop(ParsedBlock),
-    Continue,
-    Break,
-    Return(ParsedExpression),
+    Continue(Span),
+    Break(Span),
+    Return(Option<ParsedExpression>, Span),
+    /// A statement that failed to parse, covering the tokens that were
+    /// skipped to recover from it.
+    Invalid(Span),
 }
 
 #[derive(Debug, Clone)]
 pub struct ParsedImport {
+    /// The file of the module doing the importing, whose directory the
+    /// path is looked for in first
     pub current_module_path: String,
+    /// The path as written, without the `.amds` extension
     pub path: String,
+    pub span: Span,
+}
+
+impl ParsedImport {
+    /// What the imported module's declarations are prefixed with, which is
+    /// the name of its file: `core/io` is used as `io::println`.
+    pub fn name(&self) -> String {
+        Path::new(&self.path).file_stem()
+                             .map(|stem| stem.to_string_lossy().into_owned())
+                             .unwrap_or_default()
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct ParsedStructDecl {
     pub name: String,
+    pub span: Span,
     pub data_members: Vec<ParsedVarDecl>,
+    /// The text of the `///` comments in front of it
+    #[allow(dead_code)] // only printed by `--emit=ast` so far
+    pub doc: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -288,7 +546,11 @@ pub enum ParsedEnumVariant {
 #[derive(Debug, Clone)]
 pub struct ParsedEnumDecl {
     pub name: String,
+    pub span: Span,
     pub variants: Vec<ParsedEnumVariant>,
+    /// The text of the `///` comments in front of it
+    #[allow(dead_code)] // only printed by `--emit=ast` so far
+    pub doc: Option<String>,
 }
 
 /// A block of statements, denoted by matching `{` and `}`.
@@ -306,22 +568,54 @@ impl ParsedBlock {
 }
 
 /// A module, which is really just a file.
+#[derive(Debug)]
 pub struct ParsedModule {
     pub name: String,
+    /// What other modules put in front of the names declared in this one.
+    /// The module being compiled doesn't have one.
+    pub namespace: Option<String>,
+    /// The file an imported module was loaded from. Modules in different
+    /// files can share a namespace when they're imported by different
+    /// inputs of the same build.
+    pub import_path: Option<PathBuf>,
+    /// The text of the `//!` comments at the start of the file
+    pub doc: Option<String>,
     pub imports: Vec<ParsedImport>,
     pub structs: Vec<ParsedStructDecl>,
     pub enums: Vec<ParsedEnumDecl>,
     pub procs: Vec<ParsedProcDecl>,
+    pub consts: Vec<ParsedConstDecl>,
+    pub directives: Vec<ParsedDirective>,
 }
 
 impl ParsedModule {
     pub fn new(name: &str) -> Self {
         Self {
             name: String::from(name),
+            namespace: None,
+            import_path: None,
+            doc: None,
             imports: Vec::new(),
             structs: Vec::new(),
             enums: Vec::new(),
             procs: Vec::new(),
+            consts: Vec::new(),
+            directives: Vec::new(),
         }
     }
+
+    /// Makes this an imported module, whose declarations are only reachable
+    /// as `namespace::name`. Names are qualified up front so declarations
+    /// from different modules can't clash.
+    pub fn set_namespace(&mut self, namespace: &str, path: &Path) {
+        let qualify = |name: &mut String| *name = format!("{}::{}", namespace, name);
+
+        self.structs.iter_mut().for_each(|decl| qualify(&mut decl.name));
+        self.enums.iter_mut().for_each(|decl| qualify(&mut decl.name));
+        self.procs.iter_mut().for_each(|decl| qualify(&mut decl.name));
+        self.consts.iter_mut().for_each(|decl| qualify(&mut decl.name));
+
+        self.namespace = Some(String::from(namespace));
+        self.import_path = Some(path.to_path_buf());
+    }
 }
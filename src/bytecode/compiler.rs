@@ -0,0 +1,945 @@
+use std::collections::HashMap;
+
+use super::{ Instruction, Proc, Program };
+use crate::ast::{ BinaryOperator, RangeExprBound, UnaryOperator };
+use crate::checked_ast::*;
+use crate::intrinsics::{ FormatPiece, FormatVerb };
+use crate::token::{ PrimitiveType, Span };
+use crate::typechecker::{ TypeID, TypeInfo };
+
+/// Jumps out of the innermost loop that still have to be pointed at
+/// wherever they go.
+struct LoopContext {
+    /// Known up front for `while` and `loop`, but a `for` loop steps its
+    /// iterator after the body, so it's patched in later like `break`.
+    continue_target: Option<u32>,
+    continue_jumps: Vec<usize>,
+    break_jumps: Vec<usize>,
+}
+
+/// Where a value that's stored to lives: in a variable, or wherever the
+/// pointer in a hidden local points.
+enum Place {
+    Local(u32),
+    Pointer(u32),
+}
+
+struct Compiler<'a> {
+    module: &'a CheckedModule,
+    proc_ids: HashMap<&'a str, u32>,
+    strings: Vec<String>,
+    code: Vec<Instruction>,
+    spans: Vec<Span>,
+    /// The slot each local of the proc or constant being compiled starts at
+    local_offsets: Vec<u32>,
+    local_count: u32,
+    loops: Vec<LoopContext>,
+    /// The proc or constant being compiled, which instructions that don't
+    /// come from any one expression point at.
+    decl_span: Span,
+}
+
+/// Compiles a typechecked module to bytecode. Every constant becomes a
+/// proc of its own after the module's procs, which returns its value.
+pub fn compile_module(checked_module: &CheckedModule) -> Result<Program, String> {
+    let mut compiler = Compiler {
+        module: checked_module,
+        proc_ids: checked_module.procs.iter()
+                                      .enumerate()
+                                      .map(|(idx, proc_decl)| (proc_decl.name.as_str(), idx as u32))
+                                      .collect(),
+        strings: Vec::new(),
+        code: Vec::new(),
+        spans: Vec::new(),
+        local_offsets: Vec::new(),
+        local_count: 0,
+        loops: Vec::new(),
+        decl_span: Span::default(),
+    };
+
+    let mut procs = Vec::new();
+    for proc_decl in &checked_module.procs {
+        procs.push(compiler.compile_proc_decl(proc_decl)?);
+    }
+
+    for const_decl in &checked_module.consts {
+        procs.push(compiler.compile_const_decl(const_decl)?);
+    }
+
+    Ok(Program { strings: compiler.strings, procs })
+}
+
+impl<'a> Compiler<'a> {
+    fn primitive(&self, type_id: TypeID) -> PrimitiveType {
+        match self.module.type_info(type_id) {
+            TypeInfo::Primitive(primitive, _size) => primitive.clone(),
+            info => unreachable!("`{}` isn't a primitive", info.name()),
+        }
+    }
+
+    /// The primitive a scalar is kept as, which for a pointer is `u64`.
+    fn scalar(&self, type_id: TypeID) -> PrimitiveType {
+        match self.pointee_of(type_id) {
+            Some(_) => PrimitiveType::U64,
+            None => self.primitive(type_id),
+        }
+    }
+
+    fn pointee_of(&self, type_id: TypeID) -> Option<TypeID> {
+        match self.module.type_info(type_id) {
+            TypeInfo::Pointer(_, pointee) => Some(*pointee),
+            _ => None,
+        }
+    }
+
+    /// How many slots a value of the type takes up on the stack.
+    fn slots(&self, type_id: TypeID) -> Result<u32, String> {
+        let info = self.module.type_info(type_id);
+        let too_big = || format!("`{}` is too big for the bytecode VM", info.name());
+
+        match info {
+            TypeInfo::Primitive(PrimitiveType::Nothing, _size) => Ok(0),
+            TypeInfo::Primitive(_, _) | TypeInfo::Pointer(_, _) => Ok(1),
+            TypeInfo::Struct(_, fields) | TypeInfo::EnumVariant(_, fields) => self.fields_slots(fields),
+            TypeInfo::Enum(_, variants) => {
+                let mut payload = 0;
+                for variant in variants {
+                    payload = payload.max(self.slots(*variant)?);
+                }
+
+                payload.checked_add(1).ok_or_else(too_big)
+            },
+            TypeInfo::Array(_, elem_type, len) => {
+                let elem = self.slots(*elem_type)?;
+                u32::try_from(*len).ok().and_then(|len| len.checked_mul(elem)).ok_or_else(too_big)
+            },
+            TypeInfo::Unknown => unreachable!("Unknown types never make it past the typechecker"),
+        }
+    }
+
+    /// How many slots `fields` take up one after the other, which is also
+    /// where the field after them starts.
+    fn fields_slots(&self, fields: &[(String, TypeID)]) -> Result<u32, String> {
+        let mut total: u32 = 0;
+        for (_, field) in fields {
+            total = total.checked_add(self.slots(*field)?).ok_or_else(|| String::from("a struct is too big for the bytecode VM"))?;
+        }
+
+        Ok(total)
+    }
+
+    /// The index of a variant in its enum, which is what the first slot of
+    /// an enum holds.
+    fn variant_index(&self, enum_type: TypeID, variant_type: TypeID) -> u64 {
+        let TypeInfo::Enum(_, variants) = self.module.type_info(enum_type) else {
+            unreachable!("Only enums have variants");
+        };
+
+        variants.iter().position(|variant| *variant == variant_type).expect("Variants are checked by the typechecker") as u64
+    }
+
+    fn type_of(&self, expr: &CheckedExpression) -> TypeID {
+        self.module.type_of(expr)
+    }
+
+    fn emit(&mut self, instruction: Instruction, span: Span) -> usize {
+        self.code.push(instruction);
+        self.spans.push(span);
+        self.code.len() - 1
+    }
+
+    /// Where `value` is in the program's string table, which it's added to
+    /// the first time it's used.
+    fn string_idx(&mut self, value: &str) -> u32 {
+        let idx = match self.strings.iter().position(|string| string == value) {
+            Some(idx) => idx,
+            None => {
+                self.strings.push(String::from(value));
+                self.strings.len() - 1
+            }
+        };
+
+        idx as u32
+    }
+
+    fn next_pc(&self) -> u32 {
+        self.code.len() as u32
+    }
+
+    /// Points the jump at `jump_pc` to wherever the next instruction goes.
+    fn patch_jump(&mut self, jump_pc: usize) {
+        let target = self.next_pc();
+
+        match &mut self.code[jump_pc] {
+            Instruction::Jump(old_target) | Instruction::JumpIfFalse(old_target) => *old_target = target,
+            instruction => unreachable!("Tried to patch {} like a jump", instruction),
+        }
+    }
+
+    /// A local that the program can't name, for values the compiler has
+    /// to hold on to, like the end of a `for` loop's range.
+    fn hidden_local(&mut self, slots: u32) -> Result<u32, String> {
+        let offset = self.local_count;
+        self.local_count = offset.checked_add(slots).ok_or_else(|| String::from("a proc's locals are too big for the bytecode VM"))?;
+        Ok(offset)
+    }
+
+    /// Gives each of `locals` its slots, one after the other.
+    fn layout_locals(&mut self, locals: &[CheckedVarDecl]) -> Result<(), String> {
+        self.local_offsets.clear();
+        self.local_count = 0;
+
+        for local in locals {
+            let offset = self.hidden_local(self.slots(local.type_id)?)?;
+            self.local_offsets.push(offset);
+        }
+
+        Ok(())
+    }
+
+    fn emit_load_local(&mut self, offset: u32, slots: u32, span: Span) {
+        match slots {
+            0 => { },
+            1 => { self.emit(Instruction::LoadLocal(offset), span); },
+            _ => {
+                self.emit(Instruction::LocalAddress(offset), span);
+                self.emit(Instruction::Load(slots), span);
+            },
+        }
+    }
+
+    fn emit_store_local(&mut self, offset: u32, slots: u32, span: Span) {
+        match slots {
+            0 => { },
+            1 => { self.emit(Instruction::StoreLocal(offset), span); },
+            _ => {
+                self.emit(Instruction::LocalAddress(offset), span);
+                self.emit(Instruction::Store(slots), span);
+            },
+        }
+    }
+
+    /// Throws away a value of `slots` slots on top of the stack.
+    fn emit_discard(&mut self, slots: u32, span: Span) -> Result<(), String> {
+        match slots {
+            0 => { },
+            1 => { self.emit(Instruction::Pop, span); },
+            _ => {
+                let local = self.hidden_local(slots)?;
+                self.emit_store_local(local, slots, span);
+            },
+        }
+
+        Ok(())
+    }
+
+    fn compile_proc_decl(&mut self, proc_decl: &CheckedProcDecl) -> Result<Proc, String> {
+        self.code.clear();
+        self.spans.clear();
+        self.layout_locals(&proc_decl.locals)?;
+        self.decl_span = proc_decl.span;
+
+        let param_count = self.local_offsets.get(proc_decl.param_count).copied().unwrap_or(self.local_count);
+
+        self.compile_block(&proc_decl.body)?;
+
+        // The typechecker makes sure procs that return a value always do,
+        // so this is only reached by the ones that don't.
+        if !proc_decl.body.always_returns() {
+            self.emit(Instruction::Return, proc_decl.span);
+        }
+
+        Ok(Proc {
+            name: proc_decl.name.clone(),
+            param_count,
+            local_count: self.local_count,
+            return_slots: self.slots(proc_decl.return_type)?,
+            code: std::mem::take(&mut self.code),
+            spans: std::mem::take(&mut self.spans),
+        })
+    }
+
+    fn compile_const_decl(&mut self, const_decl: &CheckedConstDecl) -> Result<Proc, String> {
+        self.code.clear();
+        self.spans.clear();
+        self.layout_locals(&const_decl.locals)?;
+        self.decl_span = const_decl.span;
+
+        self.compile_expression(&const_decl.value)?;
+        self.emit(Instruction::Return, const_decl.span);
+
+        Ok(Proc {
+            name: const_decl.name.clone(),
+            param_count: 0,
+            local_count: self.local_count,
+            // Only ever 0 for the expression of a `#run`
+            return_slots: self.slots(const_decl.type_id)?,
+            code: std::mem::take(&mut self.code),
+            spans: std::mem::take(&mut self.spans),
+        })
+    }
+
+    fn compile_block(&mut self, block: &CheckedBlock) -> Result<(), String> {
+        for stmt in &block.stmts {
+            self.compile_statement(stmt)?;
+
+            // Anything after this can never run
+            if stmt.always_returns() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn compile_statement(&mut self, stmt: &CheckedStatement) -> Result<(), String> {
+        match stmt {
+            CheckedStatement::Expr(expr) => {
+                self.compile_expression(expr)?;
+                self.emit_discard(self.slots(self.type_of(expr))?, expr.span())?;
+            },
+            CheckedStatement::VarDecl(var_id, init) => {
+                self.compile_expression(init)?;
+                self.emit_store_local(self.local_offsets[*var_id], self.slots(self.type_of(init))?, init.span());
+            },
+            CheckedStatement::If(cond, then_block, else_stmt) => {
+                self.compile_expression(cond)?;
+                let jump_to_else = self.emit(Instruction::JumpIfFalse(0), cond.span());
+
+                self.compile_block(then_block)?;
+
+                match else_stmt {
+                    Some(else_stmt) => {
+                        let jump_to_end = self.emit(Instruction::Jump(0), cond.span());
+                        self.patch_jump(jump_to_else);
+                        self.compile_statement(else_stmt)?;
+                        self.patch_jump(jump_to_end);
+                    },
+                    None => self.patch_jump(jump_to_else),
+                }
+            },
+            CheckedStatement::Block(block) => self.compile_block(block)?,
+            CheckedStatement::ForLoop(var_id, range, body) => self.compile_for_loop(self.local_offsets[*var_id], range, body)?,
+            CheckedStatement::WhileLoop(cond, body) => {
+                let cond_pc = self.next_pc();
+                self.compile_expression(cond)?;
+                let jump_to_end = self.emit(Instruction::JumpIfFalse(0), cond.span());
+
+                self.compile_loop_body(body, Some(cond_pc))?;
+                self.emit(Instruction::Jump(cond_pc), cond.span());
+
+                self.patch_jump(jump_to_end);
+                self.finish_loop();
+            },
+            CheckedStatement::InfiniteLoop(body) => {
+                let body_pc = self.next_pc();
+
+                self.compile_loop_body(body, Some(body_pc))?;
+                self.emit(Instruction::Jump(body_pc), self.decl_span);
+
+                self.finish_loop();
+            },
+            CheckedStatement::Continue => {
+                let context = self.loops.last().expect("`continue` outside of a loop");
+
+                match context.continue_target {
+                    Some(target) => { self.emit(Instruction::Jump(target), self.decl_span); },
+                    None => {
+                        let jump = self.emit(Instruction::Jump(0), self.decl_span);
+                        self.loops.last_mut().unwrap().continue_jumps.push(jump);
+                    },
+                }
+            },
+            CheckedStatement::Break => {
+                let jump = self.emit(Instruction::Jump(0), self.decl_span);
+                self.loops.last_mut().expect("`break` outside of a loop").break_jumps.push(jump);
+            },
+            CheckedStatement::Return(expr) => {
+                let span = match expr {
+                    Some(expr) => {
+                        self.compile_expression(expr)?;
+                        expr.span()
+                    },
+                    None => self.decl_span,
+                };
+
+                self.emit(Instruction::Return, span);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Compiles the body of a loop. The loop has to call `finish_loop`
+    /// once the instruction after it is where a `break` should land.
+    fn compile_loop_body(&mut self, body: &CheckedBlock, continue_target: Option<u32>) -> Result<(), String> {
+        self.loops.push(LoopContext { continue_target, continue_jumps: Vec::new(), break_jumps: Vec::new() });
+        self.compile_block(body)
+    }
+
+    fn finish_loop(&mut self) {
+        let context = self.loops.pop().expect("Finished a loop that wasn't started");
+
+        for jump in context.break_jumps {
+            self.patch_jump(jump);
+        }
+    }
+
+    /// Works the same way as the LLVM backend: the end of the range is
+    /// evaluated once, and the iterator is a variable that's incremented
+    /// after each pass, unless it's already reached the end.
+    fn compile_for_loop(&mut self, var_id: u32, range: &CheckedExpression, body: &CheckedBlock) -> Result<(), String> {
+        let CheckedExpression::Range(start_bound, start, end, end_bound, type_id, span) = range else {
+            // Anything else is an array
+            return self.compile_array_loop(var_id, range, body);
+        };
+
+        let ty = self.primitive(*type_id);
+        let span = *span;
+
+        self.compile_expression(start)?;
+
+        let end_local = self.hidden_local(1)?;
+        self.compile_expression(end)?;
+        self.emit(Instruction::StoreLocal(end_local), span);
+        self.emit(Instruction::StoreLocal(var_id), span);
+
+        let mut jumps_to_end = Vec::new();
+
+        // Nothing comes after a `start` that's already at `end`, and adding
+        // one to it could wrap
+        if let RangeExprBound::Exclusive = start_bound {
+            jumps_to_end.push(self.emit_jump_unless_before_end(var_id, end_local, &ty, span));
+            self.emit_increment(var_id, &ty, span);
+        }
+
+        let cond_pc = self.next_pc();
+        let compare = match end_bound {
+            RangeExprBound::Inclusive => BinaryOperator::LEQ,
+            RangeExprBound::Exclusive => BinaryOperator::LT,
+        };
+
+        self.emit(Instruction::LoadLocal(var_id), span);
+        self.emit(Instruction::LoadLocal(end_local), span);
+        self.emit(Instruction::Binary(compare, ty.clone()), span);
+        jumps_to_end.push(self.emit(Instruction::JumpIfFalse(0), span));
+
+        self.compile_loop_body(body, None)?;
+
+        for jump in std::mem::take(&mut self.loops.last_mut().unwrap().continue_jumps) {
+            self.patch_jump(jump);
+        }
+
+        jumps_to_end.push(self.emit_jump_unless_before_end(var_id, end_local, &ty, span));
+        self.emit_increment(var_id, &ty, span);
+        self.emit(Instruction::Jump(cond_pc), span);
+
+        for jump in jumps_to_end {
+            self.patch_jump(jump);
+        }
+        self.finish_loop();
+
+        Ok(())
+    }
+
+    /// The array is evaluated once into a hidden local, and the iterator is
+    /// a copy of each of its elements in turn.
+    fn compile_array_loop(&mut self, var_id: u32, array: &CheckedExpression, body: &CheckedBlock) -> Result<(), String> {
+        let array_type = self.type_of(array);
+        let TypeInfo::Array(_, elem_type, len) = self.module.type_info(array_type) else {
+            unreachable!("Only ranges and arrays can be looped over");
+        };
+
+        let (stride, len) = (self.slots(*elem_type)?, *len);
+        let array_slots = self.slots(array_type)?;
+        let span = array.span();
+
+        let array_local = self.hidden_local(array_slots)?;
+        self.compile_expression(array)?;
+        self.emit_store_local(array_local, array_slots, span);
+
+        let index_local = self.hidden_local(1)?;
+        self.emit(Instruction::PushConst(0), span);
+        self.emit(Instruction::StoreLocal(index_local), span);
+
+        let cond_pc = self.next_pc();
+        self.emit(Instruction::LoadLocal(index_local), span);
+        self.emit(Instruction::PushConst(len), span);
+        self.emit(Instruction::Binary(BinaryOperator::LT, PrimitiveType::U64), span);
+        let jump_to_end = self.emit(Instruction::JumpIfFalse(0), span);
+
+        self.emit(Instruction::LocalAddress(array_local), span);
+        self.emit(Instruction::LoadLocal(index_local), span);
+        self.emit(Instruction::Index(PrimitiveType::U64, len, stride), span);
+        self.emit(Instruction::Load(stride), span);
+        self.emit_store_local(var_id, stride, span);
+
+        self.compile_loop_body(body, None)?;
+
+        for jump in std::mem::take(&mut self.loops.last_mut().unwrap().continue_jumps) {
+            self.patch_jump(jump);
+        }
+
+        self.emit(Instruction::LoadLocal(index_local), span);
+        self.emit(Instruction::PushConst(1), span);
+        self.emit(Instruction::Binary(BinaryOperator::Add, PrimitiveType::U64), span);
+        self.emit(Instruction::StoreLocal(index_local), span);
+        self.emit(Instruction::Jump(cond_pc), span);
+
+        self.patch_jump(jump_to_end);
+        self.finish_loop();
+
+        Ok(())
+    }
+
+    /// Jumps out of a range loop when the iterator isn't below the end,
+    /// giving back the jump to patch.
+    fn emit_jump_unless_before_end(&mut self, var_id: u32, end_local: u32, ty: &PrimitiveType, span: Span) -> usize {
+        self.emit(Instruction::LoadLocal(var_id), span);
+        self.emit(Instruction::LoadLocal(end_local), span);
+        self.emit(Instruction::Binary(BinaryOperator::LT, ty.clone()), span);
+        self.emit(Instruction::JumpIfFalse(0), span)
+    }
+
+    fn emit_increment(&mut self, var_id: u32, ty: &PrimitiveType, span: Span) {
+        self.emit(Instruction::LoadLocal(var_id), span);
+        self.emit(Instruction::PushConst(1), span);
+        self.emit(Instruction::Binary(BinaryOperator::Add, ty.clone()), span);
+        self.emit(Instruction::StoreLocal(var_id), span);
+    }
+
+    /// Compiles `expr` so its value ends up on top of the stack. Expressions
+    /// of type `nothing` don't leave anything behind.
+    fn compile_expression(&mut self, expr: &CheckedExpression) -> Result<(), String> {
+        let span = expr.span();
+
+        match expr {
+            CheckedExpression::Bool(value, _) => { self.emit(Instruction::PushConst(*value as u64), span); },
+            CheckedExpression::Integer(value, type_id, _) => {
+                let bits = self.primitive(*type_id).truncate(*value);
+                self.emit(Instruction::PushConst(bits), span);
+            },
+            CheckedExpression::Float(value, type_id, _) => {
+                let value = match self.primitive(*type_id) {
+                    PrimitiveType::F32 => *value as f32 as f64,
+                    _ => *value,
+                };
+                self.emit(Instruction::PushConst(value.to_bits()), span);
+            },
+            CheckedExpression::StringLiteral(value, _) => {
+                let idx = self.string_idx(value);
+                self.emit(Instruction::PushString(idx), span);
+            },
+            CheckedExpression::CharLiteral(value, _) => { self.emit(Instruction::PushConst(*value as u64), span); },
+            CheckedExpression::Null(_, _) => { self.emit(Instruction::PushConst(0), span); },
+            CheckedExpression::Var(var_id, type_id, _) => self.emit_load_local(self.local_offsets[*var_id], self.slots(*type_id)?, span),
+            CheckedExpression::Const(const_id, _, _) => {
+                let idx = self.module.procs.len() + *const_id;
+                self.emit(Instruction::Call(idx as u32), span);
+            },
+            CheckedExpression::UnaryOperation(operand, op, type_id, _) => self.compile_unary_operation(operand, op, *type_id, span)?,
+            CheckedExpression::BinaryOperation(lhs, op, rhs, _, _) => self.compile_binary_operation(lhs, op, rhs, span)?,
+            CheckedExpression::ProcCall(call, _, _) => {
+                for arg in &call.args {
+                    self.compile_expression(arg)?;
+                }
+
+                let idx = self.proc_ids[call.name.as_str()];
+                if self.module.procs[idx as usize].foreign.is_some() {
+                    return Err(format!("`{}` is a `#foreign` proc, which can't be called from bytecode", call.name));
+                }
+
+                self.emit(Instruction::Call(idx), span);
+            },
+            CheckedExpression::IntrinsicCall(call, _, _) => self.compile_intrinsic_call(call, span)?,
+            CheckedExpression::Range(_, _, _, _, _, _) => return Err(String::from("ranges can only be used in `for` loops")),
+            CheckedExpression::Match(matched, cases, _, _) => self.compile_match(matched, cases, span)?,
+            CheckedExpression::If(cond, then_body, else_body, _, _) => self.compile_if_expression(cond, then_body, else_body, span)?,
+            CheckedExpression::EnumValue(variant_type, payload, enum_type, _) => {
+                self.emit(Instruction::PushConst(self.variant_index(*enum_type, *variant_type)), span);
+                for field in payload {
+                    self.compile_expression(field)?;
+                }
+
+                // Every variant takes up as much room as the biggest one
+                let padding = self.slots(*enum_type)? - 1 - self.slots(*variant_type)?;
+                if padding > 0 {
+                    self.emit(Instruction::PushConst(0), span);
+                    self.emit(Instruction::Repeat(1, padding), span);
+                }
+            },
+            CheckedExpression::Array(elems, _, _) |
+            CheckedExpression::Struct(elems, _, _) => {
+                for elem in elems {
+                    self.compile_expression(elem)?;
+                }
+            },
+            CheckedExpression::ArrayRepeat(value, count, type_id, _) => {
+                // Makes sure the whole array fits before it's made
+                self.slots(*type_id)?;
+                let count = u32::try_from(*count).map_err(|_| format!("`{}` is too big for the bytecode VM", self.module.type_info(*type_id).name()))?;
+
+                self.compile_expression(value)?;
+                self.emit(Instruction::Repeat(self.slots(self.type_of(value))?, count), span);
+            },
+            CheckedExpression::Index(_, _, type_id, _) |
+            CheckedExpression::Member(_, _, type_id, _) => {
+                self.compile_address(expr)?;
+                self.emit(Instruction::Load(self.slots(*type_id)?), span);
+            },
+            CheckedExpression::Invalid(_) => unreachable!("Invalid expressions never make it past the typechecker"),
+        }
+
+        Ok(())
+    }
+
+    /// `print` and `println` write each piece of their format in turn, and
+    /// every other intrinsic is an instruction of its own.
+    fn compile_intrinsic_call(&mut self, call: &CheckedIntrinsicCall, span: Span) -> Result<(), String> {
+        if !call.intrinsic.takes_format() {
+            for arg in &call.args {
+                self.compile_expression(arg)?;
+            }
+
+            self.emit(Instruction::Intrinsic(call.intrinsic), span);
+            return Ok(());
+        }
+
+        let mut args = call.args.iter();
+
+        for piece in &call.format {
+            match piece {
+                FormatPiece::Text(text) => {
+                    let idx = self.string_idx(text);
+                    self.emit(Instruction::PushString(idx), span);
+                    self.emit(Instruction::Print(PrimitiveType::String, FormatVerb::String), span);
+                },
+                FormatPiece::Verb(verb) => {
+                    let arg = args.next().expect("The typechecker matches up verbs with arguments");
+                    let ty = self.primitive(self.type_of(arg));
+                    self.compile_expression(arg)?;
+                    self.emit(Instruction::Print(ty, *verb), span);
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compares the value against each arm in turn, like a chain of `if`s.
+    /// An enum is matched by the index of its variant, and the fields of
+    /// the payload an arm binds are copied into their locals.
+    fn compile_match(&mut self, matched: &CheckedExpression, cases: &[CheckedMatchCase], span: Span) -> Result<(), String> {
+        let matched_type = self.type_of(matched);
+        let slots = self.slots(matched_type)?;
+
+        let matched_local = self.hidden_local(slots)?;
+        self.compile_expression(matched)?;
+        self.emit_store_local(matched_local, slots, span);
+
+        let mut jumps_to_end = Vec::new();
+
+        for case in cases {
+            let jump_to_next = match case {
+                CheckedMatchCase::Expr(case_expr, _) => {
+                    self.emit(Instruction::LoadLocal(matched_local), span);
+                    self.compile_expression(case_expr)?;
+                    self.emit_operation(&BinaryOperator::EQ, matched_type, self.type_of(case_expr), case_expr.span())?;
+                    Some(self.emit(Instruction::JumpIfFalse(0), case_expr.span()))
+                },
+                CheckedMatchCase::EnumVariant(variant_type, bindings, _) => {
+                    self.emit(Instruction::LoadLocal(matched_local), span);
+                    self.emit(Instruction::PushConst(self.variant_index(matched_type, *variant_type)), span);
+                    self.emit(Instruction::Binary(BinaryOperator::EQ, PrimitiveType::U64), span);
+                    let jump = self.emit(Instruction::JumpIfFalse(0), span);
+
+                    let TypeInfo::EnumVariant(_, payload) = self.module.type_info(*variant_type) else {
+                        unreachable!("Arms only match variants");
+                    };
+
+                    for (field_idx, var_id) in bindings {
+                        // The payload starts after the variant's index
+                        let offset = 1 + self.fields_slots(&payload[..*field_idx])?;
+                        let field_slots = self.slots(payload[*field_idx].1)?;
+
+                        self.emit_load_local(matched_local + offset, field_slots, span);
+                        self.emit_store_local(self.local_offsets[*var_id], field_slots, span);
+                    }
+
+                    Some(jump)
+                },
+                CheckedMatchCase::Fallback(_) => None,
+            };
+
+            self.compile_match_body(case.body())?;
+
+            jumps_to_end.push(self.emit(Instruction::Jump(0), span));
+
+            match jump_to_next {
+                Some(jump) => self.patch_jump(jump),
+                // Nothing after an `else` arm can match
+                None => break,
+            }
+        }
+
+        for jump in jumps_to_end {
+            self.patch_jump(jump);
+        }
+
+        Ok(())
+    }
+
+    fn compile_if_expression(&mut self, cond: &CheckedExpression, then_body: &CheckedMatchBody, else_body: &CheckedMatchBody, span: Span) -> Result<(), String> {
+        self.compile_expression(cond)?;
+        let jump_to_else = self.emit(Instruction::JumpIfFalse(0), cond.span());
+
+        self.compile_match_body(then_body)?;
+        let jump_to_end = self.emit(Instruction::Jump(0), span);
+
+        self.patch_jump(jump_to_else);
+        self.compile_match_body(else_body)?;
+        self.patch_jump(jump_to_end);
+
+        Ok(())
+    }
+
+    fn compile_match_body(&mut self, body: &CheckedMatchBody) -> Result<(), String> {
+        match body {
+            CheckedMatchBody::Expr(body_expr) => self.compile_expression(body_expr),
+            CheckedMatchBody::Block(block) => self.compile_block(block),
+        }
+    }
+
+    fn compile_unary_operation(&mut self, operand: &CheckedExpression, op: &UnaryOperator, type_id: TypeID, span: Span) -> Result<(), String> {
+        match op {
+            UnaryOperator::TypeCast(_) => {
+                self.compile_expression(operand)?;
+
+                // Pointers are the position of a slot, so they're cast to
+                // and from integers like a `u64`
+                let from_type = self.scalar(self.type_of(operand));
+                let to_type = self.scalar(type_id);
+                if to_type != from_type {
+                    self.emit(Instruction::Cast(from_type, to_type), span);
+                }
+            },
+            UnaryOperator::Negate => {
+                self.compile_expression(operand)?;
+                self.emit(Instruction::Negate(self.primitive(self.type_of(operand))), span);
+            },
+            UnaryOperator::LogicalNot => {
+                self.compile_expression(operand)?;
+                self.emit(Instruction::LogicalNot, span);
+            },
+            UnaryOperator::BitwiseNot => {
+                self.compile_expression(operand)?;
+                self.emit(Instruction::BitwiseNot(self.primitive(self.type_of(operand))), span);
+            },
+            UnaryOperator::PreIncrement  |
+            UnaryOperator::PostIncrement |
+            UnaryOperator::PreDecrement  |
+            UnaryOperator::PostDecrement => {
+                let is_increment = matches!(op, UnaryOperator::PreIncrement | UnaryOperator::PostIncrement);
+                let is_prefix = matches!(op, UnaryOperator::PreIncrement | UnaryOperator::PreDecrement);
+
+                // Whichever value the expression gives back is left on the
+                // stack under the one that's stored
+                let place = self.compile_place(operand)?;
+                self.emit_load_place(&place, 1, span);
+                if !is_prefix {
+                    self.emit(Instruction::Dup, span);
+                }
+                self.emit_step(self.type_of(operand), is_increment, span)?;
+                if is_prefix {
+                    self.emit(Instruction::Dup, span);
+                }
+                self.emit_store_place(&place, 1, span);
+            },
+            UnaryOperator::AddressOf => self.compile_address(operand)?,
+            UnaryOperator::Dereference => {
+                self.compile_expression(operand)?;
+                self.emit(Instruction::Load(self.slots(type_id)?), span);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Steps the number or pointer on top of the stack by one, which for a
+    /// pointer is one of what it points at.
+    fn emit_step(&mut self, type_id: TypeID, is_increment: bool, span: Span) -> Result<(), String> {
+        if let Some(pointee) = self.pointee_of(type_id) {
+            let count = if is_increment { 1 } else { -1i64 as u64 };
+            self.emit(Instruction::PushConst(count), span);
+            self.emit(Instruction::Offset(self.slots(pointee)?), span);
+            return Ok(());
+        }
+
+        let ty = self.primitive(type_id);
+        let one = if ty.is_float() { 1.0f64.to_bits() } else { 1 };
+        let bin_op = if is_increment { BinaryOperator::Add } else { BinaryOperator::Subtract };
+
+        self.emit(Instruction::PushConst(one), span);
+        self.emit(Instruction::Binary(bin_op, ty), span);
+
+        Ok(())
+    }
+
+    /// Compiles `expr` so a pointer to it ends up on top of the stack. Like
+    /// in the LLVM backend, anything that isn't a variable, an element or
+    /// member of one or what a pointer points at, like the array a proc
+    /// returns, is put in a hidden local first so it can be indexed.
+    fn compile_address(&mut self, expr: &CheckedExpression) -> Result<(), String> {
+        let span = expr.span();
+
+        match expr {
+            CheckedExpression::Var(var_id, _, _) => { self.emit(Instruction::LocalAddress(self.local_offsets[*var_id]), span); },
+            CheckedExpression::Index(array, index, elem_type, _) => {
+                let TypeInfo::Array(_, _, len) = self.module.type_info(self.type_of(array)) else {
+                    unreachable!("Only arrays can be indexed");
+                };
+
+                let index_type = self.primitive(self.type_of(index));
+                let stride = self.slots(*elem_type)?;
+
+                self.compile_address(array)?;
+                self.compile_expression(index)?;
+                self.emit(Instruction::Index(index_type, *len, stride), span);
+            },
+            CheckedExpression::Member(base, idx, _, _) => {
+                let TypeInfo::Struct(_, members) = self.module.type_info(self.type_of(base)) else {
+                    unreachable!("Only structs have members");
+                };
+
+                let offset = self.fields_slots(&members[..*idx])?;
+
+                self.compile_address(base)?;
+                self.emit(Instruction::Member(offset), span);
+            },
+            CheckedExpression::UnaryOperation(pointer, UnaryOperator::Dereference, _, _) => self.compile_expression(pointer)?,
+            _ => {
+                let slots = self.slots(self.type_of(expr))?;
+                let local = self.hidden_local(slots)?;
+
+                self.compile_expression(expr)?;
+                self.emit_store_local(local, slots, span);
+                self.emit(Instruction::LocalAddress(local), span);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Works out where `place` is, before anything is stored to it. A
+    /// variable is stored to directly, and anything else through a pointer.
+    fn compile_place(&mut self, place: &CheckedExpression) -> Result<Place, String> {
+        if let CheckedExpression::Var(var_id, _, _) = place {
+            return Ok(Place::Local(self.local_offsets[*var_id]));
+        }
+
+        self.compile_address(place)?;
+        let pointer_local = self.hidden_local(1)?;
+        self.emit(Instruction::StoreLocal(pointer_local), place.span());
+
+        Ok(Place::Pointer(pointer_local))
+    }
+
+    fn emit_load_place(&mut self, place: &Place, slots: u32, span: Span) {
+        match place {
+            Place::Local(offset) => self.emit_load_local(*offset, slots, span),
+            Place::Pointer(pointer_local) => {
+                self.emit(Instruction::LoadLocal(*pointer_local), span);
+                self.emit(Instruction::Load(slots), span);
+            },
+        }
+    }
+
+    fn emit_store_place(&mut self, place: &Place, slots: u32, span: Span) {
+        match place {
+            Place::Local(offset) => self.emit_store_local(*offset, slots, span),
+            Place::Pointer(pointer_local) => {
+                self.emit(Instruction::LoadLocal(*pointer_local), span);
+                self.emit(Instruction::Store(slots), span);
+            },
+        }
+    }
+
+    fn compile_binary_operation(&mut self, lhs: &CheckedExpression, op: &BinaryOperator, rhs: &CheckedExpression, span: Span) -> Result<(), String> {
+        if op.is_assignment() {
+            // The place is worked out before the value, like in the
+            // interpreter and the LLVM backend
+            let lhs_type = self.type_of(lhs);
+            let place = self.compile_place(lhs)?;
+
+            match op.without_assignment() {
+                Some(bin_op) => {
+                    self.emit_load_place(&place, 1, span);
+                    self.compile_expression(rhs)?;
+                    self.emit_operation(&bin_op, lhs_type, self.type_of(rhs), span)?;
+                },
+                None => self.compile_expression(rhs)?,
+            }
+
+            self.emit_store_place(&place, self.slots(lhs_type)?, span);
+            return Ok(());
+        }
+
+        // `&&` and `||` only evaluate their right side when the left one
+        // doesn't already decide the result
+        if matches!(op, BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr) {
+            let is_and = matches!(op, BinaryOperator::LogicalAnd);
+
+            self.compile_expression(lhs)?;
+            if !is_and {
+                self.emit(Instruction::LogicalNot, span);
+            }
+            let jump_to_short_circuit = self.emit(Instruction::JumpIfFalse(0), span);
+
+            self.compile_expression(rhs)?;
+            let jump_to_end = self.emit(Instruction::Jump(0), span);
+
+            self.patch_jump(jump_to_short_circuit);
+            self.emit(Instruction::PushConst(!is_and as u64), span);
+            self.patch_jump(jump_to_end);
+
+            return Ok(());
+        }
+
+        self.compile_expression(lhs)?;
+        self.compile_expression(rhs)?;
+        self.emit_operation(op, self.type_of(lhs), self.type_of(rhs), span)
+    }
+
+    /// Applies `op` to the two operands on top of the stack. A pointer can
+    /// be moved along by an integer, and subtracting two pointers gives how
+    /// many values apart they are.
+    fn emit_operation(&mut self, op: &BinaryOperator, lhs_type: TypeID, rhs_type: TypeID, span: Span) -> Result<(), String> {
+        let Some(pointee) = self.pointee_of(lhs_type) else {
+            self.emit(Instruction::Binary(op.clone(), self.primitive(lhs_type)), span);
+            return Ok(());
+        };
+
+        let stride = self.slots(pointee)?;
+
+        match op {
+            BinaryOperator::Add | BinaryOperator::Subtract if self.pointee_of(rhs_type).is_none() => {
+                let count_type = self.primitive(rhs_type);
+                if count_type != PrimitiveType::I64 {
+                    self.emit(Instruction::Cast(count_type, PrimitiveType::I64), span);
+                }
+                if matches!(op, BinaryOperator::Subtract) {
+                    self.emit(Instruction::Negate(PrimitiveType::I64), span);
+                }
+                self.emit(Instruction::Offset(stride), span);
+            },
+            BinaryOperator::Subtract => {
+                self.emit(Instruction::Binary(BinaryOperator::Subtract, PrimitiveType::U64), span);
+                self.emit(Instruction::PushConst(stride.max(1) as u64), span);
+                self.emit(Instruction::Binary(BinaryOperator::Divide, PrimitiveType::I64), span);
+            },
+            _ => { self.emit(Instruction::Binary(op.clone(), PrimitiveType::U64), span); },
+        }
+
+        Ok(())
+    }
+}
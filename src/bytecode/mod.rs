@@ -0,0 +1,598 @@
+mod compiler;
+mod vm;
+
+use std::fmt;
+
+pub use compiler::compile_module;
+pub use vm::{ Limits, Vm };
+
+use crate::ast::BinaryOperator;
+use crate::intrinsics::{ FormatVerb, Intrinsic };
+use crate::token::{ PrimitiveType, Span };
+
+/// The first bytes of every bytecode file.
+const MAGIC: &[u8; 6] = b"AMDSBC";
+
+/// Bumped whenever the encoding changes, so old files are rejected
+/// instead of misread.
+const FORMAT_VERSION: u16 = 3;
+
+/// One instruction of the stack machine. Every value on the stack is
+/// 64 bits wide: integers are kept truncated to the width of their type,
+/// floats as the bits of an `f64` and strings as an index into the
+/// program's string table. Instructions that care about the type of
+/// their operands say what it is.
+///
+/// Structs, enums and arrays take up one slot for each scalar in them,
+/// one after the other. An enum is its variant's index followed by room
+/// for the biggest payload. A pointer is the position on the stack of the
+/// slot it points at, plus one so that `null` is 0.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    PushConst(u64),
+    PushString(u32),
+    Pop,
+    Dup,
+    LoadLocal(u32),
+    StoreLocal(u32),
+    /// Pushes a pointer to a local of the proc that's running.
+    LocalAddress(u32),
+    /// Pops a pointer and pushes this many slots from where it points.
+    Load(u32),
+    /// Pops a pointer, then this many slots, and writes them where the
+    /// pointer points.
+    Store(u32),
+    /// Pops an index of this type and a pointer to an array of this many
+    /// elements, each taking up this many slots, and pushes a pointer to
+    /// the element. Indices out of bounds stop the program.
+    Index(PrimitiveType, u64, u32),
+    /// Pops a pointer to a struct and pushes a pointer to the member this
+    /// many slots into it.
+    Member(u32),
+    /// Pops an `i64` and a pointer, and pushes the pointer moved along by
+    /// that many values of this many slots each.
+    Offset(u32),
+    /// Pops this many slots and pushes them this many times.
+    Repeat(u32, u32),
+    Negate(PrimitiveType),
+    LogicalNot,
+    BitwiseNot(PrimitiveType),
+    /// Pops the right operand, then the left one. Only arithmetic,
+    /// bitwise and comparison operators show up here.
+    Binary(BinaryOperator, PrimitiveType),
+    Cast(PrimitiveType, PrimitiveType),
+    Jump(u32),
+    /// Pops a `bool` and jumps if it's false.
+    JumpIfFalse(u32),
+    /// Calls the proc at this index in the program. Its arguments are
+    /// on the stack, in the order of its parameters.
+    Call(u32),
+    Return,
+    /// Pops a value and writes it to stdout the way the verb says to.
+    Print(PrimitiveType, FormatVerb),
+    /// Pops the arguments of an intrinsic other than `print` and `println`,
+    /// and pushes what it gives back, if anything.
+    Intrinsic(Intrinsic),
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::PushConst(bits) => write!(f, "push_const {:#x}", bits),
+            Instruction::PushString(idx) => write!(f, "push_string #{}", idx),
+            Instruction::Pop => write!(f, "pop"),
+            Instruction::Dup => write!(f, "dup"),
+            Instruction::LoadLocal(idx) => write!(f, "load_local {}", idx),
+            Instruction::StoreLocal(idx) => write!(f, "store_local {}", idx),
+            Instruction::LocalAddress(idx) => write!(f, "local_address {}", idx),
+            Instruction::Load(slots) => write!(f, "load {}", slots),
+            Instruction::Store(slots) => write!(f, "store {}", slots),
+            Instruction::Index(ty, len, stride) => write!(f, "index {} {} {}", ty.as_str(), len, stride),
+            Instruction::Member(offset) => write!(f, "member {}", offset),
+            Instruction::Offset(stride) => write!(f, "offset {}", stride),
+            Instruction::Repeat(slots, count) => write!(f, "repeat {} {}", slots, count),
+            Instruction::Negate(ty) => write!(f, "negate {}", ty.as_str()),
+            Instruction::LogicalNot => write!(f, "not"),
+            Instruction::BitwiseNot(ty) => write!(f, "bitwise_not {}", ty.as_str()),
+            Instruction::Binary(op, ty) => write!(f, "binary {} {}", op.as_str(), ty.as_str()),
+            Instruction::Cast(from, to) => write!(f, "cast {} -> {}", from.as_str(), to.as_str()),
+            Instruction::Jump(target) => write!(f, "jump {:04}", target),
+            Instruction::JumpIfFalse(target) => write!(f, "jump_if_false {:04}", target),
+            Instruction::Call(idx) => write!(f, "call {}", idx),
+            Instruction::Return => write!(f, "return"),
+            Instruction::Print(ty, verb) => write!(f, "print {} {}", ty.as_str(), verb.as_str()),
+            Instruction::Intrinsic(intrinsic) => write!(f, "intrinsic {}", intrinsic.name()),
+        }
+    }
+}
+
+/// A procedure compiled to bytecode. Its parameters take up the first
+/// `param_count` slots of its locals. Counts are in slots, not values.
+#[derive(Debug, Clone)]
+pub struct Proc {
+    pub name: String,
+    pub param_count: u32,
+    pub local_count: u32,
+    pub return_slots: u32,
+    pub code: Vec<Instruction>,
+    /// Where each instruction came from, for reporting runtime errors.
+    /// Spans don't mean anything outside of the compiler that made them,
+    /// so they aren't written out and loaded procs don't have any.
+    pub spans: Vec<Span>,
+}
+
+impl Proc {
+    fn span(&self, pc: usize) -> Span {
+        self.spans.get(pc).copied().unwrap_or_default()
+    }
+}
+
+/// A whole module compiled to bytecode.
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    pub strings: Vec<String>,
+    pub procs: Vec<Proc>,
+}
+
+impl Program {
+    pub fn find_proc(&self, name: &str) -> Option<usize> {
+        self.procs.iter().position(|proc| proc.name == name)
+    }
+
+    pub fn disassemble(&self) -> String {
+        let mut out = String::new();
+
+        for (idx, proc) in self.procs.iter().enumerate() {
+            let returns = match proc.return_slots {
+                0 => String::from("nothing"),
+                1 => String::from("1 slot"),
+                slots => format!("{} slots", slots),
+            };
+            out += &format!("proc {} `{}` (params: {}, locals: {}) -> {}\n", idx, proc.name, proc.param_count, proc.local_count, returns);
+
+            for (pc, instruction) in proc.code.iter().enumerate() {
+                out += &format!("    {:04}  {}\n", pc, instruction);
+            }
+
+            out += "\n";
+        }
+
+        if !self.strings.is_empty() {
+            out += "strings:\n";
+            for (idx, string) in self.strings.iter().enumerate() {
+                out += &format!("    #{}  {:?}\n", idx, string);
+            }
+        }
+
+        out
+    }
+
+    /// Encodes the program in the on-disk format. Everything is little
+    /// endian, and strings and lists are prefixed with their length.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer { bytes: Vec::new() };
+
+        writer.bytes.extend_from_slice(MAGIC);
+        writer.u16(FORMAT_VERSION);
+
+        writer.u32(self.strings.len() as u32);
+        for string in &self.strings {
+            writer.string(string);
+        }
+
+        writer.u32(self.procs.len() as u32);
+        for proc in &self.procs {
+            writer.string(&proc.name);
+            writer.u32(proc.param_count);
+            writer.u32(proc.local_count);
+            writer.u32(proc.return_slots);
+
+            writer.u32(proc.code.len() as u32);
+            for instruction in &proc.code {
+                writer.instruction(instruction);
+            }
+        }
+
+        writer.bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Program, String> {
+        let mut reader = Reader { bytes, pos: 0 };
+
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(String::from("not an amds bytecode file"));
+        }
+
+        let version = reader.u16()?;
+        if version != FORMAT_VERSION {
+            return Err(format!("bytecode format version {} isn't supported, expected {}", version, FORMAT_VERSION));
+        }
+
+        let mut program = Program::default();
+
+        for _ in 0..reader.u32()? {
+            program.strings.push(reader.string()?);
+        }
+
+        for _ in 0..reader.u32()? {
+            let name = reader.string()?;
+            let param_count = reader.u32()?;
+            let local_count = reader.u32()?;
+            let return_slots = reader.u32()?;
+
+            let mut code = Vec::new();
+            for _ in 0..reader.u32()? {
+                code.push(reader.instruction()?);
+            }
+
+            program.procs.push(Proc { name, param_count, local_count, return_slots, code, spans: Vec::new() });
+        }
+
+        if reader.pos != bytes.len() {
+            return Err(String::from("unexpected data after the end of the bytecode"));
+        }
+
+        program.validate()?;
+
+        Ok(program)
+    }
+
+    /// Makes sure a loaded program can't make the VM index out of bounds.
+    fn validate(&self) -> Result<(), String> {
+        for proc in &self.procs {
+            if proc.param_count > proc.local_count {
+                return Err(format!("`{}` has more parameters than locals", proc.name));
+            }
+
+            for (pc, instruction) in proc.code.iter().enumerate() {
+                let is_scalar = |ty: &PrimitiveType| ty.is_numeric() || matches!(ty, PrimitiveType::Bool | PrimitiveType::Char);
+
+                let is_valid = match instruction {
+                    Instruction::PushString(idx) => (*idx as usize) < self.strings.len(),
+                    Instruction::Negate(ty) | Instruction::BitwiseNot(ty) | Instruction::Binary(_, ty) => is_scalar(ty),
+                    Instruction::Index(ty, _, _) => ty.is_integer(),
+                    Instruction::Cast(from, to) => is_scalar(from) && is_scalar(to),
+                    Instruction::Print(ty, verb) => verb.accepts(ty),
+                    Instruction::Intrinsic(intrinsic) => !intrinsic.takes_format(),
+                    Instruction::LoadLocal(idx) | Instruction::StoreLocal(idx) => *idx < proc.local_count,
+                    Instruction::Jump(target) | Instruction::JumpIfFalse(target) => (*target as usize) < proc.code.len(),
+                    Instruction::Call(idx) => (*idx as usize) < self.procs.len(),
+                    _ => true
+                };
+
+                if !is_valid {
+                    return Err(format!("`{}` has an invalid operand at {:04}: {}", proc.name, pc, instruction));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+const PRIMITIVE_TYPES: [PrimitiveType; 14] = [
+    PrimitiveType::Nothing,
+    PrimitiveType::Bool,
+    PrimitiveType::Char,
+    PrimitiveType::String,
+    PrimitiveType::U8,
+    PrimitiveType::I8,
+    PrimitiveType::U16,
+    PrimitiveType::I16,
+    PrimitiveType::U32,
+    PrimitiveType::I32,
+    PrimitiveType::U64,
+    PrimitiveType::I64,
+    PrimitiveType::F32,
+    PrimitiveType::F64,
+];
+
+const BINARY_OPERATORS: [BinaryOperator; 17] = [
+    BinaryOperator::Add,
+    BinaryOperator::Subtract,
+    BinaryOperator::Multiply,
+    BinaryOperator::Divide,
+    BinaryOperator::Modulo,
+    BinaryOperator::LogicalXOR,
+    BinaryOperator::NEQ,
+    BinaryOperator::EQ,
+    BinaryOperator::LT,
+    BinaryOperator::GT,
+    BinaryOperator::LEQ,
+    BinaryOperator::GEQ,
+    BinaryOperator::BitwiseAnd,
+    BinaryOperator::BitwiseOr,
+    BinaryOperator::BitwiseXOR,
+    BinaryOperator::BitwiseLeftShift,
+    BinaryOperator::BitwiseRightShift,
+];
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn primitive(&mut self, ty: &PrimitiveType) {
+        self.u8(PRIMITIVE_TYPES.iter().position(|other| other == ty).unwrap() as u8);
+    }
+
+    fn format_verb(&mut self, verb: &FormatVerb) {
+        match verb {
+            FormatVerb::Value => self.u8(0),
+            FormatVerb::Decimal => self.u8(1),
+            FormatVerb::Hex => self.u8(2),
+            FormatVerb::Float(precision) => { self.u8(3); self.u8(*precision); },
+            FormatVerb::String => self.u8(4),
+            FormatVerb::Char => self.u8(5),
+            FormatVerb::Bool => self.u8(6),
+        }
+    }
+
+    fn binary_operator(&mut self, op: &BinaryOperator) {
+        match BINARY_OPERATORS.iter().position(|other| other == op) {
+            Some(code) => self.u8(code as u8),
+            None => unreachable!("{:?} is never compiled to a binary instruction", op),
+        }
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) {
+        match instruction {
+            Instruction::PushConst(bits) => { self.u8(0); self.u64(*bits); },
+            Instruction::PushString(idx) => { self.u8(1); self.u32(*idx); },
+            Instruction::Pop => self.u8(2),
+            Instruction::Dup => self.u8(3),
+            Instruction::LoadLocal(idx) => { self.u8(4); self.u32(*idx); },
+            Instruction::StoreLocal(idx) => { self.u8(5); self.u32(*idx); },
+            Instruction::Negate(ty) => { self.u8(6); self.primitive(ty); },
+            Instruction::LogicalNot => self.u8(7),
+            Instruction::BitwiseNot(ty) => { self.u8(8); self.primitive(ty); },
+            Instruction::Binary(op, ty) => { self.u8(9); self.binary_operator(op); self.primitive(ty); },
+            Instruction::Cast(from, to) => { self.u8(10); self.primitive(from); self.primitive(to); },
+            Instruction::Jump(target) => { self.u8(11); self.u32(*target); },
+            Instruction::JumpIfFalse(target) => { self.u8(12); self.u32(*target); },
+            Instruction::Call(idx) => { self.u8(13); self.u32(*idx); },
+            Instruction::Return => self.u8(14),
+            Instruction::Print(ty, verb) => { self.u8(15); self.primitive(ty); self.format_verb(verb); },
+            Instruction::Intrinsic(intrinsic) => { self.u8(16); self.u8(intrinsic.to_u8()); },
+            Instruction::LocalAddress(idx) => { self.u8(17); self.u32(*idx); },
+            Instruction::Load(slots) => { self.u8(18); self.u32(*slots); },
+            Instruction::Store(slots) => { self.u8(19); self.u32(*slots); },
+            Instruction::Index(ty, len, stride) => { self.u8(20); self.primitive(ty); self.u64(*len); self.u32(*stride); },
+            Instruction::Repeat(slots, count) => { self.u8(21); self.u32(*slots); self.u32(*count); },
+            Instruction::Member(offset) => { self.u8(22); self.u32(*offset); },
+            Instruction::Offset(stride) => { self.u8(23); self.u32(*stride); },
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(len)
+                          .filter(|end| *end <= self.bytes.len())
+                          .ok_or_else(|| String::from("the bytecode ends too early"))?;
+
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| String::from("a string in the bytecode isn't valid UTF-8"))
+    }
+
+    fn primitive(&mut self) -> Result<PrimitiveType, String> {
+        let code = self.u8()?;
+        PRIMITIVE_TYPES.get(code as usize).cloned().ok_or_else(|| format!("unknown type code {}", code))
+    }
+
+    fn format_verb(&mut self) -> Result<FormatVerb, String> {
+        let verb = match self.u8()? {
+            0 => FormatVerb::Value,
+            1 => FormatVerb::Decimal,
+            2 => FormatVerb::Hex,
+            3 => FormatVerb::Float(self.u8()?),
+            4 => FormatVerb::String,
+            5 => FormatVerb::Char,
+            6 => FormatVerb::Bool,
+            code => return Err(format!("unknown format verb code {}", code)),
+        };
+
+        Ok(verb)
+    }
+
+    fn intrinsic(&mut self) -> Result<Intrinsic, String> {
+        let code = self.u8()?;
+        Intrinsic::from_u8(code).ok_or_else(|| format!("unknown intrinsic code {}", code))
+    }
+
+    fn binary_operator(&mut self) -> Result<BinaryOperator, String> {
+        let code = self.u8()?;
+        BINARY_OPERATORS.get(code as usize).cloned().ok_or_else(|| format!("unknown operator code {}", code))
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, String> {
+        let instruction = match self.u8()? {
+            0 => Instruction::PushConst(self.u64()?),
+            1 => Instruction::PushString(self.u32()?),
+            2 => Instruction::Pop,
+            3 => Instruction::Dup,
+            4 => Instruction::LoadLocal(self.u32()?),
+            5 => Instruction::StoreLocal(self.u32()?),
+            6 => Instruction::Negate(self.primitive()?),
+            7 => Instruction::LogicalNot,
+            8 => Instruction::BitwiseNot(self.primitive()?),
+            9 => Instruction::Binary(self.binary_operator()?, self.primitive()?),
+            10 => Instruction::Cast(self.primitive()?, self.primitive()?),
+            11 => Instruction::Jump(self.u32()?),
+            12 => Instruction::JumpIfFalse(self.u32()?),
+            13 => Instruction::Call(self.u32()?),
+            14 => Instruction::Return,
+            15 => Instruction::Print(self.primitive()?, self.format_verb()?),
+            16 => Instruction::Intrinsic(self.intrinsic()?),
+            17 => Instruction::LocalAddress(self.u32()?),
+            18 => Instruction::Load(self.u32()?),
+            19 => Instruction::Store(self.u32()?),
+            20 => Instruction::Index(self.primitive()?, self.u64()?, self.u32()?),
+            21 => Instruction::Repeat(self.u32()?, self.u32()?),
+            22 => Instruction::Member(self.u32()?),
+            23 => Instruction::Offset(self.u32()?),
+            opcode => return Err(format!("unknown opcode {}", opcode)),
+        };
+
+        Ok(instruction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proc(name: &str, param_count: u32, local_count: u32, code: Vec<Instruction>) -> Proc {
+        Proc { name: String::from(name), param_count, local_count, return_slots: 1, code, spans: Vec::new() }
+    }
+
+    /// A `double`, a `second` that takes an array and a `main` that calls
+    /// `double`.
+    fn program() -> Program {
+        Program {
+            strings: vec![String::from("twice"), String::from("\u{3bb} and \u{1F600}")],
+            procs: vec![
+                proc("double", 1, 1, vec![
+                    Instruction::LoadLocal(0),
+                    Instruction::PushConst(2),
+                    Instruction::Binary(BinaryOperator::Multiply, PrimitiveType::I64),
+                    Instruction::Return,
+                ]),
+                proc("second", 2, 4, vec![
+                    Instruction::PushConst(0),
+                    Instruction::Repeat(1, 2),
+                    Instruction::LocalAddress(2),
+                    Instruction::Store(2),
+                    Instruction::LocalAddress(0),
+                    Instruction::PushConst(1),
+                    Instruction::Index(PrimitiveType::I64, 2, 1),
+                    Instruction::PushConst(0),
+                    Instruction::Offset(1),
+                    Instruction::Member(0),
+                    Instruction::Load(1),
+                    Instruction::Return,
+                ]),
+                proc("main", 0, 1, vec![
+                    Instruction::PushConst(21),
+                    Instruction::Call(0),
+                    Instruction::StoreLocal(0),
+                    Instruction::LoadLocal(0),
+                    Instruction::PushConst(42),
+                    Instruction::Binary(BinaryOperator::EQ, PrimitiveType::I64),
+                    Instruction::JumpIfFalse(9),
+                    Instruction::PushString(1),
+                    Instruction::Pop,
+                    Instruction::LoadLocal(0),
+                    Instruction::Cast(PrimitiveType::I64, PrimitiveType::U8),
+                    Instruction::Return,
+                ]),
+            ],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = program().to_bytes();
+        let loaded = Program::from_bytes(&bytes).unwrap();
+
+        assert_eq!(loaded.disassemble(), program().disassemble());
+        assert_eq!(loaded.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        let bytes = program().to_bytes();
+
+        for end in 0..bytes.len() {
+            assert!(Program::from_bytes(&bytes[..end]).is_err(), "cut off after {} bytes", end);
+        }
+
+        let mut not_bytecode = bytes.clone();
+        not_bytecode[0] = b'X';
+        assert_eq!(Program::from_bytes(&not_bytecode).unwrap_err(), "not an amds bytecode file");
+
+        let mut newer = bytes.clone();
+        newer[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(Program::from_bytes(&newer).unwrap_err().contains("isn't supported"));
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(Program::from_bytes(&trailing).unwrap_err(), "unexpected data after the end of the bytecode");
+
+        // The last byte is the opcode of `main`'s `return`
+        let mut unknown_opcode = bytes;
+        *unknown_opcode.last_mut().unwrap() = 0xff;
+        assert_eq!(Program::from_bytes(&unknown_opcode).unwrap_err(), "unknown opcode 255");
+    }
+
+    #[test]
+    fn operands_are_validated() {
+        let invalid = [
+            Instruction::LoadLocal(1),
+            Instruction::StoreLocal(7),
+            Instruction::Jump(2),
+            Instruction::Call(1),
+            Instruction::PushString(0),
+            Instruction::Binary(BinaryOperator::Add, PrimitiveType::String),
+            Instruction::Index(PrimitiveType::F64, 4, 1),
+        ];
+
+        for instruction in invalid {
+            let program = Program { strings: Vec::new(), procs: vec![proc("main", 0, 1, vec![instruction.clone(), Instruction::Return])] };
+            let error = Program::from_bytes(&program.to_bytes()).unwrap_err();
+
+            assert_eq!(error, format!("`main` has an invalid operand at 0000: {}", instruction));
+        }
+
+        let program = Program { strings: Vec::new(), procs: vec![proc("main", 2, 1, vec![Instruction::Return])] };
+        assert_eq!(Program::from_bytes(&program.to_bytes()).unwrap_err(), "`main` has more parameters than locals");
+    }
+}
@@ -0,0 +1,396 @@
+use std::io::{ self, Write };
+use std::ops::Range;
+
+use super::{ Instruction, Program };
+use crate::diagnostic::Diagnostic;
+use crate::interpreter::{ Interpreter, Value };
+use crate::intrinsics::{ self, Files, Intrinsic };
+use crate::token::{ PrimitiveType, Span };
+
+/// Bounds on what a program may use, so code that runs at compile time
+/// can't hang the compiler or eat all of its memory.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub max_instructions: u64,
+    /// Locals and temporaries share one stack of 64-bit slots.
+    pub max_stack_slots: usize,
+    pub max_call_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_instructions: 100_000_000,
+            max_stack_slots: 1 << 20,
+            max_call_depth: 4096,
+        }
+    }
+}
+
+struct CallFrame {
+    proc: usize,
+    pc: usize,
+    /// Where the frame's locals start on the stack.
+    base: usize,
+}
+
+type VmResult<T> = Result<T, Diagnostic>;
+
+/// Runs bytecode on a stack machine. Calls don't recurse on the host's
+/// stack, so only `Limits` decides how deep they can go.
+pub struct Vm<'a> {
+    program: &'a Program,
+    limits: Limits,
+    stack: Vec<u64>,
+    frames: Vec<CallFrame>,
+    executed: u64,
+    /// Strings made while running, like the lines `read_line` reads. They
+    /// come after the program's own strings.
+    strings: Vec<String>,
+    files: Files,
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(program: &'a Program, limits: Limits) -> Self {
+        Self {
+            program,
+            limits,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            executed: 0,
+            strings: Vec::new(),
+            files: Files::default(),
+        }
+    }
+
+    /// Calls the proc called `name` with `args`, given as the raw bits of
+    /// each slot, and gives back the slots of what it returned.
+    pub fn call(&mut self, name: &str, args: &[u64]) -> VmResult<Vec<u64>> {
+        let Some(proc_idx) = self.program.find_proc(name) else {
+            return Err(Diagnostic::error(format!("there's no `{}` proc to run", name), Span::default()));
+        };
+
+        self.call_proc(proc_idx, args)
+    }
+
+    /// Like `call`, but for the proc at `proc_idx` in the program.
+    pub fn call_proc(&mut self, proc_idx: usize, args: &[u64]) -> VmResult<Vec<u64>> {
+        let proc = &self.program.procs[proc_idx];
+        if args.len() != proc.param_count as usize {
+            return Err(Diagnostic::error(format!("`{}` takes {} arguments, but was given {}", proc.name, proc.param_count, args.len()), Span::default()));
+        }
+
+        self.stack.clear();
+        self.frames.clear();
+        self.stack.extend_from_slice(args);
+        self.push_frame(proc_idx, Span::default())?;
+
+        self.execute()
+    }
+
+    /// Runs `main` and gives back its exit code.
+    pub fn run_main(&mut self) -> VmResult<u8> {
+        // The C runtime only keeps the low byte of what `main` returns
+        Ok(self.call("main", &[])?.first().copied().unwrap_or(0) as u8)
+    }
+
+    /// Where the instruction that's running came from.
+    fn current_span(&self) -> Span {
+        match self.frames.last() {
+            // The pc has already moved past it
+            Some(frame) => self.program.procs[frame.proc].span(frame.pc.saturating_sub(1)),
+            None => Span::default(),
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::error(message, self.current_span())
+    }
+
+    /// The string a value of type `string` stands for.
+    fn string(&self, value: u64) -> VmResult<&str> {
+        let idx = value as usize;
+        let string = match idx.checked_sub(self.program.strings.len()) {
+            None => self.program.strings.get(idx),
+            Some(idx) => self.strings.get(idx),
+        };
+
+        string.map(String::as_str).ok_or_else(|| self.error(format!("there's no string #{}", value)))
+    }
+
+    fn new_string(&mut self, string: String) -> u64 {
+        self.strings.push(string);
+        (self.program.strings.len() + self.strings.len() - 1) as u64
+    }
+
+    /// Runs an intrinsic that isn't `print` or `println`, whose arguments
+    /// are on the stack.
+    fn run_intrinsic(&mut self, intrinsic: Intrinsic) -> VmResult<()> {
+        match intrinsic {
+            Intrinsic::ReadLine => {
+                let line = self.new_string(intrinsics::read_line());
+                self.push(line)
+            },
+            Intrinsic::Open => {
+                let mode = self.pop()?;
+                let path = self.pop()?;
+                let (path, mode) = (self.string(path)?.to_owned(), self.string(mode)?.to_owned());
+                let file = self.files.open(&path, &mode);
+                self.push(file)
+            },
+            Intrinsic::Read => {
+                let file = self.pop()?;
+                let contents = self.files.read(file);
+                let contents = self.new_string(contents);
+                self.push(contents)
+            },
+            Intrinsic::Write => {
+                let text = self.pop()?;
+                let file = self.pop()?;
+                let text = self.string(text)?.to_owned();
+                let written = self.files.write(file, &text);
+                self.push(written as u64)
+            },
+            Intrinsic::Close => {
+                let file = self.pop()?;
+                let closed = self.files.close(file);
+                self.push(closed as u64)
+            },
+            Intrinsic::Print | Intrinsic::Println => unreachable!("Formats are compiled to `print` instructions"),
+        }
+    }
+
+    /// Where the current frame's temporaries start on the stack.
+    fn temporaries_base(&self) -> usize {
+        self.frames.last().map_or(0, |frame| frame.base + self.program.procs[frame.proc].local_count as usize)
+    }
+
+    fn push(&mut self, value: u64) -> VmResult<()> {
+        if self.stack.len() == self.limits.max_stack_slots {
+            return Err(self.error(format!("ran out of stack space ({} slots)", self.limits.max_stack_slots)));
+        }
+
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> VmResult<u64> {
+        if self.stack.len() <= self.temporaries_base() {
+            return Err(self.error("popped a value that isn't there"));
+        }
+
+        Ok(self.stack.pop().unwrap())
+    }
+
+    fn pop_slots(&mut self, slots: u32) -> VmResult<Vec<u64>> {
+        match self.stack.len().checked_sub(slots as usize).filter(|top| *top >= self.temporaries_base()) {
+            Some(top) => Ok(self.stack.split_off(top)),
+            None => Err(self.error("popped a value that isn't there")),
+        }
+    }
+
+    /// Makes sure `slots` more slots fit on the stack.
+    fn reserve(&self, slots: usize) -> VmResult<()> {
+        match self.stack.len().checked_add(slots) {
+            Some(len) if len <= self.limits.max_stack_slots => Ok(()),
+            _ => Err(self.error(format!("ran out of stack space ({} slots)", self.limits.max_stack_slots))),
+        }
+    }
+
+    /// Where the `slots` slots that `pointer` points at are on the stack.
+    /// Pointers to locals of procs that have returned are only caught if
+    /// nothing took their place.
+    fn pointee(&self, pointer: u64, slots: u32) -> VmResult<Range<usize>> {
+        let Some(start) = (pointer as usize).checked_sub(1) else {
+            return Err(self.error("dereferenced a null pointer"));
+        };
+
+        match start.checked_add(slots as usize).filter(|end| *end <= self.stack.len()) {
+            Some(end) => Ok(start..end),
+            None => Err(self.error("dereferenced a pointer to a slot that isn't on the stack")),
+        }
+    }
+
+    /// Starts running the proc at `proc_idx`, whose arguments are already
+    /// on top of the stack.
+    fn push_frame(&mut self, proc_idx: usize, span: Span) -> VmResult<()> {
+        if self.frames.len() == self.limits.max_call_depth {
+            return Err(Diagnostic::error(format!("calls nested more than {} deep", self.limits.max_call_depth), span)
+                                  .with_note("this is usually infinite recursion"));
+        }
+
+        let proc = &self.program.procs[proc_idx];
+        let Some(base) = self.stack.len().checked_sub(proc.param_count as usize).filter(|base| *base >= self.temporaries_base()) else {
+            return Err(Diagnostic::error(format!("not enough arguments on the stack to call `{}`", proc.name), span));
+        };
+
+        let stack_len = base + proc.local_count as usize;
+        if stack_len > self.limits.max_stack_slots {
+            return Err(Diagnostic::error(format!("ran out of stack space ({} slots)", self.limits.max_stack_slots), span));
+        }
+
+        self.stack.resize(stack_len, 0);
+        self.frames.push(CallFrame { proc: proc_idx, pc: 0, base });
+
+        Ok(())
+    }
+
+    fn execute(&mut self) -> VmResult<Vec<u64>> {
+        let program = self.program;
+
+        loop {
+            let frame = self.frames.last_mut().unwrap();
+            let proc = &program.procs[frame.proc];
+            let base = frame.base;
+
+            let Some(instruction) = proc.code.get(frame.pc) else {
+                return Err(self.error(format!("ran past the end of `{}`", proc.name)));
+            };
+
+            frame.pc += 1;
+
+            self.executed += 1;
+            if self.executed > self.limits.max_instructions {
+                return Err(self.error(format!("ran for more than {} instructions", self.limits.max_instructions))
+                               .with_note("this is usually an infinite loop"));
+            }
+
+            match instruction {
+                Instruction::PushConst(bits) => self.push(*bits)?,
+                Instruction::PushString(idx) => self.push(*idx as u64)?,
+                Instruction::Pop => { self.pop()?; },
+                Instruction::Dup => {
+                    let value = self.pop()?;
+                    self.push(value)?;
+                    self.push(value)?;
+                },
+                Instruction::LoadLocal(idx) => self.push(self.stack[base + *idx as usize])?,
+                Instruction::StoreLocal(idx) => {
+                    let value = self.pop()?;
+                    self.stack[base + *idx as usize] = value;
+                },
+                Instruction::LocalAddress(idx) => self.push((base + *idx as usize + 1) as u64)?,
+                Instruction::Load(slots) => {
+                    let pointer = self.pop()?;
+                    let pointee = self.pointee(pointer, *slots)?;
+                    self.reserve(pointee.len())?;
+                    self.stack.extend_from_within(pointee);
+                },
+                Instruction::Store(slots) => {
+                    let pointer = self.pop()?;
+                    let values = self.pop_slots(*slots)?;
+                    let pointee = self.pointee(pointer, *slots)?;
+                    self.stack[pointee].copy_from_slice(&values);
+                },
+                Instruction::Index(ty, len, stride) => {
+                    let index = self.pop()?;
+                    let array = self.pop()?;
+
+                    let index = match ty.is_signed() {
+                        true => ty.sign_extend(index) as i128,
+                        false => index as i128,
+                    };
+
+                    if index < 0 || index >= *len as i128 {
+                        return Err(self.error(format!("index {} is out of bounds for an array of length {}", index, len)));
+                    }
+
+                    if array == 0 {
+                        return Err(self.error("dereferenced a null pointer"));
+                    }
+
+                    self.push(array.wrapping_add((index as u64).wrapping_mul(*stride as u64)))?;
+                },
+                Instruction::Member(offset) => {
+                    let pointer = self.pop()?;
+                    if pointer == 0 {
+                        return Err(self.error("dereferenced a null pointer"));
+                    }
+
+                    self.push(pointer.wrapping_add(*offset as u64))?;
+                },
+                Instruction::Offset(stride) => {
+                    let count = self.pop()?;
+                    let pointer = self.pop()?;
+                    if pointer == 0 {
+                        return Err(self.error("can't offset a null pointer"));
+                    }
+
+                    self.push(pointer.wrapping_add(count.wrapping_mul(*stride as u64)))?;
+                },
+                Instruction::Repeat(slots, count) => {
+                    let values = self.pop_slots(*slots)?;
+
+                    let Some(len) = values.len().checked_mul(*count as usize) else {
+                        return Err(self.error(format!("ran out of stack space ({} slots)", self.limits.max_stack_slots)));
+                    };
+
+                    self.reserve(len)?;
+                    for _ in 0..*count {
+                        self.stack.extend_from_slice(&values);
+                    }
+                },
+                Instruction::Negate(ty) => {
+                    let value = self.pop()?;
+                    let negated = match ty {
+                        PrimitiveType::F32 | PrimitiveType::F64 => (-f64::from_bits(value)).to_bits(),
+                        _ => ty.truncate(value.wrapping_neg()),
+                    };
+                    self.push(negated)?;
+                },
+                Instruction::LogicalNot => {
+                    let value = self.pop()?;
+                    self.push((value == 0) as u64)?;
+                },
+                Instruction::BitwiseNot(ty) => {
+                    let value = self.pop()?;
+                    self.push(ty.truncate(!value))?;
+                },
+                Instruction::Binary(op, ty) => {
+                    let rhs = Value::from_bits(self.pop()?, ty);
+                    let lhs = Value::from_bits(self.pop()?, ty);
+
+                    // Same arithmetic as the interpreter, so both agree on
+                    // what a program does
+                    let result = Interpreter::apply_binary_operation(lhs, op, rhs, self.current_span())?;
+                    self.push(result.to_bits())?;
+                },
+                Instruction::Cast(from, to) => {
+                    let value = Value::from_bits(self.pop()?, from);
+                    self.push(Interpreter::cast(value, to.clone()).to_bits())?;
+                },
+                Instruction::Jump(target) => self.frames.last_mut().unwrap().pc = *target as usize,
+                Instruction::JumpIfFalse(target) => {
+                    if self.pop()? == 0 {
+                        self.frames.last_mut().unwrap().pc = *target as usize;
+                    }
+                },
+                Instruction::Call(idx) => self.push_frame(*idx as usize, self.current_span())?,
+                Instruction::Return => {
+                    let value = self.pop_slots(proc.return_slots)?;
+
+                    self.frames.pop();
+                    self.stack.truncate(base);
+
+                    if self.frames.is_empty() {
+                        return Ok(value);
+                    }
+
+                    // It was popped from above where it goes, so it fits
+                    self.stack.extend_from_slice(&value);
+                },
+                Instruction::Print(ty, verb) => {
+                    let value = self.pop()?;
+                    let value = match ty {
+                        PrimitiveType::String => Value::String(self.string(value)?.to_owned()),
+                        _ => Value::from_bits(value, ty),
+                    };
+
+                    // Like `printf`, output that can't be written is dropped
+                    let _ = write!(io::stdout(), "{}", verb.write(&value));
+                },
+                Instruction::Intrinsic(intrinsic) => self.run_intrinsic(*intrinsic)?,
+            }
+        }
+    }
+}
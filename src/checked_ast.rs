@@ -0,0 +1,507 @@
+// Without LLVM, only the bytecode compiler reads the checked AST, and it
+// has no use for how procs are linked or inlined.
+#![cfg_attr(not(feature = "llvm"), allow(dead_code))]
+
+use std::path::PathBuf;
+
+use crate::ast::{ BinaryOperator, DirectiveKind, RangeExprBound, UnaryOperator };
+use crate::intrinsics::{ FormatPiece, Intrinsic };
+use crate::token::Span;
+use crate::typechecker::{ TypeID, TypeInfo };
+
+/// Index of a local variable in its procedure's `locals`.
+pub type VarID = usize;
+
+/// Index of a constant in its module's `consts`.
+pub type ConstID = usize;
+
+/// A variable that has been given a type. Parameters and `let`
+/// bindings both end up as one of these.
+#[derive(Debug, Clone)]
+pub struct CheckedVarDecl {
+    pub name: String,
+    pub type_id: TypeID,
+}
+
+/// A procedure whose body has been typechecked. The first
+/// `param_count` locals are its parameters.
+#[derive(Debug, Clone)]
+pub struct CheckedProcDecl {
+    pub name: String,
+    pub span: Span,
+    pub param_count: usize,
+    pub return_type: TypeID,
+    pub locals: Vec<CheckedVarDecl>,
+    pub body: CheckedBlock,
+    /// Whether calls to it should always be inlined, from `#inline`
+    pub inline: bool,
+    /// Where to find the proc if it's `#foreign`, in which case its body
+    /// is empty
+    pub foreign: Option<ForeignProc>,
+    /// Which intrinsic it is if it's `#intrinsic`, in which case its body
+    /// is empty and calls to it are `IntrinsicCall`s
+    pub intrinsic: Option<Intrinsic>,
+    /// The file of the imported module it's declared in, if it's declared
+    /// in one. Every input that imports the module is compiled with its
+    /// own copy of it.
+    pub imported_from: Option<PathBuf>,
+}
+
+/// A proc that's defined outside of the program, usually in C, and
+/// called with the C calling convention.
+#[derive(Debug, Clone)]
+pub struct ForeignProc {
+    /// The name it's linked by, which isn't qualified with its module
+    pub symbol: String,
+    /// The library it's in, if it isn't in libc
+    pub library: Option<String>,
+    /// Whether it takes extra arguments after its parameters. They come
+    /// after the parameters in the arguments of a call.
+    pub variadic: bool,
+}
+
+impl CheckedProcDecl {
+    pub fn params(&self) -> &[CheckedVarDecl] {
+        &self.locals[..self.param_count]
+    }
+}
+
+/// A constant whose value has been checked against its type. Once the
+/// module is verified, `value` has been evaluated down to a literal.
+#[derive(Debug, Clone)]
+pub struct CheckedConstDecl {
+    pub name: String,
+    pub span: Span,
+    pub type_id: TypeID,
+    /// Anything the value declares inside of a block
+    pub locals: Vec<CheckedVarDecl>,
+    pub value: CheckedExpression,
+}
+
+/// An `#assert` or `#run` whose expression has been checked. They're run
+/// while compiling, once every constant has its value, and are left out of
+/// the program.
+#[derive(Debug, Clone)]
+pub struct CheckedDirective {
+    pub kind: DirectiveKind,
+    pub span: Span,
+    pub type_id: TypeID,
+    pub locals: Vec<CheckedVarDecl>,
+    pub value: CheckedExpression,
+}
+
+/// A call whose arguments have been matched up with the parameters
+/// of the procedure, so they're in declaration order. Any extra
+/// arguments to a variadic `#foreign` proc come after them.
+#[derive(Debug, Clone)]
+pub struct CheckedProcCall {
+    pub name: String,
+    pub args: Vec<CheckedExpression>,
+}
+
+/// A call to an `#intrinsic` proc. The format of `print` and `println`
+/// has been split up and checked against the arguments, so it isn't one
+/// of them, and `println`'s line break has been added to it.
+#[derive(Debug, Clone)]
+pub struct CheckedIntrinsicCall {
+    pub intrinsic: Intrinsic,
+    pub format: Vec<FormatPiece>,
+    pub args: Vec<CheckedExpression>,
+}
+
+#[derive(Debug, Clone)]
+pub enum CheckedMatchBody {
+    Expr(CheckedExpression),
+    Block(CheckedBlock),
+}
+
+impl CheckedMatchBody {
+    fn visit_mut(&mut self, f: &mut impl FnMut(&mut CheckedExpression)) {
+        match self {
+            CheckedMatchBody::Expr(expr) => expr.visit_mut(f),
+            CheckedMatchBody::Block(block) => block.visit_mut(f),
+        }
+    }
+
+    /// Whether the body is a block that always returns, or a chain of
+    /// `else if`s that all end up in one.
+    fn always_returns(&self) -> bool {
+        match self {
+            CheckedMatchBody::Block(block) => block.always_returns(),
+            CheckedMatchBody::Expr(CheckedExpression::If(_, then_body, else_body, _, _)) => then_body.always_returns() && else_body.always_returns(),
+            CheckedMatchBody::Expr(_) => false,
+        }
+    }
+
+    /// Whether the body never finishes, since every path through it
+    /// ends in a `return`, `break` or `continue`.
+    pub fn always_leaves(&self) -> bool {
+        match self {
+            CheckedMatchBody::Block(block) => block.always_leaves(),
+            CheckedMatchBody::Expr(CheckedExpression::If(_, then_body, else_body, _, _)) => then_body.always_leaves() && else_body.always_leaves(),
+            CheckedMatchBody::Expr(_) => false,
+        }
+    }
+
+    fn breaks_out(&self) -> bool {
+        match self {
+            CheckedMatchBody::Block(block) => block.breaks_out(),
+            CheckedMatchBody::Expr(CheckedExpression::If(_, then_body, else_body, _, _)) => then_body.breaks_out() || else_body.breaks_out(),
+            CheckedMatchBody::Expr(_) => false,
+        }
+    }
+}
+
+/// An arm of a `match`. A variant arm also gives the local that each
+/// bound payload field, by its index in the payload, is copied into.
+#[derive(Debug, Clone)]
+pub enum CheckedMatchCase {
+    Expr(CheckedExpression, CheckedMatchBody),
+    EnumVariant(TypeID, Vec<(usize, VarID)>, CheckedMatchBody),
+    Fallback(CheckedMatchBody),
+}
+
+impl CheckedMatchCase {
+    pub fn body(&self) -> &CheckedMatchBody {
+        match self {
+            CheckedMatchCase::Expr(_, body)           |
+            CheckedMatchCase::EnumVariant(_, _, body) |
+            CheckedMatchCase::Fallback(body) => body,
+        }
+    }
+
+    pub fn body_mut(&mut self) -> &mut CheckedMatchBody {
+        match self {
+            CheckedMatchCase::Expr(_, body)           |
+            CheckedMatchCase::EnumVariant(_, _, body) |
+            CheckedMatchCase::Fallback(body) => body,
+        }
+    }
+
+    /// Whether the arm's body is a block that always returns.
+    fn always_returns(&self) -> bool {
+        self.body().always_returns()
+    }
+
+    fn breaks_out(&self) -> bool {
+        self.body().breaks_out()
+    }
+}
+
+/// An expression along with the type it evaluates to.
+#[derive(Debug, Clone)]
+pub enum CheckedExpression {
+    Bool(bool, Span),
+    /// A null pointer of the given pointer type
+    Null(TypeID, Span),
+    Integer(u64, TypeID, Span),
+    Float(f64, TypeID, Span),
+    StringLiteral(String, Span),
+    /// The code point of a `char`. Casts can make ones that aren't valid
+    /// Unicode scalar values, so it's kept as a number.
+    CharLiteral(u32, Span),
+    Var(VarID, TypeID, Span),
+    /// A reference to a constant. These are all replaced by the value of
+    /// the constant before the module leaves the typechecker.
+    Const(ConstID, TypeID, Span),
+    Range(RangeExprBound, Box<CheckedExpression>, Box<CheckedExpression>, RangeExprBound, TypeID, Span),
+    Match(Box<CheckedExpression>, Vec<CheckedMatchCase>, TypeID, Span),
+    /// An `if` that's used as a value, given as the condition, the branch
+    /// taken when it holds and the one taken when it doesn't
+    If(Box<CheckedExpression>, Box<CheckedMatchBody>, Box<CheckedMatchBody>, TypeID, Span),
+    /// An array made of its elements, in order
+    Array(Vec<CheckedExpression>, TypeID, Span),
+    /// An array of `count` copies of a value, which is only evaluated once
+    ArrayRepeat(Box<CheckedExpression>, u64, TypeID, Span),
+    /// An element of an array, given as the array and the index
+    Index(Box<CheckedExpression>, Box<CheckedExpression>, TypeID, Span),
+    /// A struct made of its members, in declaration order
+    Struct(Vec<CheckedExpression>, TypeID, Span),
+    /// A member of a struct, given as the struct and the member's position
+    /// in it. The typechecker puts a `Dereference` in between when the
+    /// member is reached through a pointer.
+    Member(Box<CheckedExpression>, usize, TypeID, Span),
+    UnaryOperation(Box<CheckedExpression>, UnaryOperator, TypeID, Span),
+    BinaryOperation(Box<CheckedExpression>, BinaryOperator, Box<CheckedExpression>, TypeID, Span),
+    ProcCall(CheckedProcCall, TypeID, Span),
+    IntrinsicCall(CheckedIntrinsicCall, TypeID, Span),
+    /// A value of an enum, given as the variant's type, its payload in
+    /// declaration order and the enum's type.
+    EnumValue(TypeID, Vec<CheckedExpression>, TypeID, Span),
+    /// An expression that failed to typecheck. It has already been
+    /// reported, so it's compatible with everything.
+    Invalid(Span),
+}
+
+impl CheckedExpression {
+    pub fn type_id(&self, builtins: &Builtins) -> TypeID {
+        match self {
+            CheckedExpression::Bool(_, _) => builtins.bool,
+            CheckedExpression::StringLiteral(_, _) => builtins.string,
+            CheckedExpression::CharLiteral(_, _) => builtins.char,
+            CheckedExpression::Null(type_id, _)                     |
+            CheckedExpression::Integer(_, type_id, _)               |
+            CheckedExpression::Float(_, type_id, _)                 |
+            CheckedExpression::Var(_, type_id, _)                   |
+            CheckedExpression::Const(_, type_id, _)                 |
+            CheckedExpression::Range(_, _, _, _, type_id, _)        |
+            CheckedExpression::Match(_, _, type_id, _)              |
+            CheckedExpression::If(_, _, _, type_id, _)              |
+            CheckedExpression::Array(_, type_id, _)                 |
+            CheckedExpression::ArrayRepeat(_, _, type_id, _)        |
+            CheckedExpression::Index(_, _, type_id, _)              |
+            CheckedExpression::Struct(_, type_id, _)                |
+            CheckedExpression::Member(_, _, type_id, _)             |
+            CheckedExpression::UnaryOperation(_, _, type_id, _)     |
+            CheckedExpression::BinaryOperation(_, _, _, type_id, _) |
+            CheckedExpression::ProcCall(_, type_id, _)              |
+            CheckedExpression::IntrinsicCall(_, type_id, _)         |
+            CheckedExpression::EnumValue(_, _, type_id, _) => *type_id,
+            CheckedExpression::Invalid(_) => builtins.unknown,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            CheckedExpression::Bool(_, span)                     |
+            CheckedExpression::Null(_, span)                     |
+            CheckedExpression::Integer(_, _, span)               |
+            CheckedExpression::Float(_, _, span)                 |
+            CheckedExpression::StringLiteral(_, span)            |
+            CheckedExpression::CharLiteral(_, span)              |
+            CheckedExpression::Var(_, _, span)                   |
+            CheckedExpression::Const(_, _, span)                 |
+            CheckedExpression::Range(_, _, _, _, _, span)        |
+            CheckedExpression::Match(_, _, _, span)              |
+            CheckedExpression::If(_, _, _, _, span)              |
+            CheckedExpression::Array(_, _, span)                 |
+            CheckedExpression::ArrayRepeat(_, _, _, span)        |
+            CheckedExpression::Index(_, _, _, span)              |
+            CheckedExpression::Struct(_, _, span)                |
+            CheckedExpression::Member(_, _, _, span)             |
+            CheckedExpression::UnaryOperation(_, _, _, span)     |
+            CheckedExpression::BinaryOperation(_, _, _, _, span) |
+            CheckedExpression::ProcCall(_, _, span)              |
+            CheckedExpression::IntrinsicCall(_, _, span)         |
+            CheckedExpression::EnumValue(_, _, _, span)          |
+            CheckedExpression::Invalid(span) => *span,
+        }
+    }
+
+    /// Calls `f` on every expression nested inside this one, and then on
+    /// this one.
+    pub fn visit_mut(&mut self, f: &mut impl FnMut(&mut CheckedExpression)) {
+        match self {
+            CheckedExpression::Range(_, start, end, _, _, _) => {
+                start.visit_mut(f);
+                end.visit_mut(f);
+            },
+            CheckedExpression::Match(matched, cases, _, _) => {
+                matched.visit_mut(f);
+
+                for case in cases {
+                    match case {
+                        CheckedMatchCase::Expr(case_expr, body) => {
+                            case_expr.visit_mut(f);
+                            body.visit_mut(f);
+                        },
+                        CheckedMatchCase::EnumVariant(_, _, body) |
+                        CheckedMatchCase::Fallback(body) => body.visit_mut(f),
+                    }
+                }
+            },
+            CheckedExpression::If(cond, then_body, else_body, _, _) => {
+                cond.visit_mut(f);
+                then_body.visit_mut(f);
+                else_body.visit_mut(f);
+            },
+            CheckedExpression::UnaryOperation(operand, _, _, _) |
+            CheckedExpression::ArrayRepeat(operand, _, _, _)    |
+            CheckedExpression::Member(operand, _, _, _) => operand.visit_mut(f),
+            CheckedExpression::Index(lhs, rhs, _, _) |
+            CheckedExpression::BinaryOperation(lhs, _, rhs, _, _) => {
+                lhs.visit_mut(f);
+                rhs.visit_mut(f);
+            },
+            CheckedExpression::ProcCall(CheckedProcCall { args, .. }, _, _)           |
+            CheckedExpression::IntrinsicCall(CheckedIntrinsicCall { args, .. }, _, _) |
+            CheckedExpression::Array(args, _, _)                                      |
+            CheckedExpression::Struct(args, _, _)                                     |
+            CheckedExpression::EnumValue(_, args, _, _) => {
+                for arg in args {
+                    arg.visit_mut(f);
+                }
+            },
+            _ => { }
+        }
+
+        f(self);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CheckedStatement {
+    Expr(CheckedExpression),
+    VarDecl(VarID, CheckedExpression),
+    If(CheckedExpression, CheckedBlock, Option<Box<CheckedStatement>>),
+    Block(CheckedBlock),
+    /// Loops over a `Range`, or over the elements of an array
+    ForLoop(VarID, CheckedExpression, CheckedBlock),
+    WhileLoop(CheckedExpression, CheckedBlock),
+    InfiniteLoop(CheckedBlock),
+    Continue,
+    Break,
+    Return(Option<CheckedExpression>),
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckedBlock {
+    pub stmts: Vec<CheckedStatement>,
+}
+
+impl CheckedBlock {
+    pub fn new() -> Self {
+        Self { stmts: Vec::new() }
+    }
+
+    /// Whether every path through the block ends in a `return` (or never
+    /// leaves it at all), so nothing after it can run.
+    pub fn always_returns(&self) -> bool {
+        self.stmts.iter().any(|stmt| stmt.always_returns())
+    }
+
+    /// Whether every path through the block ends in a `return`, `break`
+    /// or `continue`, so it never gets to its end.
+    pub fn always_leaves(&self) -> bool {
+        self.stmts.iter().any(|stmt| stmt.always_leaves())
+    }
+
+    /// Calls `f` on every expression in the block, inner ones first.
+    pub fn visit_mut(&mut self, f: &mut impl FnMut(&mut CheckedExpression)) {
+        for stmt in &mut self.stmts {
+            stmt.visit_mut(f);
+        }
+    }
+
+    /// Whether a `break` in this block would leave the loop it belongs to,
+    /// as opposed to a loop nested inside of it.
+    fn breaks_out(&self) -> bool {
+        self.stmts.iter().any(|stmt| stmt.breaks_out())
+    }
+}
+
+impl CheckedStatement {
+    pub fn always_returns(&self) -> bool {
+        match self {
+            CheckedStatement::Return(_) => true,
+            CheckedStatement::Block(block) => block.always_returns(),
+            CheckedStatement::If(_, then_block, Some(else_stmt)) => then_block.always_returns() && else_stmt.always_returns(),
+            CheckedStatement::InfiniteLoop(body) => !body.breaks_out(),
+            // Matches are always exhaustive, so one of the arms runs
+            CheckedStatement::Expr(CheckedExpression::Match(_, cases, _, _)) => cases.iter().all(|case| case.always_returns()),
+            _ => false
+        }
+    }
+
+    /// Loops aren't looked into, since a `break` or `continue` in one
+    /// only leaves that loop.
+    pub fn always_leaves(&self) -> bool {
+        match self {
+            CheckedStatement::Return(_) | CheckedStatement::Break | CheckedStatement::Continue => true,
+            CheckedStatement::Block(block) => block.always_leaves(),
+            CheckedStatement::If(_, then_block, Some(else_stmt)) => then_block.always_leaves() && else_stmt.always_leaves(),
+            CheckedStatement::InfiniteLoop(body) => !body.breaks_out(),
+            CheckedStatement::Expr(CheckedExpression::Match(_, cases, _, _)) => cases.iter().all(|case| case.body().always_leaves()),
+            _ => false
+        }
+    }
+
+    pub fn visit_mut(&mut self, f: &mut impl FnMut(&mut CheckedExpression)) {
+        match self {
+            CheckedStatement::Expr(expr)            |
+            CheckedStatement::VarDecl(_, expr)      |
+            CheckedStatement::Return(Some(expr)) => expr.visit_mut(f),
+            CheckedStatement::If(cond, then_block, else_stmt) => {
+                cond.visit_mut(f);
+                then_block.visit_mut(f);
+
+                if let Some(else_stmt) = else_stmt {
+                    else_stmt.visit_mut(f);
+                }
+            },
+            CheckedStatement::Block(block) |
+            CheckedStatement::InfiniteLoop(block) => block.visit_mut(f),
+            CheckedStatement::ForLoop(_, cond, body) |
+            CheckedStatement::WhileLoop(cond, body) => {
+                cond.visit_mut(f);
+                body.visit_mut(f);
+            },
+            CheckedStatement::Continue |
+            CheckedStatement::Break    |
+            CheckedStatement::Return(None) => { },
+        }
+    }
+
+    fn breaks_out(&self) -> bool {
+        match self {
+            CheckedStatement::Break => true,
+            CheckedStatement::Block(block) => block.breaks_out(),
+            CheckedStatement::If(_, then_block, else_stmt) => {
+                then_block.breaks_out() || else_stmt.as_ref().is_some_and(|stmt| stmt.breaks_out())
+            },
+            CheckedStatement::Expr(CheckedExpression::Match(_, cases, _, _)) |
+            CheckedStatement::VarDecl(_, CheckedExpression::Match(_, cases, _, _)) => cases.iter().any(|case| case.breaks_out()),
+            CheckedStatement::VarDecl(_, CheckedExpression::If(_, then_body, else_body, _, _)) => then_body.breaks_out() || else_body.breaks_out(),
+            _ => false
+        }
+    }
+}
+
+/// The `TypeID`s of the builtin types, which are registered
+/// before anything else.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Builtins {
+    pub unknown: TypeID,
+    pub nothing: TypeID,
+    pub bool: TypeID,
+    pub char: TypeID,
+    pub string: TypeID,
+    pub i32: TypeID,
+    pub i64: TypeID,
+    pub f64: TypeID,
+}
+
+/// A module that passed typechecking, along with every type it uses.
+#[derive(Debug)]
+pub struct CheckedModule {
+    pub name: String,
+    pub types: Vec<TypeInfo>,
+    pub builtins: Builtins,
+    pub procs: Vec<CheckedProcDecl>,
+    pub consts: Vec<CheckedConstDecl>,
+    pub directives: Vec<CheckedDirective>,
+}
+
+impl CheckedModule {
+    pub fn type_info(&self, type_id: TypeID) -> &TypeInfo {
+        &self.types[type_id]
+    }
+
+    pub fn type_of(&self, expr: &CheckedExpression) -> TypeID {
+        expr.type_id(&self.builtins)
+    }
+
+    /// The libraries that `#foreign` procs are in, which the program has
+    /// to be linked against.
+    pub fn foreign_libraries(&self) -> Vec<&str> {
+        let mut libraries: Vec<&str> = Vec::new();
+
+        for library in self.procs.iter().filter_map(|proc_decl| proc_decl.foreign.as_ref()?.library.as_deref()) {
+            if !libraries.contains(&library) {
+                libraries.push(library);
+            }
+        }
+
+        libraries
+    }
+}
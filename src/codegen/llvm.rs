@@ -1,10 +1,31 @@
+use std::collections::HashMap;
 use std::ffi::{ CStr, CString };
+use std::path::Path;
 
+use llvm_sys::analysis::{ LLVMVerifierFailureAction, LLVMVerifyModule };
+use llvm_sys::bit_writer::LLVMWriteBitcodeToFile;
 use llvm_sys::core::*;
+use llvm_sys::prelude::*;
 use llvm_sys::target::*;
-use llvm_sys::LLVMModule;
+use llvm_sys::target_machine::*;
+use llvm_sys::transforms::ipo::LLVMAddAlwaysInlinerPass;
+use llvm_sys::{
+    LLVMAttributeFunctionIndex,
+    LLVMAttributeIndex,
+    LLVMAttributeReturnIndex,
+    LLVMCallConv,
+    LLVMIntPredicate,
+    LLVMLinkage,
+    LLVMModule,
+    LLVMRealPredicate,
+};
 
-use crate::ast::*;
+use crate::ast::{ BinaryOperator, RangeExprBound, UnaryOperator };
+use crate::checked_ast::*;
+use crate::intrinsics::{ FormatPiece, FormatVerb, Intrinsic };
+use crate::layout;
+use crate::token::PrimitiveType;
+use crate::typechecker::{ TypeID, TypeInfo };
 
 pub fn init_llvm() {
     unsafe {
@@ -16,12 +37,170 @@ pub fn init_llvm() {
     }
 }
 
-struct Module {
+/// Takes ownership of a string LLVM allocated and hands back a copy.
+unsafe fn take_llvm_message(message: *mut i8) -> String {
+    let string = CStr::from_ptr(message).to_string_lossy().into_owned();
+    LLVMDisposeMessage(message);
+    string
+}
+
+/// The symbol a proc written in amds is emitted under. They're kept apart
+/// from C's names, so a proc called `printf` or `exit` isn't mixed up with
+/// the libc function the runtime calls. `main` keeps its name, since that's
+/// where the C runtime starts the program.
+///
+/// Procs from imported modules also get a hash of the module's file, since
+/// two inputs can import different modules with the same name.
+fn symbol_name(proc_decl: &CheckedProcDecl) -> String {
+    match (proc_decl.name.as_str(), &proc_decl.imported_from) {
+        ("main", _) => proc_decl.name.clone(),
+        (_, Some(path)) => format!("amds.{:016x}.{}", path_hash(path), proc_decl.name),
+        (_, None) => format!("amds.{}", proc_decl.name),
+    }
+}
+
+/// The 64-bit FNV-1a hash of `path`, which has to stay the same from one
+/// build of the compiler to the next, since objects it made are linked
+/// together. The module loader canonicalizes the paths of modules, so a
+/// file gets the same hash however it was imported.
+fn path_hash(path: &Path) -> u64 {
+    path.as_os_str().as_encoded_bytes().iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// What code gets generated for: a target triple along with the CPU
+/// and features to assume.
+pub struct TargetMachine {
+    machine: LLVMTargetMachineRef,
+    triple: CString,
+}
+
+impl TargetMachine {
+    /// Creates a machine for `triple`, or for the host when there isn't one.
+    /// Code for the host may use everything the host's CPU supports.
+    pub fn new(triple: Option<&str>) -> Result<Self, String> {
+        unsafe {
+            let (triple, cpu, features) = match triple {
+                Some(triple) => {
+                    let triple = CString::new(triple).map_err(|_| format!("invalid target '{}'", triple))?;
+                    (take_llvm_message(LLVMNormalizeTargetTriple(triple.as_ptr())), String::from("generic"), String::new())
+                },
+                None => (
+                    take_llvm_message(LLVMGetDefaultTargetTriple()),
+                    take_llvm_message(LLVMGetHostCPUName()),
+                    take_llvm_message(LLVMGetHostCPUFeatures()),
+                ),
+            };
+
+            let triple = CString::new(triple).unwrap();
+            let cpu = CString::new(cpu).unwrap();
+            let features = CString::new(features).unwrap();
+
+            let mut target = std::ptr::null_mut();
+            let mut message = std::ptr::null_mut();
+            if LLVMGetTargetFromTriple(triple.as_ptr(), &mut target, &mut message) != 0 {
+                return Err(format!("unknown target '{}': {}", triple.to_string_lossy(), take_llvm_message(message)));
+            }
+
+            let machine = LLVMCreateTargetMachine(
+                target,
+                triple.as_ptr(),
+                cpu.as_ptr(),
+                features.as_ptr(),
+                LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault,
+                // Position independent, since that's what `cc` links by default
+                LLVMRelocMode::LLVMRelocPIC,
+                LLVMCodeModel::LLVMCodeModelDefault,
+            );
+
+            if machine.is_null() {
+                return Err(format!("couldn't create a target machine for '{}'", triple.to_string_lossy()));
+            }
+
+            // `usize`, `isize` and the layout of types all assume 64-bit
+            // pointers, so other targets would get the wrong code
+            let data_layout = LLVMCreateTargetDataLayout(machine);
+            let pointer_size = LLVMPointerSize(data_layout) as usize;
+            LLVMDisposeTargetData(data_layout);
+
+            if pointer_size != layout::POINTER_SIZE {
+                LLVMDisposeTargetMachine(machine);
+                return Err(format!("the target '{}' has {}-bit pointers, but only targets with 64-bit pointers are supported", triple.to_string_lossy(), pointer_size * 8));
+            }
+
+            Ok(Self { machine, triple })
+        }
+    }
+
+    pub fn triple(&self) -> &str {
+        self.triple.to_str().unwrap()
+    }
+}
+
+impl Drop for TargetMachine {
+    fn drop(&mut self) {
+        unsafe {
+            LLVMDisposeTargetMachine(self.machine);
+        }
+    }
+}
+
+/// LLVM wants a name for most values, an empty one means
+/// it picks a number instead.
+const NO_NAME: *const i8 = c"".as_ptr();
+
+pub struct Module {
     llvm_mod: *mut LLVMModule,
+    builder: LLVMBuilderRef,
     static_strings: Vec<CString>,
+
+    types: Vec<TypeInfo>,
+    builtins: Builtins,
+    struct_types: HashMap<TypeID, LLVMTypeRef>,
+
+    /// Every declared procedure along with its function type,
+    /// which is needed to call it.
+    procs: HashMap<String, (LLVMValueRef, LLVMTypeRef)>,
+    /// What the C runtime calls `stdin` and `stderr` on the target
+    stdin_symbol: &'static CStr,
+    stderr_symbol: &'static CStr,
+    /// Whether array indices, divisors and shift amounts are checked
+    runtime_checks: bool,
+
+    // State of the procedure currently being compiled
+    current_proc: LLVMValueRef,
+    current_proc_needs_exit_code: bool,
+    locals: Vec<(LLVMValueRef, LLVMTypeRef)>,
+    /// The blocks `continue` and `break` jump to, innermost loop last.
+    loops: Vec<(LLVMBasicBlockRef, LLVMBasicBlockRef)>,
 }
 
 impl Module {
+    pub fn new(name: &str, types: Vec<TypeInfo>, builtins: Builtins, runtime_checks: bool) -> Self {
+        let mut module = Self {
+            llvm_mod: std::ptr::null_mut(),
+            builder: unsafe { LLVMCreateBuilder() },
+            static_strings: Vec::new(),
+            types,
+            builtins,
+            struct_types: HashMap::new(),
+            procs: HashMap::new(),
+            stdin_symbol: c"stdin",
+            stderr_symbol: c"stderr",
+            runtime_checks,
+            current_proc: std::ptr::null_mut(),
+            current_proc_needs_exit_code: false,
+            locals: Vec::new(),
+            loops: Vec::new(),
+        };
+
+        let name_ptr = module.new_string_ptr(name);
+        module.llvm_mod = unsafe { LLVMModuleCreateWithName(name_ptr) };
+
+        module
+    }
+
     fn new_string_ptr(&mut self, s: &str) -> *const i8 {
         let cstring = CString::new(s).unwrap();
         let ptr = cstring.as_ptr() as *const _;
@@ -42,19 +221,1703 @@ impl Module {
         }
     }
 
-    pub fn add_proc_decl(&mut self, proc_decl: &ParsedProcDecl) {
+    pub fn write_bitcode(&self, path: &Path) -> Result<(), String> {
+        let path_str = CString::new(path.to_string_lossy().as_bytes()).unwrap();
+
+        let status = unsafe { LLVMWriteBitcodeToFile(self.llvm_mod, path_str.as_ptr()) };
+
+        if status != 0 {
+            return Err(format!("couldn't write bitcode to '{}'", path.display()));
+        }
+
+        Ok(())
+    }
+
+    /// Tells LLVM which target the module is for, so the IR carries the
+    /// right triple and data layout.
+    pub fn set_target(&mut self, target: &TargetMachine) {
+        unsafe {
+            LLVMSetTarget(self.llvm_mod, target.triple.as_ptr());
+
+            if target.triple().contains("apple") {
+                self.stdin_symbol = c"__stdinp";
+                self.stderr_symbol = c"__stderrp";
+            }
+
+            let data_layout = LLVMCreateTargetDataLayout(target.machine);
+            LLVMSetModuleDataLayout(self.llvm_mod, data_layout);
+            LLVMDisposeTargetData(data_layout);
+        }
+    }
+
+    pub fn write_object(&self, target: &TargetMachine, path: &Path) -> Result<(), String> {
+        self.emit_to_file(target, path, LLVMCodeGenFileType::LLVMObjectFile)
+    }
+
+    pub fn write_assembly(&self, target: &TargetMachine, path: &Path) -> Result<(), String> {
+        self.emit_to_file(target, path, LLVMCodeGenFileType::LLVMAssemblyFile)
+    }
+
+    fn emit_to_file(&self, target: &TargetMachine, path: &Path, file_type: LLVMCodeGenFileType) -> Result<(), String> {
+        let path_str = CString::new(path.to_string_lossy().as_bytes()).unwrap();
+
+        unsafe {
+            let mut message = std::ptr::null_mut();
+            let failed = LLVMTargetMachineEmitToFile(target.machine, self.llvm_mod, path_str.as_ptr() as *mut _, file_type, &mut message);
+
+            if failed != 0 {
+                return Err(format!("couldn't write '{}': {}", path.display(), take_llvm_message(message)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs LLVM's verifier over the module. Anything it finds is a bug
+    /// in codegen rather than in the program being compiled.
+    /// Inlines every call to a proc marked `#inline`. Nothing else gets
+    /// optimized, so this has to be asked for explicitly.
+    pub fn inline_calls(&self) {
+        unsafe {
+            let pass_manager = LLVMCreatePassManager();
+            LLVMAddAlwaysInlinerPass(pass_manager);
+            LLVMRunPassManager(pass_manager, self.llvm_mod);
+            LLVMDisposePassManager(pass_manager);
+        }
+    }
+
+    pub fn verify(&self) -> Result<(), String> {
+        unsafe {
+            let mut message = std::ptr::null_mut();
+            let failed = LLVMVerifyModule(self.llvm_mod, LLVMVerifierFailureAction::LLVMReturnStatusAction, &mut message);
+
+            let message = take_llvm_message(message);
+
+            if failed != 0 {
+                return Err(format!("generated invalid LLVM IR:\n{}", message));
+            }
+
+            Ok(())
+        }
+    }
+
+    fn primitive(&self, type_id: TypeID) -> Option<&PrimitiveType> {
+        self.types[type_id].primitive()
+    }
+
+    fn is_float(&self, type_id: TypeID) -> bool {
+        self.primitive(type_id).is_some_and(|primitive| primitive.is_float())
+    }
+
+    fn is_signed(&self, type_id: TypeID) -> bool {
+        self.primitive(type_id).is_some_and(|primitive| primitive.is_signed())
+    }
+
+    /// What a pointer type points at. `*nothing` is an `i8*`, like
+    /// `void*` is in C.
+    fn pointee_type(&mut self, pointer_id: TypeID) -> LLVMTypeRef {
+        let TypeInfo::Pointer(_, pointee_id) = self.types[pointer_id] else {
+            unreachable!("Only pointers point at something");
+        };
+
+        match pointee_id == self.builtins.nothing {
+            true => unsafe { LLVMInt8Type() },
+            false => self.llvm_type(pointee_id),
+        }
+    }
+
+    fn is_pointer(&self, type_id: TypeID) -> bool {
+        matches!(self.types[type_id], TypeInfo::Pointer(_, _))
+    }
+
+    fn llvm_type(&mut self, type_id: TypeID) -> LLVMTypeRef {
+        unsafe {
+            match &self.types[type_id] {
+                TypeInfo::Primitive(primitive, _size) => match primitive {
+                    PrimitiveType::Nothing => LLVMVoidType(),
+                    PrimitiveType::Bool => LLVMInt1Type(),
+                    PrimitiveType::Char => LLVMInt32Type(),
+                    PrimitiveType::String => LLVMPointerType(LLVMInt8Type(), 0),
+                    PrimitiveType::U8 | PrimitiveType::I8 => LLVMInt8Type(),
+                    PrimitiveType::U16 | PrimitiveType::I16 => LLVMInt16Type(),
+                    PrimitiveType::U32 | PrimitiveType::I32 => LLVMInt32Type(),
+                    PrimitiveType::U64 | PrimitiveType::I64 => LLVMInt64Type(),
+                    PrimitiveType::F32 => LLVMFloatType(),
+                    PrimitiveType::F64 => LLVMDoubleType(),
+                },
+                TypeInfo::Struct(name, members) => {
+                    if let Some(struct_type) = self.struct_types.get(&type_id) {
+                        return *struct_type;
+                    }
+
+                    let (name, members) = (name.clone(), members.clone());
+                    let name_ptr = self.new_string_ptr(&name);
+                    let struct_type = LLVMStructCreateNamed(LLVMGetGlobalContext(), name_ptr);
+                    self.struct_types.insert(type_id, struct_type);
+
+                    let mut member_types: Vec<LLVMTypeRef> = members.iter()
+                                                                    .map(|(_, member_type)| self.llvm_type(*member_type))
+                                                                    .collect();
+                    LLVMStructSetBody(struct_type, member_types.as_mut_ptr(), member_types.len() as u32, 0);
+
+                    struct_type
+                },
+                TypeInfo::Enum(name, variants) => {
+                    if let Some(enum_type) = self.struct_types.get(&type_id) {
+                        return *enum_type;
+                    }
+
+                    // The payload is an array of integers as aligned as the
+                    // most aligned payload, so LLVM puts it where the layout
+                    // says it goes. Each variant's payload is reached by
+                    // casting a pointer to it.
+                    let layout = layout::enum_layout(&self.types, variants).expect("The typechecker only lets through types that can be laid out");
+                    let name = name.clone();
+                    let name_ptr = self.new_string_ptr(&name);
+                    let enum_type = LLVMStructCreateNamed(LLVMGetGlobalContext(), name_ptr);
+                    self.struct_types.insert(type_id, enum_type);
+
+                    let mut fields = vec![LLVMIntType(layout.discriminant.bit_width())];
+                    if layout.payload.size > 0 {
+                        let unit = LLVMIntType(layout.payload.align as u32 * 8);
+                        fields.push(LLVMArrayType(unit, (layout.payload.size / layout.payload.align) as u32));
+                    }
+                    LLVMStructSetBody(enum_type, fields.as_mut_ptr(), fields.len() as u32, 0);
+
+                    enum_type
+                },
+                TypeInfo::EnumVariant(name, payload) => {
+                    if let Some(variant_type) = self.struct_types.get(&type_id) {
+                        return *variant_type;
+                    }
+
+                    let (name, payload) = (name.clone(), payload.clone());
+                    let name_ptr = self.new_string_ptr(&name);
+                    let variant_type = LLVMStructCreateNamed(LLVMGetGlobalContext(), name_ptr);
+                    self.struct_types.insert(type_id, variant_type);
+
+                    let mut field_types: Vec<LLVMTypeRef> = payload.iter()
+                                                                   .map(|(_, field_type)| self.llvm_type(*field_type))
+                                                                   .collect();
+                    LLVMStructSetBody(variant_type, field_types.as_mut_ptr(), field_types.len() as u32, 0);
+
+                    variant_type
+                },
+                TypeInfo::Array(_, elem_id, len) => {
+                    let (elem_id, len) = (*elem_id, *len);
+                    LLVMArrayType(self.llvm_type(elem_id), len as u32)
+                },
+                TypeInfo::Pointer(_, _) => LLVMPointerType(self.pointee_type(type_id), 0),
+                TypeInfo::Unknown => unreachable!("Unknown types never make it past the typechecker"),
+            }
+        }
+    }
+
+    /// A `main` that returns nothing still has to give the C runtime an
+    /// exit code, so it's compiled to return an `i32` of 0.
+    fn needs_exit_code(&self, proc_decl: &CheckedProcDecl) -> bool {
+        proc_decl.name == "main" && proc_decl.return_type == self.builtins.nothing
+    }
+
+    pub fn add_proc_decl(&mut self, proc_decl: &CheckedProcDecl) {
+        let return_type = if self.needs_exit_code(proc_decl) {
+            unsafe { LLVMInt32Type() }
+        } else {
+            self.llvm_type(proc_decl.return_type)
+        };
+        let mut param_types: Vec<LLVMTypeRef> = proc_decl.params()
+                                                         .iter()
+                                                         .map(|param| self.llvm_type(param.type_id))
+                                                         .collect();
+
+        // Calls to intrinsics are built where they're made
+        if proc_decl.intrinsic.is_some() {
+            return;
+        }
+
+        if let Some(foreign) = &proc_decl.foreign {
+            self.add_foreign_proc_decl(proc_decl, foreign, return_type, &mut param_types);
+            return;
+        }
+
+        let name_ptr = self.new_string_ptr(&symbol_name(proc_decl));
+
+        unsafe {
+            let fn_type = LLVMFunctionType(return_type, param_types.as_mut_ptr(), param_types.len() as u32, 0);
+            let function = LLVMAddFunction(self.llvm_mod, name_ptr, fn_type);
+
+            for (idx, param) in proc_decl.params().iter().enumerate() {
+                let param_value = LLVMGetParam(function, idx as u32);
+                LLVMSetValueName2(param_value, param.name.as_ptr() as *const _, param.name.len());
+            }
+
+            // Each input of a multi-file build brings its own copy of the
+            // modules it imports, and the linker only keeps one of them
+            if proc_decl.imported_from.is_some() {
+                LLVMSetLinkage(function, LLVMLinkage::LLVMLinkOnceODRLinkage);
+            }
+
+            if proc_decl.inline {
+                self.add_attribute(function, LLVMAttributeFunctionIndex, "alwaysinline");
+            }
+
+            self.procs.insert(proc_decl.name.clone(), (function, fn_type));
+        }
+    }
+
+    /// Declares a `#foreign` proc as an external C function. Several procs
+    /// can be declared for the same symbol, which all end up calling it.
+    fn add_foreign_proc_decl(&mut self, proc_decl: &CheckedProcDecl, foreign: &ForeignProc, return_type: LLVMTypeRef, param_types: &mut [LLVMTypeRef]) {
+        let name_ptr = self.new_string_ptr(&foreign.symbol);
+
+        unsafe {
+            let fn_type = LLVMFunctionType(return_type, param_types.as_mut_ptr(), param_types.len() as u32, foreign.variadic as LLVMBool);
+
+            let mut function = LLVMGetNamedFunction(self.llvm_mod, name_ptr);
+            if function.is_null() {
+                function = LLVMAddFunction(self.llvm_mod, name_ptr, fn_type);
+                LLVMSetLinkage(function, LLVMLinkage::LLVMExternalLinkage);
+                LLVMSetFunctionCallConv(function, LLVMCallConv::LLVMCCallConv as u32);
+
+                // C expects integers narrower than an `int` to be extended
+                // to one by whoever passes them
+                if let Some(extension) = self.c_extension(proc_decl.return_type) {
+                    self.add_attribute(function, LLVMAttributeReturnIndex, extension);
+                }
+
+                for (idx, param) in proc_decl.params().iter().enumerate() {
+                    if let Some(extension) = self.c_extension(param.type_id) {
+                        self.add_attribute(function, idx as u32 + 1, extension);
+                    }
+                }
+            }
+
+            self.procs.insert(proc_decl.name.clone(), (function, fn_type));
+        }
+    }
+
+    /// The attribute that says how a value of the type is extended to an
+    /// `int` when it's passed to or returned from C, if it has to be.
+    fn c_extension(&self, type_id: TypeID) -> Option<&'static str> {
+        match self.primitive(type_id)? {
+            PrimitiveType::Bool | PrimitiveType::U8 | PrimitiveType::U16 => Some("zeroext"),
+            PrimitiveType::I8 | PrimitiveType::I16 => Some("signext"),
+            _ => None,
+        }
+    }
+
+    fn add_attribute(&self, function: LLVMValueRef, idx: LLVMAttributeIndex, name: &str) {
+        unsafe {
+            let kind = LLVMGetEnumAttributeKindForName(name.as_ptr() as *const _, name.len());
+            let attribute = LLVMCreateEnumAttribute(LLVMGetGlobalContext(), kind, 0);
+            LLVMAddAttributeAtIndex(function, idx, attribute);
+        }
+    }
+
+    pub fn add_proc_call(&mut self, proc_call: &CheckedProcCall) -> Result<LLVMValueRef, String> {
+        let (function, fn_type) = self.procs[&proc_call.name];
+
+        let mut args = Vec::new();
+        for arg in &proc_call.args {
+            args.push(self.compile_expression(arg)?);
+        }
+
+        unsafe {
+            Ok(LLVMBuildCall2(self.builder, fn_type, function, args.as_mut_ptr(), args.len() as u32, NO_NAME))
+        }
+    }
+
+    /// Intrinsics are built out of calls to libc, so compiled programs
+    /// do the same thing the interpreter does.
+    fn add_intrinsic_call(&mut self, call: &CheckedIntrinsicCall) -> Result<LLVMValueRef, String> {
+        if call.intrinsic.takes_format() {
+            return self.build_print(&call.format, &call.args);
+        }
+
+        let mut args = Vec::new();
+        for arg in &call.args {
+            args.push(self.compile_expression(arg)?);
+        }
+
+        unsafe {
+            let string_type = LLVMPointerType(LLVMInt8Type(), 0);
+            let file_type = string_type;
+
+            let value = match call.intrinsic {
+                Intrinsic::Print | Intrinsic::Println => unreachable!("Formats are handled above"),
+                Intrinsic::ReadLine => self.build_read_line(),
+                Intrinsic::Open => {
+                    let file = self.call_libc(c"fopen", file_type, &mut [string_type, string_type], &mut args);
+                    LLVMBuildPtrToInt(self.builder, file, LLVMInt64Type(), NO_NAME)
+                },
+                Intrinsic::Read => {
+                    let empty = LLVMBuildGlobalStringPtr(self.builder, c"".as_ptr(), c"str".as_ptr());
+                    self.build_if_open(args[0], empty, |this, file| this.build_read_to_end(file))
+                },
+                Intrinsic::Write => {
+                    let text = args[1];
+                    self.build_if_open(args[0], LLVMConstInt(LLVMInt1Type(), 0, 0), |this, file| {
+                        let written = this.call_libc(c"fputs", LLVMInt32Type(), &mut [string_type, file_type], &mut [text, file]);
+                        LLVMBuildICmp(this.builder, LLVMIntPredicate::LLVMIntSGE, written, LLVMConstInt(LLVMInt32Type(), 0, 0), NO_NAME)
+                    })
+                },
+                Intrinsic::Close => {
+                    self.build_if_open(args[0], LLVMConstInt(LLVMInt1Type(), 0, 0), |this, file| {
+                        let result = this.call_libc(c"fclose", LLVMInt32Type(), &mut [file_type], &mut [file]);
+                        LLVMBuildICmp(this.builder, LLVMIntPredicate::LLVMIntEQ, result, LLVMConstInt(LLVMInt32Type(), 0, 0), NO_NAME)
+                    })
+                },
+            };
+
+            Ok(value)
+        }
+    }
+
+    /// Prints everything with one `printf`, turning each verb into the
+    /// conversion that writes it the way the interpreter does.
+    fn build_print(&mut self, format: &[FormatPiece], values: &[CheckedExpression]) -> Result<LLVMValueRef, String> {
+        let mut printf_format = String::new();
+        let mut args = Vec::new();
+        let mut values = values.iter();
+
+        for piece in format {
+            let verb = match piece {
+                FormatPiece::Text(text) => {
+                    printf_format.push_str(&text.replace('%', "%%"));
+                    continue;
+                },
+                FormatPiece::Verb(verb) => verb,
+            };
+
+            let value = values.next().expect("The typechecker makes sure every verb has an argument");
+            let primitive = self.primitive(self.type_of(value)).cloned().expect("Only primitives can be printed");
+            let value = self.compile_expression(value)?;
+
+            // Variadic arguments are promoted to at least an `int` or a `double`
+            let arg = unsafe {
+                match verb.resolve(&primitive) {
+                    FormatVerb::Decimal if primitive.is_signed() => {
+                        printf_format.push_str("%lld");
+                        LLVMBuildIntCast2(self.builder, value, LLVMInt64Type(), 1, NO_NAME)
+                    },
+                    FormatVerb::Decimal => {
+                        printf_format.push_str("%llu");
+                        LLVMBuildIntCast2(self.builder, value, LLVMInt64Type(), 0, NO_NAME)
+                    },
+                    FormatVerb::Hex => {
+                        printf_format.push_str("%llx");
+                        LLVMBuildIntCast2(self.builder, value, LLVMInt64Type(), 0, NO_NAME)
+                    },
+                    FormatVerb::Float(precision) => {
+                        printf_format.push_str(&format!("%.{}f", precision));
+                        LLVMBuildFPCast(self.builder, value, LLVMDoubleType(), NO_NAME)
+                    },
+                    FormatVerb::String => {
+                        printf_format.push_str("%s");
+                        value
+                    },
+                    // `%c` only writes one byte
+                    FormatVerb::Char => {
+                        printf_format.push_str("%s");
+                        self.build_utf8(value)
+                    },
+                    FormatVerb::Bool => {
+                        printf_format.push_str("%s");
+                        let true_str = LLVMBuildGlobalStringPtr(self.builder, c"true".as_ptr(), c"str".as_ptr());
+                        let false_str = LLVMBuildGlobalStringPtr(self.builder, c"false".as_ptr(), c"str".as_ptr());
+                        LLVMBuildSelect(self.builder, value, true_str, false_str, NO_NAME)
+                    },
+                    FormatVerb::Value => unreachable!("`%v` always resolves to another verb"),
+                }
+            };
+
+            args.push(arg);
+        }
+
+        let format_ptr = self.new_string_ptr(&printf_format);
+
+        unsafe {
+            let format = LLVMBuildGlobalStringPtr(self.builder, format_ptr, c"fmt".as_ptr());
+            args.insert(0, format);
+
+            let (printf, printf_type) = self.libc_function(c"printf", LLVMInt32Type(), &mut [LLVMPointerType(LLVMInt8Type(), 0)], true);
+            LLVMBuildCall2(self.builder, printf_type, printf, args.as_mut_ptr(), args.len() as u32, NO_NAME);
+        }
+
+        Ok(std::ptr::null_mut())
+    }
+
+    /// Encodes a `char` as UTF-8 into a buffer on the stack, ended by a
+    /// null byte. Code points that aren't characters are written as U+FFFD,
+    /// the same as the interpreter does.
+    unsafe fn build_utf8(&mut self, code_point: LLVMValueRef) -> LLVMValueRef {
+        let builder = self.builder;
+        let int = |value: u64| LLVMConstInt(LLVMInt32Type(), value, 0);
+
+        let is_surrogate = LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntULT, LLVMBuildSub(builder, code_point, int(0xD800), NO_NAME), int(0x800), NO_NAME);
+        let too_large = LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntUGT, code_point, int(0x10FFFF), NO_NAME);
+        let is_invalid = LLVMBuildOr(builder, is_surrogate, too_large, NO_NAME);
+        let code_point = LLVMBuildSelect(builder, is_invalid, int(0xFFFD), code_point, NO_NAME);
+
+        // How many bytes come after the first one
+        let continuations = [0x80, 0x800, 0x10000].iter().fold(int(0), |count, start| {
+            let needs_more = LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntUGE, code_point, int(*start), NO_NAME);
+            LLVMBuildAdd(builder, count, LLVMBuildZExt(builder, needs_more, LLVMInt32Type(), NO_NAME), NO_NAME)
+        });
+
+        // The first byte starts with a 1 bit for every byte, and a 0 bit,
+        // unless it's the only one
+        let lead = LLVMBuildAnd(builder, LLVMBuildLShr(builder, int(0xFF00), LLVMBuildAdd(builder, continuations, int(1), NO_NAME), NO_NAME), int(0xFF), NO_NAME);
+        let is_ascii = LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntEQ, continuations, int(0), NO_NAME);
+        let lead = LLVMBuildSelect(builder, is_ascii, int(0), lead, NO_NAME);
+
+        let buffer = self.build_entry_alloca(LLVMArrayType(LLVMInt8Type(), 5));
+        let buffer = LLVMBuildBitCast(builder, buffer, LLVMPointerType(LLVMInt8Type(), 0), NO_NAME);
+
+        for idx in 0..5 {
+            // Each byte after the first holds 6 bits, starting from the top
+            let is_used = LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntUGE, continuations, int(idx), NO_NAME);
+            let bits_after = LLVMBuildMul(builder, LLVMBuildSub(builder, continuations, int(idx), NO_NAME), int(6), NO_NAME);
+            let bits_after = LLVMBuildSelect(builder, is_used, bits_after, int(0), NO_NAME);
+            let bits = LLVMBuildLShr(builder, code_point, bits_after, NO_NAME);
+
+            let byte = match idx {
+                0 => LLVMBuildOr(builder, lead, bits, NO_NAME),
+                _ => LLVMBuildOr(builder, int(0x80), LLVMBuildAnd(builder, bits, int(0x3F), NO_NAME), NO_NAME),
+            };
+            let byte = LLVMBuildSelect(builder, is_used, byte, int(0), NO_NAME);
+
+            let mut byte_idx = [LLVMConstInt(LLVMInt64Type(), idx, 0)];
+            let byte_ptr = LLVMBuildGEP2(builder, LLVMInt8Type(), buffer, byte_idx.as_mut_ptr(), 1, NO_NAME);
+            LLVMBuildStore(builder, LLVMBuildTrunc(builder, byte, LLVMInt8Type(), NO_NAME), byte_ptr);
+        }
+
+        buffer
+    }
+
+    /// Reads a line with `getline`, and takes the line break off of it.
+    /// There's no line once stdin runs out, which reads as an empty one.
+    unsafe fn build_read_line(&mut self) -> LLVMValueRef {
+        let string_type = LLVMPointerType(LLVMInt8Type(), 0);
+
+        // Whatever was printed as a prompt should show up before the
+        // program waits for input
+        self.call_libc(c"fflush", LLVMInt32Type(), &mut [string_type], &mut [LLVMConstNull(string_type)]);
+
+        let stdin = self.stdin();
+        let (line, len) = self.build_getline(stdin, None);
+        let empty = LLVMBuildGlobalStringPtr(self.builder, c"".as_ptr(), c"str".as_ptr());
+
+        let read_bb = LLVMGetInsertBlock(self.builder);
+        let strip_bb = self.append_block(c"read_line.strip");
+        let end_bb = self.append_block(c"read_line.end");
+
+        let got_line = LLVMBuildICmp(self.builder, LLVMIntPredicate::LLVMIntSGT, len, LLVMConstInt(LLVMInt64Type(), 0, 0), NO_NAME);
+        LLVMBuildCondBr(self.builder, got_line, strip_bb, end_bb);
+
+        self.start_block(strip_bb);
+        let mut last_idx = [LLVMBuildSub(self.builder, len, LLVMConstInt(LLVMInt64Type(), 1, 0), NO_NAME)];
+        let last_ptr = LLVMBuildGEP2(self.builder, LLVMInt8Type(), line, last_idx.as_mut_ptr(), 1, NO_NAME);
+        let last = LLVMBuildLoad2(self.builder, LLVMInt8Type(), last_ptr, NO_NAME);
+        let is_newline = LLVMBuildICmp(self.builder, LLVMIntPredicate::LLVMIntEQ, last, LLVMConstInt(LLVMInt8Type(), b'\n' as u64, 0), NO_NAME);
+        let stripped = LLVMBuildSelect(self.builder, is_newline, LLVMConstInt(LLVMInt8Type(), 0, 0), last, NO_NAME);
+        LLVMBuildStore(self.builder, stripped, last_ptr);
+        LLVMBuildBr(self.builder, end_bb);
+
+        self.start_block(end_bb);
+        let phi = LLVMBuildPhi(self.builder, string_type, NO_NAME);
+        let mut values = [empty, line];
+        let mut blocks = [read_bb, strip_bb];
+        LLVMAddIncoming(phi, values.as_mut_ptr(), blocks.as_mut_ptr(), 2);
+
+        phi
+    }
+
+    /// Reads everything that's left in `file`. `getdelim` stops at a 0
+    /// byte, which text files don't have.
+    unsafe fn build_read_to_end(&mut self, file: LLVMValueRef) -> LLVMValueRef {
+        let (contents, len) = self.build_getline(file, Some(0));
+        let empty = LLVMBuildGlobalStringPtr(self.builder, c"".as_ptr(), c"str".as_ptr());
+        let read_any = LLVMBuildICmp(self.builder, LLVMIntPredicate::LLVMIntSGT, len, LLVMConstInt(LLVMInt64Type(), 0, 0), NO_NAME);
+
+        LLVMBuildSelect(self.builder, read_any, contents, empty, NO_NAME)
+    }
+
+    /// Calls `getline`, or `getdelim` with `delimiter`, on a buffer it
+    /// allocates. Gives back the buffer along with the length that was read.
+    unsafe fn build_getline(&mut self, file: LLVMValueRef, delimiter: Option<u8>) -> (LLVMValueRef, LLVMValueRef) {
+        let string_type = LLVMPointerType(LLVMInt8Type(), 0);
+        let buffer_slot = self.build_entry_alloca(string_type);
+        let capacity_slot = self.build_entry_alloca(LLVMInt64Type());
+        LLVMBuildStore(self.builder, LLVMConstNull(string_type), buffer_slot);
+        LLVMBuildStore(self.builder, LLVMConstInt(LLVMInt64Type(), 0, 0), capacity_slot);
+
+        let buffer_ptr_type = LLVMPointerType(string_type, 0);
+        let capacity_ptr_type = LLVMPointerType(LLVMInt64Type(), 0);
+
+        let len = match delimiter {
+            Some(delimiter) => {
+                let delimiter = LLVMConstInt(LLVMInt32Type(), delimiter as u64, 0);
+                self.call_libc(c"getdelim", LLVMInt64Type(), &mut [buffer_ptr_type, capacity_ptr_type, LLVMInt32Type(), string_type],
+                               &mut [buffer_slot, capacity_slot, delimiter, file])
+            },
+            None => self.call_libc(c"getline", LLVMInt64Type(), &mut [buffer_ptr_type, capacity_ptr_type, string_type],
+                                   &mut [buffer_slot, capacity_slot, file]),
+        };
+
+        (LLVMBuildLoad2(self.builder, string_type, buffer_slot, NO_NAME), len)
+    }
+
+    /// The C runtime's `stdin`, which Apple's libc calls `__stdinp`.
+    unsafe fn stdin(&mut self) -> LLVMValueRef {
+        self.c_stream(self.stdin_symbol)
+    }
+
+    /// The C runtime's `stderr`, which Apple's libc calls `__stderrp`.
+    unsafe fn stderr(&mut self) -> LLVMValueRef {
+        self.c_stream(self.stderr_symbol)
+    }
+
+    unsafe fn c_stream(&mut self, symbol: &CStr) -> LLVMValueRef {
+        let file_type = LLVMPointerType(LLVMInt8Type(), 0);
+
+        let mut global = LLVMGetNamedGlobal(self.llvm_mod, symbol.as_ptr());
+        if global.is_null() {
+            global = LLVMAddGlobal(self.llvm_mod, file_type, symbol.as_ptr());
+            LLVMSetLinkage(global, LLVMLinkage::LLVMExternalLinkage);
+        }
+
+        LLVMBuildLoad2(self.builder, file_type, global, NO_NAME)
+    }
+
+    /// Does what `build` builds only if the file `handle` is open, and
+    /// gives `fallback` otherwise, since libc can't be handed a null
+    /// `FILE *`.
+    unsafe fn build_if_open(&mut self, handle: LLVMValueRef, fallback: LLVMValueRef, build: impl FnOnce(&mut Self, LLVMValueRef) -> LLVMValueRef) -> LLVMValueRef {
+        let closed_bb = LLVMGetInsertBlock(self.builder);
+        let open_bb = self.append_block(c"file.open");
+        let end_bb = self.append_block(c"file.end");
+
+        let is_open = LLVMBuildICmp(self.builder, LLVMIntPredicate::LLVMIntNE, handle, LLVMConstInt(LLVMInt64Type(), 0, 0), NO_NAME);
+        LLVMBuildCondBr(self.builder, is_open, open_bb, end_bb);
+
+        self.start_block(open_bb);
+        let file = LLVMBuildIntToPtr(self.builder, handle, LLVMPointerType(LLVMInt8Type(), 0), NO_NAME);
+        let value = build(self, file);
+        let open_end_bb = LLVMGetInsertBlock(self.builder);
+        LLVMBuildBr(self.builder, end_bb);
+
+        self.start_block(end_bb);
+        let phi = LLVMBuildPhi(self.builder, LLVMTypeOf(value), NO_NAME);
+        let mut values = [fallback, value];
+        let mut blocks = [closed_bb, open_end_bb];
+        LLVMAddIncoming(phi, values.as_mut_ptr(), blocks.as_mut_ptr(), 2);
+
+        phi
+    }
+
+    unsafe fn call_libc(&mut self, name: &CStr, return_type: LLVMTypeRef, param_types: &mut [LLVMTypeRef], args: &mut [LLVMValueRef]) -> LLVMValueRef {
+        let (function, fn_type) = self.libc_function(name, return_type, param_types, false);
+        LLVMBuildCall2(self.builder, fn_type, function, args.as_mut_ptr(), args.len() as u32, NO_NAME)
+    }
+
+    /// Declares a libc function the first time it's needed. A `#foreign`
+    /// proc may have declared it already with other types, in which case
+    /// it's cast to the ones the runtime calls it with.
+    fn libc_function(&mut self, name: &CStr, return_type: LLVMTypeRef, param_types: &mut [LLVMTypeRef], variadic: bool) -> (LLVMValueRef, LLVMTypeRef) {
+        unsafe {
+            let fn_type = LLVMFunctionType(return_type, param_types.as_mut_ptr(), param_types.len() as u32, variadic as LLVMBool);
+
+            let mut function = LLVMGetNamedFunction(self.llvm_mod, name.as_ptr());
+            if function.is_null() {
+                function = LLVMAddFunction(self.llvm_mod, name.as_ptr(), fn_type);
+            } else if LLVMGlobalGetValueType(function) != fn_type {
+                function = LLVMConstBitCast(function, LLVMPointerType(fn_type, 0));
+            }
+
+            (function, fn_type)
+        }
+    }
+
+    pub fn compile_proc_decl(&mut self, proc_decl: &CheckedProcDecl) -> Result<(), String> {
+        let (function, _) = self.procs[&proc_decl.name];
+        self.current_proc = function;
+        self.current_proc_needs_exit_code = self.needs_exit_code(proc_decl);
+        self.locals.clear();
+
+        unsafe {
+            let entry = LLVMAppendBasicBlock(function, c"entry".as_ptr());
+            LLVMPositionBuilderAtEnd(self.builder, entry);
+
+            // Every local gets its stack slot up front, parameters included,
+            // so they can all be assigned to the same way.
+            for local in &proc_decl.locals {
+                let local_type = self.llvm_type(local.type_id);
+                let name_ptr = self.new_string_ptr(&local.name);
+                let alloca = LLVMBuildAlloca(self.builder, local_type, name_ptr);
+                self.locals.push((alloca, local_type));
+            }
+
+            for idx in 0..proc_decl.param_count {
+                LLVMBuildStore(self.builder, LLVMGetParam(function, idx as u32), self.locals[idx].0);
+            }
+        }
+
+        self.compile_block(&proc_decl.body)?;
+
+        if !self.current_block_is_terminated() {
+            unsafe {
+                if proc_decl.return_type == self.builtins.nothing {
+                    self.build_return_nothing();
+                } else {
+                    // The typechecker makes sure procs that return a value
+                    // always do, so this is never reached.
+                    LLVMBuildUnreachable(self.builder);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn build_return_nothing(&self) {
+        unsafe {
+            if self.current_proc_needs_exit_code {
+                LLVMBuildRet(self.builder, LLVMConstInt(LLVMInt32Type(), 0, 0));
+            } else {
+                LLVMBuildRetVoid(self.builder);
+            }
+        }
+    }
+
+    fn current_block_is_terminated(&self) -> bool {
+        unsafe { !LLVMGetBasicBlockTerminator(LLVMGetInsertBlock(self.builder)).is_null() }
+    }
+
+    fn compile_block(&mut self, block: &CheckedBlock) -> Result<(), String> {
+        for stmt in &block.stmts {
+            // Anything after a `return` can't run, and LLVM doesn't
+            // allow instructions after a terminator anyway.
+            if self.current_block_is_terminated() {
+                break;
+            }
+
+            self.compile_statement(stmt)?;
+        }
+
+        Ok(())
+    }
+
+    fn compile_statement(&mut self, stmt: &CheckedStatement) -> Result<(), String> {
+        match stmt {
+            CheckedStatement::Expr(expr) => {
+                self.compile_expression(expr)?;
+            },
+            CheckedStatement::VarDecl(var_id, init) => {
+                let value = self.compile_expression(init)?;
+                unsafe { LLVMBuildStore(self.builder, value, self.locals[*var_id].0); }
+            },
+            CheckedStatement::Block(block) => self.compile_block(block)?,
+            CheckedStatement::Return(expr) => {
+                match expr {
+                    Some(expr) => {
+                        let value = self.compile_expression(expr)?;
+                        unsafe { LLVMBuildRet(self.builder, value); }
+                    },
+                    None => self.build_return_nothing(),
+                }
+            },
+            CheckedStatement::If(cond, then_block, else_stmt) => self.compile_if(cond, then_block, else_stmt.as_deref())?,
+            CheckedStatement::ForLoop(var_id, range, body) => self.compile_for_loop(*var_id, range, body)?,
+            CheckedStatement::WhileLoop(cond, body) => self.compile_while_loop(cond, body)?,
+            CheckedStatement::InfiniteLoop(body) => self.compile_infinite_loop(body)?,
+            CheckedStatement::Continue => {
+                let (continue_block, _) = *self.loops.last().expect("`continue` outside of a loop");
+                unsafe { LLVMBuildBr(self.builder, continue_block); }
+            },
+            CheckedStatement::Break => {
+                let (_, break_block) = *self.loops.last().expect("`break` outside of a loop");
+                unsafe { LLVMBuildBr(self.builder, break_block); }
+            },
+        }
+
+        Ok(())
+    }
+
+    fn append_block(&self, name: &CStr) -> LLVMBasicBlockRef {
+        unsafe { LLVMAppendBasicBlock(self.current_proc, name.as_ptr()) }
+    }
+
+    /// Moves `block` after everything emitted so far and starts putting
+    /// instructions into it, so the IR reads top to bottom.
+    fn start_block(&self, block: LLVMBasicBlockRef) {
+        unsafe {
+            LLVMMoveBasicBlockAfter(block, LLVMGetLastBasicBlock(self.current_proc));
+            LLVMPositionBuilderAtEnd(self.builder, block);
+        }
+    }
+
+    /// Jumps to `block` unless the current block already ended,
+    /// e.g. with a `return` or `break`.
+    fn branch_if_unterminated(&self, block: LLVMBasicBlockRef) {
+        if !self.current_block_is_terminated() {
+            unsafe { LLVMBuildBr(self.builder, block); }
+        }
+    }
+
+    fn compile_if(&mut self, cond: &CheckedExpression, then_block: &CheckedBlock, else_stmt: Option<&CheckedStatement>) -> Result<(), String> {
+        let cond_value = self.compile_expression(cond)?;
+
+        let then_bb = self.append_block(c"if.then");
+        let else_bb = else_stmt.map(|_| self.append_block(c"if.else"));
+        let end_bb = self.append_block(c"if.end");
+
+        unsafe {
+            LLVMBuildCondBr(self.builder, cond_value, then_bb, else_bb.unwrap_or(end_bb));
+
+            self.start_block(then_bb);
+            self.compile_block(then_block)?;
+            self.branch_if_unterminated(end_bb);
+
+            if let (Some(else_stmt), Some(else_bb)) = (else_stmt, else_bb) {
+                self.start_block(else_bb);
+                self.compile_statement(else_stmt)?;
+                self.branch_if_unterminated(end_bb);
+            }
+
+            self.start_block(end_bb);
+        }
+
+        Ok(())
+    }
+
+    /// Compiles a loop body with `continue` jumping to `continue_bb`
+    /// and `break` to `break_bb`.
+    fn compile_loop_body(&mut self, body: &CheckedBlock, continue_bb: LLVMBasicBlockRef, break_bb: LLVMBasicBlockRef) -> Result<(), String> {
+        self.loops.push((continue_bb, break_bb));
+        let result = self.compile_block(body);
+        self.loops.pop();
+
+        result?;
+        self.branch_if_unterminated(continue_bb);
+
+        Ok(())
+    }
+
+    fn compile_while_loop(&mut self, cond: &CheckedExpression, body: &CheckedBlock) -> Result<(), String> {
+        let cond_bb = self.append_block(c"while.cond");
+        let body_bb = self.append_block(c"while.body");
+        let end_bb = self.append_block(c"while.end");
+
+        unsafe {
+            LLVMBuildBr(self.builder, cond_bb);
+
+            self.start_block(cond_bb);
+            let cond_value = self.compile_expression(cond)?;
+            LLVMBuildCondBr(self.builder, cond_value, body_bb, end_bb);
+
+            self.start_block(body_bb);
+            self.compile_loop_body(body, cond_bb, end_bb)?;
+
+            self.start_block(end_bb);
+        }
+
+        Ok(())
+    }
+
+    fn compile_infinite_loop(&mut self, body: &CheckedBlock) -> Result<(), String> {
+        let body_bb = self.append_block(c"loop.body");
+        let end_bb = self.append_block(c"loop.end");
+
+        unsafe {
+            LLVMBuildBr(self.builder, body_bb);
+
+            self.start_block(body_bb);
+            self.compile_loop_body(body, body_bb, end_bb)?;
+
+            self.start_block(end_bb);
+        }
+
+        Ok(())
+    }
+
+    /// `for i in [start..end)` counts `i` up by one. An exclusive lower
+    /// bound starts one past `start`, and an inclusive upper bound runs
+    /// the body for `end` as well. `end` is only evaluated once. The loop
+    /// stops once `i` reaches `end` before adding one to it, so a bound at
+    /// the largest value of the type doesn't wrap around.
+    fn compile_for_loop(&mut self, var_id: VarID, range: &CheckedExpression, body: &CheckedBlock) -> Result<(), String> {
+        let CheckedExpression::Range(start_bound, start, end, end_bound, type_id, _) = range else {
+            return self.compile_array_loop(var_id, range, body);
+        };
+
+        let (var_ptr, var_type) = self.locals[var_id];
+        let (less, less_or_equal) = match self.is_signed(*type_id) {
+            true => (LLVMIntPredicate::LLVMIntSLT, LLVMIntPredicate::LLVMIntSLE),
+            false => (LLVMIntPredicate::LLVMIntULT, LLVMIntPredicate::LLVMIntULE),
+        };
+
+        unsafe {
+            let one = LLVMConstInt(var_type, 1, 0);
+
+            let mut start_value = self.compile_expression(start)?;
+            let end_value = self.compile_expression(end)?;
+
+            let cond_bb = self.append_block(c"for.cond");
+            let body_bb = self.append_block(c"for.body");
+            let step_bb = self.append_block(c"for.step");
+            let next_bb = self.append_block(c"for.next");
+            let end_bb = self.append_block(c"for.end");
+
+            // Nothing comes after a `start` that's already at `end`, and
+            // adding one to it could wrap
+            if matches!(start_bound, RangeExprBound::Exclusive) {
+                let first_bb = self.append_block(c"for.first");
+                let has_next = LLVMBuildICmp(self.builder, less, start_value, end_value, NO_NAME);
+                LLVMBuildCondBr(self.builder, has_next, first_bb, end_bb);
+
+                self.start_block(first_bb);
+                start_value = LLVMBuildAdd(self.builder, start_value, one, NO_NAME);
+            }
+
+            LLVMBuildStore(self.builder, start_value, var_ptr);
+            LLVMBuildBr(self.builder, cond_bb);
+
+            self.start_block(cond_bb);
+            let predicate = match end_bound {
+                RangeExprBound::Inclusive => less_or_equal,
+                RangeExprBound::Exclusive => less,
+            };
+            let current = LLVMBuildLoad2(self.builder, var_type, var_ptr, NO_NAME);
+            let in_range = LLVMBuildICmp(self.builder, predicate, current, end_value, NO_NAME);
+            LLVMBuildCondBr(self.builder, in_range, body_bb, end_bb);
+
+            self.start_block(body_bb);
+            self.compile_loop_body(body, step_bb, end_bb)?;
+
+            self.start_block(step_bb);
+            let current = LLVMBuildLoad2(self.builder, var_type, var_ptr, NO_NAME);
+            let has_next = LLVMBuildICmp(self.builder, less, current, end_value, NO_NAME);
+            LLVMBuildCondBr(self.builder, has_next, next_bb, end_bb);
+
+            self.start_block(next_bb);
+            let next = LLVMBuildAdd(self.builder, current, one, NO_NAME);
+            LLVMBuildStore(self.builder, next, var_ptr);
+            LLVMBuildBr(self.builder, cond_bb);
+
+            self.start_block(end_bb);
+        }
+
+        Ok(())
+    }
+
+    /// `for x in array` copies the array once and steps through it with a
+    /// hidden index, loading each element into the iterator.
+    fn compile_array_loop(&mut self, var_id: VarID, array: &CheckedExpression, body: &CheckedBlock) -> Result<(), String> {
+        let TypeInfo::Array(_, _, len) = self.types[self.type_of(array)] else {
+            unreachable!("For loops iterate over a range or an array");
+        };
+
+        let (var_ptr, var_type) = self.locals[var_id];
+        let array_value = self.compile_expression(array)?;
+
+        unsafe {
+            let array_type = LLVMTypeOf(array_value);
+            let array_ptr = self.build_entry_alloca(array_type);
+            LLVMBuildStore(self.builder, array_value, array_ptr);
+
+            let index_ptr = self.build_entry_alloca(LLVMInt64Type());
+            LLVMBuildStore(self.builder, LLVMConstInt(LLVMInt64Type(), 0, 0), index_ptr);
+
+            let cond_bb = self.append_block(c"for.cond");
+            let body_bb = self.append_block(c"for.body");
+            let step_bb = self.append_block(c"for.step");
+            let end_bb = self.append_block(c"for.end");
+
+            LLVMBuildBr(self.builder, cond_bb);
+
+            self.start_block(cond_bb);
+            let index = LLVMBuildLoad2(self.builder, LLVMInt64Type(), index_ptr, NO_NAME);
+            let in_range = LLVMBuildICmp(self.builder, LLVMIntPredicate::LLVMIntULT, index, LLVMConstInt(LLVMInt64Type(), len, 0), NO_NAME);
+            LLVMBuildCondBr(self.builder, in_range, body_bb, end_bb);
+
+            self.start_block(body_bb);
+            let mut indices = [LLVMConstInt(LLVMInt64Type(), 0, 0), index];
+            let elem_ptr = LLVMBuildInBoundsGEP2(self.builder, array_type, array_ptr, indices.as_mut_ptr(), 2, NO_NAME);
+            let elem = LLVMBuildLoad2(self.builder, var_type, elem_ptr, NO_NAME);
+            LLVMBuildStore(self.builder, elem, var_ptr);
+            self.compile_loop_body(body, step_bb, end_bb)?;
+
+            self.start_block(step_bb);
+            let index = LLVMBuildLoad2(self.builder, LLVMInt64Type(), index_ptr, NO_NAME);
+            let next = LLVMBuildAdd(self.builder, index, LLVMConstInt(LLVMInt64Type(), 1, 0), NO_NAME);
+            LLVMBuildStore(self.builder, next, index_ptr);
+            LLVMBuildBr(self.builder, cond_bb);
+
+            self.start_block(end_bb);
+        }
+
+        Ok(())
+    }
+
+    /// Compiles `expr` and returns its value. Expressions of type
+    /// `nothing` don't have one, so they give back a null pointer.
+    fn compile_expression(&mut self, expr: &CheckedExpression) -> Result<LLVMValueRef, String> {
+        let value = unsafe {
+            match expr {
+                CheckedExpression::Bool(value, _) => LLVMConstInt(LLVMInt1Type(), *value as u64, 0),
+                CheckedExpression::Integer(value, type_id, _) => LLVMConstInt(self.llvm_type(*type_id), *value, 0),
+                CheckedExpression::Float(value, type_id, _) => LLVMConstReal(self.llvm_type(*type_id), *value),
+                CheckedExpression::StringLiteral(value, _) => {
+                    let value_ptr = self.new_string_ptr(value);
+                    LLVMBuildGlobalStringPtr(self.builder, value_ptr, c"str".as_ptr())
+                },
+                CheckedExpression::CharLiteral(value, _) => LLVMConstInt(LLVMInt32Type(), *value as u64, 0),
+                CheckedExpression::Null(type_id, _) => LLVMConstNull(self.llvm_type(*type_id)),
+                CheckedExpression::Var(var_id, _, _) => {
+                    let (ptr, var_type) = self.locals[*var_id];
+                    LLVMBuildLoad2(self.builder, var_type, ptr, NO_NAME)
+                },
+                CheckedExpression::UnaryOperation(operand, op, type_id, _) => self.compile_unary_operation(operand, op, *type_id)?,
+                CheckedExpression::BinaryOperation(lhs, op, rhs, _, _) => self.compile_binary_operation(lhs, op, rhs)?,
+                CheckedExpression::ProcCall(call, _, _) => self.add_proc_call(call)?,
+                CheckedExpression::IntrinsicCall(call, _, _) => self.add_intrinsic_call(call)?,
+                CheckedExpression::EnumValue(variant_id, payload, enum_id, _) => self.build_enum_value(*variant_id, payload, *enum_id)?,
+                CheckedExpression::Const(_, _, _) => unreachable!("Constants are folded by the typechecker"),
+                CheckedExpression::Range(_, _, _, _, _, _) => return Err(String::from("ranges can only be used in `for` loops")),
+                CheckedExpression::Match(matched, cases, type_id, _) => self.compile_match(matched, cases, *type_id)?,
+                CheckedExpression::If(cond, then_body, else_body, type_id, _) => self.compile_if_expression(cond, then_body, else_body, *type_id)?,
+                CheckedExpression::Array(elems, type_id, _) => {
+                    let mut array = LLVMGetUndef(self.llvm_type(*type_id));
+                    for (idx, elem) in elems.iter().enumerate() {
+                        let value = self.compile_expression(elem)?;
+                        array = LLVMBuildInsertValue(self.builder, array, value, idx as u32, NO_NAME);
+                    }
+
+                    array
+                },
+                CheckedExpression::ArrayRepeat(value, len, type_id, _) => self.build_array_repeat(value, *len, *type_id)?,
+                CheckedExpression::Struct(members, type_id, _) => {
+                    let mut value = LLVMGetUndef(self.llvm_type(*type_id));
+                    for (idx, member) in members.iter().enumerate() {
+                        let member_value = self.compile_expression(member)?;
+                        value = LLVMBuildInsertValue(self.builder, value, member_value, idx as u32, NO_NAME);
+                    }
+
+                    value
+                },
+                CheckedExpression::Index(_, _, _, _) |
+                CheckedExpression::Member(_, _, _, _) => {
+                    let (ptr, value_type) = self.compile_place(expr)?;
+                    LLVMBuildLoad2(self.builder, value_type, ptr, NO_NAME)
+                },
+                CheckedExpression::Invalid(_) => unreachable!("Invalid expressions never make it past the typechecker"),
+            }
+        };
+
+        Ok(value)
+    }
+
+    /// The address of a variable, an element or member of one or what a
+    /// pointer points at, along with the type that's stored there. Anything
+    /// else, like the array a proc returns, is spilled to a stack slot
+    /// first so it can be indexed.
+    fn compile_place(&mut self, expr: &CheckedExpression) -> Result<(LLVMValueRef, LLVMTypeRef), String> {
+        match expr {
+            CheckedExpression::Var(var_id, _, _) => Ok(self.locals[*var_id]),
+            CheckedExpression::Index(array, index, elem_id, _) => {
+                let array_id = self.type_of(array);
+                let (array_ptr, array_type) = self.compile_place(array)?;
+                let index_value = self.compile_expression(index)?;
+
+                unsafe {
+                    let index_value = LLVMBuildIntCast2(self.builder, index_value, LLVMInt64Type(), self.is_signed(self.type_of(index)) as i32, NO_NAME);
+
+                    if self.runtime_checks {
+                        let TypeInfo::Array(_, _, len) = self.types[array_id] else {
+                            unreachable!("Only arrays can be indexed");
+                        };
+
+                        self.build_bounds_check(index_value, len, self.is_signed(self.type_of(index)));
+                    }
+
+                    let mut indices = [LLVMConstInt(LLVMInt64Type(), 0, 0), index_value];
+                    let elem_ptr = LLVMBuildInBoundsGEP2(self.builder, array_type, array_ptr, indices.as_mut_ptr(), 2, NO_NAME);
+                    Ok((elem_ptr, self.llvm_type(*elem_id)))
+                }
+            },
+            CheckedExpression::Member(base, idx, member_id, _) => {
+                let (base_ptr, base_type) = self.compile_place(base)?;
+
+                unsafe {
+                    let member_ptr = LLVMBuildStructGEP2(self.builder, base_type, base_ptr, *idx as u32, NO_NAME);
+                    Ok((member_ptr, self.llvm_type(*member_id)))
+                }
+            },
+            CheckedExpression::UnaryOperation(pointer, UnaryOperator::Dereference, _, _) => {
+                let pointee_type = self.pointee_type(self.type_of(pointer));
+                Ok((self.compile_expression(pointer)?, pointee_type))
+            },
+            _ => {
+                let value = self.compile_expression(expr)?;
+
+                unsafe {
+                    let value_type = LLVMTypeOf(value);
+                    let slot = self.build_entry_alloca(value_type);
+                    LLVMBuildStore(self.builder, value, slot);
+                    Ok((slot, value_type))
+                }
+            },
+        }
+    }
+
+    /// Stops the program with an error if `index`, which has been widened
+    /// to an `i64`, isn't less than `len`. Negative indices count as huge
+    /// ones here, so they're caught by the same comparison.
+    unsafe fn build_bounds_check(&mut self, index: LLVMValueRef, len: u64, is_signed: bool) {
+        let in_bounds = LLVMBuildICmp(self.builder, LLVMIntPredicate::LLVMIntULT, index, LLVMConstInt(LLVMInt64Type(), len, 0), NO_NAME);
+
+        let message = match is_signed {
+            true => "error: index %lld is out of bounds for an array of length %llu\n",
+            false => "error: index %llu is out of bounds for an array of length %llu\n",
+        };
+        self.build_runtime_check(in_bounds, "bounds", message, &[index, LLVMConstInt(LLVMInt64Type(), len, 0)]);
+    }
+
+    /// Stops the program with an error unless `ok` holds, the same way the
+    /// interpreter does. `message` is a `printf` format for `args`, and the
+    /// blocks are named after `kind`.
+    unsafe fn build_runtime_check(&mut self, ok: LLVMValueRef, kind: &str, message: &str, args: &[LLVMValueRef]) {
+        let fail_bb = self.append_block(&CString::new(format!("{}.fail", kind)).unwrap());
+        let ok_bb = self.append_block(&CString::new(format!("{}.ok", kind)).unwrap());
+
+        LLVMBuildCondBr(self.builder, ok, ok_bb, fail_bb);
+
+        self.start_block(fail_bb);
+        let message_ptr = self.new_string_ptr(message);
+        let message = LLVMBuildGlobalStringPtr(self.builder, message_ptr, c"str".as_ptr());
+
+        // Whatever the program printed so far goes out before the error
+        let string_type = LLVMPointerType(LLVMInt8Type(), 0);
+        self.call_libc(c"fflush", LLVMInt32Type(), &mut [string_type], &mut [LLVMConstNull(string_type)]);
+
+        let stderr = self.stderr();
+        let (fprintf, fprintf_type) = self.libc_function(c"fprintf", LLVMInt32Type(), &mut [string_type, string_type], true);
+        let mut fprintf_args = [&[stderr, message][..], args].concat();
+        LLVMBuildCall2(self.builder, fprintf_type, fprintf, fprintf_args.as_mut_ptr(), fprintf_args.len() as u32, NO_NAME);
+
+        self.call_libc(c"exit", LLVMVoidType(), &mut [LLVMInt32Type()], &mut [LLVMConstInt(LLVMInt32Type(), 1, 0)]);
+        LLVMBuildUnreachable(self.builder);
+
+        self.start_block(ok_bb);
+    }
+
+    /// Builds `[value; len]` in memory with a loop, since the array can be
+    /// too long to spell out element by element.
+    fn build_array_repeat(&mut self, value: &CheckedExpression, len: u64, type_id: TypeID) -> Result<LLVMValueRef, String> {
+        let value = self.compile_expression(value)?;
+
+        unsafe {
+            let array_type = self.llvm_type(type_id);
+            let slot = self.build_entry_alloca(array_type);
+
+            let start_bb = LLVMGetInsertBlock(self.builder);
+            let cond_bb = self.append_block(c"repeat.cond");
+            let body_bb = self.append_block(c"repeat.body");
+            let end_bb = self.append_block(c"repeat.end");
+
+            LLVMBuildBr(self.builder, cond_bb);
+
+            self.start_block(cond_bb);
+            let index = LLVMBuildPhi(self.builder, LLVMInt64Type(), NO_NAME);
+            let in_range = LLVMBuildICmp(self.builder, LLVMIntPredicate::LLVMIntULT, index, LLVMConstInt(LLVMInt64Type(), len, 0), NO_NAME);
+            LLVMBuildCondBr(self.builder, in_range, body_bb, end_bb);
+
+            self.start_block(body_bb);
+            let mut indices = [LLVMConstInt(LLVMInt64Type(), 0, 0), index];
+            let elem_ptr = LLVMBuildInBoundsGEP2(self.builder, array_type, slot, indices.as_mut_ptr(), 2, NO_NAME);
+            LLVMBuildStore(self.builder, value, elem_ptr);
+            let next = LLVMBuildAdd(self.builder, index, LLVMConstInt(LLVMInt64Type(), 1, 0), NO_NAME);
+            LLVMBuildBr(self.builder, cond_bb);
+
+            let mut values = [LLVMConstInt(LLVMInt64Type(), 0, 0), next];
+            let mut blocks = [start_bb, body_bb];
+            LLVMAddIncoming(index, values.as_mut_ptr(), blocks.as_mut_ptr(), 2);
+
+            self.start_block(end_bb);
+            Ok(LLVMBuildLoad2(self.builder, array_type, slot, NO_NAME))
+        }
+    }
+
+    /// A stack slot in the entry block, so one that's needed inside of a
+    /// loop isn't allocated over and over.
+    unsafe fn build_entry_alloca(&mut self, ty: LLVMTypeRef) -> LLVMValueRef {
+        let entry = LLVMGetEntryBasicBlock(self.current_proc);
+        let builder = LLVMCreateBuilder();
+
+        match LLVMGetFirstInstruction(entry) {
+            first if first.is_null() => LLVMPositionBuilderAtEnd(builder, entry),
+            first => LLVMPositionBuilderBefore(builder, first),
+        }
+
+        let alloca = LLVMBuildAlloca(builder, ty, NO_NAME);
+        LLVMDisposeBuilder(builder);
+
+        alloca
+    }
+
+    /// Builds an enum value in memory, since the payload has to be written
+    /// through a pointer to the variant's own struct type.
+    fn build_enum_value(&mut self, variant_id: TypeID, payload: &[CheckedExpression], enum_id: TypeID) -> Result<LLVMValueRef, String> {
+        let TypeInfo::Enum(_, variants) = &self.types[enum_id] else {
+            unreachable!("Enum values always have an enum type");
+        };
+
+        let discriminant = variants.iter().position(|variant| *variant == variant_id).unwrap();
+        let discriminant_type = layout::discriminant_type(variants.len());
+
+        let mut values = Vec::new();
+        for value in payload {
+            values.push(self.compile_expression(value)?);
+        }
+
+        unsafe {
+            let enum_type = self.llvm_type(enum_id);
+            let slot = self.build_entry_alloca(enum_type);
+
+            let discriminant_ptr = LLVMBuildStructGEP2(self.builder, enum_type, slot, 0, NO_NAME);
+            LLVMBuildStore(self.builder, LLVMConstInt(LLVMIntType(discriminant_type.bit_width()), discriminant as u64, 0), discriminant_ptr);
+
+            if !values.is_empty() {
+                let variant_type = self.llvm_type(variant_id);
+                let payload_ptr = LLVMBuildStructGEP2(self.builder, enum_type, slot, 1, NO_NAME);
+                let payload_ptr = LLVMBuildBitCast(self.builder, payload_ptr, LLVMPointerType(variant_type, 0), NO_NAME);
+
+                for (idx, value) in values.into_iter().enumerate() {
+                    let field_ptr = LLVMBuildStructGEP2(self.builder, variant_type, payload_ptr, idx as u32, NO_NAME);
+                    LLVMBuildStore(self.builder, value, field_ptr);
+                }
+            }
+
+            Ok(LLVMBuildLoad2(self.builder, enum_type, slot, NO_NAME))
+        }
     }
 
-    pub fn add_proc_call(&mut self, proc_call: &ParsedProcCall) {
+    /// Enums switch on their discriminant, and each arm copies the payload
+    /// fields it binds out of the variant's struct. Anything else is
+    /// compared against one arm after the other. The values the arms give
+    /// come together in a phi at the end.
+    fn compile_match(&mut self, matched: &CheckedExpression, cases: &[CheckedMatchCase], result_type: TypeID) -> Result<LLVMValueRef, String> {
+        let matched_type = self.type_of(matched);
+        let matched_value = self.compile_expression(matched)?;
+
+        let end_bb = self.append_block(c"match.end");
+        let mut incoming = Vec::new();
+
+        unsafe {
+            if let TypeInfo::Enum(_, variants) = &self.types[matched_type] {
+                let variants = variants.clone();
+                let enum_type = self.llvm_type(matched_type);
+                let discriminant_type = LLVMIntType(layout::discriminant_type(variants.len()).bit_width());
+
+                // The payload is read through a pointer, so the value needs
+                // to be in memory
+                let slot = self.build_entry_alloca(enum_type);
+                LLVMBuildStore(self.builder, matched_value, slot);
+                let discriminant = LLVMBuildExtractValue(self.builder, matched_value, 0, NO_NAME);
+
+                let has_fallback = cases.iter().any(|case| matches!(case, CheckedMatchCase::Fallback(_)));
+                let default_bb = self.append_block(if has_fallback { c"match.else" } else { c"match.unreachable" });
+                let switch = LLVMBuildSwitch(self.builder, discriminant, default_bb, cases.len() as u32);
+
+                // A switch can't have the same case twice, and arms that
+                // come after another one for the same variant never run
+                let mut covered = Vec::new();
+
+                for case in cases {
+                    match case {
+                        CheckedMatchCase::EnumVariant(variant_id, bindings, body) => {
+                            if covered.contains(variant_id) {
+                                continue;
+                            }
+
+                            covered.push(*variant_id);
+
+                            let discriminant = variants.iter().position(|variant| variant == variant_id).unwrap();
+                            let arm_bb = self.append_block(c"match.arm");
+                            LLVMAddCase(switch, LLVMConstInt(discriminant_type, discriminant as u64, 0), arm_bb);
+
+                            self.start_block(arm_bb);
+
+                            if !bindings.is_empty() {
+                                let variant_type = self.llvm_type(*variant_id);
+                                let payload_ptr = LLVMBuildStructGEP2(self.builder, enum_type, slot, 1, NO_NAME);
+                                let payload_ptr = LLVMBuildBitCast(self.builder, payload_ptr, LLVMPointerType(variant_type, 0), NO_NAME);
+
+                                for (idx, var_id) in bindings {
+                                    let (var_ptr, var_type) = self.locals[*var_id];
+                                    let field_ptr = LLVMBuildStructGEP2(self.builder, variant_type, payload_ptr, *idx as u32, NO_NAME);
+                                    let field = LLVMBuildLoad2(self.builder, var_type, field_ptr, NO_NAME);
+                                    LLVMBuildStore(self.builder, field, var_ptr);
+                                }
+                            }
+
+                            self.compile_match_body(body, end_bb, &mut incoming)?;
+                        },
+                        CheckedMatchCase::Fallback(body) => {
+                            self.start_block(default_bb);
+                            self.compile_match_body(body, end_bb, &mut incoming)?;
+                            break;
+                        },
+                        CheckedMatchCase::Expr(_, _) => unreachable!("Enums are only matched by variant"),
+                    }
+                }
+
+                // The typechecker made sure every variant has an arm
+                if !has_fallback {
+                    self.start_block(default_bb);
+                    LLVMBuildUnreachable(self.builder);
+                }
+            } else {
+                let mut has_fallback = false;
+
+                for case in cases {
+                    match case {
+                        CheckedMatchCase::Expr(case_expr, body) => {
+                            let case_value = self.compile_expression(case_expr)?;
+                            let is_match = self.build_binary_operation(matched_value, &BinaryOperator::EQ, case_value, matched_type);
+
+                            let arm_bb = self.append_block(c"match.arm");
+                            let next_bb = self.append_block(c"match.next");
+                            LLVMBuildCondBr(self.builder, is_match, arm_bb, next_bb);
+
+                            self.start_block(arm_bb);
+                            self.compile_match_body(body, end_bb, &mut incoming)?;
+
+                            self.start_block(next_bb);
+                        },
+                        CheckedMatchCase::Fallback(body) => {
+                            self.compile_match_body(body, end_bb, &mut incoming)?;
+                            has_fallback = true;
+                            break;
+                        },
+                        CheckedMatchCase::EnumVariant(_, _, _) => unreachable!("Only enums are matched by variant"),
+                    }
+                }
+
+                // Only a `bool` with both values matched gets here
+                if !has_fallback {
+                    LLVMBuildUnreachable(self.builder);
+                }
+            }
+
+            self.start_block(end_bb);
+        }
+
+        Ok(self.build_result_phi(result_type, incoming))
+    }
+
+    fn compile_if_expression(
+        &mut self,
+        cond: &CheckedExpression,
+        then_body: &CheckedMatchBody,
+        else_body: &CheckedMatchBody,
+        result_type: TypeID
+    ) -> Result<LLVMValueRef, String> {
+        let cond_value = self.compile_expression(cond)?;
+
+        let then_bb = self.append_block(c"if.then");
+        let else_bb = self.append_block(c"if.else");
+        let end_bb = self.append_block(c"if.end");
+        let mut incoming = Vec::new();
+
+        unsafe {
+            LLVMBuildCondBr(self.builder, cond_value, then_bb, else_bb);
+        }
+
+        self.start_block(then_bb);
+        self.compile_match_body(then_body, end_bb, &mut incoming)?;
+
+        self.start_block(else_bb);
+        self.compile_match_body(else_body, end_bb, &mut incoming)?;
+
+        self.start_block(end_bb);
+
+        Ok(self.build_result_phi(result_type, incoming))
+    }
+
+    /// Joins the values that the arms of a `match`, or the branches of an
+    /// `if`, gave on their way to the block the builder is at.
+    fn build_result_phi(&mut self, result_type: TypeID, incoming: Vec<(LLVMValueRef, LLVMBasicBlockRef)>) -> LLVMValueRef {
+        if result_type == self.builtins.nothing {
+            return std::ptr::null_mut();
+        }
+
+        let result_llvm_type = self.llvm_type(result_type);
+
+        unsafe {
+            // Every arm returned, so the end is never reached
+            if incoming.is_empty() {
+                return LLVMGetUndef(result_llvm_type);
+            }
+
+            let phi = LLVMBuildPhi(self.builder, result_llvm_type, NO_NAME);
+            let (mut values, mut blocks): (Vec<_>, Vec<_>) = incoming.into_iter().unzip();
+            LLVMAddIncoming(phi, values.as_mut_ptr(), blocks.as_mut_ptr(), values.len() as u32);
+
+            phi
+        }
+    }
+
+    /// Compiles the body of a match arm and jumps to `end_bb`, noting the
+    /// value it gives and the block it comes from in `incoming`.
+    fn compile_match_body(
+        &mut self,
+        body: &CheckedMatchBody,
+        end_bb: LLVMBasicBlockRef,
+        incoming: &mut Vec<(LLVMValueRef, LLVMBasicBlockRef)>
+    ) -> Result<(), String> {
+        match body {
+            CheckedMatchBody::Expr(body_expr) => {
+                let value = self.compile_expression(body_expr)?;
+
+                if !value.is_null() && !self.current_block_is_terminated() {
+                    incoming.push((value, unsafe { LLVMGetInsertBlock(self.builder) }));
+                }
+            },
+            CheckedMatchBody::Block(block) => self.compile_block(block)?,
+        }
+
+        self.branch_if_unterminated(end_bb);
+
+        Ok(())
+    }
+
+    fn compile_unary_operation(&mut self, operand: &CheckedExpression, op: &UnaryOperator, type_id: TypeID) -> Result<LLVMValueRef, String> {
+        let operand_type = self.type_of(operand);
+
+        if let UnaryOperator::TypeCast(_) = op {
+            let value = self.compile_expression(operand)?;
+            return Ok(self.build_cast(value, operand_type, type_id));
+        }
+
+        unsafe {
+            let value = match op {
+                UnaryOperator::Negate if self.is_float(type_id) => LLVMBuildFNeg(self.builder, self.compile_expression(operand)?, NO_NAME),
+                UnaryOperator::Negate => LLVMBuildNeg(self.builder, self.compile_expression(operand)?, NO_NAME),
+                // `not` on an i1 is a logical not
+                UnaryOperator::LogicalNot |
+                UnaryOperator::BitwiseNot => LLVMBuildNot(self.builder, self.compile_expression(operand)?, NO_NAME),
+                UnaryOperator::PreIncrement  |
+                UnaryOperator::PostIncrement |
+                UnaryOperator::PreDecrement  |
+                UnaryOperator::PostDecrement => {
+                    let (ptr, var_type) = self.compile_place(operand)?;
+                    let old_value = LLVMBuildLoad2(self.builder, var_type, ptr, NO_NAME);
+
+                    let is_increment = matches!(op, UnaryOperator::PreIncrement | UnaryOperator::PostIncrement);
+                    let new_value = match (self.is_float(type_id), is_increment) {
+                        _ if self.is_pointer(type_id) => {
+                            let mut offset = [LLVMConstInt(LLVMInt64Type(), if is_increment { 1 } else { -1i64 as u64 }, 1)];
+                            LLVMBuildGEP2(self.builder, self.pointee_type(type_id), old_value, offset.as_mut_ptr(), 1, NO_NAME)
+                        },
+                        (true, true) => LLVMBuildFAdd(self.builder, old_value, LLVMConstReal(var_type, 1.0), NO_NAME),
+                        (true, false) => LLVMBuildFSub(self.builder, old_value, LLVMConstReal(var_type, 1.0), NO_NAME),
+                        (false, true) => LLVMBuildAdd(self.builder, old_value, LLVMConstInt(var_type, 1, 0), NO_NAME),
+                        (false, false) => LLVMBuildSub(self.builder, old_value, LLVMConstInt(var_type, 1, 0), NO_NAME),
+                    };
+
+                    LLVMBuildStore(self.builder, new_value, ptr);
+
+                    match op {
+                        UnaryOperator::PreIncrement | UnaryOperator::PreDecrement => new_value,
+                        _ => old_value,
+                    }
+                },
+                UnaryOperator::AddressOf => self.compile_place(operand)?.0,
+                UnaryOperator::Dereference => {
+                    let pointee_type = self.pointee_type(operand_type);
+                    LLVMBuildLoad2(self.builder, pointee_type, self.compile_expression(operand)?, NO_NAME)
+                },
+                UnaryOperator::TypeCast(_) => unreachable!(),
+            };
+
+            Ok(value)
+        }
+    }
+
+    /// Converts between any two of the numeric types, `bool` and `char`,
+    /// and between pointers and pointers or integers.
+    fn build_cast(&mut self, value: LLVMValueRef, from: TypeID, to: TypeID) -> LLVMValueRef {
+        if from == to {
+            return value;
+        }
+
+        let to_type = self.llvm_type(to);
+
+        unsafe {
+            match (self.is_pointer(from), self.is_pointer(to)) {
+                (true, true) => return LLVMBuildPointerCast(self.builder, value, to_type, NO_NAME),
+                (true, false) => return LLVMBuildPtrToInt(self.builder, value, to_type, NO_NAME),
+                (false, true) => return LLVMBuildIntToPtr(self.builder, value, to_type, NO_NAME),
+                (false, false) => {},
+            }
+
+            if to == self.builtins.bool {
+                // Anything that isn't zero is true
+                return if self.is_float(from) {
+                    LLVMBuildFCmp(self.builder, LLVMRealPredicate::LLVMRealONE, value, LLVMConstReal(self.llvm_type(from), 0.0), NO_NAME)
+                } else {
+                    LLVMBuildICmp(self.builder, LLVMIntPredicate::LLVMIntNE, value, LLVMConstInt(self.llvm_type(from), 0, 0), NO_NAME)
+                };
+            }
+
+            match (self.is_float(from), self.is_float(to)) {
+                (true, true) => LLVMBuildFPCast(self.builder, value, to_type, NO_NAME),
+                (true, false) => self.build_saturating_float_to_int(value, from, to),
+                (false, true) if self.is_signed(from) => LLVMBuildSIToFP(self.builder, value, to_type, NO_NAME),
+                (false, true) => LLVMBuildUIToFP(self.builder, value, to_type, NO_NAME),
+                (false, false) => LLVMBuildIntCast2(self.builder, value, to_type, self.is_signed(from) as i32, NO_NAME),
+            }
+        }
+    }
+
+    /// Floats that don't fit the integer type end up as its smallest or
+    /// largest value, and NaN as 0, which is what the interpreter does too.
+    unsafe fn build_saturating_float_to_int(&mut self, value: LLVMValueRef, from: TypeID, to: TypeID) -> LLVMValueRef {
+        let (from_type, to_type) = (self.llvm_type(from), self.llvm_type(to));
+        let name = format!("llvm.{}.sat.i{}.f{}", if self.is_signed(to) { "fptosi" } else { "fptoui" }, LLVMGetIntTypeWidth(to_type), if from_type == LLVMFloatType() { 32 } else { 64 });
+        let name = CString::new(name).unwrap();
+
+        let (function, fn_type) = self.libc_function(&name, to_type, &mut [from_type], false);
+        LLVMBuildCall2(self.builder, fn_type, function, [value].as_mut_ptr(), 1, NO_NAME)
+    }
+
+    fn compile_binary_operation(&mut self, lhs: &CheckedExpression, op: &BinaryOperator, rhs: &CheckedExpression) -> Result<LLVMValueRef, String> {
+        let operand_type = self.type_of(lhs);
+
+        if op.is_assignment() {
+            // The target is worked out first, so its indices are only
+            // evaluated once
+            let (ptr, lhs_type) = self.compile_place(lhs)?;
+
+            let value = match op.without_assignment() {
+                Some(bin_op) => {
+                    let lhs_value = unsafe { LLVMBuildLoad2(self.builder, lhs_type, ptr, NO_NAME) };
+                    let rhs_value = self.compile_expression(rhs)?;
+                    match self.is_pointer(operand_type) {
+                        true => self.build_pointer_operation(lhs_value, &bin_op, rhs_value, operand_type, self.type_of(rhs)),
+                        false => self.build_binary_operation(lhs_value, &bin_op, rhs_value, operand_type),
+                    }
+                },
+                None => self.compile_expression(rhs)?,
+            };
+
+            unsafe { LLVMBuildStore(self.builder, value, ptr); }
+
+            return Ok(std::ptr::null_mut());
+        }
+
+        if matches!(op, BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr) {
+            return self.compile_short_circuit(lhs, op, rhs);
+        }
+
+        let lhs_value = self.compile_expression(lhs)?;
+        let rhs_value = self.compile_expression(rhs)?;
+
+        match self.is_pointer(operand_type) {
+            true => Ok(self.build_pointer_operation(lhs_value, op, rhs_value, operand_type, self.type_of(rhs))),
+            false => Ok(self.build_binary_operation(lhs_value, op, rhs_value, operand_type)),
+        }
+    }
+
+    /// Offsetting a pointer moves it by whole elements, and subtracting
+    /// two gives the number of elements between them, like in C.
+    /// Comparisons are the same as for unsigned integers.
+    fn build_pointer_operation(&mut self, lhs: LLVMValueRef, op: &BinaryOperator, rhs: LLVMValueRef, pointer_type: TypeID, rhs_type: TypeID) -> LLVMValueRef {
+        let b = self.builder;
+
+        unsafe {
+            match op {
+                BinaryOperator::Add | BinaryOperator::Subtract if rhs_type != pointer_type => {
+                    let mut offset = LLVMBuildIntCast2(b, rhs, LLVMInt64Type(), self.is_signed(rhs_type) as i32, NO_NAME);
+                    if matches!(op, BinaryOperator::Subtract) {
+                        offset = LLVMBuildNeg(b, offset, NO_NAME);
+                    }
+
+                    let mut offset = [offset];
+                    LLVMBuildGEP2(b, self.pointee_type(pointer_type), lhs, offset.as_mut_ptr(), 1, NO_NAME)
+                },
+                BinaryOperator::Subtract => LLVMBuildPtrDiff2(b, self.pointee_type(pointer_type), lhs, rhs, NO_NAME),
+                _ => self.build_binary_operation(lhs, op, rhs, pointer_type),
+            }
+        }
+    }
+
+    /// Builds the instruction for `lhs op rhs`, picking the signed,
+    /// unsigned or floating point variant based on the operands' type.
+    fn build_binary_operation(&mut self, lhs: LLVMValueRef, op: &BinaryOperator, rhs: LLVMValueRef, operand_type: TypeID) -> LLVMValueRef {
+        let is_float = self.is_float(operand_type);
+        let is_signed = self.is_signed(operand_type);
+        let b = self.builder;
+
+        unsafe {
+            match op {
+                BinaryOperator::Divide |
+                BinaryOperator::Modulo if !is_float => return self.build_integer_division(lhs, op, rhs, is_signed),
+                BinaryOperator::BitwiseLeftShift |
+                BinaryOperator::BitwiseRightShift if self.runtime_checks => self.build_shift_check(rhs, operand_type),
+                _ => {},
+            }
+
+            match op {
+                BinaryOperator::Add if is_float => LLVMBuildFAdd(b, lhs, rhs, NO_NAME),
+                BinaryOperator::Add => LLVMBuildAdd(b, lhs, rhs, NO_NAME),
+                BinaryOperator::Subtract if is_float => LLVMBuildFSub(b, lhs, rhs, NO_NAME),
+                BinaryOperator::Subtract => LLVMBuildSub(b, lhs, rhs, NO_NAME),
+                BinaryOperator::Multiply if is_float => LLVMBuildFMul(b, lhs, rhs, NO_NAME),
+                BinaryOperator::Multiply => LLVMBuildMul(b, lhs, rhs, NO_NAME),
+                BinaryOperator::Divide => LLVMBuildFDiv(b, lhs, rhs, NO_NAME),
+                BinaryOperator::Modulo => LLVMBuildFRem(b, lhs, rhs, NO_NAME),
+
+                BinaryOperator::BitwiseAnd => LLVMBuildAnd(b, lhs, rhs, NO_NAME),
+                BinaryOperator::BitwiseOr => LLVMBuildOr(b, lhs, rhs, NO_NAME),
+                BinaryOperator::BitwiseXOR |
+                BinaryOperator::LogicalXOR => LLVMBuildXor(b, lhs, rhs, NO_NAME),
+                BinaryOperator::BitwiseLeftShift => LLVMBuildShl(b, lhs, rhs, NO_NAME),
+                BinaryOperator::BitwiseRightShift if is_signed => LLVMBuildAShr(b, lhs, rhs, NO_NAME),
+                BinaryOperator::BitwiseRightShift => LLVMBuildLShr(b, lhs, rhs, NO_NAME),
+
+                BinaryOperator::EQ  |
+                BinaryOperator::NEQ |
+                BinaryOperator::LT  |
+                BinaryOperator::GT  |
+                BinaryOperator::LEQ |
+                BinaryOperator::GEQ if is_float => {
+                    let predicate = match op {
+                        BinaryOperator::EQ => LLVMRealPredicate::LLVMRealOEQ,
+                        BinaryOperator::NEQ => LLVMRealPredicate::LLVMRealUNE,
+                        BinaryOperator::LT => LLVMRealPredicate::LLVMRealOLT,
+                        BinaryOperator::GT => LLVMRealPredicate::LLVMRealOGT,
+                        BinaryOperator::LEQ => LLVMRealPredicate::LLVMRealOLE,
+                        _ => LLVMRealPredicate::LLVMRealOGE,
+                    };
+                    LLVMBuildFCmp(b, predicate, lhs, rhs, NO_NAME)
+                },
+                BinaryOperator::EQ  |
+                BinaryOperator::NEQ |
+                BinaryOperator::LT  |
+                BinaryOperator::GT  |
+                BinaryOperator::LEQ |
+                BinaryOperator::GEQ => {
+                    let predicate = match (op, is_signed) {
+                        (BinaryOperator::EQ, _) => LLVMIntPredicate::LLVMIntEQ,
+                        (BinaryOperator::NEQ, _) => LLVMIntPredicate::LLVMIntNE,
+                        (BinaryOperator::LT, true) => LLVMIntPredicate::LLVMIntSLT,
+                        (BinaryOperator::LT, false) => LLVMIntPredicate::LLVMIntULT,
+                        (BinaryOperator::GT, true) => LLVMIntPredicate::LLVMIntSGT,
+                        (BinaryOperator::GT, false) => LLVMIntPredicate::LLVMIntUGT,
+                        (BinaryOperator::LEQ, true) => LLVMIntPredicate::LLVMIntSLE,
+                        (BinaryOperator::LEQ, false) => LLVMIntPredicate::LLVMIntULE,
+                        (_, true) => LLVMIntPredicate::LLVMIntSGE,
+                        (_, false) => LLVMIntPredicate::LLVMIntUGE,
+                    };
+                    LLVMBuildICmp(b, predicate, lhs, rhs, NO_NAME)
+                },
+
+                _ => unreachable!("{:?} has no single instruction", op),
+            }
+        }
+    }
+
+    /// Integer `/` and `%` stop the program when dividing by zero, if runtime
+    /// checks are on. The smallest signed value divided by -1 wraps around to
+    /// itself, where `sdiv` would trap, so -1 divides as 1 and the quotient is
+    /// negated.
+    unsafe fn build_integer_division(&mut self, lhs: LLVMValueRef, op: &BinaryOperator, rhs: LLVMValueRef, is_signed: bool) -> LLVMValueRef {
+        let b = self.builder;
+        let int_type = LLVMTypeOf(rhs);
+
+        if self.runtime_checks {
+            let not_zero = LLVMBuildICmp(b, LLVMIntPredicate::LLVMIntNE, rhs, LLVMConstInt(int_type, 0, 0), NO_NAME);
+            self.build_runtime_check(not_zero, "div", "error: attempt to divide by zero\n", &[]);
+        }
+
+        if !is_signed {
+            return match op {
+                BinaryOperator::Divide => LLVMBuildUDiv(b, lhs, rhs, NO_NAME),
+                _ => LLVMBuildURem(b, lhs, rhs, NO_NAME),
+            };
+        }
+
+        let is_minus_one = LLVMBuildICmp(b, LLVMIntPredicate::LLVMIntEQ, rhs, LLVMConstAllOnes(int_type), NO_NAME);
+        let divisor = LLVMBuildSelect(b, is_minus_one, LLVMConstInt(int_type, 1, 0), rhs, NO_NAME);
+
+        match op {
+            BinaryOperator::Divide => {
+                let quotient = LLVMBuildSDiv(b, lhs, divisor, NO_NAME);
+                LLVMBuildSelect(b, is_minus_one, LLVMBuildNeg(b, quotient, NO_NAME), quotient, NO_NAME)
+            },
+            _ => LLVMBuildSRem(b, lhs, divisor, NO_NAME),
+        }
+    }
+
+    /// Shifting by the width of the type or more stops the program, since
+    /// LLVM leaves what it gives undefined.
+    unsafe fn build_shift_check(&mut self, amount: LLVMValueRef, operand_type: TypeID) {
+        let int_type = LLVMTypeOf(amount);
+        let width = LLVMGetIntTypeWidth(int_type);
+
+        let in_range = LLVMBuildICmp(self.builder, LLVMIntPredicate::LLVMIntULT, amount, LLVMConstInt(int_type, width as u64, 0), NO_NAME);
+        let message = format!("error: attempt to shift a `{}` by %llu, which is more than its width\n", self.primitive(operand_type).unwrap().as_str());
+        let amount = LLVMBuildIntCast2(self.builder, amount, LLVMInt64Type(), 0, NO_NAME);
+        self.build_runtime_check(in_range, "shift", &message, &[amount]);
+    }
+
+    /// `&&` and `||` only evaluate their right side when the left
+    /// one doesn't already decide the result.
+    fn compile_short_circuit(&mut self, lhs: &CheckedExpression, op: &BinaryOperator, rhs: &CheckedExpression) -> Result<LLVMValueRef, String> {
+        let is_and = matches!(op, BinaryOperator::LogicalAnd);
+
+        unsafe {
+            let lhs_value = self.compile_expression(lhs)?;
+            let lhs_block = LLVMGetInsertBlock(self.builder);
+
+            let rhs_block = self.append_block(if is_and { c"and.rhs" } else { c"or.rhs" });
+            let end_block = self.append_block(if is_and { c"and.end" } else { c"or.end" });
+
+            if is_and {
+                LLVMBuildCondBr(self.builder, lhs_value, rhs_block, end_block);
+            } else {
+                LLVMBuildCondBr(self.builder, lhs_value, end_block, rhs_block);
+            }
+
+            self.start_block(rhs_block);
+            let rhs_value = self.compile_expression(rhs)?;
+            let rhs_end_block = LLVMGetInsertBlock(self.builder);
+            LLVMBuildBr(self.builder, end_block);
+
+            self.start_block(end_block);
+            let phi = LLVMBuildPhi(self.builder, LLVMInt1Type(), NO_NAME);
+
+            // Skipping the right side means `&&` is false and `||` is true
+            let mut values = [LLVMConstInt(LLVMInt1Type(), !is_and as u64, 0), rhs_value];
+            let mut blocks = [lhs_block, rhs_end_block];
+            LLVMAddIncoming(phi, values.as_mut_ptr(), blocks.as_mut_ptr(), 2);
+
+            Ok(phi)
+        }
     }
 
-    pub fn compile_proc_decl(&mut self, proc_decl: &ParsedProcDecl) {
+    fn type_of(&self, expr: &CheckedExpression) -> TypeID {
+        expr.type_id(&self.builtins)
     }
 }
 
 impl Drop for Module {
     fn drop(&mut self) {
         unsafe {
+            LLVMDisposeBuilder(self.builder);
             LLVMDisposeModule(self.llvm_mod);
         }
     }
@@ -1,5 +1,33 @@
 mod llvm;
 
+pub use llvm::{ Module, TargetMachine };
+
+use crate::checked_ast::CheckedModule;
+
 pub fn init() {
     llvm::init_llvm();
 }
+
+/// Lowers a typechecked module into an LLVM module. `runtime_checks` is
+/// whether array indices, divisors and shift amounts are checked at
+/// runtime.
+pub fn generate_module(checked_module: &CheckedModule, target: &TargetMachine, runtime_checks: bool) -> Result<Module, String> {
+    let mut module = Module::new(&checked_module.name, checked_module.types.clone(), checked_module.builtins, runtime_checks);
+    module.set_target(target);
+
+    for proc_decl in &checked_module.procs {
+        module.add_proc_decl(proc_decl);
+    }
+
+    for proc_decl in checked_module.procs.iter().filter(|proc_decl| proc_decl.foreign.is_none() && proc_decl.intrinsic.is_none()) {
+        module.compile_proc_decl(proc_decl)?;
+    }
+
+    module.verify()?;
+
+    if checked_module.procs.iter().any(|proc_decl| proc_decl.inline) {
+        module.inline_calls();
+    }
+
+    Ok(module)
+}
@@ -0,0 +1,363 @@
+use std::collections::{ HashMap, HashSet };
+
+use crate::ast::{ BinaryOperator, DirectiveKind, UnaryOperator };
+use crate::bytecode::{ self, Limits, Program, Vm };
+use crate::checked_ast::*;
+use crate::diagnostic::{ Diagnostic, DiagnosticSink };
+use crate::token::{ PrimitiveType, Span };
+use crate::typechecker::TypeID;
+
+/// The constants and procs an expression refers to directly.
+#[derive(Default)]
+struct Uses {
+    consts: Vec<(ConstID, Span)>,
+    procs: Vec<String>,
+}
+
+impl Uses {
+    fn record(&mut self, expr: &CheckedExpression) {
+        match expr {
+            CheckedExpression::Const(const_id, _, span) => self.consts.push((*const_id, *span)),
+            CheckedExpression::ProcCall(call, _, _) => self.procs.push(call.name.clone()),
+            _ => { }
+        }
+    }
+}
+
+/// Works out the value of every constant in `checked_module` by running
+/// it on the bytecode VM, and replaces each use of a constant with its
+/// value. Constants that depend on themselves, or that can't be evaluated,
+/// are reported to `diagnostics` and leave the module as it is.
+pub fn evaluate_consts(checked_module: &mut CheckedModule, diagnostics: &DiagnosticSink) {
+    if checked_module.consts.is_empty() {
+        return;
+    }
+
+    let const_uses: Vec<Uses> = checked_module.consts.iter_mut().map(|const_decl| {
+        let mut uses = Uses::default();
+        const_decl.value.visit_mut(&mut |expr| uses.record(expr));
+        uses
+    }).collect();
+
+    let proc_uses = proc_uses(checked_module);
+
+    // Every constant a constant needs to be evaluated, whether it uses it
+    // directly or through the procs it calls, and every proc it might run.
+    let mut depends_on: Vec<Vec<(ConstID, Span)>> = Vec::new();
+    let mut needed_procs: HashSet<&str> = HashSet::new();
+
+    for uses in &const_uses {
+        let (consts, visited) = reach(uses, &proc_uses);
+        depends_on.push(consts);
+        needed_procs.extend(visited);
+    }
+
+    if has_cycles(checked_module, &depends_on, diagnostics) {
+        return;
+    }
+
+    // Only what the constants can reach is compiled, so `#foreign` procs,
+    // which the VM can't call, only matter if a constant calls them.
+    let reachable = CheckedModule {
+        name: checked_module.name.clone(),
+        types: checked_module.types.clone(),
+        builtins: checked_module.builtins,
+        procs: checked_module.procs.iter()
+                                   .filter(|proc_decl| needed_procs.contains(proc_decl.name.as_str()))
+                                   .cloned()
+                                   .collect(),
+        consts: checked_module.consts.clone(),
+        directives: Vec::new(),
+    };
+
+    let program = match bytecode::compile_module(&reachable) {
+        Ok(program) => program,
+        Err(message) => {
+            let first = &checked_module.consts[0];
+            diagnostics.emit(Diagnostic::error("can't evaluate constants at compile time", first.span)
+                                        .with_note(message));
+            return;
+        }
+    };
+
+    let mut values = Vec::new();
+
+    for (const_id, const_decl) in checked_module.consts.iter().enumerate() {
+        // Each constant gets a fresh VM, so they all get the same budget
+        let mut vm = Vm::new(&program, Limits::default());
+
+        // Constants are all primitives, so they take up one slot
+        match vm.call_proc(reachable.procs.len() + const_id, &[]) {
+            Ok(value) => values.push(literal(checked_module, &program, value[0], const_decl.type_id, const_decl.span)),
+            Err(diagnostic) => {
+                diagnostics.emit(diagnostic.with_label(const_decl.span, format!("while evaluating `{}`", const_decl.name)));
+            },
+        }
+    }
+
+    if values.len() != checked_module.consts.len() {
+        return;
+    }
+
+    // The VM wraps around like the program would, but a constant that's
+    // just arithmetic on literals and other constants has to fit its type
+    for const_decl in &checked_module.consts {
+        if let Err(Overflow(value, primitive, span)) = fold_exact(checked_module, &const_decl.value, &values) {
+            let (min, max) = integer_range(&primitive);
+            diagnostics.emit(Diagnostic::error(format!("the value of `{}` overflows `{}`", const_decl.name, primitive.as_str()), span)
+                                        .with_primary_label(format!("this is {}", value))
+                                        .with_note(format!("`{}` goes from {} to {}", primitive.as_str(), min, max)));
+        }
+    }
+
+    let mut fold = |expr: &mut CheckedExpression| {
+        if let CheckedExpression::Const(const_id, _, span) = expr {
+            *expr = with_span(&values[*const_id], *span);
+        }
+    };
+
+    for proc_decl in &mut checked_module.procs {
+        proc_decl.body.visit_mut(&mut fold);
+    }
+
+    for directive in &mut checked_module.directives {
+        directive.value.visit_mut(&mut fold);
+    }
+
+    for (const_decl, value) in checked_module.consts.iter_mut().zip(&values) {
+        const_decl.value = value.clone();
+    }
+}
+
+/// An operation in a constant whose exact result doesn't fit its type.
+struct Overflow(i128, PrimitiveType, Span);
+
+/// The exact value of `expr` if it's made of integer literals, constants
+/// and arithmetic on them, checking that every step fits its type. It's
+/// `Ok(None)` for anything else, like casts, bitwise not and shifts, which
+/// are meant to drop bits.
+fn fold_exact(checked_module: &CheckedModule, expr: &CheckedExpression, values: &[CheckedExpression]) -> Result<Option<i128>, Overflow> {
+    let Some(primitive) = checked_module.type_info(checked_module.type_of(expr)).primitive().filter(|primitive| primitive.is_integer()) else {
+        return Ok(None);
+    };
+
+    let value = match expr {
+        CheckedExpression::Integer(bits, _, _) if primitive.is_signed() => Some(primitive.sign_extend(*bits) as i128),
+        CheckedExpression::Integer(bits, _, _) => Some(*bits as i128),
+        CheckedExpression::Const(const_id, _, _) => return fold_exact(checked_module, &values[*const_id], values),
+        CheckedExpression::UnaryOperation(operand, UnaryOperator::Negate, _, _) => {
+            fold_exact(checked_module, operand, values)?.map(|operand| -operand)
+        },
+        CheckedExpression::BinaryOperation(lhs, op, rhs, _, _) => {
+            let (Some(lhs), Some(rhs)) = (fold_exact(checked_module, lhs, values)?, fold_exact(checked_module, rhs, values)?) else {
+                return Ok(None);
+            };
+
+            match op {
+                BinaryOperator::Add => Some(lhs + rhs),
+                BinaryOperator::Subtract => Some(lhs - rhs),
+                BinaryOperator::Multiply => Some(lhs * rhs),
+                BinaryOperator::Divide => lhs.checked_div(rhs),
+                BinaryOperator::Modulo => lhs.checked_rem(rhs),
+                BinaryOperator::BitwiseAnd => Some(lhs & rhs),
+                BinaryOperator::BitwiseOr => Some(lhs | rhs),
+                BinaryOperator::BitwiseXOR => Some(lhs ^ rhs),
+                _ => None,
+            }
+        },
+        _ => None,
+    };
+
+    let (min, max) = integer_range(primitive);
+    match value {
+        Some(value) if value < min || value > max => Err(Overflow(value, primitive.clone(), expr.span())),
+        _ => Ok(value),
+    }
+}
+
+fn integer_range(primitive: &PrimitiveType) -> (i128, i128) {
+    let bits = primitive.bit_width();
+    match primitive.is_signed() {
+        true => (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1),
+        false => (0, (1i128 << bits) - 1),
+    }
+}
+
+/// Runs every `#assert` and `#run` in `checked_module` on the bytecode VM,
+/// in the order they're declared in. Constants have to have been evaluated
+/// already. Assertions that don't hold are reported to `diagnostics`.
+pub fn run_directives(checked_module: &mut CheckedModule, diagnostics: &DiagnosticSink) {
+    if checked_module.directives.is_empty() {
+        return;
+    }
+
+    let proc_uses = proc_uses(checked_module);
+    let mut needed_procs: HashSet<&str> = HashSet::new();
+
+    for directive in &mut checked_module.directives {
+        let mut uses = Uses::default();
+        directive.value.visit_mut(&mut |expr| uses.record(expr));
+        needed_procs.extend(reach(&uses, &proc_uses).1);
+    }
+
+    // Every directive is compiled like a constant, so it becomes a proc
+    // that gives back its value
+    let reachable = CheckedModule {
+        name: checked_module.name.clone(),
+        types: checked_module.types.clone(),
+        builtins: checked_module.builtins,
+        procs: checked_module.procs.iter()
+                                   .filter(|proc_decl| needed_procs.contains(proc_decl.name.as_str()))
+                                   .cloned()
+                                   .collect(),
+        consts: checked_module.directives.iter().map(|directive| CheckedConstDecl {
+            name: String::from(directive.kind.name()),
+            span: directive.span,
+            type_id: directive.type_id,
+            locals: directive.locals.clone(),
+            value: directive.value.clone(),
+        }).collect(),
+        directives: Vec::new(),
+    };
+
+    let program = match bytecode::compile_module(&reachable) {
+        Ok(program) => program,
+        Err(message) => {
+            let first = &checked_module.directives[0];
+            diagnostics.emit(Diagnostic::error(format!("can't run `{}` at compile time", first.kind.name()), first.span)
+                                        .with_note(message));
+            return;
+        }
+    };
+
+    for (idx, directive) in checked_module.directives.iter().enumerate() {
+        let mut vm = Vm::new(&program, Limits::default());
+
+        match (vm.call_proc(reachable.procs.len() + idx, &[]), &directive.kind) {
+            (Ok(value), DirectiveKind::Assert(message)) if value == [0] => {
+                let mut diagnostic = Diagnostic::error("compile-time assertion failed", directive.span);
+                if let Some(message) = message {
+                    diagnostic = diagnostic.with_note(message.clone());
+                }
+
+                diagnostics.emit(diagnostic);
+            },
+            (Ok(_), _) => { },
+            (Err(diagnostic), kind) => {
+                diagnostics.emit(diagnostic.with_label(directive.span, format!("while running `{}`", kind.name())));
+            },
+        }
+    }
+}
+
+/// What each proc in `checked_module` uses, by name.
+fn proc_uses(checked_module: &mut CheckedModule) -> HashMap<String, Uses> {
+    checked_module.procs.iter_mut().map(|proc_decl| {
+        let mut uses = Uses::default();
+        proc_decl.body.visit_mut(&mut |expr| uses.record(expr));
+        (proc_decl.name.clone(), uses)
+    }).collect()
+}
+
+/// Follows `uses` through every proc it calls, directly or not. Gives back
+/// every constant used along the way, and the procs that were called.
+fn reach<'a>(uses: &Uses, proc_uses: &'a HashMap<String, Uses>) -> (Vec<(ConstID, Span)>, HashSet<&'a str>) {
+    let mut consts = uses.consts.clone();
+    let mut visited: HashSet<&str> = HashSet::new();
+    let mut pending: Vec<&str> = uses.procs.iter().map(String::as_str).collect();
+
+    while let Some(name) = pending.pop() {
+        let (name, uses) = proc_uses.get_key_value(name).expect("Calls only make it past the typechecker if the proc exists");
+
+        if visited.insert(name) {
+            consts.extend_from_slice(&uses.consts);
+            pending.extend(uses.procs.iter().map(String::as_str));
+        }
+    }
+
+    (consts, visited)
+}
+
+/// Reports every constant that needs its own value to be evaluated. Gives
+/// back whether there were any.
+fn has_cycles(checked_module: &CheckedModule, depends_on: &[Vec<(ConstID, Span)>], diagnostics: &DiagnosticSink) -> bool {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Visit { Unvisited, InProgress, Done }
+
+    fn visit(
+        checked_module: &CheckedModule,
+        id: ConstID,
+        depends_on: &[Vec<(ConstID, Span)>],
+        diagnostics: &DiagnosticSink,
+        visits: &mut [Visit],
+        path: &mut Vec<(ConstID, Span)>,
+    ) -> bool {
+        let mut found_cycle = false;
+        visits[id] = Visit::InProgress;
+
+        for (dependency, use_span) in &depends_on[id] {
+            path.push((id, *use_span));
+
+            match visits[*dependency] {
+                Visit::Unvisited => found_cycle |= visit(checked_module, *dependency, depends_on, diagnostics, visits, path),
+                Visit::InProgress => {
+                    // Everything on the path from `dependency` onwards is the cycle
+                    let cycle_start = path.iter().position(|(on_path, _)| on_path == dependency).unwrap();
+                    let const_decl = &checked_module.consts[*dependency];
+
+                    let mut diagnostic = Diagnostic::error(format!("the value of `{}` depends on itself", const_decl.name), const_decl.span);
+                    for (on_path, span) in &path[cycle_start..] {
+                        diagnostic = diagnostic.with_label(*span, format!("used while evaluating `{}`", checked_module.consts[*on_path].name));
+                    }
+
+                    diagnostics.emit(diagnostic.with_note("constants are evaluated before the program runs, so they can't be defined in terms of themselves"));
+                    found_cycle = true;
+                },
+                Visit::Done => { },
+            }
+
+            path.pop();
+        }
+
+        visits[id] = Visit::Done;
+        found_cycle
+    }
+
+    let mut visits = vec![Visit::Unvisited; depends_on.len()];
+    let mut found_cycle = false;
+
+    for id in 0..depends_on.len() {
+        if visits[id] == Visit::Unvisited {
+            found_cycle |= visit(checked_module, id, depends_on, diagnostics, &mut visits, &mut Vec::new());
+        }
+    }
+
+    found_cycle
+}
+
+/// Turns the raw bits the VM gave back into a literal of type `type_id`.
+fn literal(checked_module: &CheckedModule, program: &Program, bits: u64, type_id: TypeID, span: Span) -> CheckedExpression {
+    let Some(primitive) = checked_module.type_info(type_id).primitive() else {
+        unreachable!("Only primitive constants make it past the typechecker");
+    };
+
+    match primitive {
+        PrimitiveType::Bool => CheckedExpression::Bool(bits != 0, span),
+        PrimitiveType::Char => CheckedExpression::CharLiteral(bits as u32, span),
+        PrimitiveType::String => CheckedExpression::StringLiteral(program.strings[bits as usize].clone(), span),
+        PrimitiveType::F32 | PrimitiveType::F64 => CheckedExpression::Float(f64::from_bits(bits), type_id, span),
+        _ => CheckedExpression::Integer(bits, type_id, span),
+    }
+}
+
+/// A copy of `literal` that points at `span` instead.
+fn with_span(literal: &CheckedExpression, span: Span) -> CheckedExpression {
+    match literal {
+        CheckedExpression::Bool(value, _) => CheckedExpression::Bool(*value, span),
+        CheckedExpression::CharLiteral(value, _) => CheckedExpression::CharLiteral(*value, span),
+        CheckedExpression::StringLiteral(value, _) => CheckedExpression::StringLiteral(value.clone(), span),
+        CheckedExpression::Float(value, type_id, _) => CheckedExpression::Float(*value, *type_id, span),
+        CheckedExpression::Integer(value, type_id, _) => CheckedExpression::Integer(*value, *type_id, span),
+        _ => unreachable!("{:?} isn't a literal", literal),
+    }
+}
@@ -0,0 +1,305 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use unicode_width::UnicodeWidthChar;
+
+use crate::source_map::SourceMap;
+use crate::token::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// A secondary span that gets pointed at alongside the
+/// primary one, with a message explaining why it matters.
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// Something the compiler has to tell the user about the
+/// code it was given, usually an error.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    /// What's written next to the carets under the primary span. It's a
+    /// `Box<str>` to keep the `Result`s that carry diagnostics small.
+    pub primary_label: Option<Box<str>>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity,
+            message: message.into(),
+            span,
+            primary_label: None,
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Self::new(Severity::Error, message, span)
+    }
+
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Self::new(Severity::Warning, message, span)
+    }
+
+    pub fn with_primary_label(mut self, message: impl Into<String>) -> Self {
+        self.primary_label = Some(message.into().into_boxed_str());
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span, message: message.into() });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const BLUE: &str = "\x1b[1;34m";
+
+const TAB_WIDTH: usize = 4;
+
+/// One span that should be underlined in a rendered snippet.
+struct Annotation<'a> {
+    span: Span,
+    message: Option<&'a str>,
+    is_primary: bool,
+}
+
+impl Diagnostic {
+    /// Renders the diagnostic the way rustc does: a header, the source
+    /// lines the spans point into, and carets under the spans themselves.
+    pub fn render(&self, source_map: &SourceMap, color: bool) -> String {
+        let paint = |style: &str, text: &str| -> String {
+            if color { format!("{}{}{}", style, text, RESET) } else { String::from(text) }
+        };
+
+        let severity_style = match self.severity {
+            Severity::Error => "\x1b[1;31m",
+            Severity::Warning => "\x1b[1;33m",
+        };
+
+        let mut annotations = vec![Annotation { span: self.span, message: self.primary_label.as_deref(), is_primary: true }];
+        annotations.extend(self.labels.iter().map(|label| Annotation {
+            span: label.span,
+            message: Some(label.message.as_str()),
+            is_primary: false,
+        }));
+
+        let gutter_width = annotations.iter()
+                                      .map(|ann| source_map.location(ann.span).line.to_string().len())
+                                      .max()
+                                      .unwrap_or(1);
+        let gutter = " ".repeat(gutter_width);
+        let pipe = paint(BLUE, "|");
+
+        let mut out = String::new();
+        out += &format!("{}{}\n", paint(severity_style, &format!("{}:", self.severity.as_str())), paint(BOLD, &format!(" {}", self.message)));
+
+        // Group the annotations by file (the primary span's file first) and
+        // then by line, so each source line is only printed once.
+        let mut files: Vec<u64> = Vec::new();
+        for ann in &annotations {
+            if !files.contains(&ann.span.file_id) {
+                files.push(ann.span.file_id);
+            }
+        }
+
+        for file_id in files {
+            let file = source_map.file(file_id);
+
+            let mut file_annotations: Vec<&Annotation> = annotations.iter()
+                                                                    .filter(|ann| ann.span.file_id == file_id)
+                                                                    .collect();
+            file_annotations.sort_by_key(|ann| (ann.span.start, !ann.is_primary));
+
+            let arrow_loc = if file_id == self.span.file_id {
+                file.location(self.span.start)
+            } else {
+                file.location(file_annotations[0].span.start)
+            };
+
+            out += &format!("{}{} {}:{}:{}\n", gutter, paint(BLUE, "-->"), file.path.display(), arrow_loc.line, arrow_loc.column);
+            out += &format!("{} {}\n", gutter, pipe);
+
+            // Annotations are sorted by where they start, so their lines are too
+            let mut lines: Vec<usize> = file_annotations.iter().map(|ann| file.location(ann.span.start).line).collect();
+            lines.dedup();
+
+            let mut last_line: Option<usize> = None;
+
+            for line in lines {
+                if let Some(last_line) = last_line {
+                    if line > last_line + 1 {
+                        out += &format!("{}\n", paint(BLUE, "..."));
+                    }
+                }
+                last_line = Some(line);
+
+                let line_text = file.line_text(line);
+                let line_number = format!("{:>width$}", line, width = gutter_width);
+                out += &format!("{} {} {}\n", paint(BLUE, &line_number), pipe, expand_tabs(line_text));
+
+                // The columns each annotation on the line covers. Spans that
+                // go past the line are underlined to its end.
+                let marks: Vec<(&Annotation, usize, usize)> = file_annotations.iter().filter_map(|ann| {
+                    let start = file.location(ann.span.start);
+                    let end = file.location(ann.span.end.max(ann.span.start));
+                    if start.line != line {
+                        return None;
+                    }
+
+                    let from = display_width(line_text, start.column - 1);
+                    let to = if end.line == line {
+                        display_width(line_text, end.column - 1)
+                    } else {
+                        display_width(line_text, line_text.chars().count())
+                    };
+                    Some((*ann, from, to.max(from + 1)))
+                }).collect();
+
+                let style_of = |ann: &Annotation| if ann.is_primary { severity_style } else { BLUE };
+                let width = marks.iter().map(|(_, _, to)| *to).max().unwrap_or(0);
+
+                // All the underlines share one row. Narrower spans are drawn
+                // over wider ones so every span keeps some of its marks, and
+                // the primary span wins when they're the same.
+                let mut by_width: Vec<usize> = (0..marks.len()).collect();
+                by_width.sort_by_key(|idx| {
+                    let (ann, from, to) = marks[*idx];
+                    (std::cmp::Reverse(to - from), ann.is_primary)
+                });
+
+                // Which annotation's mark goes in each column
+                let mut cells: Vec<Option<usize>> = vec![None; width];
+                for idx in by_width {
+                    let (_, from, to) = marks[idx];
+                    cells[from..to].iter_mut().for_each(|cell| *cell = Some(idx));
+                }
+
+                let mut underline = String::new();
+                for run in cells.chunk_by(|a, b| a == b) {
+                    underline += &match run[0] {
+                        Some(idx) => {
+                            let ann = marks[idx].0;
+                            paint(style_of(ann), &(if ann.is_primary { "^" } else { "-" }).repeat(run.len()))
+                        },
+                        None => " ".repeat(run.len()),
+                    };
+                }
+
+                // The rightmost message goes after the underlines if its span
+                // reaches their end, and the others hang below their spans
+                let mut hanging: Vec<&(&Annotation, usize, usize)> = marks.iter().filter(|(ann, _, _)| ann.message.is_some()).collect();
+                if let Some((ann, _, _)) = hanging.last().filter(|(_, _, to)| *to == width) {
+                    underline += &format!(" {}", paint(style_of(ann), ann.message.unwrap_or_default()));
+                    hanging.pop();
+                }
+                out += &format!("{} {} {}\n", gutter, pipe, underline);
+
+                let connectors = |hanging: &[&(&Annotation, usize, usize)]| {
+                    let mut row = String::new();
+                    let mut column = 0;
+                    for (ann, from, _) in hanging {
+                        row += &" ".repeat(from.saturating_sub(column));
+                        row += &paint(style_of(ann), "|");
+                        column = from + 1;
+                    }
+                    (row, column)
+                };
+
+                if !hanging.is_empty() {
+                    out += &format!("{} {} {}\n", gutter, pipe, connectors(&hanging).0);
+                }
+
+                while let Some((ann, from, _)) = hanging.pop() {
+                    let (mut row, column) = connectors(&hanging);
+                    row += &" ".repeat(from.saturating_sub(column));
+                    row += &paint(style_of(ann), ann.message.unwrap_or_default());
+                    out += &format!("{} {} {}\n", gutter, pipe, row);
+                }
+            }
+        }
+
+        for note in &self.notes {
+            out += &format!("{} {} {} {}\n", gutter, paint(BLUE, "="), paint(BOLD, "note:"), note);
+        }
+
+        out
+    }
+}
+
+/// How many columns the first `chars` characters of `line` take up in a
+/// terminal once tabs are expanded. Most CJK characters and emoji take up
+/// two, and combining marks none.
+fn display_width(line: &str, chars: usize) -> usize {
+    line.chars()
+        .take(chars)
+        .map(|c| if c == '\t' { TAB_WIDTH } else { c.width().unwrap_or(0) })
+        .sum()
+}
+
+fn expand_tabs(line: &str) -> String {
+    line.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+/// Where every stage of the compiler puts its diagnostics. Cloning
+/// the sink hands out another handle to the same list, so the
+/// tokenizer, parser, etc. can all report into one place.
+#[derive(Clone, Default)]
+pub struct DiagnosticSink {
+    diagnostics: Rc<RefCell<Vec<Diagnostic>>>,
+}
+
+impl DiagnosticSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn emit(&self, diagnostic: Diagnostic) {
+        self.diagnostics.borrow_mut().push(diagnostic);
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.error_count() > 0
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.diagnostics.borrow().iter().filter(|diag| diag.is_error()).count()
+    }
+
+    /// Removes and returns everything reported so far.
+    pub fn take(&self) -> Vec<Diagnostic> {
+        std::mem::take(&mut *self.diagnostics.borrow_mut())
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+
+use crate::ast::{ BinaryOperator, ParsedExpression, UnaryOperator };
+use crate::diagnostic::Diagnostic;
+use crate::token::{ IntegerLiteralFormat, NumericConstant, Span };
+
+/// A value an `#if` condition works with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConditionValue {
+    Bool(bool),
+    Integer(i64),
+    String(String),
+}
+
+impl ConditionValue {
+    /// Reads the value given to a `-D NAME=value`, which is a `bool` or
+    /// an integer if it looks like one and a string otherwise.
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "true" => ConditionValue::Bool(true),
+            "false" => ConditionValue::Bool(false),
+            _ => match value.parse() {
+                Ok(value) => ConditionValue::Integer(value),
+                Err(_) => ConditionValue::String(String::from(value)),
+            }
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            ConditionValue::Bool(_) => "bool",
+            ConditionValue::Integer(_) => "integer",
+            ConditionValue::String(_) => "string",
+        }
+    }
+}
+
+impl fmt::Display for ConditionValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConditionValue::Bool(value) => write!(f, "{}", value),
+            ConditionValue::Integer(value) => write!(f, "{}", value),
+            ConditionValue::String(value) => write!(f, "{:?}", value),
+        }
+    }
+}
+
+/// What `#if` conditions can ask about the build: the `OS` and `ARCH` of
+/// the target, and anything defined with `-D` on the command line.
+pub struct BuildConfig {
+    pub os: String,
+    pub arch: String,
+    pub defines: HashMap<String, ConditionValue>,
+}
+
+impl BuildConfig {
+    /// The config for building for `target`, or the host if there isn't one.
+    pub fn new(target: Option<&str>, defines: HashMap<String, ConditionValue>) -> Self {
+        let (os, arch) = match target {
+            Some(triple) => (os_of_triple(triple), arch_of_triple(triple)),
+            None => (String::from(env::consts::OS), String::from(env::consts::ARCH)),
+        };
+
+        Self { os, arch, defines }
+    }
+
+    fn lookup(&self, name: &str) -> Option<ConditionValue> {
+        match name {
+            "OS" => Some(ConditionValue::String(self.os.clone())),
+            "ARCH" => Some(ConditionValue::String(self.arch.clone())),
+            _ => self.defines.get(name).cloned(),
+        }
+    }
+
+    /// Works out whether the condition of an `#if` holds.
+    pub fn eval_condition(&self, cond: &ParsedExpression) -> Result<bool, Diagnostic> {
+        match self.eval(cond)? {
+            ConditionValue::Bool(value) => Ok(value),
+            value => Err(Diagnostic::error(format!("the condition of an `#if` has to be a bool, but this is the {} {}", value.kind(), value), cond.span())),
+        }
+    }
+
+    fn eval(&self, expr: &ParsedExpression) -> Result<ConditionValue, Diagnostic> {
+        match expr {
+            ParsedExpression::Bool(value, _) => Ok(ConditionValue::Bool(*value)),
+            ParsedExpression::StringLiteral(value, _) => Ok(ConditionValue::String(value.clone())),
+            ParsedExpression::NumericConstant(NumericConstant::Integer(digits, format, _), span) => {
+                let radix = match format {
+                    IntegerLiteralFormat::Binary => 2,
+                    IntegerLiteralFormat::Octal => 8,
+                    IntegerLiteralFormat::Decimal => 10,
+                    IntegerLiteralFormat::Hexadecimal => 16,
+                };
+
+                i64::from_str_radix(digits, radix)
+                    .map(ConditionValue::Integer)
+                    .map_err(|_| Diagnostic::error("integer literal is too large", *span))
+            },
+            // Like in C, names that were never defined are false
+            ParsedExpression::Var(name, _) => Ok(self.lookup(name).unwrap_or(ConditionValue::Bool(false))),
+            ParsedExpression::UnaryOperation(operand, UnaryOperator::LogicalNot, span) => match self.eval(operand)? {
+                ConditionValue::Bool(value) => Ok(ConditionValue::Bool(!value)),
+                value => Err(Diagnostic::error(format!("`!` can't be applied to the {} {}", value.kind(), value), *span)),
+            },
+            ParsedExpression::UnaryOperation(operand, UnaryOperator::Negate, span) => match self.eval(operand)? {
+                ConditionValue::Integer(value) => Ok(ConditionValue::Integer(value.wrapping_neg())),
+                value => Err(Diagnostic::error(format!("`-` can't be applied to the {} {}", value.kind(), value), *span)),
+            },
+            ParsedExpression::BinaryOperation(lhs, op, rhs, span) => self.eval_binary_operation(lhs, op, rhs, *span),
+            _ => Err(Diagnostic::error("this can't be used in the condition of an `#if`", expr.span())
+                                  .with_note("conditions can use `OS`, `ARCH`, names defined with `-D`, literals, comparisons, `!`, `&&` and `||`")),
+        }
+    }
+
+    fn eval_binary_operation(&self, lhs: &ParsedExpression, op: &BinaryOperator, rhs: &ParsedExpression, span: Span) -> Result<ConditionValue, Diagnostic> {
+        let lhs = self.eval(lhs)?;
+
+        // `&&` and `||` only look at their right side if they have to, like
+        // they do at runtime
+        match (op, &lhs) {
+            (BinaryOperator::LogicalAnd, ConditionValue::Bool(false)) => return Ok(lhs),
+            (BinaryOperator::LogicalOr, ConditionValue::Bool(true)) => return Ok(lhs),
+            _ => { }
+        }
+
+        let rhs = self.eval(rhs)?;
+
+        let result = match (&lhs, op, &rhs) {
+            (ConditionValue::Bool(_), BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr, ConditionValue::Bool(value)) => *value,
+            (_, BinaryOperator::EQ, _) if lhs.kind() == rhs.kind() => lhs == rhs,
+            (_, BinaryOperator::NEQ, _) if lhs.kind() == rhs.kind() => lhs != rhs,
+            (ConditionValue::Integer(lhs), _, ConditionValue::Integer(rhs)) => match op {
+                BinaryOperator::LT => lhs < rhs,
+                BinaryOperator::GT => lhs > rhs,
+                BinaryOperator::LEQ => lhs <= rhs,
+                BinaryOperator::GEQ => lhs >= rhs,
+                _ => return Err(Diagnostic::error(format!("`{}` can't be used in the condition of an `#if`", op.as_str()), span)),
+            },
+            _ => return Err(Diagnostic::error(format!("`{}` can't be applied to the {} {} and the {} {}", op.as_str(), lhs.kind(), lhs, rhs.kind(), rhs), span)),
+        };
+
+        Ok(ConditionValue::Bool(result))
+    }
+}
+
+/// The architecture of a target triple, named the way `ARCH` is on the
+/// host.
+fn arch_of_triple(triple: &str) -> String {
+    let arch = triple.split('-').next().unwrap_or_default();
+
+    let arch = match arch {
+        "amd64" => "x86_64",
+        "arm64" => "aarch64",
+        "i386" | "i486" | "i586" | "i686" => "x86",
+        arch if arch.starts_with("armv") || arch.starts_with("thumbv") => "arm",
+        arch => arch,
+    };
+
+    String::from(arch)
+}
+
+/// The operating system of a target triple, named the way `OS` is on the
+/// host.
+fn os_of_triple(triple: &str) -> String {
+    let parts: Vec<&str> = triple.split('-').skip(1).collect();
+    let has = |name: &str| parts.iter().any(|part| part.starts_with(name));
+
+    let os = if has("android") {
+        "android"
+    } else if has("linux") {
+        "linux"
+    } else if has("windows") || has("mingw") || has("win32") {
+        "windows"
+    } else if has("darwin") || has("macos") {
+        "macos"
+    } else if has("ios") {
+        "ios"
+    } else {
+        ["freebsd", "netbsd", "openbsd", "dragonfly"].into_iter().find(|bsd| has(bsd)).unwrap_or("none")
+    };
+
+    String::from(os)
+}
@@ -0,0 +1,669 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{ self, IsTerminal };
+use std::path::{ Path, PathBuf };
+use std::process::{ self, ExitCode };
+
+use crate::bytecode::{ self, Limits, Program, Vm };
+use crate::checked_ast::CheckedModule;
+#[cfg(feature = "llvm")]
+use crate::codegen::{ self, TargetMachine };
+use crate::diagnostic::{ Diagnostic, DiagnosticSink };
+use crate::directives::{ BuildConfig, ConditionValue };
+use crate::interpreter;
+use crate::module_loader::{ self, ModuleLoader };
+use crate::source_map::SourceMap;
+use crate::tokenizer::Tokenizer;
+use crate::typechecker::Typechecker;
+
+pub const USAGE: &str = "\
+Usage: amds <command> [options] <input>...
+
+Commands:
+    build       Compile the inputs (by default into an executable)
+    check       Parse and typecheck the inputs without generating code
+    run         Build the inputs and run the resulting executable
+    tokens      Print the token stream of each input
+    ast         Print the parsed module of each input
+    emit-llvm   Print the LLVM IR of each input
+    disasm      Print the instructions in bytecode files
+
+Options:
+    -o <path>           Write the output to <path>
+    --emit=<kind>       Stop after producing <kind>, one of:
+                        tokens, ast, bytecode, llvm-ir, bitcode, asm, obj, exe
+    --target <triple>   Generate code for <triple> instead of the host
+    --interpret         Make 'run' use the interpreter instead of building
+    --vm                Make 'run' use the bytecode VM instead of building
+    --release           Leave out runtime checks, like array bounds checks
+    -I <dir>            Also look for imported modules in <dir>
+    -D <name>[=<value>] Define <name> for `#if` conditions, as `true` if
+                        there's no value
+    -h, --help          Print this message
+
+Imports are looked for next to the importing file, then in each -I <dir>,
+then in the directories listed in $AMDS_PATH, and last in the standard
+library.
+Executables are linked with the C compiler in $CC, or `cc` if it isn't set.
+When amds is built without LLVM, 'run' always uses the interpreter.";
+
+/// The command given as the first argument to `amds`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Build,
+    Check,
+    Run,
+    Tokens,
+    Ast,
+    EmitLlvm,
+    Disasm,
+}
+
+/// The artifact the pipeline should stop at and write out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmitKind {
+    Tokens,
+    Ast,
+    Bytecode,
+    LlvmIr,
+    Bitcode,
+    Asm,
+    Obj,
+    Exe,
+}
+
+impl EmitKind {
+    fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "tokens" => Some(EmitKind::Tokens),
+            "ast" => Some(EmitKind::Ast),
+            "bytecode" => Some(EmitKind::Bytecode),
+            "llvm-ir" => Some(EmitKind::LlvmIr),
+            "bitcode" => Some(EmitKind::Bitcode),
+            "asm" => Some(EmitKind::Asm),
+            "obj" => Some(EmitKind::Obj),
+            "exe" => Some(EmitKind::Exe),
+            _ => None
+        }
+    }
+
+    /// Whether producing this needs LLVM, and with it a target.
+    fn needs_codegen(&self) -> bool {
+        !matches!(self, EmitKind::Tokens | EmitKind::Ast | EmitKind::Bytecode)
+    }
+
+    fn extension(&self) -> &str {
+        match self {
+            EmitKind::Tokens => "tokens",
+            EmitKind::Ast => "ast",
+            EmitKind::Bytecode => "amdsbc",
+            EmitKind::LlvmIr => "ll",
+            EmitKind::Bitcode => "bc",
+            EmitKind::Asm => "s",
+            EmitKind::Obj => "o",
+            EmitKind::Exe => "",
+        }
+    }
+}
+
+/// Where to stop the pipeline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stage {
+    Check,
+    Interpret,
+    RunBytecode,
+    Disassemble,
+    Emit(EmitKind),
+}
+
+pub struct Options {
+    pub command: Command,
+    pub inputs: Vec<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub emit: Option<EmitKind>,
+    pub target: Option<String>,
+    pub interpret: bool,
+    pub vm: bool,
+    pub release: bool,
+    pub import_paths: Vec<PathBuf>,
+    pub defines: HashMap<String, ConditionValue>,
+}
+
+/// Why the command line could not be turned into `Options`.
+pub enum ArgsError {
+    Help,
+    Invalid(String),
+}
+
+impl Options {
+    pub fn from_args(args: &[String]) -> Result<Self, ArgsError> {
+        let mut args = args.iter();
+
+        let command = match args.next().map(|s| s.as_str()) {
+            Some("build") => Command::Build,
+            Some("check") => Command::Check,
+            Some("run") => Command::Run,
+            Some("tokens") => Command::Tokens,
+            Some("ast") => Command::Ast,
+            Some("emit-llvm") => Command::EmitLlvm,
+            Some("disasm") => Command::Disasm,
+            Some("-h") | Some("--help") => return Err(ArgsError::Help),
+            Some(other) => return Err(ArgsError::Invalid(format!("unknown command '{}'", other))),
+            None => return Err(ArgsError::Invalid(String::from("no command given"))),
+        };
+
+        let mut options = Self {
+            command,
+            inputs: Vec::new(),
+            output: None,
+            emit: None,
+            target: None,
+            interpret: false,
+            vm: false,
+            release: false,
+            import_paths: Vec::new(),
+            defines: HashMap::new(),
+        };
+
+        while let Some(arg) = args.next() {
+            if arg == "-h" || arg == "--help" {
+                return Err(ArgsError::Help);
+            } else if arg == "-o" {
+                match args.next() {
+                    Some(path) => options.output = Some(PathBuf::from(path)),
+                    None => return Err(ArgsError::Invalid(String::from("'-o' expects a path"))),
+                }
+            } else if arg == "--interpret" {
+                options.interpret = true;
+            } else if arg == "--vm" {
+                options.vm = true;
+            } else if arg == "--release" {
+                options.release = true;
+            } else if arg == "-I" {
+                match args.next() {
+                    Some(dir) => options.import_paths.push(PathBuf::from(dir)),
+                    None => return Err(ArgsError::Invalid(String::from("'-I' expects a directory"))),
+                }
+            } else if let Some(dir) = arg.strip_prefix("-I").filter(|dir| !dir.is_empty()) {
+                options.import_paths.push(PathBuf::from(dir));
+            } else if arg == "-D" {
+                match args.next() {
+                    Some(define) => options.define(define)?,
+                    None => return Err(ArgsError::Invalid(String::from("'-D' expects a name"))),
+                }
+            } else if let Some(define) = arg.strip_prefix("-D").filter(|define| !define.is_empty()) {
+                options.define(define)?;
+            } else if arg == "--target" {
+                match args.next() {
+                    Some(triple) => options.target = Some(triple.clone()),
+                    None => return Err(ArgsError::Invalid(String::from("'--target' expects a target triple"))),
+                }
+            } else if let Some(triple) = arg.strip_prefix("--target=") {
+                options.target = Some(String::from(triple));
+            } else if let Some(kind) = arg.strip_prefix("--emit=") {
+                match EmitKind::from_str(kind) {
+                    Some(kind) => options.emit = Some(kind),
+                    None => return Err(ArgsError::Invalid(format!("unknown emit kind '{}'", kind))),
+                }
+            } else if arg.starts_with('-') {
+                return Err(ArgsError::Invalid(format!("unknown option '{}'", arg)));
+            } else {
+                options.inputs.push(PathBuf::from(arg));
+            }
+        }
+
+        if options.inputs.is_empty() {
+            return Err(ArgsError::Invalid(String::from("no input files")));
+        }
+
+        if options.emit.is_some() && !matches!(options.command, Command::Build) {
+            return Err(ArgsError::Invalid(String::from("'--emit' can only be used with 'build'")));
+        }
+
+        if options.interpret && !matches!(options.command, Command::Run) {
+            return Err(ArgsError::Invalid(String::from("'--interpret' can only be used with 'run'")));
+        }
+
+        if options.vm && !matches!(options.command, Command::Run) {
+            return Err(ArgsError::Invalid(String::from("'--vm' can only be used with 'run'")));
+        }
+
+        if options.interpret && options.vm {
+            return Err(ArgsError::Invalid(String::from("'--interpret' and '--vm' can't be used together")));
+        }
+
+        if matches!(options.stage(), Stage::Interpret | Stage::RunBytecode) && options.inputs.len() > 1 {
+            return Err(ArgsError::Invalid(String::from("only one input can be run at a time without building it")));
+        }
+
+        if options.output.is_some() && options.inputs.len() > 1 && options.stage() != Stage::Emit(EmitKind::Exe) {
+            return Err(ArgsError::Invalid(String::from("'-o' with multiple inputs is only allowed when building an executable")));
+        }
+
+        Ok(options)
+    }
+
+    fn stage(&self) -> Stage {
+        match self.command {
+            Command::Build => Stage::Emit(self.emit.unwrap_or(EmitKind::Exe)),
+            Command::Check => Stage::Check,
+            Command::Run if self.vm => Stage::RunBytecode,
+            Command::Run if self.interpret || !cfg!(feature = "llvm") => Stage::Interpret,
+            Command::Run => Stage::Emit(EmitKind::Exe),
+            Command::Tokens => Stage::Emit(EmitKind::Tokens),
+            Command::Ast => Stage::Emit(EmitKind::Ast),
+            Command::EmitLlvm => Stage::Emit(EmitKind::LlvmIr),
+            Command::Disasm => Stage::Disassemble,
+        }
+    }
+
+    /// Adds a `-D name=value` define, or `-D name` which is `true`.
+    fn define(&mut self, define: &str) -> Result<(), ArgsError> {
+        let (name, value) = match define.split_once('=') {
+            Some((name, value)) => (name, ConditionValue::parse(value)),
+            None => (define, ConditionValue::Bool(true)),
+        };
+
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') || name.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(ArgsError::Invalid(format!("'{}' can't be defined, it isn't a valid name", name)));
+        }
+
+        if name == "OS" || name == "ARCH" {
+            return Err(ArgsError::Invalid(format!("'{}' can't be defined, it's set by the target", name)));
+        }
+
+        self.defines.insert(String::from(name), value);
+        Ok(())
+    }
+
+    /// Where imports are looked for, after the directory of the module
+    /// importing them.
+    fn search_paths(&self) -> Vec<PathBuf> {
+        let mut paths = self.import_paths.clone();
+
+        if let Some(amds_path) = env::var_os("AMDS_PATH") {
+            paths.extend(env::split_paths(&amds_path).filter(|path| !path.as_os_str().is_empty()));
+        }
+
+        paths.push(stdlib_dir());
+        paths
+    }
+
+    /// The path an artifact for `input` should be written to. `None` means
+    /// the artifact goes to stdout.
+    fn output_path(&self, input: &Path, kind: EmitKind) -> Option<PathBuf> {
+        if let Some(path) = &self.output {
+            return Some(path.clone());
+        }
+
+        match (self.command, kind) {
+            (Command::Build, _) => Some(input.with_extension(kind.extension())),
+            // What `run` builds is thrown away once it's been run, so it
+            // doesn't end up next to the source
+            (Command::Run, _) => {
+                let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+                Some(env::temp_dir().join(format!("amds-{}-{}", process::id(), stem)).with_extension(kind.extension()))
+            },
+            _ => None
+        }
+    }
+}
+
+/// State shared by every file compiled in one invocation.
+struct Session {
+    source_map: SourceMap,
+    diagnostics: DiagnosticSink,
+    color: bool,
+    /// Only created when the pipeline goes as far as codegen.
+    #[cfg(feature = "llvm")]
+    target: Option<TargetMachine>,
+}
+
+impl Session {
+    fn new() -> Self {
+        Self {
+            source_map: SourceMap::new(),
+            diagnostics: DiagnosticSink::new(),
+            color: io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none(),
+            #[cfg(feature = "llvm")]
+            target: None,
+        }
+    }
+
+    #[cfg(feature = "llvm")]
+    fn init_codegen(&mut self, target: Option<&str>) -> Result<(), String> {
+        codegen::init();
+        self.target = Some(TargetMachine::new(target)?);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "llvm"))]
+    fn init_codegen(&mut self, _target: Option<&str>) -> Result<(), String> {
+        Err(String::from("generating code needs LLVM, but amds was built without the `llvm` feature"))
+    }
+
+    /// Prints everything reported so far and fails if any of it was an error.
+    fn report_diagnostics(&self) -> Result<(), String> {
+        let error_count = self.diagnostics.error_count();
+
+        for diagnostic in self.diagnostics.take() {
+            eprintln!("{}", diagnostic.render(&self.source_map, self.color));
+        }
+
+        match error_count {
+            0 => Ok(()),
+            1 => Err(String::from("could not compile due to a previous error")),
+            n => Err(format!("could not compile due to {} previous errors", n)),
+        }
+    }
+
+    /// Prints an error that stopped a program while it was running.
+    fn report_runtime_error(&self, diagnostic: Diagnostic) -> String {
+        eprintln!("{}", diagnostic.render(&self.source_map, self.color));
+        String::from("the program was stopped due to a previous error")
+    }
+}
+
+/// Runs the whole pipeline described by `options` and reports how it went.
+pub fn run(options: &Options) -> ExitCode {
+    let stage = options.stage();
+    let mut session = Session::new();
+    let mut failed = false;
+
+    if let Stage::Emit(kind) = stage {
+        if kind.needs_codegen() {
+            if let Err(msg) = session.init_codegen(options.target.as_deref()) {
+                eprintln!("error: {}", msg);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    if stage == Stage::Disassemble {
+        return disassemble(options);
+    }
+
+    let mut objects = Vec::new();
+    let mut libraries: Vec<String> = Vec::new();
+    let mut exit_code = None;
+
+    for input in &options.inputs {
+        match compile_file(&mut session, options, input, stage) {
+            Ok(Compiled::Done) => { },
+            Ok(Compiled::Object(object, object_libraries)) => {
+                objects.push(object);
+
+                for library in object_libraries {
+                    if !libraries.contains(&library) {
+                        libraries.push(library);
+                    }
+                }
+            },
+            Ok(Compiled::Exited(code)) => exit_code = Some(code),
+            Err(msg) => {
+                eprintln!("error: {}: {}", input.display(), msg);
+                failed = true;
+            }
+        }
+    }
+
+    if !failed && stage == Stage::Emit(EmitKind::Exe) {
+        let exe_path = options.output_path(&options.inputs[0], EmitKind::Exe)
+                              .expect("Executables always go to a file");
+
+        if let Err(msg) = link(&objects, &libraries, &exe_path, options.target.as_deref()) {
+            eprintln!("error: {}", msg);
+            failed = true;
+        }
+    }
+
+    for object in &objects {
+        let _ = fs::remove_file(object);
+    }
+
+    if failed {
+        return ExitCode::FAILURE;
+    }
+
+    if let Some(code) = exit_code {
+        return ExitCode::from(code);
+    }
+
+    if options.command == Command::Run {
+        return run_executable(options);
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn run_executable(options: &Options) -> ExitCode {
+    let exe_path = options.output_path(&options.inputs[0], EmitKind::Exe)
+                          .expect("Executables always go to a file");
+
+    // A bare file name would be looked up in $PATH instead of the cwd.
+    let exe_path = if exe_path.is_relative() { Path::new(".").join(exe_path) } else { exe_path };
+
+    let exit_code = match process::Command::new(&exe_path).status() {
+        Ok(status) => match status.code() {
+            Some(code) => ExitCode::from(code as u8),
+            None => ExitCode::FAILURE,
+        },
+        Err(err) => {
+            eprintln!("error: couldn't run '{}': {}", exe_path.display(), err);
+            ExitCode::FAILURE
+        }
+    };
+
+    // Only the executable `-o` asked for is kept
+    if options.output.is_none() {
+        let _ = fs::remove_file(&exe_path);
+    }
+
+    exit_code
+}
+
+/// What's left to do with a file once `compile_file` is done with it.
+/// Only LLVM makes objects.
+#[cfg_attr(not(feature = "llvm"), allow(dead_code))]
+enum Compiled {
+    Done,
+    /// An object file that still has to be linked into the executable,
+    /// and the libraries its `#foreign` procs are in.
+    Object(PathBuf, Vec<String>),
+    /// The exit code of the interpreted program.
+    Exited(u8),
+}
+
+/// Runs the pipeline over one file.
+fn compile_file(session: &mut Session, options: &Options, input: &Path, stage: Stage) -> Result<Compiled, String> {
+    let file_id = session.source_map.load_file(input)
+        .map_err(|err| format!("couldn't read file: {}", err))?;
+    let contents = session.source_map.file(file_id).contents.clone();
+
+    let token_stream = Tokenizer::new(contents, file_id, session.diagnostics.clone()).tokenize();
+
+    if stage == Stage::Emit(EmitKind::Tokens) {
+        let dump: String = token_stream.iter()
+                                       .map(|token| format!("{:?}\n", token))
+                                       .collect();
+        write_output(options.output_path(input, EmitKind::Tokens), dump.as_bytes())?;
+        return session.report_diagnostics().map(|_| Compiled::Done);
+    }
+
+    let config = BuildConfig::new(options.target.as_deref(), options.defines.clone());
+    let module = module_loader::parse(input, token_stream, &config, session.diagnostics.clone());
+
+    if stage == Stage::Emit(EmitKind::Ast) {
+        let dump = format!("{:#?}\n", module);
+        write_output(options.output_path(input, EmitKind::Ast), dump.as_bytes())?;
+        return session.report_diagnostics().map(|_| Compiled::Done);
+    }
+
+    let search_paths = options.search_paths();
+    let mut modules = ModuleLoader::new(&mut session.source_map, session.diagnostics.clone(), &config, &search_paths).load(module, input);
+
+    session.report_diagnostics()?;
+
+    let mut typechecker = Typechecker::new(session.diagnostics.clone());
+    typechecker.preregister_primitives();
+    let checked_module = typechecker.verify_modules(&mut modules);
+
+    session.report_diagnostics()?;
+
+    let kind = match stage {
+        Stage::Check => return Ok(Compiled::Done),
+        Stage::Interpret => {
+            return interpreter::run(&modules, &checked_module.types)
+                .map(Compiled::Exited)
+                .map_err(|diagnostic| session.report_runtime_error(diagnostic));
+        },
+        Stage::RunBytecode => {
+            let program = bytecode::compile_module(&checked_module)?;
+
+            // Only code that runs while compiling has to be kept from
+            // running forever
+            let limits = Limits { max_instructions: u64::MAX, ..Limits::default() };
+
+            return Vm::new(&program, limits).run_main()
+                .map(Compiled::Exited)
+                .map_err(|diagnostic| session.report_runtime_error(diagnostic));
+        },
+        Stage::Disassemble => unreachable!("Bytecode files are disassembled without compiling anything"),
+        Stage::Emit(kind) => kind,
+    };
+
+    if kind == EmitKind::Bytecode {
+        let program = bytecode::compile_module(&checked_module)?;
+        write_output(options.output_path(input, kind), &program.to_bytes())?;
+        return Ok(Compiled::Done);
+    }
+
+    emit(session, options, input, kind, &checked_module)
+}
+
+#[cfg(feature = "llvm")]
+fn emit(session: &Session, options: &Options, input: &Path, kind: EmitKind, checked_module: &CheckedModule) -> Result<Compiled, String> {
+    let target = session.target.as_ref().expect("Codegen stages always have a target");
+    let llvm_module = codegen::generate_module(checked_module, target, !options.release)?;
+
+    match kind {
+        EmitKind::LlvmIr => {
+            let ir = llvm_module.to_cstring();
+            write_output(options.output_path(input, kind), ir.as_bytes())?;
+        },
+        EmitKind::Bitcode => {
+            let path = options.output_path(input, kind).expect("Bitcode always goes to a file");
+            llvm_module.write_bitcode(&path)?;
+        },
+        EmitKind::Asm => {
+            let path = options.output_path(input, kind).expect("Assembly always goes to a file");
+            llvm_module.write_assembly(target, &path)?;
+        },
+        EmitKind::Obj => {
+            let path = options.output_path(input, kind).expect("Object files always go to a file");
+            llvm_module.write_object(target, &path)?;
+        },
+        EmitKind::Exe => {
+            // The object only lives until it's linked, so it goes somewhere
+            // it can't clobber one the user made with `--emit=obj`.
+            let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+            let path = env::temp_dir().join(format!("amds-{}-{}.o", process::id(), stem));
+            llvm_module.write_object(target, &path)?;
+            let libraries = checked_module.foreign_libraries().into_iter().map(String::from).collect();
+            return Ok(Compiled::Object(path, libraries));
+        },
+        EmitKind::Tokens | EmitKind::Ast | EmitKind::Bytecode => unreachable!(),
+    }
+
+    Ok(Compiled::Done)
+}
+
+/// Prints the instructions in each of the bytecode files given as inputs.
+fn disassemble(options: &Options) -> ExitCode {
+    let mut failed = false;
+
+    for input in &options.inputs {
+        let result = fs::read(input)
+            .map_err(|err| format!("couldn't read file: {}", err))
+            .and_then(|bytes| Program::from_bytes(&bytes))
+            .and_then(|program| write_output(options.output_path(input, EmitKind::Bytecode), program.disassemble().as_bytes()));
+
+        if let Err(msg) = result {
+            eprintln!("error: {}: {}", input.display(), msg);
+            failed = true;
+        }
+    }
+
+    if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS }
+}
+
+#[cfg(not(feature = "llvm"))]
+fn emit(_session: &Session, _options: &Options, _input: &Path, _kind: EmitKind, _checked_module: &CheckedModule) -> Result<Compiled, String> {
+    unreachable!("Codegen stages fail before any file is compiled without LLVM")
+}
+
+/// Links `objects` into an executable with the system's C compiler, which
+/// knows where the C runtime and libc live. Cross-compiling needs `$CC` to
+/// point at a compiler for the target.
+fn link(objects: &[PathBuf], libraries: &[String], exe_path: &Path, target: Option<&str>) -> Result<(), String> {
+    let cc = env::var("CC").unwrap_or_else(|_| String::from("cc"));
+
+    let status = process::Command::new(&cc)
+        .args(objects)
+        .args(libraries.iter().map(|library| library_arg(library)))
+        // `%` on floats is lowered to a call to libm's `fmod`
+        .arg("-lm")
+        .arg("-o")
+        .arg(exe_path)
+        .status()
+        .map_err(|err| format!("couldn't run the linker '{}': {}", cc, err))?;
+
+    if !status.success() {
+        let mut msg = format!("linking '{}' failed", exe_path.display());
+        if let Some(target) = target {
+            msg += &format!(" (is '{}' able to link for '{}'? set $CC to pick another linker)", cc, target);
+        }
+        return Err(msg);
+    }
+
+    Ok(())
+}
+
+/// How the linker is told about a library a `#foreign` proc is in. A
+/// path to the library's file is passed as it is, and anything else is a
+/// name like the `m` in `-lm`.
+fn library_arg(library: &str) -> String {
+    let is_file = library.contains('/') || [".a", ".so", ".dylib", ".lib"].iter().any(|ext| library.ends_with(ext));
+
+    if is_file {
+        String::from(library)
+    } else {
+        format!("-l{}", library)
+    }
+}
+
+/// Where the standard library is: a `std` directory next to the `amds`
+/// executable if there is one, or the one in the source tree it was built
+/// from otherwise.
+fn stdlib_dir() -> PathBuf {
+    let installed = env::current_exe().ok().and_then(|exe| Some(exe.parent()?.join("std")));
+
+    match installed {
+        Some(dir) if dir.is_dir() => dir,
+        _ => PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/std")),
+    }
+}
+
+fn write_output(path: Option<PathBuf>, contents: &[u8]) -> Result<(), String> {
+    match path {
+        Some(path) => {
+            fs::write(&path, contents)
+                .map_err(|err| format!("couldn't write '{}': {}", path.display(), err))
+        },
+        None => {
+            print!("{}", String::from_utf8_lossy(contents));
+            Ok(())
+        }
+    }
+}
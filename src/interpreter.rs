@@ -0,0 +1,1319 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{ self, Write };
+use std::thread;
+
+use crate::ast::{
+    BinaryOperator,
+    MatchExprBody,
+    MatchExprCase,
+    ParsedBlock,
+    ParsedConstDecl,
+    ParsedExpression,
+    ParsedModule,
+    ParsedProcCall,
+    ParsedProcDecl,
+    ParsedStatement,
+    ParsedType,
+    ParsedVarDecl,
+    RangeExprBound,
+    UnaryOperator,
+};
+use crate::diagnostic::Diagnostic;
+use crate::intrinsics::{ self, Files, FormatPiece, Intrinsic };
+use crate::token::{ IntegerLiteralFormat, NumericConstant, PrimitiveType, Span };
+use crate::typechecker::{ TypeID, TypeInfo };
+
+/// How deeply calls can nest before the program is stopped, instead of
+/// overflowing the interpreter's own stack.
+const MAX_CALL_DEPTH: usize = 4096;
+
+/// Every call recurses through a handful of the interpreter's own
+/// functions, so it gets a stack with room for `MAX_CALL_DEPTH` of them.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// A value produced while interpreting. Integers keep the bits of their
+/// type, truncated to its width, and `f32`s are kept rounded to `f32`
+/// precision, so they behave the same as they do once compiled.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Nothing,
+    Bool(bool),
+    /// The code point, which `as` can make any 32 bit number
+    Char(u32),
+    String(String),
+    Integer(u64, PrimitiveType),
+    Float(f64, PrimitiveType),
+    Struct(TypeID, Vec<Value>),
+    /// The type of the variant, along with its payload in declaration order
+    Enum(TypeID, Vec<Value>),
+    Array(Vec<Value>),
+    /// `None` is `null`
+    Pointer(Option<Address>),
+}
+
+impl Value {
+    pub fn primitive(&self) -> Option<PrimitiveType> {
+        match self {
+            Value::Nothing => Some(PrimitiveType::Nothing),
+            Value::Bool(_) => Some(PrimitiveType::Bool),
+            Value::Char(_) => Some(PrimitiveType::Char),
+            Value::String(_) => Some(PrimitiveType::String),
+            Value::Integer(_, ty) | Value::Float(_, ty) => Some(ty.clone()),
+            Value::Struct(_, _) | Value::Enum(_, _) | Value::Array(_) | Value::Pointer(_) => None,
+        }
+    }
+
+    fn integer(bits: u64, ty: PrimitiveType) -> Value {
+        Value::Integer(ty.truncate(bits), ty)
+    }
+
+    fn float(value: f64, ty: PrimitiveType) -> Value {
+        match ty {
+            PrimitiveType::F32 => Value::Float(value as f32 as f64, ty),
+            _ => Value::Float(value, ty),
+        }
+    }
+
+    /// Reads a scalar out of the raw bits the bytecode VM keeps its
+    /// values in.
+    pub fn from_bits(bits: u64, ty: &PrimitiveType) -> Value {
+        match ty {
+            PrimitiveType::Bool => Value::Bool(bits != 0),
+            PrimitiveType::Char => Value::Char(bits as u32),
+            PrimitiveType::F32 | PrimitiveType::F64 => Value::Float(f64::from_bits(bits), ty.clone()),
+            ty if ty.is_integer() => Value::Integer(bits, ty.clone()),
+            _ => unreachable!("`{}` isn't a scalar", ty.as_str()),
+        }
+    }
+
+    pub fn to_bits(&self) -> u64 {
+        match self {
+            Value::Bool(value) => *value as u64,
+            Value::Char(value) => *value as u64,
+            Value::Integer(bits, _) => *bits,
+            Value::Float(value, _) => value.to_bits(),
+            _ => unreachable!("{:?} isn't a scalar", self),
+        }
+    }
+}
+
+/// Writes values the way `%v` does once compiled, which goes through
+/// `printf`.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Nothing => Ok(()),
+            Value::Bool(value) => write!(f, "{}", value),
+            // Like `%c`, code points that aren't characters come out as U+FFFD
+            Value::Char(value) => write!(f, "{}", char::from_u32(*value).unwrap_or(char::REPLACEMENT_CHARACTER)),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Integer(bits, ty) if ty.is_signed() => write!(f, "{}", ty.sign_extend(*bits)),
+            Value::Integer(bits, _) => write!(f, "{}", bits),
+            // `%f` spells these differently than Rust does
+            Value::Float(value, _) if value.is_nan() => write!(f, "{}nan", if value.is_sign_negative() { "-" } else { "" }),
+            Value::Float(value, _) if value.is_infinite() => write!(f, "{}inf", if value.is_sign_negative() { "-" } else { "" }),
+            Value::Float(value, _) => write!(f, "{:.6}", value),
+            Value::Struct(_, members) | Value::Enum(_, members) => {
+                let members: Vec<String> = members.iter().map(|member| member.to_string()).collect();
+                write!(f, "{{ {} }}", members.join(", "))
+            },
+            Value::Array(elems) => {
+                let elems: Vec<String> = elems.iter().map(|elem| elem.to_string()).collect();
+                write!(f, "[{}]", elems.join(", "))
+            },
+            Value::Pointer(None) => write!(f, "null"),
+            Value::Pointer(Some(address)) => {
+                write!(f, "&{}", address.name)?;
+                address.path.iter().try_for_each(|step| match step {
+                    Step::Elem(index) => write!(f, "[{}]", index),
+                    Step::Member(idx) => write!(f, ".{}", idx),
+                })
+            },
+        }
+    }
+}
+
+/// Where execution goes after a statement.
+enum Flow {
+    Next,
+    Continue,
+    Break,
+    Return(Value),
+}
+
+type RunResult<T> = Result<T, Diagnostic>;
+
+/// Where a value lives: a local in one of the frames, or something in
+/// one with the steps that lead to it. It's what assignments store to and
+/// what pointers hold, so they can only point at locals. A pointer to a
+/// local that went out of scope is only caught if nothing took its place.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Address {
+    frame: usize,
+    scope: usize,
+    name: String,
+    path: Vec<Step>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Step {
+    /// An element of an array. Pointer arithmetic can move it out of
+    /// bounds, which is only an error once the pointer is dereferenced.
+    Elem(i64),
+    /// A member of a struct, by its position
+    Member(usize),
+}
+
+/// The locals of one call. Like in the typechecker, every block gets
+/// its own scope.
+struct Frame<'a> {
+    /// What a `return` gives its value as
+    return_type: &'a ParsedType,
+    scopes: Vec<HashMap<String, Value>>,
+}
+
+/// Runs `main` from the last of `modules`, which is the one that imports
+/// the others. They have to have passed typechecking. Gives back the exit
+/// code of the program.
+pub fn run(modules: &[ParsedModule], types: &[TypeInfo]) -> Result<u8, Diagnostic> {
+    thread::scope(|scope| {
+        thread::Builder::new()
+            .name(String::from("interpreter"))
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, || Interpreter::new(modules, types).run_main())
+            .expect("Couldn't spawn the interpreter's thread")
+            .join()
+            .expect("The interpreter panicked")
+    })
+}
+
+/// Evaluates a typechecked `ParsedModule` directly, without going through
+/// LLVM. It's meant to behave exactly like the compiled program, which
+/// makes it a reference to test the backend against.
+pub struct Interpreter<'a> {
+    procs: HashMap<&'a str, &'a ParsedProcDecl>,
+    /// Every module's constants, in the order they're evaluated in
+    consts: Vec<&'a ParsedConstDecl>,
+    /// Constants are evaluated once, the first time they're needed
+    const_values: HashMap<&'a str, Value>,
+    types: &'a [TypeInfo],
+    frames: Vec<Frame<'a>>,
+    /// Set while a `return`, `break` or `continue` in the block of a match
+    /// arm or an `if` branch leaves the expression it's part of. It travels up as an
+    /// error, so nothing else in the expression runs, and the statement
+    /// around it turns it back into the flow it stands for.
+    unwinding: Option<Flow>,
+    /// The files the program opened with `io::open`
+    files: Files,
+}
+
+impl<'a> Interpreter<'a> {
+    /// The typechecker gave every declaration and every use of one its
+    /// full name, so the modules can be flattened into one.
+    pub fn new(modules: &'a [ParsedModule], types: &'a [TypeInfo]) -> Self {
+        Self {
+            procs: modules.iter().flat_map(|module| &module.procs).map(|proc| (proc.name.as_str(), proc)).collect(),
+            consts: modules.iter().flat_map(|module| &module.consts).collect(),
+            const_values: HashMap::new(),
+            types,
+            frames: Vec::new(),
+            unwinding: None,
+            files: Files::default(),
+        }
+    }
+
+    pub fn run_main(&mut self) -> Result<u8, Diagnostic> {
+        let Some(main) = self.procs.get("main").copied() else {
+            return Err(Diagnostic::error("there's no `main` proc to run", Span::default()));
+        };
+
+        // The compiler evaluates every constant before the program runs, in
+        // the order they're declared
+        for const_decl in self.consts.clone() {
+            self.eval_const(&const_decl.name, const_decl.span)?;
+        }
+
+        // The C runtime only keeps the low byte of what `main` returns
+        match self.call_proc(main, Vec::new(), main.span)? {
+            Value::Integer(bits, _) => Ok(bits as u8),
+            _ => Ok(0),
+        }
+    }
+
+    fn frame(&mut self) -> &mut Frame<'a> {
+        self.frames.last_mut().expect("Statements only run inside of a call")
+    }
+
+    fn declare(&mut self, name: &str, value: Value) {
+        self.frame().scopes.last_mut().unwrap().insert(String::from(name), value);
+    }
+
+    fn find_var(&mut self, name: &str, span: Span) -> RunResult<&mut Value> {
+        self.frame().scopes.iter_mut()
+                           .rev()
+                           .find_map(|scope| scope.get_mut(name))
+                           .ok_or_else(|| Diagnostic::error(format!("cannot find `{}` in this scope", name), span))
+    }
+
+    fn resolve_type(&self, ty: &ParsedType) -> Option<TypeID> {
+        match ty {
+            ParsedType::CheckedType(id) => Some(*id),
+            ParsedType::Name(_, name, _) => self.types.iter().position(|info| info.name() == name),
+            ParsedType::Array(_, _) | ParsedType::Pointer(_) => None,
+        }
+    }
+
+    /// The primitive behind `ty`, which is what literals need as a hint.
+    fn primitive_of(&self, ty: &ParsedType) -> Option<PrimitiveType> {
+        self.types[self.resolve_type(ty)?].primitive().cloned()
+    }
+
+    /// The primitive the elements of an array type are, if they are one.
+    fn elem_primitive_of(&self, array_type: &ParsedType) -> Option<PrimitiveType> {
+        match &self.types[self.resolve_type(array_type)?] {
+            TypeInfo::Array(_, elem_id, _) => self.types[*elem_id].primitive().cloned(),
+            _ => None,
+        }
+    }
+
+    /// Turns an index into an array of `len` elements into a position in
+    /// it. Arrays are always bounds checked here, like they are in a debug
+    /// build.
+    fn array_index(index: &Value, len: usize, span: Span) -> RunResult<usize> {
+        let Value::Integer(bits, ty) = index else {
+            return Err(Diagnostic::error("arrays can only be indexed by integers", span));
+        };
+
+        let index = match ty.is_signed() {
+            true => ty.sign_extend(*bits) as i128,
+            false => *bits as i128,
+        };
+
+        match usize::try_from(index) {
+            Ok(index) if index < len => Ok(index),
+            _ => Err(Diagnostic::error(format!("index {} is out of bounds for an array of length {}", index, len), span)),
+        }
+    }
+
+    fn var_address(&mut self, name: &str, span: Span) -> RunResult<Address> {
+        let frame = self.frames.len() - 1;
+        let Some(scope) = self.frame().scopes.iter().rposition(|scope| scope.contains_key(name)) else {
+            return Err(Diagnostic::error(format!("cannot find `{}` in this scope", name), span));
+        };
+
+        Ok(Address { frame, scope, name: String::from(name), path: Vec::new() })
+    }
+
+    /// Works out where an assignment's target or the operand of `&` lives.
+    /// The indices are evaluated before the value that's assigned, the same
+    /// as once compiled.
+    fn eval_place(&mut self, expr: &'a ParsedExpression) -> RunResult<Address> {
+        match expr {
+            ParsedExpression::Var(name, span) => self.var_address(name, *span),
+            ParsedExpression::Index(base, index, _) => {
+                let mut address = self.eval_place(base)?;
+                let index_value = self.eval(index, Some(PrimitiveType::I64))?;
+
+                let Value::Array(elems) = self.value_at(&address, base.span())? else {
+                    unreachable!("Only arrays can be indexed");
+                };
+
+                let index = Interpreter::array_index(&index_value, elems.len(), index.span())?;
+                address.path.push(Step::Elem(index as i64));
+                Ok(address)
+            },
+            ParsedExpression::Member(base, name, span) => {
+                // Anything that isn't a place has to be a pointer to a struct
+                let mut address = if base.is_assignable() {
+                    self.eval_place(base)?
+                } else {
+                    self.deref(base, *span)?
+                };
+
+                // Members are reached through pointers without
+                // dereferencing them first
+                if let Value::Pointer(pointer) = self.value_at(&address, *span)? {
+                    let pointer = pointer.clone();
+                    address = pointer.ok_or_else(|| Diagnostic::error("dereferenced a null pointer", *span))?;
+                }
+
+                let type_id = match self.value_at(&address, *span)? {
+                    Value::Struct(type_id, _) => *type_id,
+                    _ => unreachable!("Only structs have members"),
+                };
+
+                let idx = self.member_index(type_id, name);
+                address.path.push(Step::Member(idx));
+                Ok(address)
+            },
+            ParsedExpression::UnaryOperation(operand, UnaryOperator::Dereference, span) => self.deref(operand, *span),
+            _ => Err(Diagnostic::error("can't assign to this expression", expr.span())),
+        }
+    }
+
+    fn deref(&mut self, pointer: &'a ParsedExpression, span: Span) -> RunResult<Address> {
+        match self.eval(pointer, None)? {
+            Value::Pointer(Some(address)) => Ok(address),
+            Value::Pointer(None) => Err(Diagnostic::error("dereferenced a null pointer", span)),
+            _ => Err(Diagnostic::error("can only dereference pointers", span)),
+        }
+    }
+
+    fn value_at(&mut self, address: &Address, span: Span) -> RunResult<&mut Value> {
+        let mut value = self.frames.get_mut(address.frame)
+                                   .and_then(|frame| frame.scopes.get_mut(address.scope))
+                                   .and_then(|scope| scope.get_mut(&address.name))
+                                   .ok_or_else(|| Diagnostic::error(format!("dereferenced a pointer to `{}`, which went out of scope", address.name), span))?;
+
+        for step in &address.path {
+            value = match (step, value) {
+                (Step::Member(idx), Value::Struct(_, members)) => &mut members[*idx],
+                (Step::Elem(index), Value::Array(elems)) => {
+                    let len = elems.len();
+                    usize::try_from(*index).ok()
+                                           .and_then(|index| elems.get_mut(index))
+                                           .ok_or_else(|| Diagnostic::error(format!("dereferenced a pointer to element {} of an array of length {}", index, len), span))?
+                },
+                _ => unreachable!("Only arrays are indexed and only structs have members"),
+            };
+        }
+
+        Ok(value)
+    }
+
+    /// The position of the member `name` in the struct `type_id`.
+    fn member_index(&self, type_id: TypeID, name: &str) -> usize {
+        let TypeInfo::Struct(_, members) = &self.types[type_id] else {
+            unreachable!("Only structs have members");
+        };
+
+        members.iter().position(|(member_name, _)| member_name == name).expect("Members are checked by the typechecker")
+    }
+
+    /// Moves a pointer `offset` elements along the array it points into.
+    /// The interpreter has no flat memory, so pointers to anything else
+    /// can't be moved.
+    fn offset_pointer(pointer: Option<Address>, offset: i64, span: Span) -> RunResult<Value> {
+        let Some(mut address) = pointer else {
+            return Err(Diagnostic::error("can't offset a null pointer", span));
+        };
+
+        if offset == 0 {
+            return Ok(Value::Pointer(Some(address)));
+        }
+
+        let Some(Step::Elem(last)) = address.path.last_mut() else {
+            return Err(Diagnostic::error("the interpreter can only offset pointers to elements of arrays", span));
+        };
+
+        *last = last.wrapping_add(offset);
+        Ok(Value::Pointer(Some(address)))
+    }
+
+    /// The value of the constant called `name`, which is only worked out
+    /// the first time.
+    fn eval_const(&mut self, name: &str, span: Span) -> RunResult<Value> {
+        if let Some(value) = self.const_values.get(name) {
+            return Ok(value.clone());
+        }
+
+        let Some(const_decl) = self.consts.iter().copied().find(|const_decl| const_decl.name == name) else {
+            return Err(Diagnostic::error(format!("cannot find `{}` in this scope", name), span));
+        };
+
+        // The typechecker rules out constants that depend on themselves, so
+        // this can't recurse forever
+        let hint = self.primitive_of(&const_decl.parsed_type);
+        self.frames.push(Frame { return_type: &const_decl.parsed_type, scopes: vec![HashMap::new()] });
+        let value = self.eval(&const_decl.value, hint);
+        self.frames.pop();
+
+        // A `return` in a match arm gives the constant its value
+        let value = match self.unwinding.take() {
+            Some(Flow::Return(value)) => value,
+            _ => value?,
+        };
+        self.const_values.insert(&const_decl.name, value.clone());
+
+        Ok(value)
+    }
+
+    fn call_proc(&mut self, proc: &'a ParsedProcDecl, args: Vec<Value>, span: Span) -> RunResult<Value> {
+        if self.frames.len() == MAX_CALL_DEPTH {
+            return Err(Diagnostic::error(format!("calls nested more than {} deep", MAX_CALL_DEPTH), span)
+                                  .with_note("this is usually infinite recursion"));
+        }
+
+        let Some(body) = &proc.body else {
+            return Err(Diagnostic::error(format!("`{}` is a `#foreign` proc, which the interpreter can't call", proc.name), span)
+                                  .with_note("build the program to link it with the library the proc is in"));
+        };
+
+        let params = proc.parameters.iter().map(|param| param.name.clone()).zip(args).collect();
+        self.frames.push(Frame { return_type: &proc.parsed_return_type, scopes: vec![params] });
+
+        let flow = self.exec_block(body);
+
+        self.frames.pop();
+
+        match flow? {
+            Flow::Return(value) => Ok(value),
+            _ => Ok(Value::Nothing),
+        }
+    }
+
+    fn exec_block(&mut self, block: &'a ParsedBlock) -> RunResult<Flow> {
+        self.frame().scopes.push(HashMap::new());
+
+        let mut flow = Ok(Flow::Next);
+        for stmt in &block.stmts {
+            flow = self.exec_statement(stmt);
+
+            if !matches!(flow, Ok(Flow::Next)) {
+                break;
+            }
+        }
+
+        self.frame().scopes.pop();
+
+        flow
+    }
+
+    fn exec_statement(&mut self, stmt: &'a ParsedStatement) -> RunResult<Flow> {
+        let flow = self.run_statement(stmt);
+
+        match self.unwinding.take() {
+            Some(unwound) => Ok(unwound),
+            None => flow,
+        }
+    }
+
+    fn run_statement(&mut self, stmt: &'a ParsedStatement) -> RunResult<Flow> {
+        match stmt {
+            ParsedStatement::Expr(expr) => { self.eval(expr, None)?; },
+            ParsedStatement::VarAssign(name, op, value, span) => {
+                let address = self.var_address(name, *span)?;
+                self.eval_assignment(&address, op, value, span.to(value.span()))?;
+            },
+            ParsedStatement::VarDecl(var_decl) => {
+                let hint = self.primitive_of(&var_decl.parsed_type);
+                let value = self.eval(&var_decl.defualt_value, hint)?;
+                self.declare(&var_decl.name, value);
+            },
+            ParsedStatement::If(cond, then_block, else_stmt) => {
+                if self.eval_condition(cond)? {
+                    return self.exec_block(then_block);
+                }
+
+                if let Some(else_stmt) = else_stmt {
+                    return self.run_statement(else_stmt);
+                }
+            },
+            ParsedStatement::Block(block) => return self.exec_block(block),
+            ParsedStatement::ForLoop(it_decl, range, body) => return self.exec_for_loop(&it_decl.name, range, body),
+            ParsedStatement::WhileLoop(cond, body) => {
+                while self.eval_condition(cond)? {
+                    match self.exec_block(body)? {
+                        Flow::Next | Flow::Continue => { },
+                        Flow::Break => break,
+                        flow @ Flow::Return(_) => return Ok(flow),
+                    }
+                }
+            },
+            ParsedStatement::InfiniteLoop(body) => loop {
+                match self.exec_block(body)? {
+                    Flow::Next | Flow::Continue => { },
+                    Flow::Break => break,
+                    flow @ Flow::Return(_) => return Ok(flow),
+                }
+            },
+            ParsedStatement::Continue(_) => return Ok(Flow::Continue),
+            ParsedStatement::Break(_) => return Ok(Flow::Break),
+            ParsedStatement::Return(expr, _) => {
+                let value = match expr {
+                    Some(expr) => {
+                        let hint = self.primitive_of(self.frames.last().unwrap().return_type);
+                        self.eval(expr, hint)?
+                    },
+                    None => Value::Nothing,
+                };
+
+                return Ok(Flow::Return(value));
+            },
+            ParsedStatement::Invalid(span) => unreachable!("Invalid statement at {:?} made it past the typechecker", span),
+        }
+
+        Ok(Flow::Next)
+    }
+
+    /// Steps through the range the same way the compiled loop does: the
+    /// iterator is a variable that's incremented after each pass, so
+    /// assigning to it in the body changes what runs next. The loop ends
+    /// once it reaches `end`, before it's incremented.
+    fn exec_for_loop(&mut self, it_name: &str, range: &'a ParsedExpression, body: &'a ParsedBlock) -> RunResult<Flow> {
+        let ParsedExpression::Range(start_bound, start, end, end_bound, _) = range else {
+            return self.exec_array_loop(it_name, range, body);
+        };
+
+        let (start, end) = self.eval_operands(start, end, None)?;
+        let (Value::Integer(mut current, ty), Value::Integer(end, _)) = (start, end) else {
+            return Err(Diagnostic::error("can only loop over a range of integers", range.span()));
+        };
+
+        let compare_to_end = |current: u64| match ty.is_signed() {
+            true => ty.sign_extend(current).cmp(&ty.sign_extend(end)),
+            false => current.cmp(&end),
+        };
+
+        let in_range = |current: u64| match end_bound {
+            RangeExprBound::Inclusive => compare_to_end(current).is_le(),
+            RangeExprBound::Exclusive => compare_to_end(current).is_lt(),
+        };
+
+        // Nothing comes after a `start` that's already at `end`, and adding
+        // one to it could wrap
+        if let RangeExprBound::Exclusive = start_bound {
+            if compare_to_end(current).is_ge() {
+                return Ok(Flow::Next);
+            }
+
+            current = ty.truncate(current.wrapping_add(1));
+        }
+
+        // The iterator only lives as long as the loop. If anything goes
+        // wrong, the whole frame is thrown away anyway.
+        self.frame().scopes.push(HashMap::new());
+
+        let mut flow = Flow::Next;
+        while in_range(current) {
+            self.declare(it_name, Value::Integer(current, ty.clone()));
+
+            match self.exec_block(body)? {
+                Flow::Next | Flow::Continue => { },
+                Flow::Break => break,
+                returned @ Flow::Return(_) => {
+                    flow = returned;
+                    break;
+                },
+            }
+
+            if let Ok(Value::Integer(bits, _)) = self.find_var(it_name, range.span()) {
+                current = *bits;
+            }
+
+            // Checked before adding one, so an `end` at the largest value
+            // of the type doesn't wrap around
+            if compare_to_end(current).is_ge() {
+                break;
+            }
+
+            current = ty.truncate(current.wrapping_add(1));
+        }
+
+        self.frame().scopes.pop();
+
+        Ok(flow)
+    }
+
+    /// The array is evaluated once, and the iterator is a copy of each of
+    /// its elements in turn.
+    fn exec_array_loop(&mut self, it_name: &str, array: &'a ParsedExpression, body: &'a ParsedBlock) -> RunResult<Flow> {
+        let Value::Array(elems) = self.eval(array, None)? else {
+            return Err(Diagnostic::error("can only loop over a range of integers or an array", array.span()));
+        };
+
+        self.frame().scopes.push(HashMap::new());
+
+        let mut flow = Flow::Next;
+        for elem in elems {
+            self.declare(it_name, elem);
+
+            match self.exec_block(body)? {
+                Flow::Next | Flow::Continue => { },
+                Flow::Break => break,
+                returned @ Flow::Return(_) => {
+                    flow = returned;
+                    break;
+                },
+            }
+        }
+
+        self.frame().scopes.pop();
+
+        Ok(flow)
+    }
+
+    fn eval_condition(&mut self, cond: &'a ParsedExpression) -> RunResult<bool> {
+        match self.eval(cond, Some(PrimitiveType::Bool))? {
+            Value::Bool(value) => Ok(value),
+            _ => Err(Diagnostic::error("expected a `bool` condition", cond.span())),
+        }
+    }
+
+    /// Evaluates `expr`. Like in the typechecker, `hint` is the type the
+    /// surrounding code expects, which untyped literals adopt if they can.
+    fn eval(&mut self, expr: &'a ParsedExpression, hint: Option<PrimitiveType>) -> RunResult<Value> {
+        match expr {
+            ParsedExpression::Bool(value, _) => Ok(Value::Bool(*value)),
+            ParsedExpression::NumericConstant(constant, span) => Interpreter::eval_numeric_constant(constant, *span, hint),
+            ParsedExpression::StringLiteral(value, _) => Ok(Value::String(value.clone())),
+            ParsedExpression::CharLiteral(value, _) => Ok(Value::Char(*value as u32)),
+            ParsedExpression::Null(_) => Ok(Value::Pointer(None)),
+            ParsedExpression::Var(name, span) => {
+                let local = self.frame().scopes.iter().rev().find_map(|scope| scope.get(name.as_str()));
+                match local {
+                    Some(value) => Ok(value.clone()),
+                    None => self.eval_const(name, *span),
+                }
+            },
+            ParsedExpression::NamespacedVar(namespace, name, span) => self.eval_enum_value(namespace, name, &[], *span),
+            ParsedExpression::Range(_, _, _, _, span) => Err(Diagnostic::error("ranges can only be used in `for` loops", *span)),
+            ParsedExpression::Match(matched, cases, result_type, span) => {
+                let hint = self.primitive_of(result_type);
+                self.eval_match(matched, cases, *span, hint)
+            },
+            ParsedExpression::If(cond, then_body, else_body, result_type, span) => {
+                let hint = self.primitive_of(result_type);
+
+                match self.eval_condition(cond)? {
+                    true => self.eval_match_body(then_body, *span, hint),
+                    false => self.eval_match_body(else_body, *span, hint),
+                }
+            },
+            ParsedExpression::ArrayLiteral(elems, array_type, _) => {
+                let hint = self.elem_primitive_of(array_type);
+                let elems = elems.iter().map(|elem| self.eval(elem, hint.clone())).collect::<RunResult<_>>()?;
+                Ok(Value::Array(elems))
+            },
+            ParsedExpression::ArrayRepeat(value, _, array_type, _) => {
+                let hint = self.elem_primitive_of(array_type);
+                let value = self.eval(value, hint)?;
+
+                // The typechecker already worked out how many copies there are
+                let Some(TypeInfo::Array(_, _, len)) = self.resolve_type(array_type).map(|id| &self.types[id]) else {
+                    unreachable!("Array literals are given their type by the typechecker");
+                };
+
+                Ok(Value::Array(vec![value; *len as usize]))
+            },
+            ParsedExpression::Index(array, index, _) => {
+                let array = self.eval(array, None)?;
+                let index_value = self.eval(index, Some(PrimitiveType::I64))?;
+
+                let Value::Array(mut elems) = array else {
+                    return Err(Diagnostic::error("can only index into arrays", expr.span()));
+                };
+
+                let index = Interpreter::array_index(&index_value, elems.len(), index.span())?;
+                Ok(elems.swap_remove(index))
+            },
+            ParsedExpression::StructLiteral(struct_type, members, span) => self.eval_struct_literal(struct_type, members, *span),
+            ParsedExpression::Member(base, name, span) => {
+                let value = match self.eval(base, None)? {
+                    Value::Pointer(Some(address)) => self.value_at(&address, *span)?.clone(),
+                    Value::Pointer(None) => return Err(Diagnostic::error("dereferenced a null pointer", *span)),
+                    value => value,
+                };
+
+                let Value::Struct(type_id, mut members) = value else {
+                    return Err(Diagnostic::error("only structs have members", *span));
+                };
+
+                let idx = self.member_index(type_id, name);
+                Ok(members.swap_remove(idx))
+            },
+            ParsedExpression::MethodCall(_, _) => unreachable!("Method calls are turned into proc calls by the typechecker"),
+            ParsedExpression::UnaryOperation(operand, op, span) => self.eval_unary_operation(operand, op, *span, hint),
+            ParsedExpression::BinaryOperation(lhs, op, rhs, span) => self.eval_binary_operation(lhs, op, rhs, *span, hint),
+            ParsedExpression::ProcCall(call) => self.eval_proc_call(call),
+            ParsedExpression::Operator(_, span) => unreachable!("Stray operator at {:?}", span),
+            ParsedExpression::Invalid => unreachable!("Invalid expressions never make it past the typechecker"),
+        }
+    }
+
+    fn eval_numeric_constant(constant: &NumericConstant, span: Span, hint: Option<PrimitiveType>) -> RunResult<Value> {
+        // A suffix decides the type, no matter what the surrounding code
+        // expects
+        let hint = constant.suffix().cloned().or(hint);
+
+        match constant {
+            NumericConstant::Integer(digits, format, _) => {
+                let radix = match format {
+                    IntegerLiteralFormat::Binary => 2,
+                    IntegerLiteralFormat::Octal => 8,
+                    IntegerLiteralFormat::Decimal => 10,
+                    IntegerLiteralFormat::Hexadecimal => 16,
+                };
+
+                let value = u64::from_str_radix(digits, radix)
+                    .map_err(|_| Diagnostic::error("invalid integer literal", span))?;
+
+                match hint {
+                    Some(hint) if hint.is_float() => Ok(Value::float(value as f64, hint)),
+                    Some(hint) if hint.is_integer() => Ok(Value::integer(value, hint)),
+                    _ => Ok(Value::Integer(value, PrimitiveType::I64)),
+                }
+            },
+            NumericConstant::FloatingPoint(digits, _format, _) => {
+                let value: f64 = digits.parse()
+                    .map_err(|_| Diagnostic::error("invalid floating point literal", span))?;
+
+                match hint {
+                    Some(hint) if hint.is_float() => Ok(Value::float(value, hint)),
+                    _ => Ok(Value::Float(value, PrimitiveType::F64)),
+                }
+            },
+        }
+    }
+
+    /// Evaluates both operands of a binary operation. When only one of them
+    /// is an untyped literal, the other one goes first so the literal can
+    /// take on its type. Literals can't have side effects, so this doesn't
+    /// change what the program does.
+    fn eval_operands(
+        &mut self,
+        lhs: &'a ParsedExpression,
+        rhs: &'a ParsedExpression,
+        hint: Option<PrimitiveType>
+    ) -> RunResult<(Value, Value)> {
+        if lhs.is_untyped_literal() && !rhs.is_untyped_literal() {
+            let rhs = self.eval(rhs, hint)?;
+            let lhs = self.eval(lhs, rhs.primitive())?;
+            Ok((lhs, rhs))
+        } else {
+            let lhs = self.eval(lhs, hint)?;
+            let rhs = self.eval(rhs, lhs.primitive())?;
+            Ok((lhs, rhs))
+        }
+    }
+
+    fn eval_unary_operation(
+        &mut self,
+        operand: &'a ParsedExpression,
+        op: &'a UnaryOperator,
+        span: Span,
+        hint: Option<PrimitiveType>
+    ) -> RunResult<Value> {
+        match op {
+            UnaryOperator::TypeCast(to_type) => {
+                let value = self.eval(operand, None)?;
+                let to = self.primitive_of(to_type);
+
+                // There's no flat memory to turn pointers into integers
+                // with, so only `null` and `0` convert between them
+                match (value, to) {
+                    (Value::Pointer(None), Some(to)) => Ok(Value::integer(0, to)),
+                    (Value::Pointer(Some(_)), Some(_)) => Err(Diagnostic::error("the interpreter can't turn a pointer into an integer", span)),
+                    (Value::Integer(0, _), None) => Ok(Value::Pointer(None)),
+                    (Value::Integer(_, _), None) => Err(Diagnostic::error("the interpreter can only turn `0` into a pointer", span)),
+                    (value, Some(to)) => Ok(Interpreter::cast(value, to)),
+                    (value, None) => Ok(value),
+                }
+            },
+            UnaryOperator::Negate => match self.eval(operand, hint)? {
+                Value::Integer(bits, ty) => Ok(Value::integer(bits.wrapping_neg(), ty)),
+                Value::Float(value, ty) => Ok(Value::Float(-value, ty)),
+                _ => Err(Diagnostic::error("cannot apply unary operator `-` to this value", span)),
+            },
+            UnaryOperator::LogicalNot => match self.eval(operand, Some(PrimitiveType::Bool))? {
+                Value::Bool(value) => Ok(Value::Bool(!value)),
+                _ => Err(Diagnostic::error("cannot apply unary operator `!` to this value", span)),
+            },
+            UnaryOperator::BitwiseNot => match self.eval(operand, hint)? {
+                Value::Integer(bits, ty) => Ok(Value::integer(!bits, ty)),
+                _ => Err(Diagnostic::error("cannot apply unary operator `~` to this value", span)),
+            },
+            UnaryOperator::PreIncrement  |
+            UnaryOperator::PostIncrement |
+            UnaryOperator::PreDecrement  |
+            UnaryOperator::PostDecrement => {
+                let place = self.eval_place(operand)?;
+
+                let is_increment = matches!(op, UnaryOperator::PreIncrement | UnaryOperator::PostIncrement);
+                let var = self.value_at(&place, span)?;
+                let old_value = var.clone();
+
+                *var = match &old_value {
+                    Value::Pointer(pointer) => Interpreter::offset_pointer(pointer.clone(), if is_increment { 1 } else { -1 }, span)?,
+                    Value::Integer(bits, ty) if is_increment => Value::integer(bits.wrapping_add(1), ty.clone()),
+                    Value::Integer(bits, ty) => Value::integer(bits.wrapping_sub(1), ty.clone()),
+                    Value::Float(value, ty) if is_increment => Value::float(value + 1.0, ty.clone()),
+                    Value::Float(value, ty) => Value::float(value - 1.0, ty.clone()),
+                    _ => return Err(Diagnostic::error("can only increment or decrement numbers", span)),
+                };
+
+                match op {
+                    UnaryOperator::PreIncrement | UnaryOperator::PreDecrement => Ok(var.clone()),
+                    _ => Ok(old_value),
+                }
+            },
+            UnaryOperator::AddressOf => Ok(Value::Pointer(Some(self.eval_place(operand)?))),
+            UnaryOperator::Dereference => {
+                let address = self.deref(operand, span)?;
+                Ok(self.value_at(&address, span)?.clone())
+            },
+        }
+    }
+
+    /// Converts between any two of the numeric types, `bool` and `char`,
+    /// following the same rules as the LLVM backend.
+    pub fn cast(value: Value, to: PrimitiveType) -> Value {
+        // Integers are widened according to the signedness of the type
+        // they come from. `bool` and `char` count as unsigned.
+        let (bits, from_signed) = match &value {
+            Value::Bool(value) => (*value as u64, false),
+            Value::Char(value) => (*value as u64, false),
+            Value::Integer(bits, ty) if ty.is_signed() => (ty.sign_extend(*bits) as u64, true),
+            Value::Integer(bits, _) => (*bits, false),
+            Value::Float(float, _) => {
+                return match to {
+                    PrimitiveType::Bool => Value::Bool(*float != 0.0 && !float.is_nan()),
+                    PrimitiveType::Char => Value::Char(*float as u32),
+                    _ if to.is_float() => Value::float(*float, to),
+                    // Floats that don't fit saturate at the smallest or
+                    // largest value of the type, and NaN becomes 0
+                    _ if to.is_signed() => {
+                        let max = (to.truncate(u64::MAX) >> 1) as i64;
+                        Value::integer((*float as i64).clamp(-max - 1, max) as u64, to)
+                    },
+                    _ if to.is_integer() => Value::integer((*float as u64).min(to.truncate(u64::MAX)), to),
+                    _ => value,
+                };
+            },
+            _ => return value,
+        };
+
+        match to {
+            PrimitiveType::Bool => Value::Bool(bits != 0),
+            PrimitiveType::Char => Value::Char(bits as u32),
+            PrimitiveType::F32 if from_signed => Value::Float(bits as i64 as f32 as f64, to),
+            PrimitiveType::F32 => Value::Float(bits as f32 as f64, to),
+            PrimitiveType::F64 if from_signed => Value::Float(bits as i64 as f64, to),
+            PrimitiveType::F64 => Value::Float(bits as f64, to),
+            _ if to.is_integer() => Value::integer(bits, to),
+            _ => value,
+        }
+    }
+
+    fn eval_binary_operation(
+        &mut self,
+        lhs: &'a ParsedExpression,
+        op: &'a BinaryOperator,
+        rhs: &'a ParsedExpression,
+        span: Span,
+        hint: Option<PrimitiveType>
+    ) -> RunResult<Value> {
+        if op.is_assignment() {
+            let place = self.eval_place(lhs)?;
+            return self.eval_assignment(&place, op, rhs, span);
+        }
+
+        // `&&` and `||` only evaluate their right side when the left one
+        // doesn't already decide the result
+        if matches!(op, BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr) {
+            let is_and = matches!(op, BinaryOperator::LogicalAnd);
+
+            if self.eval_condition(lhs)? != is_and {
+                return Ok(Value::Bool(!is_and));
+            }
+
+            return Ok(Value::Bool(self.eval_condition(rhs)?));
+        }
+
+        let operand_hint = match op {
+            BinaryOperator::LogicalXOR => Some(PrimitiveType::Bool),
+            BinaryOperator::NEQ | BinaryOperator::EQ |
+            BinaryOperator::LT  | BinaryOperator::GT |
+            BinaryOperator::LEQ | BinaryOperator::GEQ => None,
+            _ => hint,
+        };
+
+        let (lhs, rhs) = self.eval_operands(lhs, rhs, operand_hint)?;
+        Interpreter::apply_binary_operation(lhs, op, rhs, span)
+    }
+
+    fn eval_assignment(
+        &mut self,
+        place: &Address,
+        op: &BinaryOperator,
+        rhs: &'a ParsedExpression,
+        span: Span
+    ) -> RunResult<Value> {
+        let current = self.value_at(place, span)?.clone();
+        let rhs = self.eval(rhs, current.primitive())?;
+
+        let value = match op.without_assignment() {
+            Some(bin_op) => Interpreter::apply_binary_operation(current, &bin_op, rhs, span)?,
+            None => rhs,
+        };
+
+        *self.value_at(place, span)? = value;
+
+        Ok(Value::Nothing)
+    }
+
+    pub fn apply_binary_operation(lhs: Value, op: &BinaryOperator, rhs: Value, span: Span) -> RunResult<Value> {
+        let value = match (lhs, rhs) {
+            (Value::Integer(lhs, ty), Value::Integer(rhs, _)) => return Interpreter::apply_integer_operation(lhs, op, rhs, ty, span),
+            (Value::Float(lhs, ty), Value::Float(rhs, _)) => match op {
+                BinaryOperator::Add => Value::float(lhs + rhs, ty),
+                BinaryOperator::Subtract => Value::float(lhs - rhs, ty),
+                BinaryOperator::Multiply => Value::float(lhs * rhs, ty),
+                BinaryOperator::Divide => Value::float(lhs / rhs, ty),
+                BinaryOperator::Modulo => Value::float(lhs % rhs, ty),
+                BinaryOperator::EQ => Value::Bool(lhs == rhs),
+                BinaryOperator::NEQ => Value::Bool(lhs != rhs),
+                BinaryOperator::LT => Value::Bool(lhs < rhs),
+                BinaryOperator::GT => Value::Bool(lhs > rhs),
+                BinaryOperator::LEQ => Value::Bool(lhs <= rhs),
+                BinaryOperator::GEQ => Value::Bool(lhs >= rhs),
+                _ => return Err(Diagnostic::error(format!("cannot apply `{}` to floats", op.as_str()), span)),
+            },
+            (Value::Bool(lhs), Value::Bool(rhs)) => match op {
+                BinaryOperator::EQ => Value::Bool(lhs == rhs),
+                BinaryOperator::NEQ |
+                BinaryOperator::LogicalXOR => Value::Bool(lhs != rhs),
+                _ => return Err(Diagnostic::error(format!("cannot apply `{}` to `bool`", op.as_str()), span)),
+            },
+            (Value::Char(lhs), Value::Char(rhs)) => match op {
+                BinaryOperator::EQ => Value::Bool(lhs == rhs),
+                BinaryOperator::NEQ => Value::Bool(lhs != rhs),
+                BinaryOperator::LT => Value::Bool(lhs < rhs),
+                BinaryOperator::GT => Value::Bool(lhs > rhs),
+                BinaryOperator::LEQ => Value::Bool(lhs <= rhs),
+                BinaryOperator::GEQ => Value::Bool(lhs >= rhs),
+                _ => return Err(Diagnostic::error(format!("cannot apply `{}` to `char`", op.as_str()), span)),
+            },
+            (Value::Pointer(pointer), Value::Integer(bits, ty)) => {
+                let offset = match ty.is_signed() {
+                    true => ty.sign_extend(bits),
+                    false => bits as i64,
+                };
+
+                return match op {
+                    BinaryOperator::Add => Interpreter::offset_pointer(pointer, offset, span),
+                    BinaryOperator::Subtract => Interpreter::offset_pointer(pointer, offset.wrapping_neg(), span),
+                    _ => Err(Diagnostic::error(format!("cannot apply `{}` to a pointer and an integer", op.as_str()), span)),
+                };
+            },
+            (Value::Pointer(lhs), Value::Pointer(rhs)) => match op {
+                BinaryOperator::EQ => Value::Bool(lhs == rhs),
+                BinaryOperator::NEQ => Value::Bool(lhs != rhs),
+                _ => return Interpreter::apply_pointer_operation(lhs, op, rhs, span),
+            },
+            _ => return Err(Diagnostic::error(format!("mismatched types for `{}`", op.as_str()), span)),
+        };
+
+        Ok(value)
+    }
+
+    /// Subtracting and ordering pointers only means something for two
+    /// pointers into the same array.
+    fn apply_pointer_operation(lhs: Option<Address>, op: &BinaryOperator, rhs: Option<Address>, span: Span) -> RunResult<Value> {
+        let not_same_array = || Diagnostic::error(format!("can only apply `{}` to pointers into the same array", op.as_str()), span);
+
+        let (Some(mut lhs), Some(mut rhs)) = (lhs, rhs) else {
+            return Err(not_same_array());
+        };
+
+        let (Some(Step::Elem(lhs_index)), Some(Step::Elem(rhs_index))) = (lhs.path.pop(), rhs.path.pop()) else {
+            return Err(not_same_array());
+        };
+
+        if lhs != rhs {
+            return Err(not_same_array());
+        }
+
+        let value = match op {
+            BinaryOperator::Subtract => Value::Integer(lhs_index.wrapping_sub(rhs_index) as u64, PrimitiveType::I64),
+            BinaryOperator::LT => Value::Bool(lhs_index < rhs_index),
+            BinaryOperator::GT => Value::Bool(lhs_index > rhs_index),
+            BinaryOperator::LEQ => Value::Bool(lhs_index <= rhs_index),
+            BinaryOperator::GEQ => Value::Bool(lhs_index >= rhs_index),
+            _ => return Err(Diagnostic::error(format!("cannot apply `{}` to pointers", op.as_str()), span)),
+        };
+
+        Ok(value)
+    }
+
+    /// Integer arithmetic wraps around like it does in the compiled
+    /// program. Dividing by zero and shifting by more than the width of
+    /// the type are undefined there, so they're reported instead.
+    fn apply_integer_operation(lhs: u64, op: &BinaryOperator, rhs: u64, ty: PrimitiveType, span: Span) -> RunResult<Value> {
+        let is_signed = ty.is_signed();
+        let (signed_lhs, signed_rhs) = (ty.sign_extend(lhs), ty.sign_extend(rhs));
+
+        if matches!(op, BinaryOperator::Divide | BinaryOperator::Modulo) && rhs == 0 {
+            return Err(Diagnostic::error("attempt to divide by zero", span));
+        }
+
+        if matches!(op, BinaryOperator::BitwiseLeftShift | BinaryOperator::BitwiseRightShift) && rhs >= ty.bit_width() as u64 {
+            return Err(Diagnostic::error(format!("attempt to shift a `{}` by {}, which is more than its width", ty.as_str(), rhs), span));
+        }
+
+        let bits = match op {
+            BinaryOperator::Add => lhs.wrapping_add(rhs),
+            BinaryOperator::Subtract => lhs.wrapping_sub(rhs),
+            BinaryOperator::Multiply => lhs.wrapping_mul(rhs),
+            BinaryOperator::Divide if is_signed => signed_lhs.wrapping_div(signed_rhs) as u64,
+            BinaryOperator::Divide => lhs / rhs,
+            BinaryOperator::Modulo if is_signed => signed_lhs.wrapping_rem(signed_rhs) as u64,
+            BinaryOperator::Modulo => lhs % rhs,
+
+            BinaryOperator::BitwiseAnd => lhs & rhs,
+            BinaryOperator::BitwiseOr => lhs | rhs,
+            BinaryOperator::BitwiseXOR => lhs ^ rhs,
+            BinaryOperator::BitwiseLeftShift => lhs << rhs,
+            BinaryOperator::BitwiseRightShift if is_signed => (signed_lhs >> rhs) as u64,
+            BinaryOperator::BitwiseRightShift => lhs >> rhs,
+
+            BinaryOperator::EQ  |
+            BinaryOperator::NEQ |
+            BinaryOperator::LT  |
+            BinaryOperator::GT  |
+            BinaryOperator::LEQ |
+            BinaryOperator::GEQ => {
+                let ordering = match is_signed {
+                    true => signed_lhs.cmp(&signed_rhs),
+                    false => lhs.cmp(&rhs),
+                };
+
+                let result = match op {
+                    BinaryOperator::EQ => ordering.is_eq(),
+                    BinaryOperator::NEQ => ordering.is_ne(),
+                    BinaryOperator::LT => ordering.is_lt(),
+                    BinaryOperator::GT => ordering.is_gt(),
+                    BinaryOperator::LEQ => ordering.is_le(),
+                    _ => ordering.is_ge(),
+                };
+
+                return Ok(Value::Bool(result));
+            },
+
+            _ => return Err(Diagnostic::error(format!("cannot apply `{}` to integers", op.as_str()), span)),
+        };
+
+        Ok(Value::integer(bits, ty))
+    }
+
+    /// Members are evaluated in the order they're declared in, not the order
+    /// they're given in, same as in the compiled program.
+    fn eval_struct_literal(&mut self, struct_type: &ParsedType, members: &'a [ParsedVarDecl], span: Span) -> RunResult<Value> {
+        let Some(type_id) = self.resolve_type(struct_type) else {
+            return Err(Diagnostic::error("can only make structs with `Name{...}`", span));
+        };
+
+        let TypeInfo::Struct(_, fields) = &self.types[type_id] else {
+            return Err(Diagnostic::error("can only make structs with `Name{...}`", span));
+        };
+
+        let mut values = Vec::new();
+
+        for (field_name, field_type) in fields {
+            let Some(member) = members.iter().find(|member| member.name == *field_name) else {
+                return Err(Diagnostic::error(format!("missing member `{}`", field_name), span));
+            };
+
+            let hint = self.types[*field_type].primitive().cloned();
+            values.push(self.eval(&member.defualt_value, hint)?);
+        }
+
+        Ok(Value::Struct(type_id, values))
+    }
+
+    /// Arguments are evaluated in the order of the proc's parameters, not
+    /// the order they're passed in, same as in the compiled program.
+    fn eval_proc_call(&mut self, call: &'a ParsedProcCall) -> RunResult<Value> {
+        if !call.namespace.is_empty() {
+            return self.eval_enum_value(&call.namespace, &call.name, &call.passed_parameters, call.span);
+        }
+
+        let Some(proc) = self.procs.get(call.name.as_str()).copied() else {
+            return Err(Diagnostic::error(format!("cannot find proc `{}`", call.name), call.span));
+        };
+
+        let mut args = Vec::new();
+
+        for param in &proc.parameters {
+            let Some(arg) = call.passed_parameters.iter().find(|arg| arg.name == param.name) else {
+                return Err(Diagnostic::error(format!("missing argument for `{}` in call to `{}`", param.name, call.name), call.span));
+            };
+
+            let hint = self.primitive_of(&param.parsed_type);
+            args.push(self.eval(&arg.defualt_value, hint)?);
+        }
+
+        if proc.attributes.intrinsic.is_some() {
+            return self.eval_intrinsic_call(proc, call, args);
+        }
+
+        self.call_proc(proc, args, call.span)
+    }
+
+    /// Runs an `#intrinsic` proc, which does what the libc calls it's
+    /// compiled to would. The extra arguments come after the labeled ones,
+    /// in the order they're passed.
+    fn eval_intrinsic_call(&mut self, proc: &'a ParsedProcDecl, call: &'a ParsedProcCall, mut args: Vec<Value>) -> RunResult<Value> {
+        let name = proc.name.rsplit("::").next().unwrap_or(&proc.name);
+        let Some(intrinsic) = Intrinsic::from_name(name) else {
+            return Err(Diagnostic::error(format!("there's no intrinsic called `{}`", name), call.span));
+        };
+
+        for arg in call.passed_parameters.iter().filter(|arg| arg.name.is_empty()) {
+            args.push(self.eval(&arg.defualt_value, None)?);
+        }
+
+        let string = |value: &Value| match value {
+            Value::String(value) => value.clone(),
+            _ => unreachable!("The typechecker makes sure this is a string"),
+        };
+        let handle = |value: &Value| value.to_bits();
+
+        let value = match intrinsic {
+            Intrinsic::Print | Intrinsic::Println => {
+                let format = intrinsics::parse_format(&string(&args[0])).map_err(|message| Diagnostic::error(message, call.span))?;
+                let mut values = args[1..].iter();
+                let mut output = String::new();
+
+                for piece in format {
+                    match piece {
+                        FormatPiece::Text(text) => output.push_str(&text),
+                        FormatPiece::Verb(verb) => output.push_str(&verb.write(values.next().expect("The typechecker makes sure every verb has an argument"))),
+                    }
+                }
+
+                if intrinsic == Intrinsic::Println {
+                    output.push('\n');
+                }
+
+                // Like `printf`, output that can't be written is dropped
+                let _ = write!(io::stdout(), "{}", output);
+                Value::Nothing
+            },
+            Intrinsic::ReadLine => Value::String(intrinsics::read_line()),
+            Intrinsic::Open => Value::Integer(self.files.open(&string(&args[0]), &string(&args[1])), PrimitiveType::U64),
+            Intrinsic::Read => Value::String(self.files.read(handle(&args[0]))),
+            Intrinsic::Write => Value::Bool(self.files.write(handle(&args[0]), &string(&args[1]))),
+            Intrinsic::Close => Value::Bool(self.files.close(handle(&args[0]))),
+        };
+
+        Ok(value)
+    }
+
+    /// Builds `Enum::Variant`, whose payload is evaluated in the order it's
+    /// declared in, like the arguments of a call.
+    fn eval_enum_value(&mut self, namespace: &[String], name: &str, args: &'a [ParsedVarDecl], span: Span) -> RunResult<Value> {
+        let types = self.types;
+
+        let variant_name = format!("{}::{}", namespace.join("::"), name);
+        let variant = types.iter().position(|info| matches!(info, TypeInfo::EnumVariant(name, _) if *name == variant_name));
+
+        let Some(variant_id) = variant else {
+            return Err(Diagnostic::error(format!("cannot find `{}`", variant_name), span));
+        };
+
+        let TypeInfo::EnumVariant(variant_name, payload) = &types[variant_id] else {
+            unreachable!();
+        };
+
+        let mut values = Vec::new();
+
+        for (idx, (field_name, field_type)) in payload.iter().enumerate() {
+            // Unlabeled payloads are passed in order
+            let arg = args.iter()
+                          .find(|arg| arg.name == *field_name)
+                          .or_else(|| args.get(idx).filter(|arg| arg.name.is_empty()));
+
+            let Some(arg) = arg else {
+                return Err(Diagnostic::error(format!("missing field `{}` in payload of `{}`", field_name, variant_name), span));
+            };
+
+            let hint = types[*field_type].primitive().cloned();
+            values.push(self.eval(&arg.defualt_value, hint)?);
+        }
+
+        Ok(Value::Enum(variant_id, values))
+    }
+
+    /// Runs the first arm whose pattern matches. The typechecker made sure
+    /// there always is one.
+    fn eval_match(
+        &mut self,
+        matched: &'a ParsedExpression,
+        cases: &'a [MatchExprCase],
+        span: Span,
+        hint: Option<PrimitiveType>
+    ) -> RunResult<Value> {
+        let value = self.eval(matched, None)?;
+
+        for case in cases {
+            let (bindings, body) = match case {
+                MatchExprCase::Expr(case_expr, body) => {
+                    let case_value = self.eval(case_expr, value.primitive())?;
+                    if Interpreter::apply_binary_operation(value.clone(), &BinaryOperator::EQ, case_value, span)? != Value::Bool(true) {
+                        continue;
+                    }
+
+                    (HashMap::new(), body)
+                },
+                MatchExprCase::EnumVariant(pattern, body) => {
+                    let Value::Enum(variant_id, payload) = &value else { continue };
+                    let TypeInfo::EnumVariant(variant_name, fields) = &self.types[*variant_id] else { continue };
+
+                    if *variant_name != format!("{}::{}", pattern.namespace.join("::"), pattern.name) {
+                        continue;
+                    }
+
+                    // Unlabeled payload fields are named after their position
+                    let is_labeled = fields.first().is_some_and(|(field_name, _)| field_name.parse::<usize>().is_err());
+                    let mut bindings = HashMap::new();
+
+                    for (position, binding) in pattern.bindings.iter().flatten().enumerate() {
+                        let idx = match is_labeled {
+                            true => {
+                                let field = if binding.label.is_empty() { &binding.name } else { &binding.label };
+                                fields.iter().position(|(field_name, _)| field_name == field)
+                            },
+                            false => Some(position),
+                        };
+
+                        if let Some(value) = idx.and_then(|idx| payload.get(idx)).filter(|_| binding.name != "_") {
+                            bindings.insert(binding.name.clone(), value.clone());
+                        }
+                    }
+
+                    (bindings, body)
+                },
+                MatchExprCase::Fallback(_, body) => (HashMap::new(), body),
+            };
+
+            // The bindings are popped even if the arm unwinds, since the
+            // frame keeps being used after that
+            self.frame().scopes.push(bindings);
+
+            let result = self.eval_match_body(body, span, hint);
+            self.frame().scopes.pop();
+
+            return result;
+        }
+
+        unreachable!("Matches always cover every value")
+    }
+
+    /// Gives the value of a match arm, or a branch of an `if`. A block
+    /// yields nothing, and control flow that leaves it unwinds up to
+    /// whatever it's leaving to.
+    fn eval_match_body(&mut self, body: &'a MatchExprBody, span: Span, hint: Option<PrimitiveType>) -> RunResult<Value> {
+        match body {
+            MatchExprBody::Expr(body_expr) => self.eval(body_expr, hint),
+            MatchExprBody::Block(block) => match self.exec_block(block)? {
+                Flow::Next => Ok(Value::Nothing),
+                flow => {
+                    self.unwinding = Some(flow);
+                    Err(Diagnostic::error("control flow left the middle of an expression", span))
+                },
+            },
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use std::fs::{ File, OpenOptions };
+use std::io::{ self, Read, Write };
+
+use crate::interpreter::Value;
+use crate::token::PrimitiveType;
+
+/// A proc the compiler implements itself. The standard library declares
+/// them with `#intrinsic`, so they're imported like any other proc.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Intrinsic {
+    Print,
+    Println,
+    ReadLine,
+    Open,
+    Read,
+    Write,
+    Close,
+}
+
+impl Intrinsic {
+    pub const ALL: [Intrinsic; 7] = [
+        Intrinsic::Print,
+        Intrinsic::Println,
+        Intrinsic::ReadLine,
+        Intrinsic::Open,
+        Intrinsic::Read,
+        Intrinsic::Write,
+        Intrinsic::Close,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Intrinsic> {
+        Self::ALL.into_iter().find(|intrinsic| intrinsic.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Intrinsic::Print => "print",
+            Intrinsic::Println => "println",
+            Intrinsic::ReadLine => "read_line",
+            Intrinsic::Open => "open",
+            Intrinsic::Read => "read",
+            Intrinsic::Write => "write",
+            Intrinsic::Close => "close",
+        }
+    }
+
+    /// How the intrinsic has to be declared, written the way it would be
+    /// after `decl name :`.
+    pub fn signature(&self) -> &'static str {
+        match self {
+            Intrinsic::Print | Intrinsic::Println => "(format: string, ..)",
+            Intrinsic::ReadLine => "() -> string",
+            Intrinsic::Open => "(path: string, mode: string) -> u64",
+            Intrinsic::Read => "(file: u64) -> string",
+            Intrinsic::Write => "(file: u64, text: string) -> bool",
+            Intrinsic::Close => "(file: u64) -> bool",
+        }
+    }
+
+    /// Whether the intrinsic's first argument is a format that's checked
+    /// against the rest of them.
+    pub fn takes_format(&self) -> bool {
+        matches!(self, Intrinsic::Print | Intrinsic::Println)
+    }
+
+    pub fn to_u8(self) -> u8 {
+        Self::ALL.iter().position(|intrinsic| *intrinsic == self).unwrap() as u8
+    }
+
+    pub fn from_u8(value: u8) -> Option<Intrinsic> {
+        Self::ALL.get(value as usize).copied()
+    }
+}
+
+/// How an argument is written out by a format verb.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FormatVerb {
+    /// `%v`, any primitive written the usual way
+    Value,
+    /// `%d`, an integer in decimal
+    Decimal,
+    /// `%x`, an integer in lowercase hexadecimal
+    Hex,
+    /// `%f` or `%.2f`, a float with that many digits after the point
+    Float(u8),
+    /// `%s`
+    String,
+    /// `%c`
+    Char,
+    /// `%b`, a bool as `true` or `false`
+    Bool,
+}
+
+/// The digits `%f` writes after the point, like `printf`'s.
+pub const DEFAULT_FLOAT_PRECISION: u8 = 6;
+
+/// A piece of a parsed format: text to write as it is, or a verb that
+/// writes the next argument.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatPiece {
+    Text(String),
+    Verb(FormatVerb),
+}
+
+impl FormatVerb {
+    pub fn as_str(&self) -> String {
+        match self {
+            FormatVerb::Value => String::from("%v"),
+            FormatVerb::Decimal => String::from("%d"),
+            FormatVerb::Hex => String::from("%x"),
+            FormatVerb::Float(DEFAULT_FLOAT_PRECISION) => String::from("%f"),
+            FormatVerb::Float(precision) => format!("%.{}f", precision),
+            FormatVerb::String => String::from("%s"),
+            FormatVerb::Char => String::from("%c"),
+            FormatVerb::Bool => String::from("%b"),
+        }
+    }
+
+    /// What kind of value the verb takes, for error messages.
+    pub fn expects(&self) -> &'static str {
+        match self {
+            FormatVerb::Value => "a primitive",
+            FormatVerb::Decimal | FormatVerb::Hex => "an integer",
+            FormatVerb::Float(_) => "a float",
+            FormatVerb::String => "a `string`",
+            FormatVerb::Char => "a `char`",
+            FormatVerb::Bool => "a `bool`",
+        }
+    }
+
+    pub fn accepts(&self, primitive: &PrimitiveType) -> bool {
+        match self {
+            FormatVerb::Value => *primitive != PrimitiveType::Nothing,
+            FormatVerb::Decimal | FormatVerb::Hex => primitive.is_integer(),
+            FormatVerb::Float(_) => primitive.is_float(),
+            FormatVerb::String => *primitive == PrimitiveType::String,
+            FormatVerb::Char => *primitive == PrimitiveType::Char,
+            FormatVerb::Bool => *primitive == PrimitiveType::Bool,
+        }
+    }
+
+    /// `%v` stands for whichever verb writes the type the usual way.
+    pub fn resolve(self, primitive: &PrimitiveType) -> FormatVerb {
+        match (self, primitive) {
+            (FormatVerb::Value, PrimitiveType::Bool) => FormatVerb::Bool,
+            (FormatVerb::Value, PrimitiveType::Char) => FormatVerb::Char,
+            (FormatVerb::Value, PrimitiveType::String) => FormatVerb::String,
+            (FormatVerb::Value, primitive) if primitive.is_float() => FormatVerb::Float(DEFAULT_FLOAT_PRECISION),
+            (FormatVerb::Value, _) => FormatVerb::Decimal,
+            (verb, _) => verb,
+        }
+    }
+
+    /// Writes `value` the way the verb says to, which is the same as
+    /// what `printf` writes once the program is compiled.
+    pub fn write(&self, value: &Value) -> String {
+        let Some(primitive) = value.primitive() else {
+            return value.to_string();
+        };
+
+        match (self.resolve(&primitive), value) {
+            (FormatVerb::Hex, Value::Integer(bits, _)) => format!("{:x}", bits),
+            (FormatVerb::Float(precision), Value::Float(value, _)) if value.is_finite() => format!("{:.*}", precision as usize, value),
+            _ => value.to_string(),
+        }
+    }
+}
+
+/// Splits a format into text and verbs. `%%` is a `%` on its own.
+pub fn parse_format(format: &str) -> Result<Vec<FormatPiece>, String> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            text.push(c);
+            continue;
+        }
+
+        let mut precision = None;
+        if chars.next_if_eq(&'.').is_some() {
+            let mut digits = String::new();
+            while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                digits.push(digit);
+            }
+
+            match digits.parse::<u8>() {
+                Ok(value) if value <= 20 => precision = Some(value),
+                _ => return Err(String::from("the precision after `%.` has to be a number from 0 to 20")),
+            }
+        }
+
+        let verb = match (chars.next(), precision) {
+            (Some('%'), None) => {
+                text.push('%');
+                continue;
+            },
+            (Some('f'), precision) => FormatVerb::Float(precision.unwrap_or(DEFAULT_FLOAT_PRECISION)),
+            (Some(_), Some(_)) => return Err(String::from("only `%f` can be given a precision")),
+            (Some('v'), None) => FormatVerb::Value,
+            (Some('d'), None) => FormatVerb::Decimal,
+            (Some('x'), None) => FormatVerb::Hex,
+            (Some('s'), None) => FormatVerb::String,
+            (Some('c'), None) => FormatVerb::Char,
+            (Some('b'), None) => FormatVerb::Bool,
+            (Some(other), None) => return Err(format!("`%{}` isn't a format verb", other)),
+            (None, _) => return Err(String::from("the format ends in the middle of a verb")),
+        };
+
+        if !text.is_empty() {
+            pieces.push(FormatPiece::Text(std::mem::take(&mut text)));
+        }
+        pieces.push(FormatPiece::Verb(verb));
+    }
+
+    if !text.is_empty() {
+        pieces.push(FormatPiece::Text(text));
+    }
+
+    Ok(pieces)
+}
+
+/// Reads a line from stdin, without the line break. It's empty once
+/// there's nothing left to read.
+pub fn read_line() -> String {
+    // Whatever was printed as a prompt should show up before the program
+    // waits for input
+    let _ = io::stdout().flush();
+
+    let mut line = String::new();
+    let _ = io::stdin().read_line(&mut line);
+
+    if line.ends_with('\n') {
+        line.pop();
+    }
+
+    line
+}
+
+/// The files a program has open. They're handed out as handles starting
+/// at 1, so 0 can mean that a file couldn't be opened, like the null
+/// `FILE *` it is once compiled.
+#[derive(Default)]
+pub struct Files {
+    open: Vec<Option<File>>,
+}
+
+impl Files {
+    /// Opens the file at `path` with a C `fopen` mode.
+    pub fn open(&mut self, path: &str, mode: &str) -> u64 {
+        let mut options = OpenOptions::new();
+        let update = mode.contains('+');
+
+        match mode.trim_end_matches(['b', '+']) {
+            "r" => options.read(true).write(update),
+            "w" => options.write(true).create(true).truncate(true).read(update),
+            "a" => options.append(true).create(true).read(update),
+            _ => return 0,
+        };
+
+        match options.open(path) {
+            Ok(file) => {
+                self.open.push(Some(file));
+                self.open.len() as u64
+            },
+            Err(_) => 0,
+        }
+    }
+
+    fn get(&mut self, handle: u64) -> Option<&mut File> {
+        let idx = (handle as usize).checked_sub(1)?;
+        self.open.get_mut(idx)?.as_mut()
+    }
+
+    /// Reads everything that's left in the file.
+    pub fn read(&mut self, handle: u64) -> String {
+        let mut contents = Vec::new();
+
+        if let Some(file) = self.get(handle) {
+            let _ = file.read_to_end(&mut contents);
+        }
+
+        String::from_utf8_lossy(&contents).into_owned()
+    }
+
+    pub fn write(&mut self, handle: u64, text: &str) -> bool {
+        self.get(handle).is_some_and(|file| file.write_all(text.as_bytes()).is_ok())
+    }
+
+    pub fn close(&mut self, handle: u64) -> bool {
+        let Some(idx) = (handle as usize).checked_sub(1) else { return false };
+        self.open.get_mut(idx).and_then(Option::take).is_some()
+    }
+}
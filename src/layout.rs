@@ -0,0 +1,275 @@
+use crate::token::PrimitiveType;
+use crate::typechecker::{ TypeID, TypeInfo };
+
+/// How many bytes a pointer takes up. Only targets with 64-bit pointers
+/// are supported, which `TargetMachine::new` makes sure of.
+pub const POINTER_SIZE: usize = 8;
+
+/// The most bytes a type can take up. Offsets into it have to fit in a
+/// signed pointer-sized integer.
+pub const MAX_SIZE: usize = isize::MAX as usize;
+
+/// How many bytes a type takes up and what its address has to be a
+/// multiple of. Structs are laid out the way C lays them out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Layout {
+    pub size: usize,
+    pub align: usize,
+}
+
+impl Layout {
+    fn scalar(size: usize) -> Self {
+        Self { size, align: size.max(1) }
+    }
+
+    /// Lays `fields` out one after the other, each at the next offset that
+    /// suits its alignment. Gives back the whole thing's layout and the
+    /// offset of every field, or `None` if it's bigger than `MAX_SIZE`.
+    pub fn of_fields(fields: impl IntoIterator<Item = Layout>) -> Option<(Layout, Vec<usize>)> {
+        let mut layout = Layout { size: 0, align: 1 };
+        let mut offsets = Vec::new();
+
+        for field in fields {
+            let offset = align_up(layout.size, field.align)?;
+            offsets.push(offset);
+
+            layout.size = offset.checked_add(field.size).filter(|size| *size <= MAX_SIZE)?;
+            layout.align = layout.align.max(field.align);
+        }
+
+        // Padded so that every element of an array is aligned
+        layout.size = align_up(layout.size, layout.align)?;
+        Some((layout, offsets))
+    }
+}
+
+/// Why a type can't be laid out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayoutError {
+    /// It takes up more than `MAX_SIZE` bytes
+    TooBig,
+    /// It contains itself by value, so it would take up infinitely many
+    Recursive,
+}
+
+/// An enum is a tagged union: a discriminant saying which variant a value
+/// is, followed by enough room for the payload of any of its variants.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumLayout {
+    pub discriminant: PrimitiveType,
+    /// Where the payload starts, which is aligned for every variant's payload
+    pub payload_offset: usize,
+    /// The room for the payloads, which is as big and as aligned as the
+    /// biggest and most aligned of them
+    pub payload: Layout,
+    pub layout: Layout,
+}
+
+fn align_up(offset: usize, align: usize) -> Option<usize> {
+    offset.div_ceil(align).checked_mul(align).filter(|offset| *offset <= MAX_SIZE)
+}
+
+/// The smallest unsigned integer that can number `variant_count` variants.
+pub fn discriminant_type(variant_count: usize) -> PrimitiveType {
+    match variant_count {
+        0..=0x100 => PrimitiveType::U8,
+        0x101..=0x1_0000 => PrimitiveType::U16,
+        _ => PrimitiveType::U32,
+    }
+}
+
+pub fn layout_of(types: &[TypeInfo], type_id: TypeID) -> Result<Layout, LayoutError> {
+    layout_within(types, type_id, &mut Vec::new())
+}
+
+// Only LLVM needs more of an enum's layout than its size
+#[cfg_attr(not(feature = "llvm"), allow(dead_code))]
+pub fn enum_layout(types: &[TypeInfo], variants: &[TypeID]) -> Result<EnumLayout, LayoutError> {
+    enum_layout_within(types, variants, &mut Vec::new())
+}
+
+/// Lays out a type that's part of each of `outer`, the structs and enums
+/// it's nested in.
+fn layout_within(types: &[TypeInfo], type_id: TypeID, outer: &mut Vec<TypeID>) -> Result<Layout, LayoutError> {
+    let fields_within = |fields: &[(String, TypeID)], outer: &mut Vec<TypeID>| {
+        let fields = fields.iter().map(|(_, field)| layout_within(types, *field, outer)).collect::<Result<Vec<_>, _>>()?;
+        Layout::of_fields(fields).map(|(layout, _)| layout).ok_or(LayoutError::TooBig)
+    };
+
+    let is_aggregate = matches!(types[type_id], TypeInfo::Struct(_, _) | TypeInfo::Enum(_, _));
+    if is_aggregate {
+        if outer.contains(&type_id) {
+            return Err(LayoutError::Recursive);
+        }
+
+        outer.push(type_id);
+    }
+
+    let layout = match &types[type_id] {
+        TypeInfo::Primitive(_primitive, size) => Ok(Layout::scalar(*size)),
+        TypeInfo::Struct(_name, members) => fields_within(members, outer),
+        TypeInfo::Enum(_name, variants) => enum_layout_within(types, variants, outer).map(|layout| layout.layout),
+        TypeInfo::EnumVariant(_name, payload) => fields_within(payload, outer),
+        // Elements are already padded to their alignment, so they're packed
+        TypeInfo::Array(_name, elem_type, len) => {
+            let elem = layout_within(types, *elem_type, outer)?;
+            let size = usize::try_from(*len).ok()
+                                            .and_then(|len| elem.size.checked_mul(len))
+                                            .filter(|size| *size <= MAX_SIZE)
+                                            .ok_or(LayoutError::TooBig)?;
+            Ok(Layout { size, align: elem.align })
+        },
+        TypeInfo::Pointer(_name, _pointee) => Ok(Layout::scalar(POINTER_SIZE)),
+        TypeInfo::Unknown => unreachable!("Unknown types never make it past the typechecker"),
+    };
+
+    if is_aggregate {
+        outer.pop();
+    }
+
+    layout
+}
+
+fn enum_layout_within(types: &[TypeInfo], variants: &[TypeID], outer: &mut Vec<TypeID>) -> Result<EnumLayout, LayoutError> {
+    let discriminant = discriminant_type(variants.len());
+    let discriminant_size = match discriminant {
+        PrimitiveType::U8 => 1,
+        PrimitiveType::U16 => 2,
+        _ => 4,
+    };
+
+    let mut payload = Layout { size: 0, align: 1 };
+    for variant in variants {
+        let variant_layout = layout_within(types, *variant, outer)?;
+        payload.size = payload.size.max(variant_layout.size);
+        payload.align = payload.align.max(variant_layout.align);
+    }
+
+    payload.size = align_up(payload.size, payload.align).ok_or(LayoutError::TooBig)?;
+
+    let (layout, offsets) = Layout::of_fields([Layout::scalar(discriminant_size), payload]).ok_or(LayoutError::TooBig)?;
+
+    Ok(EnumLayout {
+        discriminant,
+        payload_offset: offsets[1],
+        payload,
+        layout,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const U8: TypeID = 0;
+    const U16: TypeID = 1;
+    const U32: TypeID = 2;
+    const U64: TypeID = 3;
+
+    /// The primitives above, followed by `extra`.
+    fn with_primitives(extra: Vec<TypeInfo>) -> Vec<TypeInfo> {
+        let mut types = vec![
+            TypeInfo::Primitive(PrimitiveType::U8, 1),
+            TypeInfo::Primitive(PrimitiveType::U16, 2),
+            TypeInfo::Primitive(PrimitiveType::U32, 4),
+            TypeInfo::Primitive(PrimitiveType::U64, 8),
+        ];
+        types.extend(extra);
+        types
+    }
+
+    fn variant(name: &str, payload: &[TypeID]) -> TypeInfo {
+        let payload = payload.iter().enumerate().map(|(i, field)| (i.to_string(), *field)).collect();
+        TypeInfo::EnumVariant(String::from(name), payload)
+    }
+
+    #[test]
+    fn fields_are_laid_out_like_c() {
+        let u8 = Layout::scalar(1);
+        let u32 = Layout::scalar(4);
+        let u64 = Layout::scalar(8);
+
+        assert_eq!(Layout::of_fields([]), Some((Layout { size: 0, align: 1 }, vec![])));
+        assert_eq!(Layout::of_fields([u8, u32, u8]), Some((Layout { size: 12, align: 4 }, vec![0, 4, 8])));
+        assert_eq!(Layout::of_fields([u64, u8]), Some((Layout { size: 16, align: 8 }, vec![0, 8])));
+        assert_eq!(Layout::of_fields([u8, u8, u32]), Some((Layout { size: 8, align: 4 }, vec![0, 1, 4])));
+
+        let huge = Layout { size: MAX_SIZE - 2, align: 1 };
+        assert_eq!(Layout::of_fields([huge, u8, u8]), Some((Layout { size: MAX_SIZE, align: 1 }, vec![0, MAX_SIZE - 2, MAX_SIZE - 1])));
+        assert_eq!(Layout::of_fields([huge, u8, u8, u8]), None);
+        assert_eq!(Layout::of_fields([huge, u32]), None);
+    }
+
+    #[test]
+    fn enum_payloads_go_after_the_discriminant() {
+        // enum { A(u8), B(u64) }
+        let types = with_primitives(vec![variant("A", &[U8]), variant("B", &[U64])]);
+        let layout = enum_layout(&types, &[4, 5]).unwrap();
+
+        assert_eq!(layout.discriminant, PrimitiveType::U8);
+        assert_eq!(layout.payload, Layout { size: 8, align: 8 });
+        assert_eq!(layout.payload_offset, 8);
+        assert_eq!(layout.layout, Layout { size: 16, align: 8 });
+
+        // enum { A, B(u16, u8), C(u8) }
+        let types = with_primitives(vec![variant("A", &[]), variant("B", &[U16, U8]), variant("C", &[U8])]);
+        let layout = enum_layout(&types, &[4, 5, 6]).unwrap();
+
+        assert_eq!(layout.payload, Layout { size: 4, align: 2 });
+        assert_eq!(layout.payload_offset, 2);
+        assert_eq!(layout.layout, Layout { size: 6, align: 2 });
+
+        // enum { A, B } has nothing but its discriminant
+        let types = with_primitives(vec![variant("A", &[]), variant("B", &[])]);
+        let layout = enum_layout(&types, &[4, 5]).unwrap();
+
+        assert_eq!(layout.payload, Layout { size: 0, align: 1 });
+        assert_eq!(layout.payload_offset, 1);
+        assert_eq!(layout.layout, Layout { size: 1, align: 1 });
+    }
+
+    #[test]
+    fn discriminants_grow_with_the_variants() {
+        assert_eq!(discriminant_type(2), PrimitiveType::U8);
+        assert_eq!(discriminant_type(0x100), PrimitiveType::U8);
+        assert_eq!(discriminant_type(0x101), PrimitiveType::U16);
+        assert_eq!(discriminant_type(0x1_0001), PrimitiveType::U32);
+
+        let mut extra: Vec<_> = (0..0x101).map(|i| variant(&i.to_string(), &[])).collect();
+        extra.push(variant("last", &[U32]));
+        let types = with_primitives(extra);
+        let variants: Vec<TypeID> = (4..types.len()).collect();
+        let layout = enum_layout(&types, &variants).unwrap();
+
+        assert_eq!(layout.discriminant, PrimitiveType::U16);
+        assert_eq!(layout.payload_offset, 4);
+        assert_eq!(layout.layout, Layout { size: 8, align: 4 });
+    }
+
+    #[test]
+    fn nested_types_and_arrays() {
+        // S : struct { a: u8, b: [3]u16 }, E : enum { A(S), B(u32) }
+        let types = with_primitives(vec![
+            TypeInfo::Array(String::from("[3]u16"), U16, 3),
+            TypeInfo::Struct(String::from("S"), vec![(String::from("a"), U8), (String::from("b"), 4)]),
+            variant("A", &[5]),
+            variant("B", &[U32]),
+            TypeInfo::Enum(String::from("E"), vec![6, 7]),
+        ]);
+
+        assert_eq!(layout_of(&types, 4), Ok(Layout { size: 6, align: 2 }));
+        assert_eq!(layout_of(&types, 5), Ok(Layout { size: 8, align: 2 }));
+        assert_eq!(layout_of(&types, 8), Ok(Layout { size: 12, align: 4 }));
+    }
+
+    #[test]
+    fn types_that_cant_be_laid_out() {
+        let types = with_primitives(vec![
+            TypeInfo::Array(String::from("[1152921504606846976]u64"), U64, 1 << 60),
+            TypeInfo::Struct(String::from("Loop"), vec![(String::from("next"), 5)]),
+        ]);
+
+        assert_eq!(layout_of(&types, 4), Err(LayoutError::TooBig));
+        assert_eq!(layout_of(&types, 5), Err(LayoutError::Recursive));
+    }
+}
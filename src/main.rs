@@ -1,51 +1,40 @@
+
 mod ast;
+mod bytecode;
+mod checked_ast;
+#[cfg(feature = "llvm")]
 mod codegen;
+mod consteval;
+mod diagnostic;
+mod directives;
+mod driver;
+mod interpreter;
+mod intrinsics;
+mod layout;
+mod module_loader;
 mod parser;
+mod source_map;
 mod token;
 mod tokenizer;
 mod typechecker;
 
-use token::Token;
-use tokenizer::Tokenizer;
-use typechecker::Typechecker;
-use parser::Parser;
-
-fn main() {
-    let mut tokenizer = Tokenizer::new(String::from("test_files/test.amds"));
-    tokenizer.dump_file_contents();
-
-    let mut current_token = tokenizer.read_next_token();
-    let mut token_stream = Vec::new();
-
-    while current_token != Token::EOF {
-        token_stream.push(current_token);
-        current_token = tokenizer.read_next_token();
-    }
-
-    let mut parser = Parser {
-        token_stream,
-        idx: 0,
-    };
+use std::process::ExitCode;
 
-    let main_module = parser.parse_module();
+use driver::{ ArgsError, Options };
 
-    codegen::init();
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
 
-    println!("main_module:");
-    println!("    name: {}", main_module.name);
-    println!("    structs:");
-    for struct_decl in main_module.structs {
-        println!("    |-->{}", struct_decl.name);
-        for member in struct_decl.data_members {
-            println!("        |-->{}: {}", member.name, match &member.parsed_type { ast::ParsedType::Name(_vec, name) => name, _ => panic!() });
+    match Options::from_args(&args) {
+        Ok(options) => driver::run(&options),
+        Err(ArgsError::Help) => {
+            println!("{}", driver::USAGE);
+            ExitCode::SUCCESS
+        },
+        Err(ArgsError::Invalid(msg)) => {
+            eprintln!("error: {}\n", msg);
+            eprintln!("{}", driver::USAGE);
+            ExitCode::from(2)
         }
     }
-    println!("    enums:");
-    for struct_decl in main_module.enums {
-        println!("    |-->{}", struct_decl.name);
-    }
-    println!("    procs:");
-    for struct_decl in main_module.procs {
-        println!("    |-->{}", struct_decl.name);
-    }
 }
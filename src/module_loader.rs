@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+use crate::ast::{ ParsedImport, ParsedModule };
+use crate::diagnostic::{ Diagnostic, DiagnosticSink };
+use crate::directives::BuildConfig;
+use crate::parser::Parser;
+use crate::source_map::SourceMap;
+use crate::token::{ Span, Token };
+use crate::tokenizer::Tokenizer;
+
+/// The extension of source files, which `#import` paths leave out.
+const SOURCE_EXTENSION: &str = "amds";
+
+/// A module that's had its imports loaded, or is having them loaded.
+struct LoadedModule {
+    name: String,
+    path: PathBuf,
+}
+
+/// Follows the `#import`s of a module, and of the modules it imports,
+/// parsing every file once no matter how many modules import it.
+pub struct ModuleLoader<'a> {
+    source_map: &'a mut SourceMap,
+    diagnostics: DiagnosticSink,
+    config: &'a BuildConfig,
+    /// Where imports are looked for when they aren't next to the module
+    /// importing them, in order
+    search_paths: &'a [PathBuf],
+    /// Every imported module, after the modules it imports
+    modules: Vec<ParsedModule>,
+    /// The canonical path of each module, by name. Names have to be unique
+    /// since they're what declarations are qualified with.
+    paths: HashMap<String, PathBuf>,
+    /// The chain of imports that led to the module being loaded, starting
+    /// at the root. Importing anything on it is a cycle.
+    stack: Vec<(LoadedModule, Option<Span>)>,
+}
+
+impl<'a> ModuleLoader<'a> {
+    pub fn new(source_map: &'a mut SourceMap, diagnostics: DiagnosticSink, config: &'a BuildConfig, search_paths: &'a [PathBuf]) -> Self {
+        Self {
+            source_map,
+            diagnostics,
+            config,
+            search_paths,
+            modules: Vec::new(),
+            paths: HashMap::new(),
+            stack: Vec::new(),
+        }
+    }
+
+    /// Loads everything `root` imports. The modules come back in the order
+    /// they have to be checked in, every one after those it imports, with
+    /// `root` last.
+    pub fn load(mut self, root: ParsedModule, root_path: &Path) -> Vec<ParsedModule> {
+        let root_path = canonical(root_path);
+
+        self.stack.push((LoadedModule { name: root.name.clone(), path: root_path.clone() }, None));
+        self.load_imports(&root.imports);
+        self.stack.pop();
+
+        self.modules.push(root);
+        self.modules
+    }
+
+    fn load_imports(&mut self, imports: &[ParsedImport]) {
+        for import in imports {
+            let dir = Path::new(&import.current_module_path).parent().unwrap_or(Path::new("."));
+            let Some(path) = self.find(import, dir) else { continue };
+            let path = canonical(&path);
+            let name = import.name();
+
+            if let Some(idx) = self.stack.iter().position(|(module, _)| module.path == path) {
+                self.report_cycle(idx, import);
+                continue;
+            }
+
+            match self.paths.get(&name) {
+                Some(loaded) if *loaded == path => continue,
+                Some(loaded) => {
+                    self.diagnostics.emit(Diagnostic::error(format!("there's already a different module called `{}`", name), import.span)
+                                                     .with_note(format!("`{}` was imported from `{}`", name, loaded.display()))
+                                                     .with_note("imported modules are named after their file, so two of them can't share a file name"));
+                    continue;
+                },
+                None => { },
+            }
+
+            let Some(mut module) = self.parse(&path, import) else { continue };
+            module.set_namespace(&name, &path);
+            self.paths.insert(name.clone(), path.clone());
+
+            self.stack.push((LoadedModule { name, path: path.clone() }, Some(import.span)));
+            self.load_imports(&module.imports.clone());
+            self.stack.pop();
+
+            self.modules.push(module);
+        }
+    }
+
+    /// Looks for the file `import` names next to the module importing it,
+    /// then in each of the search paths.
+    fn find(&self, import: &ParsedImport, dir: &Path) -> Option<PathBuf> {
+        let file = PathBuf::from(format!("{}.{}", import.path, SOURCE_EXTENSION));
+        let mut dirs: Vec<&Path> = std::iter::once(dir).chain(self.search_paths.iter().map(PathBuf::as_path)).collect();
+        dirs.dedup();
+
+        if let Some(path) = dirs.iter().map(|dir| dir.join(&file)).find(|path| path.is_file()) {
+            return Some(path);
+        }
+
+        let looked_in = dirs.iter().map(|dir| format!("`{}`", dir.display())).collect::<Vec<_>>().join(", ");
+        self.diagnostics.emit(Diagnostic::error(format!("cannot find module `{}`", import.path), import.span)
+                                         .with_note(format!("looked for `{}` in {}", file.display(), looked_in))
+                                         .with_note("more directories can be searched with `-I <dir>` or $AMDS_PATH"));
+        None
+    }
+
+    fn parse(&mut self, path: &Path, import: &ParsedImport) -> Option<ParsedModule> {
+        let file_id = match self.source_map.load_file(path) {
+            Ok(file_id) => file_id,
+            Err(err) => {
+                self.diagnostics.emit(Diagnostic::error(format!("couldn't read module `{}`: {}", import.path, err), import.span));
+                return None;
+            }
+        };
+
+        let contents = self.source_map.file(file_id).contents.clone();
+        let token_stream = Tokenizer::new(contents, file_id, self.diagnostics.clone()).tokenize();
+
+        Some(parse(path, token_stream, self.config, self.diagnostics.clone()))
+    }
+
+    /// Reports `import`, which imports `self.stack[idx]` back from the end of
+    /// the chain of imports that started there.
+    fn report_cycle(&self, idx: usize, import: &ParsedImport) {
+        let (importer, _) = self.stack.last().unwrap();
+        let cycle = &self.stack[idx..];
+
+        let mut diagnostic = match cycle {
+            [_] => Diagnostic::error(format!("the module `{}` imports itself", importer.name), import.span),
+            _ => {
+                let chain: Vec<&str> = cycle.iter()
+                                            .map(|(module, _)| module.name.as_str())
+                                            .chain(std::iter::once(cycle[0].0.name.as_str()))
+                                            .collect();
+
+                Diagnostic::error(format!("the module `{}` imports itself through other modules", cycle[0].0.name), import.span)
+                           .with_note(format!("the imports go {}", chain.join(" -> ")))
+            },
+        };
+
+        for (module, span) in &cycle[1..] {
+            if let Some(span) = span {
+                diagnostic = diagnostic.with_label(*span, format!("`{}` is imported here", module.name));
+            }
+        }
+
+        self.diagnostics.emit(diagnostic);
+    }
+}
+
+/// Parses the tokens of the file at `path` into a module named after it.
+pub fn parse(path: &Path, token_stream: Vec<Token>, config: &BuildConfig, diagnostics: DiagnosticSink) -> ParsedModule {
+    let mut parser = Parser::new(token_stream, config, diagnostics);
+
+    let module_name = path.file_stem()
+                          .map(|stem| stem.to_string_lossy().into_owned())
+                          .unwrap_or_else(|| String::from("main"));
+
+    parser.parse_module(&module_name, &path.display().to_string())
+}
+
+/// Two imports are of the same module if they lead to the same file, which
+/// could be through different paths.
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
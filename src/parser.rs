@@ -1,12 +1,92 @@
+use std::collections::BTreeMap;
+
 use crate::ast::*;
-use crate::token::Token;
+use crate::diagnostic::{ Diagnostic, DiagnosticSink };
+use crate::directives::BuildConfig;
+use crate::token::{ Span, Token };
+
+pub type ParseResult<T> = Result<T, Diagnostic>;
+
+/// How deeply expressions, types and blocks can be nested. Every stage
+/// after the parser walks the tree recursively, so this keeps them from
+/// running out of stack.
+const MAX_NESTING: usize = 256;
+
+/// How many operators deep an expression can be. A chain like `a + b + c`
+/// is a tree that gets a level deeper with each operator, but generated
+/// code can have long ones, so they get more room than other nesting.
+/// Later stages walk chains recursively too, so this is as much as fits
+/// on the main thread's stack along with `MAX_NESTING` levels of blocks.
+const MAX_OPERATOR_DEPTH: usize = 512;
 
-pub struct Parser {
+pub struct Parser<'a> {
     pub token_stream: Vec<Token>,
     pub idx: usize,
+    /// What `#if` conditions are checked against
+    config: &'a BuildConfig,
+    /// The file being parsed, which imports remember
+    module_path: String,
+    diagnostics: DiagnosticSink,
+    /// Whether `Name {` starts a struct literal. It doesn't in front of
+    /// the `{` of a block, like in `if ready {`, unless it's inside of
+    /// parentheses or brackets.
+    struct_literals: bool,
+    /// The `///` comments, which are taken out of the token stream and
+    /// joined into one for each run of them. They're keyed by the index of
+    /// the token they come right before, where a `decl` picks them up.
+    doc_comments: BTreeMap<usize, (Span, String)>,
+    /// The `//!` comments, which document the module itself
+    module_doc_comments: Vec<(Span, String)>,
+    /// How deep in the tree the parser is, counting blocks and operands
+    depth: usize,
+    /// How many operators deep the expression parsed last is, counting
+    /// the ones in the expressions nested in it
+    operator_depth: usize,
 }
 
-impl Parser {
+impl<'a> Parser<'a> {
+    pub fn new(token_stream: Vec<Token>, config: &'a BuildConfig, diagnostics: DiagnosticSink) -> Self {
+        let mut tokens = Vec::with_capacity(token_stream.len());
+        let mut doc_comments = BTreeMap::new();
+        let mut module_doc_comments = Vec::new();
+        let mut pending_doc: Option<(Span, String)> = None;
+
+        for token in token_stream {
+            match token {
+                Token::DocComment(span, text) => match &mut pending_doc {
+                    Some((doc_span, doc)) => {
+                        *doc_span = doc_span.to(span);
+                        doc.push('\n');
+                        doc.push_str(&text);
+                    },
+                    None => pending_doc = Some((span, text)),
+                },
+                Token::ModuleDocComment(span, text) => module_doc_comments.push((span, text)),
+                Token::EOL(_span) => tokens.push(token),
+                token => {
+                    if let Some(doc) = pending_doc.take() {
+                        doc_comments.insert(tokens.len(), doc);
+                    }
+
+                    tokens.push(token);
+                },
+            }
+        }
+
+        Self {
+            token_stream: tokens,
+            idx: 0,
+            config,
+            module_path: String::new(),
+            diagnostics,
+            struct_literals: true,
+            doc_comments,
+            module_doc_comments,
+            depth: 0,
+            operator_depth: 0,
+        }
+    }
+
     fn at_end(&self) -> bool {
         self.idx >= self.token_stream.len()
     }
@@ -15,784 +95,1823 @@ impl Parser {
         &self.token_stream[self.idx]
     }
 
+    /// The token after the current one, which is the `EOF` at the end of
+    /// the stream once there's nothing left.
     fn peek(&self) -> &Token {
-        assert!(self.idx + 1 < self.token_stream.len());
-        &self.token_stream[self.idx + 1]
+        let idx = (self.idx + 1).min(self.token_stream.len() - 1);
+        &self.token_stream[idx]
+    }
+
+    /// The token that was just consumed.
+    fn previous(&self) -> &Token {
+        assert!(self.idx > 0);
+        &self.token_stream[self.idx - 1]
     }
 
-    pub fn parse_module(&mut self) -> ParsedModule {
-        let mut token: &Token;
-        // FIXME: Add different module names (current filename)
-        let mut module = ParsedModule::new("main");
+    pub fn parse_module(&mut self, module_name: &str, module_path: &str) -> ParsedModule {
+        let mut module = ParsedModule::new(module_name);
+        self.module_path = String::from(module_path);
+        module.doc = self.take_module_doc_comment();
 
         while !self.at_end() {
-            token = self.current();
+            match self.current() {
+                Token::EOF(_span) => break,
+                // Just ignore EOLs
+                Token::EOL(_span) => self.idx += 1,
+                _ => self.parse_item(&mut module),
+            }
+        }
 
-            match token {
-                Token::EOF => break,
-                Token::EOL(_span) => { /* Just ignore EOLs */ },
+        for (span, _text) in std::mem::take(&mut self.doc_comments).into_values() {
+            self.diagnostics.emit(Diagnostic::warning("this doc comment doesn't document anything", span)
+                                            .with_note("`///` comments go right before a `decl`, other comments are written with `//`"));
+        }
 
-                Token::Hash(span) => {
-                    panic!("Handle directives here: {:?}", span);
-                },
+        module
+    }
 
-                Token::KeywordDecl(_span) => {
-                    // First determine what complex type we're using.
-                    // This could be one of the following:
-                    //
-                    //  - procedure:
-                    //      decl something : () { }
-                    //  - struct:
-                    //      decl SomeType : struct { }
-                    //  - enum:
-                    //      decl SomeEnum : enum { }
-                    //
-                    // If it's none of those, we also might be
-                    // declaring a constant.
-                    //
-                    //      decl SOME_CONST: i32 = 0x2a;
-                    //
-
-                    // Get the identifier name
-                    self.idx += 1;
-                    let ident = match self.current() {
-                        Token::IdentName(_span, name) => name.clone(),
-                        _ => panic!("Syntax Error! Expected identifier, but got something else!")
-                    };
+    /// Joins the `//!` comments that come before the first item of the
+    /// module. The ones after it are reported, since they'd read as if
+    /// they were about what's around them.
+    fn take_module_doc_comment(&mut self) -> Option<String> {
+        let first_item = self.token_stream.iter()
+                                          .find(|token| !matches!(token, Token::EOL(_span)))
+                                          .map(|token| token.span().start)
+                                          .unwrap_or(usize::MAX);
+        let mut lines = Vec::new();
+
+        for (span, text) in std::mem::take(&mut self.module_doc_comments) {
+            if span.start < first_item {
+                lines.push(text);
+            } else {
+                self.diagnostics.emit(Diagnostic::warning("`//!` comments have to come before anything else in the module", span)
+                                                .with_note("use `///` to document the declaration after it"));
+            }
+        }
+
+        if lines.is_empty() {
+            None
+        } else {
+            Some(lines.join("\n"))
+        }
+    }
+
+    /// The `///` comments right before the current token, which are given
+    /// to the declaration it starts.
+    fn take_doc_comment(&mut self) -> Option<String> {
+        self.doc_comments.remove(&self.idx).map(|(_span, text)| text)
+    }
+
+    /// Parses a declaration or directive at the top level of a module.
+    fn parse_item(&mut self, module: &mut ParsedModule) {
+        let result = match self.current() {
+            Token::Hash(_span) => self.parse_directive(module),
+
+            Token::KeywordDecl(_span) => {
+                let doc = self.take_doc_comment();
+                self.parse_decl(module, doc)
+            },
+
+            token => Err(Diagnostic::error(format!("expected a declaration, but got {}", token), token.span())
+                                   .with_note("only `decl` items and directives are allowed at the top level of a module")),
+        };
+
+        if let Err(diagnostic) = result {
+            self.diagnostics.emit(diagnostic);
+            self.synchronize_to_decl();
+        }
+    }
+
+    /// Skips ahead to the next `decl` or directive so a broken declaration
+    /// doesn't stop the rest of the module from being parsed.
+    fn synchronize_to_decl(&mut self) {
+        while !matches!(self.current(), Token::KeywordDecl(_span) | Token::Hash(_span) | Token::EOF(_span)) {
+            self.idx += 1;
+        }
+    }
+
+    /// Parses a directive at the top level of a module, like
+    /// `#import "core/io"`.
+    fn parse_directive(&mut self, module: &mut ParsedModule) -> ParseResult<()> {
+        let start = self.current().span();
+        let name = self.parse_directive_name()?;
+
+        match name.as_str() {
+            "import" => {
+                let path = match self.current() {
+                    Token::StringLiteral(_span, path) => path.clone(),
+                    _ => return self.error_here("expected the path of a module after `#import`")
+                };
+
+                module.imports.push(ParsedImport {
+                    current_module_path: self.module_path.clone(),
+                    path,
+                    span: start.to(self.current().span()),
+                });
+
+                self.idx += 1;
+                Ok(())
+            },
+            "if" => self.parse_conditional(|parser| parser.parse_items_in_braces(module)),
+            "assert" => {
+                match self.current() {
+                    Token::LParen(_span) => self.idx += 1,
+                    _ => return self.error_here("expected `(` after `#assert`")
+                }
+
+                let value = self.parse_expression(false, true)?;
+                let mut message = None;
 
-                    // Ensure the type operator (`:`) is present.
+                if let Token::Comma(_span) = self.current() {
                     self.idx += 1;
+
                     match self.current() {
-                        Token::Colon(_span) => { },
-                        _ => panic!("Syntax Error! Expected a colon, but got something else!")
+                        Token::StringLiteral(_span, text) => message = Some(text.clone()),
+                        _ => return self.error_here("expected a message after the condition of `#assert`")
                     }
 
-                    // Check what kind of complex type we're trying to declare.
                     self.idx += 1;
-                    let complex_type = match self.current() {
-                        Token::LParen(_span) => ComplexType::Procedure,
-                        Token::KeywordStruct(_span) => ComplexType::Struct,
-                        Token::KeywordEnum(_span) => ComplexType::Enum,
-                        Token::IdentName(_span, _name) => ComplexType::Constant,
-                        Token::BuiltinType(_span, _type_name) => ComplexType::Constant,
-                        _ => panic!("Syntax Error! Expected complex type identifier ('struct', 'enum', '()'), but got something else!")
-                    };
+                }
+
+                match self.current() {
+                    Token::RParen(_span) => self.idx += 1,
+                    _ => return self.error_here("expected `)` to close `#assert`")
+                }
 
-                    let (params, return_type) = match complex_type {
-                        ComplexType::Procedure => {
-                            let param_list: Vec<ParsedVarDecl>;
-                            param_list = self.parse_param_list_decl();
+                module.directives.push(ParsedDirective { kind: DirectiveKind::Assert(message), value, span: start.to(self.previous().span()) });
+                Ok(())
+            },
+            "run" => {
+                let value = self.parse_expression(false, false)?;
+                let span = start.to(self.previous().span());
 
-                            self.eat_newlines();
+                if let Token::Semicolon(_span) = self.current() {
+                    self.idx += 1;
+                }
 
-                            let has_return_type = match self.current() {
-                                Token::ThinArrow(_span) => true,
-                                Token::LCurly(_span) => false,
-                                _ => { panic!("Syntax Error! Unexpected Token in procedure definition! Expected '{{' or '->' but got: {:?}", self.current()); },
-                            };
+                module.directives.push(ParsedDirective { kind: DirectiveKind::Run, value, span });
+                Ok(())
+            },
+            "inline" | "foreign" | "intrinsic" => {
+                // Back to the `#`, so every attribute is parsed the same way
+                let span = start.to(self.previous().span());
+                self.idx -= 2;
+                let doc = self.take_doc_comment();
+                let attributes = self.parse_proc_attributes()?;
+
+                if !matches!(self.current(), Token::KeywordDecl(_span)) {
+                    return self.error_here("expected the `decl` of a proc after its attributes");
+                }
 
+                let proc_count = module.procs.len();
+                self.parse_decl(module, doc)?;
 
-                            let return_type: ParsedType;
-                            if has_return_type {
-                                self.idx += 1;
-                                return_type = self.parse_type_name();
-                            } else {
-                                return_type = ParsedType::Name(Vec::new(), String::from("nothing"));
-                            }
+                match module.procs.get_mut(proc_count) {
+                    Some(proc) => proc.attributes = attributes,
+                    None => self.diagnostics.emit(Diagnostic::error(format!("`#{}` can only be put on procs", name), span)),
+                }
 
-                            (Some(param_list), return_type)
-                        },
-                        _ => (None, ParsedType::Name(Vec::new(), String::from("nothing")))
-                    };
+                Ok(())
+            },
+            "else" => Err(Diagnostic::error("`#else` without an `#if`", start.to(self.previous().span()))),
+            _ => Err(Self::unknown_directive(&name, start.to(self.previous().span())))
+        }
+    }
 
-                    match complex_type {
-                        ComplexType::Procedure => {
-                            let body: ParsedBlock;
-                            body = self.parse_block();
-                            let proc = ParsedProcDecl {
-                                name: ident,
-                                parameters: params.expect("Syntax Error! You defined a function without even an empty parameter list. How?!?!"),
-                                parsed_return_type: return_type,
-                                body
-                            };
-                            module.procs.push(proc);
-                        },
+    /// Moves past the `#` and the name of a directive, and gives back the name.
+    fn parse_directive_name(&mut self) -> ParseResult<String> {
+        self.idx += 1;
 
-                        ComplexType::Struct => {
-                            self.idx += 1;
+        let name = match self.current() {
+            Token::IdentName(_span, name) => name.clone(),
+            Token::KeywordIf(_span) => String::from("if"),
+            Token::KeywordElse(_span) => String::from("else"),
+            _ => return self.error_here("expected the name of a directive after `#`")
+        };
 
-                            let mut struct_decl = ParsedStructDecl {
-                                name: ident,
-                                data_members: Vec::new()
-                            };
+        self.idx += 1;
+        Ok(name)
+    }
 
-                            let mut member_name: Option<String> = None;
-                            let mut member_type: Option<ParsedType> = None;
-                            let mut is_past_colon = false;
-                            let mut in_struct_body = match self.current() {
-                                Token::LCurly(_span) => true,
-                                _ => { panic!("Syntax Error! Expected '{{' in struct declaration, got {:?}", self.current()); }
-                            };
+    fn unknown_directive(name: &str, span: Span) -> Diagnostic {
+        Diagnostic::error(format!("unknown directive `#{}`", name), span)
+                   .with_note("the directives are `#import`, `#if`, `#else`, `#assert`, `#run`, `#inline`, `#foreign` and `#intrinsic`")
+    }
 
+    /// Parses `#inline`, `#foreign "library"` and `#intrinsic` in front of
+    /// a proc.
+    fn parse_proc_attributes(&mut self) -> ParseResult<ProcAttributes> {
+        let mut attributes = ProcAttributes::default();
+
+        while let Token::Hash(start) = self.current() {
+            let start = *start;
+            let name = self.parse_directive_name()?;
+            let span = start.to(self.previous().span());
+
+            let already_given = match name.as_str() {
+                "inline" => attributes.inline.replace(span).is_some(),
+                "intrinsic" => attributes.intrinsic.replace(span).is_some(),
+                "foreign" => {
+                    let library = match self.current() {
+                        Token::StringLiteral(_span, library) => {
+                            let library = library.clone();
                             self.idx += 1;
-
-                            while in_struct_body {
-                                self.eat_newlines();
-                                token = self.current();
-
-                                match token {
-                                    Token::RCurly(_span) => in_struct_body = false,
-                                    Token::IdentName(_span, name) => {
-                                        // First determine if we're looking at a member name or type name.
-                                        if member_name.is_none() && member_type.is_none() {
-                                            // We're looking at a member name
-                                            member_name = Some(name.clone());
-                                        } else if is_past_colon && (member_type.is_none() && member_name.is_some()) {
-                                            // We're looking at a non-primitive type name
-                                            member_type = Some(self.parse_type_name());
-                                        } else {
-                                            panic!("Syntax Error! Invalid Placement of identifier name in struct member declaration [{:?}]", token);
-                                        }
-                                    },
-                                    Token::Colon(_span) => {
-                                        if member_name.is_some() && member_type.is_none() {
-                                            is_past_colon = true;
-                                        } else {
-                                            panic!("Syntax Error! Invalid Placement of ':' in struct member declaration [{:?}]", token);
-                                        }
-                                    },
-                                    Token::BuiltinType(_span, _primitive) => {
-                                        if is_past_colon && (member_type.is_none() && member_name.is_some()) {
-                                            member_type = Some(self.parse_type_name());
-                                            continue;
-                                        } else {
-                                            panic!("Syntax Error! Invalid Placement of type name in struct member declaration [{:?}]", token);
-                                        }
-                                    },
-                                    Token::Comma(_span) => {
-                                        assert!(member_name.is_some() && member_type.is_some(), "Unexpected ',' in struct member definition");
-
-                                        struct_decl.data_members.push(ParsedVarDecl {
-                                                                          parsed_type: member_type.unwrap(),
-                                                                          name: member_name.unwrap(),
-                                                                          defualt_value: ParsedExpression::Invalid
-                                                                      });
-
-                                        member_name = None;
-                                        member_type = None;
-                                        is_past_colon = false;
-                                    },
-                                    _ => {
-                                        assert!(member_name.is_some() && member_type.is_some(), "Unexpected token in struct member definition: {:?}", self.current());
-                                        member_name = None;
-                                        member_type = None;
-                                        is_past_colon = false;
-                                    }
-                                }
-
-                                self.idx += 1;
-                            }
-
-                            module.structs.push(struct_decl);
+                            Some(library)
                         },
+                        _ => None
+                    };
 
-                        ComplexType::Enum => { todo!("Implement Enums") },
-
-                        ComplexType::Constant => { todo!("Implement constants") },
-                    }
+                    attributes.foreign.replace((library, start.to(self.previous().span()))).is_some()
                 },
+                _ => return Err(Diagnostic::error(format!("`#{}` isn't an attribute, so it can't be put on a proc", name), span)),
+            };
 
-                _ => {
-                    // FIXME: Propagate this error rather than just freaking out...
-                    panic!("Unexpected token: {:?}", token);
-                }
+            if already_given {
+                return Err(Diagnostic::error(format!("`#{}` is given more than once", name), span));
             }
 
-            self.idx += 1;
+            self.eat_newlines();
         }
 
-        module
+        Ok(attributes)
     }
 
-    fn parse_type_name(&mut self) -> ParsedType {
-        let mut last_was_ident = false;
-        let mut at_type_end = false;
+    /// Parses `#if cond { ... }` from right after the `if`, along with any
+    /// `#else #if` and `#else` branches after it. The first branch whose
+    /// condition holds is handed to `parse_branch` at its `{`, and the others
+    /// are skipped without being parsed, so they can hold code for other
+    /// targets.
+    fn parse_conditional(&mut self, mut parse_branch: impl FnMut(&mut Self) -> ParseResult<()>) -> ParseResult<()> {
+        let mut taken = false;
 
-        let mut mod_path: Vec<String> = Vec::new();
+        loop {
+            let cond = self.parse_before_block()?;
 
-        // Short-circuit for builtins
-        if let Token::BuiltinType(_span, primitive) = self.current() {
-            let ty = ParsedType::Name(Vec::new(), String::from(primitive.as_str()));
+            // A broken condition is reported, but its branch still has to
+            // be skipped over
+            let holds = self.config.eval_condition(&cond).unwrap_or_else(|diagnostic| {
+                self.diagnostics.emit(diagnostic);
+                false
+            });
 
-            self.idx += 1;
-            return ty;
-        }
+            self.parse_or_skip_branch(!taken && holds, &mut parse_branch)?;
+            taken |= holds;
 
-        assert!(matches!(self.current(), Token::LSquare(_span)), "Add array types");
+            if !self.eat_else() {
+                return Ok(());
+            }
 
-        // '*' is only allowed to prefix a type name, not suffix
-        loop {
-            match self.current() {
-                Token::Star(_span) => {
-                    mod_path.push(String::from("*"));
-                    last_was_ident = false;
-                },
-                _ => {
-                    self.idx += 1;
-                    break;
+            if let Token::Hash(_span) = self.current() {
+                if self.parse_directive_name()? != "if" {
+                    return Err(Diagnostic::error("expected `{` or `#if` after `#else`", self.previous().span()));
                 }
+
+                continue;
             }
-            self.idx += 1;
+
+            return self.parse_or_skip_branch(!taken, &mut parse_branch);
+        }
+    }
+
+    fn parse_or_skip_branch(&mut self, parse: bool, parse_branch: &mut impl FnMut(&mut Self) -> ParseResult<()>) -> ParseResult<()> {
+        self.eat_newlines();
+
+        if !matches!(self.current(), Token::LCurly(_span)) {
+            return self.error_here("expected `{` after the condition of `#if`");
+        }
+
+        if parse {
+            return parse_branch(self);
         }
 
-        while !at_type_end {
+        let open_span = self.current().span();
+        let mut depth = 0;
+
+        loop {
             match self.current() {
-                Token::IdentName(_span, name) => {
-                    last_was_ident = true;
-                    mod_path.push(name.clone());
+                Token::LCurly(_span) => depth += 1,
+                Token::RCurly(_span) => depth -= 1,
+                Token::EOF(_span) => {
+                    return Err(Diagnostic::error("expected `}` to close the `#if`, but got the end of the file", self.current().span())
+                                          .with_label(open_span, "this block is never closed"));
                 },
-                Token::DoubleColon(_span) => last_was_ident = false,
-                _ => at_type_end = true
+                _ => { }
             }
-            self.idx += 1;
-        }
 
-        let type_name: String = if last_was_ident {
-            mod_path.pop().expect("Sytax Error! No type name given!")
-        } else {
-            println!("{:?}", self.current());
-            panic!("Syntax Error! Incomplete type name. No type name following '::'");
-        };
+            // Doc comments in a skipped branch are for another target, so
+            // they aren't reported as documenting nothing
+            self.doc_comments.remove(&self.idx);
+            self.idx += 1;
 
-        ParsedType::Name(mod_path, type_name)
+            if depth == 0 {
+                return Ok(());
+            }
+        }
     }
 
-    fn parse_param_list_decl(&mut self) -> Vec<ParsedVarDecl> {
-        let mut is_at_list_end = false;
-        let mut found_name = false;
-        let mut passed_colon = false;
-
-        let mut name = String::new();
-        let mut parsed_type = ParsedType::Name(Vec::new(), String::new());
-        let mut params = Vec::new();
+    /// Moves past an `#else` that comes after the `}` of an `#if`, if
+    /// there is one.
+    fn eat_else(&mut self) -> bool {
+        let before = self.idx;
+        self.eat_newlines();
 
-        match self.current() {
-            Token::LParen(_span) => self.idx += 1,
-            _ => panic!("Syntax Error! Parameter list did not start with '('")
+        if matches!(self.current(), Token::Hash(_span)) && matches!(self.peek(), Token::KeywordElse(_span)) {
+            self.idx += 2;
+            return true;
         }
 
-        while !is_at_list_end {
-            match self.current() {
-                Token::IdentName(_span, ident_name) => {
-                    if !passed_colon {
-                        name = ident_name.clone();
-                        found_name = true;
-                    } else if passed_colon && found_name {
-                        parsed_type = self.parse_type_name();
-                        // We increment at that end of this loop, so we need to
-                        // decrement here so we don't accidentally skip a token.
-                        self.idx -= 1;
-                    } else { panic!("Unknown state. I had no brain power to find what this means so figure it out future me :)"); }
-                },
-                Token::Colon(_span) => {
-                    passed_colon = true;
-                }
-                Token::BuiltinType(_span, _primitive) => {
-                    assert!(passed_colon && found_name, "Syntax Error! No name on parameter declaration or missing ':' operator!");
-                    parsed_type = self.parse_type_name();
-                    // We increment at that end of this loop, so we need to
-                    // decrement here so we don't accidentally skip a token.
-                    self.idx -= 1;
-                },
-                Token::Star(_span) => {
-                    assert!(passed_colon && found_name, "Syntax Error! No name on parameter declaration or missing ':' operator!");
-                    parsed_type = self.parse_type_name();
-                    // We increment at that end of this loop, so we need to
-                    // decrement here so we don't accidentally skip a token.
-                    self.idx -= 1;
-                }, Token::LSquare(_span) => { assert!(passed_colon && found_name, "Syntax Error! No name on parameter declaration or missing ':' operator!");
-                    parsed_type = self.parse_type_name();
-                    // We increment at that end of this loop, so we need to
-                    // decrement here so we don't accidentally skip a token.
-                    self.idx -= 1;
-                },
-                Token::Comma(_span) => {
-                    params.push(ParsedVarDecl {
-                        parsed_type: parsed_type.clone(),
-                        name: name.clone(),
-                        defualt_value: ParsedExpression::Invalid
-                    });
-
-                    found_name = false;
-                    passed_colon = false;
-                },
-                Token::RParen(_span) => {
-                    assert!(!(found_name ^ passed_colon), "Syntax Error! No name or type on parameter declaration!");
-
-                    if found_name && passed_colon {
-                        params.push(ParsedVarDecl {
-                            parsed_type: parsed_type.clone(),
-                            name: name.clone(),
-                            defualt_value: ParsedExpression::Invalid
-                        });
-                    }
+        self.idx = before;
+        false
+    }
 
-                    is_at_list_end = true;
-                },
-                _ => panic!("Syntax Error! Unexpected token in parameter list declaration: {:?}", self.current())
-            }
+    /// Moves past an `else` that comes after the `}` of an `if`, if there
+    /// is one.
+    fn eat_else_keyword(&mut self) -> bool {
+        let before = self.idx;
+        self.eat_newlines();
 
+        if let Token::KeywordElse(_span) = self.current() {
             self.idx += 1;
+            return true;
         }
 
-        params
+        self.idx = before;
+        false
     }
 
-    fn parse_param_list_usage(&mut self) -> Vec<ParsedVarDecl> {
-        let mut is_at_list_end = false;
-        let mut found_name = false;
-        let mut passed_colon = false;
+    /// Parses the declarations in the taken branch of an `#if` at the top
+    /// level of a module.
+    fn parse_items_in_braces(&mut self, module: &mut ParsedModule) -> ParseResult<()> {
+        let open_span = self.current().span();
+        self.idx += 1;
 
-        let parsed_type = ParsedType::Name(Vec::new(), String::new());
-        let mut name = String::new();
-        let mut default_value = ParsedExpression::Invalid;
-        let mut params = Vec::new();
+        loop {
+            match self.current() {
+                Token::RCurly(_span) => {
+                    self.idx += 1;
+                    return Ok(());
+                },
+                Token::EOL(_span) => self.idx += 1,
+                Token::EOF(_span) => {
+                    return Err(Diagnostic::error("expected `}` to close the `#if`, but got the end of the file", self.current().span())
+                                          .with_label(open_span, "this block is never closed"));
+                },
+                _ => self.parse_item(module),
+            }
+        }
+    }
 
-        match self.current() {
-            Token::LParen(_span) => self.idx += 1,
-            _ => panic!("Syntax Error! Parameter list did not start with '('")
+    /// Parses a directive in a block, which can only be an `#if` choosing
+    /// between statements. The statements of the branch that's taken are
+    /// given back to go straight into the block, without a scope of their
+    /// own.
+    fn parse_statement_directive(&mut self) -> ParseResult<Vec<ParsedStatement>> {
+        let start = self.current().span();
+        let name = self.parse_directive_name()?;
+        let mut stmts = Vec::new();
+
+        match name.as_str() {
+            "if" => {
+                self.parse_conditional(|parser| {
+                    stmts = parser.parse_block()?.stmts;
+                    Ok(())
+                })?;
+
+                Ok(stmts)
+            },
+            "import" | "assert" | "run" | "inline" | "foreign" | "intrinsic" => {
+                Err(Diagnostic::error(format!("`#{}` can only be used at the top level of a module", name), start.to(self.previous().span())))
+            },
+            "else" => Err(Diagnostic::error("`#else` without an `#if`", start.to(self.previous().span()))),
+            _ => Err(Self::unknown_directive(&name, start.to(self.previous().span())))
         }
+    }
 
-        while !is_at_list_end {
+    /// Skips the rest of a broken statement. That's everything up to and
+    /// including the next `;` or balanced `{ }` block, but stops before a
+    /// `}` closing the enclosing block or a `decl` starting a new item.
+    /// Returns the span of everything that was skipped.
+    fn synchronize_to_statement_end(&mut self, start: Span) -> Span {
+        let mut depth = 0;
+        let mut end = start;
+
+        loop {
             match self.current() {
-                Token::IdentName(_span, ident_name) => {
-                    if !passed_colon {
-                        name = ident_name.clone();
-                        found_name = true;
-                    } else {
-                        default_value = self.parse_expression(false, true);
-                        continue;
-                    }
-                },
-                Token::Colon(_span) => {
-                    passed_colon = true;
-                }
-                Token::Comma(_span) => {
-                    params.push(ParsedVarDecl {
-                        parsed_type: parsed_type.clone(),
-                        name: name.clone(),
-                        defualt_value: default_value.clone()
-                    });
-
-                    found_name = false;
-                    passed_colon = false;
+                Token::EOF(_span) | Token::KeywordDecl(_span) => break,
+                Token::Semicolon(span) if depth == 0 => {
+                    end = *span;
+                    self.idx += 1;
+                    break;
                 },
-                Token::RParen(_span) => {
-                    assert!(!(found_name ^ passed_colon), "Syntax Error! No label on parameter name");
-                    if found_name && passed_colon {
-                        params.push(ParsedVarDecl {
-                            parsed_type: parsed_type.clone(),
-                            name: name.clone(),
-                            defualt_value: default_value.clone()
-                        });
+                Token::LCurly(_span) => depth += 1,
+                Token::RCurly(span) => {
+                    if depth == 0 {
+                        break;
+                    }
+
+                    depth -= 1;
+
+                    if depth == 0 {
+                        end = *span;
+                        self.idx += 1;
+                        break;
                     }
-                    is_at_list_end = true;
                 },
-                _ => {
-                    assert!(found_name && passed_colon, "No label on parameter name, got {:?}", self.current());
-                    default_value = self.parse_expression(false, true);
-                    continue;
-                }
+                _ => { }
             }
 
+            end = self.current().span();
             self.idx += 1;
         }
 
-        params
+        Span { file_id: start.file_id, start: start.start, end: end.end.max(start.end) }
     }
 
-    fn parse_block(&mut self) -> ParsedBlock {
-        let mut block = ParsedBlock::new();
-
-        self.eat_newlines();
+    fn parse_decl(&mut self, module: &mut ParsedModule, doc: Option<String>) -> ParseResult<()> {
+        // First determine what complex type we're using.
+        // This could be one of the following:
+        //
+        //  - procedure:
+        //      decl something : () { }
+        //  - struct:
+        //      decl SomeType : struct { }
+        //  - enum:
+        //      decl SomeEnum : enum { }
+        //
+        // If it's none of those, we also might be
+        // declaring a constant.
+        //
+        //      decl SOME_CONST: i32 = 0x2a;
+        //
+
+        // Get the identifier name
+        self.idx += 1;
+        let (ident, ident_span) = match self.current() {
+            Token::IdentName(span, name) => (name.clone(), *span),
+            _ => return self.error_here("expected an identifier after `decl`")
+        };
 
+        // Ensure the type operator (`:`) is present.
+        self.idx += 1;
         match self.current() {
-            Token::LCurly(_span) => self.idx += 1,
-            _ => panic!("Syntax Error! Block does not start with '{{'")
+            Token::Colon(_span) => { },
+            Token::ColonAssign(span) => {
+                let diagnostic = Diagnostic::error(format!("the type of `{}` has to be written out", ident), *span)
+                                            .with_note(format!("constants can't infer their type, write `decl {}: type = value;`", ident));
+                return Err(diagnostic);
+            },
+            _ => return self.error_here(format!("expected `:` after the name of `{}`", ident))
         }
 
-        loop {
-            match self.current() {
-                Token::RCurly(_span) => {
-                    self.idx += 1;
-                    break;
-                }
-                Token::EOL(_span) => {
-                    self.idx += 1;
-                    continue;
-                },
-                _ => { }
+        // Check what kind of complex type we're trying to declare.
+        self.idx += 1;
+        let complex_type = match self.current() {
+            Token::LParen(_span) => ComplexType::Procedure,
+            Token::KeywordStruct(_span) => ComplexType::Struct,
+            Token::KeywordEnum(_span) => ComplexType::Enum,
+            Token::IdentName(_span, _name) => ComplexType::Constant,
+            Token::BuiltinType(_span, _type_name) => ComplexType::Constant,
+            _ => {
+                let diagnostic = Diagnostic::error(format!("expected a procedure, `struct`, `enum` or constant type, but got {}", self.current()), self.current().span())
+                                            .with_note("declarations look like `decl name : (...) { }`, `decl Name : struct { }` or `decl NAME: type = value;`");
+                return Err(diagnostic);
             }
+        };
 
-            let stmt: ParsedStatement;
-            stmt = self.parse_statement();
-            block.stmts.push(stmt);
-        }
+        match complex_type {
+            ComplexType::Procedure => {
+                let (parameters, variadic) = self.parse_param_list_decl()?;
 
-        block
-    }
+                self.eat_newlines();
 
-    fn parse_statement(&mut self) -> ParsedStatement {
-        let stmt = match self.current() {
-            Token::KeywordLet(_span) => {
-                self.idx += 1;
-                let mut var_decl: ParsedVarDecl = ParsedVarDecl {
-                    parsed_type: ParsedType::Name(Vec::new(), String::from("")),
-                    name: String::new(),
-                    defualt_value: ParsedExpression::Invalid
+                let has_return_type = match self.current() {
+                    Token::ThinArrow(_span) => true,
+                    Token::LCurly(_span) | Token::Semicolon(_span) => false,
+                    _ => return self.error_here(format!("expected `{{` or `->` in the declaration of `{}`", ident)),
                 };
 
-                match self.current() {
-                    Token::IdentName(_span, name) => var_decl.name = name.clone(),
-                    _ => panic!("Syntax Error! No name in varable declaration. Got: {:?}", self.current())
-                }
+                let return_type = if has_return_type {
+                    self.idx += 1;
+                    self.parse_type_name()?
+                } else {
+                    ParsedType::Name(Vec::new(), String::from("nothing"), ident_span)
+                };
 
-                self.idx += 1;
+                self.eat_newlines();
 
-                let iterpret_type = match self.current() {
-                    Token::ColonAssign(_span) => {
-                        self.idx += 1;
-                        true
-                    },
-                    Token::Colon(_span) => {
+                // A `;` in place of the body declares a proc that's defined
+                // somewhere else, which only `#foreign` and `#intrinsic`
+                // procs can be
+                let body = match self.current() {
+                    Token::Semicolon(_span) => {
                         self.idx += 1;
-                        var_decl.parsed_type = self.parse_type_name();
-                        false
+                        None
                     },
-                    _ => panic!("Syntax Error! Unexpected token in variable declaration: {:?}", self.current())
+                    _ => Some(self.parse_block()?),
                 };
 
-                if !iterpret_type {
-                    match self.current() {
-                        Token::Assign(_span) => self.idx += 1,
-                        _ => panic!("Syntax Error! Expected '=', but got: {:?}", self.current())
-                    }
-                }
-
-                let default_value = self.parse_expression(false, true);
-                var_decl.defualt_value = default_value;
-
-                assert!(matches!(self.current(), Token::Semicolon(_span)), "Syntax Error! Expected ';' at end of variable declaration, got {:?}", self.current());
-                self.idx += 1;
-
-                ParsedStatement::VarDecl(var_decl)
+                let proc = ParsedProcDecl {
+                    name: ident,
+                    span: ident_span,
+                    parameters,
+                    variadic,
+                    parsed_return_type: return_type,
+                    body,
+                    attributes: ProcAttributes::default(),
+                    doc,
+                };
+                module.procs.push(proc);
             },
 
-            Token::KeywordIf(_span) => {
+            ComplexType::Struct => {
                 self.idx += 1;
+                self.eat_newlines();
+
+                match self.current() {
+                    Token::LCurly(_span) => self.idx += 1,
+                    _ => return self.error_here("expected `{` after `struct`")
+                }
 
-                let if_cond = self.parse_expression(false, true);
-                let if_body = self.parse_block();
+                let data_members = self.parse_struct_body(&ident, module)?;
 
-                // FIXME: Add if/else chaining
-                ParsedStatement::If(if_cond, if_body, None)
+                module.structs.push(ParsedStructDecl {
+                    name: ident,
+                    span: ident_span,
+                    data_members,
+                    doc
+                });
             },
 
-            Token::KeywordFor(_span) => {
+            ComplexType::Enum => {
                 self.idx += 1;
-
-                let mut it_decl: ParsedVarDecl = ParsedVarDecl {
-                    parsed_type: ParsedType::Name(Vec::new(), String::from("")),
-                    name: String::new(),
-                    defualt_value: ParsedExpression::Invalid
-                };
+                self.eat_newlines();
 
                 match self.current() {
-                    Token::IdentName(_span, name) => it_decl.name = name.clone(),
-                    _ => panic!("Syntax Error! No name in varable declaration. Got: {:?}", self.current())
+                    Token::LCurly(_span) => self.idx += 1,
+                    _ => return self.error_here("expected `{` after `enum`")
                 }
 
-                self.idx += 1;
+                let variants = self.parse_enum_variants()?;
+
+                module.enums.push(ParsedEnumDecl {
+                    name: ident,
+                    span: ident_span,
+                    variants,
+                    doc
+                });
+            },
+
+            ComplexType::Constant => {
+                let parsed_type = self.parse_type_name()?;
 
                 match self.current() {
-                    Token::KeywordIn(_span) => self.idx += 1,
-                    _ => panic!("Syntax Error! Expected 'in' after for loop iterator definition, got: {:?}", self.current())
+                    Token::Assign(_span) => self.idx += 1,
+                    _ => return self.error_here(format!("expected `=` after the type of `{}`", ident))
                 }
 
-                let range_expr = self.parse_range_expression();
-                let body = self.parse_block();
+                let value = self.parse_expression(false, true)?;
+                self.expect_semicolon("at the end of constant declaration")?;
 
-                ParsedStatement::ForLoop(it_decl, range_expr, body)
+                module.consts.push(ParsedConstDecl {
+                    name: ident,
+                    span: ident_span,
+                    parsed_type,
+                    value,
+                    doc
+                });
             },
+        }
 
-            Token::KeywordWhile(_span) => {
-                self.idx += 1;
+        Ok(())
+    }
 
-                let cond = self.parse_expression(false, true);
-                let body = self.parse_block();
+    fn parse_type_name(&mut self) -> ParseResult<ParsedType> {
+        let start = self.current().span();
+        let mut mod_path: Vec<String> = Vec::new();
 
-                ParsedStatement::WhileLoop(cond, body)
-            },
+        // `[N]T`, an array of `N` `T`s
+        if let Token::LSquare(_span) = self.current() {
+            self.idx += 1;
+            let size = self.parse_expression(false, true)?;
+            self.eat_newlines();
+
+            match self.current() {
+                Token::RSquare(_span) => self.idx += 1,
+                _ => return self.error_here("expected `]` after the size of the array")
+            }
+
+            let elem_type = self.nested(1, |parser| parser.parse_type_name())?;
+            return Ok(ParsedType::Array(Box::new(elem_type), size));
+        }
+
+        // `*T`, a pointer to a `T`
+        if let Token::Star(_span) = self.current() {
+            self.idx += 1;
+            let pointee = self.nested(1, |parser| parser.parse_type_name())?;
+            return Ok(ParsedType::Pointer(Box::new(pointee)));
+        }
+
+        // Short-circuit for builtins
+        if let Token::BuiltinType(span, primitive) = self.current() {
+            let ty = ParsedType::Name(mod_path, String::from(primitive.as_str()), start.to(*span));
+
+            self.idx += 1;
+            return Ok(ty);
+        }
+
+        loop {
+            let name = match self.current() {
+                Token::IdentName(_span, name) => name.clone(),
+                _ if mod_path.is_empty() => return self.error_here("expected a type name"),
+                _ => return self.error_here("incomplete type name, expected a type name after `::`")
+            };
+
+            let end = self.current().span();
+            self.idx += 1;
+
+            match self.current() {
+                Token::DoubleColon(_span) => {
+                    mod_path.push(name);
+                    self.idx += 1;
+                },
+                _ => return Ok(ParsedType::Name(mod_path, name, start.to(end)))
+            }
+        }
+    }
+
+    /// Parses `name: type` pairs separated by commas up to the token
+    /// `is_closer` accepts, which is consumed. A trailing comma is allowed.
+    fn parse_typed_names(&mut self, what: &str, is_closer: fn(&Token) -> bool) -> ParseResult<Vec<ParsedVarDecl>> {
+        let mut decls: Vec<ParsedVarDecl> = Vec::new();
+
+        loop {
+            self.eat_newlines();
+
+            if is_closer(self.current()) {
+                self.idx += 1;
+                break;
+            }
+
+            if let Some(decl) = self.parse_typed_name(what, &decls)? {
+                decls.push(decl);
+            }
+
+            self.eat_newlines();
+
+            match self.current() {
+                Token::Comma(_span) => self.idx += 1,
+                token if is_closer(token) => { },
+                _ => return self.error_here(format!("expected `,` after {} declaration", what))
+            }
+        }
+
+        Ok(decls)
+    }
+
+    /// Parses one `name: type` pair. If the name is one of `previous`,
+    /// that's reported and the pair is left out, but parsing goes on.
+    fn parse_typed_name(&mut self, what: &str, previous: &[ParsedVarDecl]) -> ParseResult<Option<ParsedVarDecl>> {
+        let (name, span) = match self.current() {
+            Token::IdentName(span, name) => (name.clone(), *span),
+            _ => return self.error_here(format!("expected a {} name", what))
+        };
+
+        self.idx += 1;
+
+        match self.current() {
+            Token::Colon(_span) => self.idx += 1,
+            _ => return self.error_here(format!("expected `:` after the {} name `{}`", what, name))
+        }
+
+        let parsed_type = self.parse_type_name()?;
+
+        if let Some(previous) = previous.iter().find(|decl| decl.name == name) {
+            self.diagnostics.emit(Diagnostic::error(format!("duplicate {} `{}`", what, name), span)
+                                             .with_label(previous.span, "first declared here"));
+            return Ok(None);
+        }
+
+        Ok(Some(ParsedVarDecl {
+            parsed_type,
+            name,
+            span,
+            defualt_value: ParsedExpression::Invalid
+        }))
+    }
+
+    /// Parses the members of a struct up to and including its closing `}`.
+    /// Procs can be declared in between them, which puts them in the
+    /// struct's namespace: `decl length : (...)` in `Vec3` is `Vec3::length`.
+    fn parse_struct_body(&mut self, struct_name: &str, module: &mut ParsedModule) -> ParseResult<Vec<ParsedVarDecl>> {
+        let mut members: Vec<ParsedVarDecl> = Vec::new();
+
+        loop {
+            self.eat_newlines();
+
+            match self.current() {
+                Token::RCurly(_span) => {
+                    self.idx += 1;
+                    break;
+                },
+                Token::KeywordDecl(span) => {
+                    let span = *span;
+                    let proc_count = module.procs.len();
+                    let others = (module.structs.len(), module.enums.len(), module.consts.len());
+
+                    let doc = self.take_doc_comment();
+                    self.parse_decl(module, doc)?;
+
+                    if others != (module.structs.len(), module.enums.len(), module.consts.len()) {
+                        return Err(Diagnostic::error("only procs can be declared inside of a struct", span));
+                    }
+
+                    for proc in &mut module.procs[proc_count..] {
+                        proc.name = format!("{}::{}", struct_name, proc.name);
+                    }
+
+                    continue;
+                },
+                _ => { }
+            }
+
+            if let Some(member) = self.parse_typed_name("member", &members)? {
+                members.push(member);
+            }
+
+            self.eat_newlines();
+
+            match self.current() {
+                Token::Comma(_span) => self.idx += 1,
+                Token::RCurly(_span) => { },
+                _ => return self.error_here("expected `,` after member declaration")
+            }
+        }
+
+        Ok(members)
+    }
+
+    /// Parses the variants of an enum up to and including its closing `}`.
+    /// A variant is just a name, or a name followed by the types of its
+    /// payload, which may be labeled:
+    ///
+    ///     Quit,
+    ///     KeyPressed(Key),
+    ///     Moved(x: i32, y: i32),
+    ///
+    fn parse_enum_variants(&mut self) -> ParseResult<Vec<ParsedEnumVariant>> {
+        let mut variants = Vec::new();
+
+        loop {
+            self.eat_newlines();
+
+            if let Token::RCurly(_span) = self.current() {
+                self.idx += 1;
+                break;
+            }
+
+            let name = match self.current() {
+                Token::IdentName(_span, name) => name.clone(),
+                _ => return self.error_here("expected a variant name")
+            };
+
+            self.idx += 1;
+
+            let variant = match self.current() {
+                Token::LParen(_span) => {
+                    self.idx += 1;
+                    self.eat_newlines();
+
+                    match (self.current(), self.peek()) {
+                        (Token::RParen(_span), _) => {
+                            self.idx += 1;
+                            ParsedEnumVariant::Untyped(name)
+                        },
+                        (Token::IdentName(_, _), Token::Colon(_)) => {
+                            let fields = self.parse_typed_names("payload field", |token| matches!(token, Token::RParen(_span)))?;
+                            ParsedEnumVariant::LabeledTypes(name, fields)
+                        },
+                        _ => ParsedEnumVariant::UnlabeledTypes(name, self.parse_type_list()?),
+                    }
+                },
+                _ => ParsedEnumVariant::Untyped(name)
+            };
+
+            variants.push(variant);
+
+            self.eat_newlines();
+
+            match self.current() {
+                Token::Comma(_span) => self.idx += 1,
+                Token::RCurly(_span) => { },
+                _ => return self.error_here("expected `,` after enum variant")
+            }
+        }
+
+        Ok(variants)
+    }
+
+    /// Parses types separated by commas up to and including a `)`. A
+    /// trailing comma is allowed.
+    fn parse_type_list(&mut self) -> ParseResult<Vec<ParsedType>> {
+        let mut types = Vec::new();
+
+        loop {
+            self.eat_newlines();
+
+            if let Token::RParen(_span) = self.current() {
+                self.idx += 1;
+                break;
+            }
+
+            types.push(self.parse_type_name()?);
+
+            self.eat_newlines();
+
+            match self.current() {
+                Token::Comma(_span) => self.idx += 1,
+                Token::RParen(_span) => { },
+                _ => return self.error_here("expected `,` or `)` after type")
+            }
+        }
+
+        Ok(types)
+    }
+
+    /// Parses the parameters of a proc, and where they end with `..` if
+    /// they do.
+    fn parse_param_list_decl(&mut self) -> ParseResult<(Vec<ParsedVarDecl>, Option<Span>)> {
+        match self.current() {
+            Token::LParen(_span) => self.idx += 1,
+            _ => return self.error_here("expected `(` to start the parameter list")
+        }
+
+        let params = self.parse_typed_names("parameter", |token| matches!(token, Token::RParen(_span) | Token::DotDot(_span)))?;
+
+        let Token::DotDot(span) = self.previous() else {
+            return Ok((params, None));
+        };
+        let span = *span;
+
+        self.eat_newlines();
+
+        match self.current() {
+            Token::RParen(_span) => self.idx += 1,
+            _ => return self.error_here("expected `)` after `..`, it has to come last")
+        }
+
+        Ok((params, Some(span)))
+    }
+
+    fn parse_block(&mut self) -> ParseResult<ParsedBlock> {
+        let mut block = ParsedBlock::new();
+
+        self.eat_newlines();
+
+        let open_span = match self.current() {
+            Token::LCurly(span) => *span,
+            _ => return self.error_here("expected `{` to start a block")
+        };
+
+        self.idx += 1;
+
+        loop {
+            match self.current() {
+                Token::RCurly(_span) => {
+                    self.idx += 1;
+                    break;
+                }
+                Token::EOL(_span) => {
+                    self.idx += 1;
+                    continue;
+                },
+                Token::EOF(_span) | Token::KeywordDecl(_span) => {
+                    // Hand back what we have so the rest of the module can
+                    // still be parsed.
+                    self.diagnostics.emit(
+                        Diagnostic::error(format!("expected `}}` to close the block, but got {}", self.current()), self.current().span())
+                                   .with_label(open_span, "this block is never closed")
+                    );
+                    break;
+                },
+                _ => { }
+            }
+
+            let stmt_start = self.current().span();
+
+            if let Token::Hash(_span) = self.current() {
+                match self.parse_statement_directive() {
+                    Ok(stmts) => block.stmts.extend(stmts),
+                    Err(diagnostic) => {
+                        self.diagnostics.emit(diagnostic);
+                        let skipped = self.synchronize_to_statement_end(stmt_start);
+                        block.stmts.push(ParsedStatement::Invalid(skipped));
+                    }
+                }
+
+                continue;
+            }
+
+            match self.nested(1, Self::parse_statement) {
+                Ok(stmt) => block.stmts.push(stmt),
+                Err(diagnostic) => {
+                    self.diagnostics.emit(diagnostic);
+                    let skipped = self.synchronize_to_statement_end(stmt_start);
+                    block.stmts.push(ParsedStatement::Invalid(skipped));
+                }
+            }
+        }
+
+        Ok(block)
+    }
+
+    fn parse_statement(&mut self) -> ParseResult<ParsedStatement> {
+        let stmt = match self.current() {
+            Token::KeywordLet(_span) => {
+                self.idx += 1;
+                let mut var_decl: ParsedVarDecl = ParsedVarDecl {
+                    parsed_type: ParsedType::Name(Vec::new(), String::new(), Span::default()),
+                    name: String::new(),
+                    span: Span::default(),
+                    defualt_value: ParsedExpression::Invalid
+                };
+
+                match self.current() {
+                    Token::IdentName(span, name) => {
+                        var_decl.name = name.clone();
+                        var_decl.span = *span;
+                    },
+                    _ => return self.error_here("expected a variable name after `let`")
+                }
+
+                self.idx += 1;
+
+                let iterpret_type = match self.current() {
+                    Token::ColonAssign(_span) => {
+                        self.idx += 1;
+                        true
+                    },
+                    Token::Colon(_span) => {
+                        self.idx += 1;
+                        var_decl.parsed_type = self.parse_type_name()?;
+                        false
+                    },
+                    _ => return self.error_here("expected `:` or `:=` in variable declaration")
+                };
+
+                if !iterpret_type {
+                    match self.current() {
+                        Token::Assign(_span) => self.idx += 1,
+                        _ => return self.error_here("expected `=` after the variable's type")
+                    }
+                }
+
+                let default_value = self.parse_expression(false, true)?;
+                var_decl.defualt_value = default_value;
+
+                self.expect_semicolon("at the end of variable declaration")?;
+
+                ParsedStatement::VarDecl(var_decl)
+            },
+
+            Token::KeywordIf(_span) => self.parse_if_statement()?,
+
+            Token::KeywordFor(_span) => {
+                self.idx += 1;
+
+                let mut it_decl: ParsedVarDecl = ParsedVarDecl {
+                    parsed_type: ParsedType::Name(Vec::new(), String::new(), Span::default()),
+                    name: String::new(),
+                    span: Span::default(),
+                    defualt_value: ParsedExpression::Invalid
+                };
+
+                match self.current() {
+                    Token::IdentName(span, name) => {
+                        it_decl.name = name.clone();
+                        it_decl.span = *span;
+                    },
+                    _ => return self.error_here("expected an iterator name after `for`")
+                }
+
+                self.idx += 1;
+
+                match self.current() {
+                    Token::KeywordIn(_span) => self.idx += 1,
+                    _ => return self.error_here("expected `in` after the for loop's iterator name")
+                }
+
+                let range_expr = self.with_struct_literals(false, Self::parse_iterable)?;
+                let body = self.parse_block()?;
+
+                ParsedStatement::ForLoop(it_decl, range_expr, body)
+            },
+
+            Token::KeywordWhile(_span) => {
+                self.idx += 1;
+
+                let cond = self.parse_before_block()?;
+                let body = self.parse_block()?;
+
+                ParsedStatement::WhileLoop(cond, body)
+            },
 
             Token::KeywordLoop(_span) => {
                 self.idx += 1;
 
-                let body = self.parse_block();
+                let body = self.parse_block()?;
 
                 ParsedStatement::InfiniteLoop(body)
             },
 
-            Token::KeywordContinue(_span) => {
+            Token::KeywordMatch(_span) => {
+                // Like a block, a `match` that starts a statement ends at
+                // its `}`, so the `;` is optional
+                let expr = self.parse_match()?;
+
+                if let Token::Semicolon(_span) = self.current() {
+                    self.idx += 1;
+                }
+
+                ParsedStatement::Expr(expr)
+            },
+
+            Token::KeywordContinue(span) => {
+                let span = *span;
                 self.idx += 1;
 
-                match self.current() {
-                    Token::Semicolon(_span) => self.idx += 1,
-                    _ => panic!("Syntax Error! Expected ';', got: {:?}", self.current())
+                self.expect_semicolon("after `continue`")?;
+
+                ParsedStatement::Continue(span)
+            },
+
+            Token::KeywordBreak(span) => {
+                let span = *span;
+                self.idx += 1;
+
+                self.expect_semicolon("after `break`")?;
+
+                ParsedStatement::Break(span)
+            },
+
+            Token::KeywordReturn(span) => {
+                let span = *span;
+                self.idx += 1;
+
+                // `return;` is allowed in procs that return nothing
+                let expr = match self.current() {
+                    Token::Semicolon(_span) => None,
+                    _ => Some(self.parse_expression(false, true)?)
+                };
+
+                self.expect_semicolon("at the end of return statement")?;
+
+                ParsedStatement::Return(expr, span)
+            },
+
+            _ => {
+                let expr = self.parse_expression(true, true)?;
+
+                self.expect_semicolon("at the end of expression")?;
+
+                match expr {
+                    ParsedExpression::BinaryOperation(target, op, value, span) if op.is_assignment() => match *target {
+                        ParsedExpression::Var(name, name_span) => ParsedStatement::VarAssign(name, op, *value, name_span),
+                        target => ParsedStatement::Expr(ParsedExpression::BinaryOperation(Box::new(target), op, value, span)),
+                    },
+                    expr => ParsedStatement::Expr(expr),
                 }
+            }
+        };
 
-                ParsedStatement::Continue
+        Ok(stmt)
+    }
+
+    fn parse_expression(&mut self, can_assign: bool, allow_newlines: bool) -> ParseResult<ParsedExpression> {
+        let mut expr_stack: Vec<ParsedExpression> = Vec::new();
+        let mut last_op_priority = 1_000_000;
+
+        // The operator depth of each operand on the stack. Sibling
+        // expressions, like the arguments of a call, don't add up.
+        let outer_operator_depth = self.operator_depth;
+        let mut operator_depths: Vec<usize> = Vec::new();
+
+        let (lhs, lhs_depth) = self.parse_operand_counting_operators()?;
+        expr_stack.push(lhs);
+        operator_depths.push(lhs_depth);
+
+        loop {
+            if allow_newlines {
+                if self.is_eof() || matches!(self.current(), Token::LCurly(_span))
+                { break; }
+
+                self.eat_newlines();
+            } else if self.is_eol() {
+                break;
+            }
+
+            let op = self.parse_operator(can_assign)?;
+            let op_priority = op.priority();
+
+            if let ParsedExpression::Operator(b_op, _span) = &op {
+                if matches!(&b_op, BinaryOperator::Invalid) {
+                    break
+                }
+            }
+
+            self.eat_newlines();
+
+            let (rhs, rhs_depth) = self.parse_operand_counting_operators()?;
+
+            while op_priority <= last_op_priority && expr_stack.len() > 1 {
+                let pop_rhs = expr_stack.pop().unwrap();
+                let pop_op = expr_stack.pop().unwrap();
+
+                last_op_priority = pop_op.priority();
+
+                // This might look backwards, but remember we're using a stack,
+                // so we pop it off in reverse, so the high priority ones end
+                // up at the top of the stack.
+                if last_op_priority < op_priority {
+                    expr_stack.push(pop_op);
+                    expr_stack.push(pop_rhs);
+                    break;
+                }
+
+                let pop_lhs = expr_stack.pop().unwrap();
+
+                match &pop_op {
+                    ParsedExpression::Operator(bin_op, span) => {
+                        Self::join_operator_depths(&mut operator_depths, *span)?;
+
+                        let span = pop_lhs.span().to(pop_rhs.span());
+                        expr_stack.push(ParsedExpression::BinaryOperation(Box::new(pop_lhs), bin_op.clone(), Box::new(pop_rhs), span));
+                    },
+                    _ => unreachable!("Operator is not an operator")
+                }
+            }
+
+            expr_stack.push(op);
+            expr_stack.push(rhs);
+            operator_depths.push(rhs_depth);
+
+            last_op_priority = op_priority;
+        }
+
+        while expr_stack.len() > 1 {
+            let pop_rhs = expr_stack.pop().unwrap();
+            let pop_op = expr_stack.pop().unwrap();
+            let pop_lhs = expr_stack.pop().unwrap();
+
+            match pop_op {
+                ParsedExpression::Operator(bin_op, span) => {
+                    Self::join_operator_depths(&mut operator_depths, span)?;
+
+                    let span = pop_lhs.span().to(pop_rhs.span());
+                    expr_stack.push(ParsedExpression::BinaryOperation(Box::new(pop_lhs), bin_op, Box::new(pop_rhs), span))
+                },
+                _ => unreachable!("Operator is not an operator")
+            }
+        }
+
+        self.operator_depth = outer_operator_depth.max(operator_depths[0]);
+        Ok(expr_stack.swap_remove(0))
+    }
+
+    /// Parses an operand of a chain of operators, along with how many
+    /// operators deep it is.
+    fn parse_operand_counting_operators(&mut self) -> ParseResult<(ParsedExpression, usize)> {
+        self.operator_depth = 0;
+        let operand = self.parse_operand()?;
+        Ok((operand, self.operator_depth))
+    }
+
+    /// Replaces the operator depths of the two operands on top of
+    /// `operator_depths` with the depth of the operator at `span` joining
+    /// them, unless that's deeper than `MAX_OPERATOR_DEPTH`.
+    fn join_operator_depths(operator_depths: &mut Vec<usize>, span: Span) -> ParseResult<()> {
+        let rhs = operator_depths.pop().unwrap();
+        let lhs = operator_depths.pop().unwrap();
+        let depth = lhs.max(rhs) + 1;
+
+        if depth > MAX_OPERATOR_DEPTH {
+            return Err(Diagnostic::error("this chain of operators is too long", span)
+                                  .with_note(format!("an expression can be at most {} operators deep, like a chain of {} `+`s", MAX_OPERATOR_DEPTH, MAX_OPERATOR_DEPTH)));
+        }
+
+        operator_depths.push(depth);
+        Ok(())
+    }
+
+    /// Parses the expression in front of a block, like the condition of an
+    /// `if`, which can't be a struct literal.
+    fn parse_before_block(&mut self) -> ParseResult<ParsedExpression> {
+        self.with_struct_literals(false, |parser| parser.parse_expression(false, true))
+    }
+
+    /// Parses an expression nested in parentheses or brackets, where struct
+    /// literals are always allowed again.
+    fn parse_nested_expression(&mut self) -> ParseResult<ParsedExpression> {
+        self.with_struct_literals(true, |parser| parser.parse_expression(false, true))
+    }
+
+    fn with_struct_literals<T>(&mut self, allowed: bool, parse: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        let outer = std::mem::replace(&mut self.struct_literals, allowed);
+        let result = parse(self);
+        self.struct_literals = outer;
+        result
+    }
+
+    /// Parses something `levels` deeper in the tree than the parser is,
+    /// unless that's deeper than `MAX_NESTING`.
+    fn nested<T>(&mut self, levels: usize, parse: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        if self.depth + levels > MAX_NESTING {
+            return Err(Diagnostic::error("this is nested too deeply", self.current().span())
+                                  .with_note(format!("expressions, types and blocks can be nested at most {} levels deep", MAX_NESTING)));
+        }
+
+        self.depth += levels;
+        let result = parse(self);
+        self.depth -= levels;
+        result
+    }
+
+    fn parse_operand(&mut self) -> ParseResult<ParsedExpression> {
+        self.nested(1, |parser| {
+            parser.eat_newlines();
+            let expr = parser.parse_operand_prefix()?;
+            parser.parse_operand_postfix(expr)
+        })
+    }
+
+    fn parse_operand_prefix(&mut self) -> ParseResult<ParsedExpression> {
+        let expr = match self.current() {
+            Token::LAngle(span) => {
+                let start = *span;
+                self.idx += 1;
+                let expr = self.parse_operand()?;
+                let span = start.to(expr.span());
+                ParsedExpression::UnaryOperation(Box::new(expr), UnaryOperator::Dereference, span)
+            },
+            Token::Minus(span) => {
+                let start = *span;
+                self.idx += 1;
+                let expr = self.parse_operand()?;
+                let span = start.to(expr.span());
+                ParsedExpression::UnaryOperation(Box::new(expr), UnaryOperator::Negate, span)
+            },
+            Token::Ampersand(span) => {
+                let start = *span;
+                self.idx += 1;
+                let expr = self.parse_operand()?;
+                let span = start.to(expr.span());
+                ParsedExpression::UnaryOperation(Box::new(expr), UnaryOperator::AddressOf, span)
             },
+            Token::Bang(span) => {
+                let start = *span;
+                self.idx += 1;
+                let expr = self.parse_operand()?;
+                let span = start.to(expr.span());
+                ParsedExpression::UnaryOperation(Box::new(expr), UnaryOperator::LogicalNot, span)
+            },
+            Token::Tilde(span) => {
+                let start = *span;
+                self.idx += 1;
+                let expr = self.parse_operand()?;
+                let span = start.to(expr.span());
+                ParsedExpression::UnaryOperation(Box::new(expr), UnaryOperator::BitwiseNot, span)
+            },
+            Token::PlusPlus(span) => {
+                let start = *span;
+                self.idx += 1;
+                let expr = self.parse_operand()?;
+                let span = start.to(expr.span());
+                ParsedExpression::UnaryOperation(Box::new(expr), UnaryOperator::PreIncrement, span)
+            },
+            Token::MinusMinus(span) => {
+                let start = *span;
+                self.idx += 1;
+                let expr = self.parse_operand()?;
+                let span = start.to(expr.span());
+                ParsedExpression::UnaryOperation(Box::new(expr), UnaryOperator::PreDecrement, span)
+            },
+            Token::StringLiteral(span, lit) => {
+                let expr = ParsedExpression::StringLiteral(lit.clone(), *span);
+                self.idx += 1;
+                expr
+            },
+            Token::CharLiteral(span, lit) => {
+                let expr = ParsedExpression::CharLiteral(*lit, *span);
+                self.idx += 1;
+                expr
+            },
+            Token::BooleanLiteral(span, val) => {
+                let expr = ParsedExpression::Bool(*val, *span);
+                self.idx += 1;
+                expr
+            },
+            Token::KeywordNull(span) => {
+                let expr = ParsedExpression::Null(*span);
+                self.idx += 1;
+                expr
+            },
+            Token::Number(span, constant) => {
+                let expr = ParsedExpression::NumericConstant(constant.clone(), *span);
+                self.idx += 1;
+                expr
+            },
+            Token::IdentName(span, name) => {
+                let ident = name.clone();
 
-            Token::KeywordBreak(_span) => {
+                match self.peek() {
+                    Token::LParen(_span) => {
+                        let call = self.parse_proc_call()?;
+                        ParsedExpression::ProcCall(call)
+                    }
+                    Token::DoubleColon(_span) => self.parse_namespaced_operand()?,
+                    Token::LCurly(_span) if self.struct_literals => {
+                        let struct_type = ParsedType::Name(Vec::new(), ident, *span);
+                        self.idx += 1;
+                        self.parse_struct_literal(struct_type)?
+                    },
+                    _ => {
+                        let expr = ParsedExpression::Var(ident, *span);
+                        self.idx += 1;
+                        expr
+                    }
+                }
+            },
+            Token::BuiltinType(_span, _) if matches!(self.peek(), Token::DoubleColon(_span)) => self.parse_namespaced_operand()?,
+            Token::KeywordMatch(_span) => self.parse_match()?,
+            Token::KeywordIf(_span) => self.parse_if_expression()?,
+            Token::LSquare(_span) => self.parse_array_literal()?,
+            // The parentheses only group, so the expression inside is all
+            // that's kept
+            Token::LParen(_span) => {
                 self.idx += 1;
+                let expr = self.parse_nested_expression()?;
+                self.eat_newlines();
 
                 match self.current() {
-                    Token::Semicolon(_span) => self.idx += 1,
-                    _ => panic!("Syntax Error! Expected ';', got: {:?}", self.current())
+                    Token::RParen(_span) => self.idx += 1,
+                    _ => return self.error_here("expected `)` to close the parentheses")
                 }
 
-                ParsedStatement::Break
+                expr
             },
+            token => return Err(Diagnostic::error(format!("expected an expression, but got {}", token), token.span()))
+        };
+
+        Ok(expr)
+    }
+
+    /// Parses `if cond { ... }`, along with the `else if`s and the `else`
+    /// chained after it. An `else if` is an `else` holding another `if`.
+    fn parse_if_statement(&mut self) -> ParseResult<ParsedStatement> {
+        self.idx += 1;
+
+        let cond = self.parse_before_block()?;
+        let then_block = self.parse_block()?;
+
+        if !self.eat_else_keyword() {
+            return Ok(ParsedStatement::If(cond, then_block, None));
+        }
+
+        self.eat_newlines();
+
+        let else_stmt = match self.current() {
+            Token::KeywordIf(_span) => self.parse_if_statement()?,
+            Token::LCurly(_span) => ParsedStatement::Block(self.parse_block()?),
+            _ => return self.error_here("expected `{` or `if` after `else`")
+        };
+
+        Ok(ParsedStatement::If(cond, then_block, Some(Box::new(else_stmt))))
+    }
+
+    /// Parses an `if` that's used as a value. It needs an `else`, so there's
+    /// always something to yield, and an `else if` is an `If` in the `else`
+    /// branch.
+    fn parse_if_expression(&mut self) -> ParseResult<ParsedExpression> {
+        let start = self.current().span();
+        self.idx += 1;
+
+        let cond = self.parse_before_block()?;
+        let then_body = self.parse_if_branch()?;
+
+        if !self.eat_else_keyword() {
+            return Err(Diagnostic::error("an `if` that's used as a value needs an `else`", start.to(self.previous().span()))
+                                  .with_note("there would be nothing to yield when the condition doesn't hold otherwise"));
+        }
+
+        self.eat_newlines();
+
+        let else_body = match self.current() {
+            Token::KeywordIf(_span) => MatchExprBody::Expr(self.parse_if_expression()?),
+            Token::LCurly(_span) => self.parse_if_branch()?,
+            _ => return self.error_here("expected `{` or `if` after `else`")
+        };
+
+        let span = start.to(self.previous().span());
+        let result_type = ParsedType::Name(Vec::new(), String::new(), Span::default());
+        Ok(ParsedExpression::If(Box::new(cond), Box::new(then_body), Box::new(else_body), Box::new(result_type), span))
+    }
+
+    /// Parses a branch of an `if` that's used as a value, which is either
+    /// `{ value }` or a block, like the body of a match arm.
+    fn parse_if_branch(&mut self) -> ParseResult<MatchExprBody> {
+        self.eat_newlines();
+
+        if !self.is_branch_value() {
+            return Ok(MatchExprBody::Block(self.parse_block()?));
+        }
 
-            Token::KeywordReturn(_span) => {
+        self.idx += 1;
+        let value = self.parse_nested_expression()?;
+        self.eat_newlines();
+
+        match self.current() {
+            Token::RCurly(_span) => self.idx += 1,
+            _ => return self.error_here("expected `}` after the value of the branch")
+        }
+
+        Ok(MatchExprBody::Expr(value))
+    }
+
+    /// Whether the `{` at the current token holds a lone value, rather than
+    /// statements. Statements end in a `;` or start with a keyword that
+    /// only a statement can, so a value is anything else up to the `}`.
+    fn is_branch_value(&self) -> bool {
+        let Token::LCurly(_span) = self.current() else {
+            return false;
+        };
+
+        let mut idx = self.idx + 1;
+        while let Token::EOL(_span) = &self.token_stream[idx] {
+            idx += 1;
+        }
+
+        if matches!(&self.token_stream[idx], Token::RCurly(_)           |
+                                             Token::LCurly(_)           |
+                                             Token::Hash(_)             |
+                                             Token::KeywordLet(_)       |
+                                             Token::KeywordFor(_)       |
+                                             Token::KeywordWhile(_)     |
+                                             Token::KeywordLoop(_)      |
+                                             Token::KeywordContinue(_)  |
+                                             Token::KeywordBreak(_)     |
+                                             Token::KeywordReturn(_)) {
+            return false;
+        }
+
+        let mut depth = 0;
+
+        for token in &self.token_stream[self.idx..] {
+            match token {
+                Token::LCurly(_span) => depth += 1,
+                Token::RCurly(_span) => {
+                    depth -= 1;
+
+                    if depth == 0 {
+                        return true;
+                    }
+                },
+                Token::Semicolon(_span) if depth == 1 => return false,
+                Token::EOF(_span) => return false,
+                _ => { }
+            }
+        }
+
+        false
+    }
+
+    /// Parses `[a, b, c]`, or `[value; N]` for `N` copies of the value.
+    fn parse_array_literal(&mut self) -> ParseResult<ParsedExpression> {
+        let start = self.current().span();
+        self.idx += 1;
+
+        // The element type is worked out by the typechecker
+        let array_type = || Box::new(ParsedType::Name(Vec::new(), String::new(), Span::default()));
+        let mut elems = Vec::new();
+
+        loop {
+            self.eat_newlines();
+
+            if let Token::RSquare(_span) = self.current() {
                 self.idx += 1;
-                let expr = self.parse_expression(false, true);
+                break;
+            }
+
+            let elem = self.parse_nested_expression()?;
+            self.eat_newlines();
+
+            match self.current() {
+                Token::Semicolon(_span) if elems.is_empty() => {
+                    self.idx += 1;
+                    let count = self.parse_nested_expression()?;
+                    self.eat_newlines();
+
+                    match self.current() {
+                        Token::RSquare(_span) => self.idx += 1,
+                        _ => return self.error_here("expected `]` after the number of copies")
+                    }
+
+                    let span = start.to(self.previous().span());
+                    return Ok(ParsedExpression::ArrayRepeat(Box::new(elem), Box::new(count), array_type(), span));
+                },
+                Token::Comma(_span) => self.idx += 1,
+                Token::RSquare(_span) => { },
+                _ => return self.error_here("expected `,` or `]` after an element of the array")
+            }
+
+            elems.push(elem);
+        }
+
+        Ok(ParsedExpression::ArrayLiteral(elems, array_type(), start.to(self.previous().span())))
+    }
+
+    /// Parses `match value { pattern => body, ... }`. A pattern is either
+    /// `else`, a variant like `Enum::Variant(a, b)`, or any other expression
+    /// to compare the value against. Bodies that are blocks don't need a
+    /// `,` after them.
+    fn parse_match(&mut self) -> ParseResult<ParsedExpression> {
+        let start = self.current().span();
+        self.idx += 1;
+
+        let matched = self.parse_before_block()?;
+        self.eat_newlines();
+
+        let open_span = match self.current() {
+            Token::LCurly(span) => *span,
+            _ => return self.error_here("expected `{` after the value to match")
+        };
+
+        self.idx += 1;
+
+        let mut cases = Vec::new();
+
+        loop {
+            self.eat_newlines();
 
-                assert!(matches!(self.current(), Token::Semicolon(_span)), "Syntax Error! Expected ';' at end of return expression, got {:?}", self.current());
-                self.idx += 1;
+            match self.current() {
+                Token::RCurly(_span) => {
+                    self.idx += 1;
+                    break;
+                },
+                Token::EOF(span) | Token::KeywordDecl(span) => {
+                    return Err(Diagnostic::error(format!("expected `}}` to close the `match`, but got {}", self.current()), *span)
+                                          .with_label(open_span, "this `match` is never closed"));
+                },
+                _ => { }
+            }
 
-                ParsedStatement::Return(expr)
-            },
+            let case = match self.current() {
+                Token::KeywordElse(span) => {
+                    let span = *span;
+                    self.idx += 1;
+                    MatchExprCase::Fallback(span, self.parse_match_body()?)
+                },
+                Token::IdentName(_span, _name) if matches!(self.peek(), Token::DoubleColon(_span)) => {
+                    let pattern = self.parse_variant_pattern()?;
+                    MatchExprCase::EnumVariant(pattern, self.parse_match_body()?)
+                },
+                _ => {
+                    let expr = self.parse_expression(false, true)?;
+                    MatchExprCase::Expr(expr, self.parse_match_body()?)
+                },
+            };
 
-            _ => {
-                let expr = self.parse_expression(true, true);
+            let ends_in_block = matches!(case, MatchExprCase::Expr(_, MatchExprBody::Block(_))        |
+                                               MatchExprCase::EnumVariant(_, MatchExprBody::Block(_)) |
+                                               MatchExprCase::Fallback(_, MatchExprBody::Block(_)));
+            cases.push(case);
 
-                assert!(matches!(self.current(), Token::Semicolon(_span)), "Syntax Error! Expected ';' at end of expression, got {:?}", self.current());
-                self.idx += 1;
+            self.eat_newlines();
 
-                ParsedStatement::Expr(expr)
+            match self.current() {
+                Token::Comma(_span) => self.idx += 1,
+                Token::RCurly(_span) => { },
+                _ if ends_in_block => { },
+                _ => return self.error_here("expected `,` or `}` after match arm")
             }
-        };
+        }
 
-        stmt
+        let span = start.to(self.previous().span());
+        let result_type = ParsedType::Name(Vec::new(), String::new(), Span::default());
+        Ok(ParsedExpression::Match(Box::new(matched), cases, Box::new(result_type), span))
     }
 
-    fn parse_expression(&mut self, can_assign: bool, allow_newlines: bool) -> ParsedExpression {
-        let mut expr_stack: Vec<ParsedExpression> = Vec::new();
-        let mut last_op_priority = 1_000_000;
+    /// Parses the `=> body` of a match arm.
+    fn parse_match_body(&mut self) -> ParseResult<MatchExprBody> {
+        match self.current() {
+            Token::ThiccArrow(_span) => self.idx += 1,
+            _ => return self.error_here("expected `=>` after the pattern")
+        }
 
-        let lhs = self.parse_operand();
-        expr_stack.push(lhs);
+        self.eat_newlines();
 
-        loop {
-            if allow_newlines {
-                if self.is_eof() || matches!(self.current(), Token::LCurly(_span))
-                { break; }
+        match self.current() {
+            Token::LCurly(_span) => Ok(MatchExprBody::Block(self.parse_block()?)),
+            // Arms can assign, like `1 => total += i`, since a `match`
+            // is often used as a statement
+            _ => Ok(MatchExprBody::Expr(self.parse_expression(true, true)?)),
+        }
+    }
 
-                self.eat_newlines();
-            } else {
-                if self.is_eol() {
-                    break;
-                }
-            }
+    /// Parses `Enum::Variant`, optionally followed by names to bind the
+    /// payload to, like `(key)` or `(x: new_x, y)`.
+    fn parse_variant_pattern(&mut self) -> ParseResult<ParsedVariantPattern> {
+        let start = self.current().span();
+        let mut namespace = Vec::new();
+
+        let name = loop {
+            let name = match self.current() {
+                Token::IdentName(_span, name) => name.clone(),
+                // A module can be named after a builtin type, like
+                // `core/string`
+                Token::BuiltinType(_span, ty) if matches!(self.peek(), Token::DoubleColon(_span)) => String::from(ty.as_str()),
+                _ => return self.error_here("expected a name after `::`")
+            };
 
-            let op = self.parse_operator(can_assign);
-            let op_priority = op.priority();
+            self.idx += 1;
 
-            if let ParsedExpression::Operator(b_op) = &op {
-                if matches!(&b_op, BinaryOperator::Invalid) {
-                    break
-                }
+            match self.current() {
+                Token::DoubleColon(_span) => {
+                    namespace.push(name);
+                    self.idx += 1;
+                },
+                _ => break name,
             }
+        };
 
-            self.eat_newlines();
-
-            let rhs = self.parse_operand();
-
-            while op_priority <= last_op_priority && expr_stack.len() > 1 {
-                let pop_rhs = expr_stack.pop().unwrap();
-                let pop_op = expr_stack.pop().unwrap();
+        if !matches!(self.current(), Token::LParen(_span)) {
+            return Ok(ParsedVariantPattern { namespace, name, bindings: None, span: start.to(self.previous().span()) });
+        }
 
-                last_op_priority = pop_op.priority();
+        self.idx += 1;
 
-                // This might look backwards, but remember we're using a stack,
-                // so we pop it off in reverse, so the high priority ones end
-                // up at the top of the stack.
-                if last_op_priority < op_priority {
-                    expr_stack.push(pop_op);
-                    expr_stack.push(pop_rhs);
-                    break;
-                }
+        let mut bindings = Vec::new();
 
-                let pop_lhs = expr_stack.pop().unwrap();
+        loop {
+            self.eat_newlines();
 
-                match &pop_op {
-                    ParsedExpression::Operator(bin_op) => {
-                        expr_stack.push(ParsedExpression::BinaryOperation(Box::new(pop_lhs), bin_op.clone(), Box::new(pop_rhs)));
-                    },
-                    _ => panic!("WHAT?!?! Operator is not an operator")
-                }
+            if let Token::RParen(_span) = self.current() {
+                self.idx += 1;
+                break;
             }
 
-            expr_stack.push(op);
-            expr_stack.push(rhs);
+            let (first, first_span) = match self.current() {
+                Token::IdentName(span, name) => (name.clone(), *span),
+                _ => return self.error_here("expected a name to bind the payload to")
+            };
 
-            last_op_priority = op_priority;
-        }
+            self.idx += 1;
 
-        while expr_stack.len() > 1 {
-            let pop_rhs = expr_stack.pop().unwrap();
-            let pop_op = expr_stack.pop().unwrap();
-            let pop_lhs = expr_stack.pop().unwrap();
+            let binding = match self.current() {
+                Token::Colon(_span) => {
+                    self.idx += 1;
 
-            match pop_op {
-                ParsedExpression::Operator(bin_op) => {
-                    expr_stack.push(ParsedExpression::BinaryOperation(Box::new(pop_lhs), bin_op, Box::new(pop_rhs)))
+                    match self.current() {
+                        Token::IdentName(span, name) => {
+                            let binding = MatchBinding { label: first, name: name.clone(), span: first_span.to(*span) };
+                            self.idx += 1;
+                            binding
+                        },
+                        _ => return self.error_here(format!("expected a name to bind `{}` to", first))
+                    }
                 },
-                _ => panic!("WHAT?!?! Operator is not an operator")
+                _ => MatchBinding { label: String::new(), name: first, span: first_span },
+            };
+
+            bindings.push(binding);
+
+            self.eat_newlines();
+
+            match self.current() {
+                Token::Comma(_span) => self.idx += 1,
+                Token::RParen(_span) => { },
+                _ => return self.error_here("expected `,` or `)` after binding")
             }
         }
 
-        expr_stack[0].clone()
-    }
-
-    fn parse_operand(&mut self) -> ParsedExpression {
-        self.eat_newlines();
-        let expr = self.parse_operand_prefix();
-        self.parse_operand_postfix(expr)
+        Ok(ParsedVariantPattern { namespace, name, bindings: Some(bindings), span: start.to(self.previous().span()) })
     }
 
-    fn parse_operand_prefix(&mut self) -> ParsedExpression {
-        match self.current() {
-            Token::LAngle(_span) => {
-                self.idx += 1;
-                let expr = self.parse_operand();
-                ParsedExpression::UnaryOperation(Box::new(expr), UnaryOperator::Dereference)
-            },
-            Token::Ampersand(_span) => {
-                self.idx += 1;
-                let expr = self.parse_operand();
-                ParsedExpression::UnaryOperation(Box::new(expr), UnaryOperator::AddressOf)
-            },
-            Token::Bang(_span) => {
-                self.idx += 1;
-                let expr = self.parse_operand();
-                ParsedExpression::UnaryOperation(Box::new(expr), UnaryOperator::LogicalNot)
-            },
-            Token::Tilde(_span) => {
+    fn parse_operand_postfix(&mut self, base: ParsedExpression) -> ParseResult<ParsedExpression> {
+        let expr = match self.current() {
+            Token::PlusPlus(span) => {
+                let span = base.span().to(*span);
                 self.idx += 1;
-                let expr = self.parse_operand();
-                ParsedExpression::UnaryOperation(Box::new(expr), UnaryOperator::BitwiseNot)
+                ParsedExpression::UnaryOperation(Box::new(base), UnaryOperator::PostIncrement, span)
             },
-            Token::PlusPlus(_span) => {
+            Token::MinusMinus(span) => {
+                let span = base.span().to(*span);
                 self.idx += 1;
-                let expr = self.parse_operand();
-                ParsedExpression::UnaryOperation(Box::new(expr), UnaryOperator::PreIncrement)
+                ParsedExpression::UnaryOperation(Box::new(base), UnaryOperator::PostDecrement, span)
             },
-            Token::MinusMinus(_span) => {
-                self.idx += 1;
-                let expr = self.parse_operand();
-                ParsedExpression::UnaryOperation(Box::new(expr), UnaryOperator::PreDecrement)
-            },
-            Token::StringLiteral(_span, lit) => {
-                let string = lit.clone();
-                self.idx += 1;
-                ParsedExpression::StringLiteral(string)
-            },
-            Token::CharLiteral(_span, lit) => {
-                let ch = *lit;
+            Token::KeywordAs(_span) => {
                 self.idx += 1;
-                ParsedExpression::CharLiteral(ch)
-            },
-            Token::BooleanLiteral(_span, val) => {
-                let b = *val;
+                let to_type = self.parse_type_name()?;
+                let span = base.span().to(self.previous().span());
+                ParsedExpression::UnaryOperation(Box::new(base), UnaryOperator::TypeCast(Box::new(to_type)), span)
+            }
+            Token::LSquare(_span) => {
                 self.idx += 1;
-                ParsedExpression::Bool(b)
+                let index = self.parse_nested_expression()?;
+                self.eat_newlines();
+
+                match self.current() {
+                    Token::RSquare(_span) => self.idx += 1,
+                    _ => return self.error_here("expected `]` after the index")
+                }
+
+                let span = base.span().to(self.previous().span());
+                let expr = ParsedExpression::Index(Box::new(base), Box::new(index), span);
+
+                // Indexing can be chained, like `grid[y][x]`
+                return self.nested(1, |parser| parser.parse_operand_postfix(expr));
             },
-            Token::Number(_span, constant) => {
-                let n = constant.clone();
+            Token::Dot(_span) => {
                 self.idx += 1;
-                ParsedExpression::NumericConstant(n)
-            },
-            Token::IdentName(_span, name) => {
-                let ident = name.clone();
 
-                match self.peek() {
+                let (name, name_span) = match self.current() {
+                    Token::IdentName(span, name) => (name.clone(), *span),
+                    _ => return self.error_here("expected a member name after `.`")
+                };
+
+                let expr = match self.peek() {
                     Token::LParen(_span) => {
-                        let call = self.parse_proc_call();
-                        ParsedExpression::ProcCall(call)
-                    }
+                        let mut call = self.parse_proc_call()?;
+                        call.span = base.span().to(call.span);
+                        ParsedExpression::MethodCall(Box::new(base), call)
+                    },
                     _ => {
                         self.idx += 1;
-                        ParsedExpression::Var(ident)
+                        let span = base.span().to(name_span);
+                        ParsedExpression::Member(Box::new(base), name, span)
                     }
-                }
-            },
-            _ => panic!("Unexpected token: {:?}", self.current())
-        }
-    }
+                };
 
-    fn parse_operand_postfix(&mut self, base: ParsedExpression) -> ParsedExpression {
-        match self.current() {
-            Token::PlusPlus(_span) => {
-                self.idx += 1;
-                ParsedExpression::UnaryOperation(Box::new(base), UnaryOperator::PostIncrement)
+                // Member accesses can be chained too, like `player.position.x`
+                return self.nested(1, |parser| parser.parse_operand_postfix(expr));
             },
-            Token::MinusMinus(_span) => {
-                self.idx += 1;
-                ParsedExpression::UnaryOperation(Box::new(base), UnaryOperator::PostDecrement)
-            },
-            Token::KeywordAs(_span) => {
-                self.idx += 1;
-                let to_type = self.parse_type_name();
-                ParsedExpression::UnaryOperation(Box::new(base), UnaryOperator::TypeCast(Box::new(to_type)))
-            }
-            Token::LAngle(_span) => panic!("Add array indexing"),
             _ => base
+        };
+
+        Ok(expr)
+    }
+
+    /// Parses what a `for` loop goes over, which is a range or an array.
+    /// Both can start with a `[` or a `(`, so it's only an array if it
+    /// doesn't parse as a range.
+    fn parse_iterable(&mut self) -> ParseResult<ParsedExpression> {
+        self.eat_newlines();
+
+        if !matches!(self.current(), Token::LSquare(_span) | Token::LParen(_span)) {
+            return self.parse_expression(false, true);
         }
+
+        let start_idx = self.idx;
+        let range_error = match self.parse_range_expression() {
+            Ok(range) => return Ok(range),
+            Err(diagnostic) => diagnostic,
+        };
+
+        self.idx = start_idx;
+        self.parse_expression(false, true).map_err(|_| range_error)
     }
 
-    fn parse_range_expression(&mut self) -> ParsedExpression {
+    fn parse_range_expression(&mut self) -> ParseResult<ParsedExpression> {
         self.eat_newlines();
 
+        let start_span = self.current().span();
         let left_bound = match self.current() {
             Token::LSquare(_span) => RangeExprBound::Inclusive,
             Token::LParen(_span) => RangeExprBound::Exclusive,
-            _ => panic!("Syntax Error! Expected '[' or '(' to specify lower range inclusivity, but got: {:?}", self.current())
+            _ => return self.error_here("expected `[` or `(` to specify the lower bound's inclusivity")
         };
 
         self.idx += 1;
 
-        let start = self.parse_expression(false, true);
+        let start = self.parse_expression(false, true)?;
 
         match self.current() {
             Token::DotDot(_span) => self.idx += 1,
-            _ => panic!("Syntax Error! Expected '..' in range expression, got: {:?}", self.current())
+            _ => return self.error_here("expected `..` in range expression")
         }
 
-        let end = self.parse_expression(false, true);
+        let end = self.parse_expression(false, true)?;
         let right_bound = match self.current() {
             Token::RSquare(_span) => RangeExprBound::Inclusive,
             Token::RParen(_span) => RangeExprBound::Exclusive,
-            _ => panic!("Syntax Error! Expected ']' or ')' to specify lower range inclusivity, but got: {:?}", self.current())
+            _ => return self.error_here("expected `]` or `)` to specify the upper bound's inclusivity")
         };
 
+        let span = start_span.to(self.current().span());
         self.idx += 1;
 
-        ParsedExpression::Range(left_bound, Box::new(start), Box::new(end), right_bound)
+        Ok(ParsedExpression::Range(left_bound, Box::new(start), Box::new(end), right_bound, span))
     }
 
-    fn parse_operator(&mut self, can_assign: bool) -> ParsedExpression {
+    fn parse_operator(&mut self, can_assign: bool) -> ParseResult<ParsedExpression> {
         self.eat_newlines();
 
         let ret = match self.current() {
@@ -832,56 +1951,334 @@ impl Parser {
             Token::CaretAssign(_span) => BinaryOperator::XORAssign,
             Token::LShiftAssign(_span) => BinaryOperator::LeftShiftAssign,
             Token::RShiftAssign(_span) => BinaryOperator::RightShiftAssign,
-            _ => {
-                self.idx -= 1;
-                BinaryOperator::Invalid
-            }
+            token => return Ok(ParsedExpression::Operator(BinaryOperator::Invalid, token.span()))
         };
 
         if ret.is_assignment() && !can_assign {
-            panic!("Syntax Error! Got an unexpected assignment operator [{:?}]", self.current());
+            return self.error_here(format!("unexpected assignment operator {}", self.current()));
         }
 
+        let span = self.current().span();
         self.idx += 1;
 
-        ParsedExpression::Operator(ret)
+        Ok(ParsedExpression::Operator(ret, span))
+    }
+
+    /// Parses `a::b::c`, which may be followed by arguments like a call.
+    /// Whether it's an enum variant or something from another module is
+    /// up to the typechecker.
+    fn parse_namespaced_operand(&mut self) -> ParseResult<ParsedExpression> {
+        let start = self.current().span();
+        let mut namespace = Vec::new();
+
+        let name = loop {
+            let name = match self.current() {
+                Token::IdentName(_span, name) => name.clone(),
+                // A module can be named after a builtin type, like
+                // `core/string`
+                Token::BuiltinType(_span, ty) if matches!(self.peek(), Token::DoubleColon(_span)) => String::from(ty.as_str()),
+                _ => return self.error_here("expected a name after `::`")
+            };
+
+            self.idx += 1;
+
+            match self.current() {
+                Token::DoubleColon(_span) => {
+                    namespace.push(name);
+                    self.idx += 1;
+                },
+                _ => break name,
+            }
+        };
+
+        if matches!(self.current(), Token::LCurly(_span)) && self.struct_literals {
+            let struct_type = ParsedType::Name(namespace, name, start.to(self.previous().span()));
+            return self.parse_struct_literal(struct_type);
+        }
+
+        if !matches!(self.current(), Token::LParen(_span)) {
+            return Ok(ParsedExpression::NamespacedVar(namespace, name, start.to(self.previous().span())));
+        }
+
+        let passed_parameters = self.parse_arg_list()?;
+
+        Ok(ParsedExpression::ProcCall(ParsedProcCall {
+            namespace,
+            name,
+            span: start.to(self.previous().span()),
+            passed_parameters,
+        }))
+    }
+
+    /// Parses the `{ member: value, ... }` of a struct literal, which
+    /// `struct_type` is in front of. A trailing comma is allowed.
+    fn parse_struct_literal(&mut self, struct_type: ParsedType) -> ParseResult<ParsedExpression> {
+        let start = match &struct_type {
+            ParsedType::Name(_, _, span) => *span,
+            _ => self.current().span(),
+        };
+
+        match self.current() {
+            Token::LCurly(_span) => self.idx += 1,
+            _ => return self.error_here("expected `{` to start the struct literal")
+        }
+
+        let mut members: Vec<ParsedVarDecl> = Vec::new();
+
+        loop {
+            self.eat_newlines();
+
+            if let Token::RCurly(_span) = self.current() {
+                self.idx += 1;
+                break;
+            }
+
+            let (name, span) = match self.current() {
+                Token::IdentName(span, name) => (name.clone(), *span),
+                _ => return self.error_here("expected a member name")
+            };
+
+            self.idx += 1;
+
+            match self.current() {
+                Token::Colon(_span) => self.idx += 1,
+                _ => return self.error_here(format!("expected `:` after the member name `{}`", name))
+            }
+
+            let value = self.parse_nested_expression()?;
+
+            members.push(ParsedVarDecl {
+                parsed_type: ParsedType::Name(Vec::new(), String::new(), Span::default()),
+                name,
+                span,
+                defualt_value: value
+            });
+
+            self.eat_newlines();
+
+            match self.current() {
+                Token::Comma(_span) => self.idx += 1,
+                Token::RCurly(_span) => { },
+                _ => return self.error_here("expected `,` or `}` after a member of the struct literal")
+            }
+        }
+
+        let span = start.to(self.previous().span());
+        Ok(ParsedExpression::StructLiteral(Box::new(struct_type), members, span))
+    }
+
+    /// Parses a parenthesized list of arguments that may or may not be
+    /// labeled, like the payload in `Event::KeyPressed(key)`. Unlabeled
+    /// arguments are left with an empty name.
+    fn parse_arg_list(&mut self) -> ParseResult<Vec<ParsedVarDecl>> {
+        let mut args = Vec::new();
+
+        match self.current() {
+            Token::LParen(_span) => self.idx += 1,
+            _ => return self.error_here("expected `(` to start the argument list")
+        }
+
+        loop {
+            self.eat_newlines();
+
+            if let Token::RParen(_span) = self.current() {
+                self.idx += 1;
+                break;
+            }
+
+            let label = match (self.current(), self.peek()) {
+                (Token::IdentName(span, name), Token::Colon(_)) => {
+                    let label = (name.clone(), *span);
+                    self.idx += 2;
+                    Some(label)
+                },
+                _ => None,
+            };
+
+            let value = self.parse_nested_expression()?;
+            let (name, span) = label.unwrap_or_else(|| (String::new(), value.span()));
+
+            args.push(ParsedVarDecl {
+                parsed_type: ParsedType::Name(Vec::new(), String::new(), Span::default()),
+                name,
+                span,
+                defualt_value: value
+            });
+
+            self.eat_newlines();
+
+            match self.current() {
+                Token::Comma(_span) => self.idx += 1,
+                Token::RParen(_span) => { },
+                _ => return self.error_here("expected `,` or `)` after argument")
+            }
+        }
+
+        Ok(args)
     }
 
-    fn parse_proc_call(&mut self) -> ParsedProcCall {
+    fn parse_proc_call(&mut self) -> ParseResult<ParsedProcCall> {
         let mut ret = ParsedProcCall {
+            namespace: Vec::new(),
             name: String::new(),
+            span: self.current().span(),
             passed_parameters: Vec::new(),
         };
 
         ret.name = match self.current() {
             Token::IdentName(_span, name) => name.clone(),
-            _ => panic!("Expected identifier")
+            _ => return self.error_here("expected the name of a procedure")
         };
 
         self.idx += 1;
 
         ret.passed_parameters = match self.current() {
-            Token::LParen(_span) => self.parse_param_list_usage(),
-            _ => panic!("Syntax Error! Expected '(' in procedure call.")
+            Token::LParen(_span) => self.parse_arg_list()?,
+            _ => return self.error_here("expected `(` in procedure call")
         };
 
-        ret
+        ret.span = ret.span.to(self.previous().span());
+
+        Ok(ret)
+    }
+
+    fn expect_semicolon(&mut self, context: &str) -> ParseResult<()> {
+        match self.current() {
+            Token::Semicolon(_span) => {
+                self.idx += 1;
+                Ok(())
+            },
+            token => Err(Diagnostic::error(format!("expected `;` {}, but got {}", context, token), token.span()))
+        }
+    }
+
+    /// Builds an error that points at the current token. Messages that
+    /// say what was expected get told what was found instead.
+    fn error_here<T>(&self, message: impl Into<String>) -> ParseResult<T> {
+        let token = self.current();
+        let mut message = message.into();
+
+        if message.starts_with("expected") {
+            message = format!("{}, but got {}", message, token);
+        }
+
+        Err(Diagnostic::error(message, token.span()))
     }
 
     fn eat_newlines(&mut self) {
-        loop {
-            match self.current() {
-                Token::EOL(_span) => self.idx += 1,
-                _ => break
-            }
+        while let Token::EOL(_span) = self.current() {
+            self.idx += 1;
         }
     }
 
     fn is_eof(&self) -> bool {
-        matches!(self.current(), Token::EOF)
+        matches!(self.current(), Token::EOF(_span))
     }
 
     fn is_eol(&self) -> bool {
         matches!(self.current(), Token::EOL(_span))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::tokenizer::Tokenizer;
+
+    fn parse(source: &str) -> (ParsedModule, DiagnosticSink) {
+        let diagnostics = DiagnosticSink::new();
+        let config = BuildConfig::new(None, HashMap::new());
+        let tokens = Tokenizer::new(String::from(source), 0, diagnostics.clone()).tokenize();
+        let module = Parser::new(tokens, &config, diagnostics.clone()).parse_module("test", "test.amds");
+
+        (module, diagnostics)
+    }
+
+    #[test]
+    fn doc_comments_attach_to_the_next_decl() {
+        let (module, diagnostics) = parse("//! The module\n\n/// First line\n/// second line\ndecl Point : struct { x: i32 }\n\n// Not a doc comment\ndecl main : () { }\n");
+        assert!(!diagnostics.has_errors());
+
+        assert_eq!(module.doc.as_deref(), Some("The module"));
+        assert_eq!(module.structs[0].doc.as_deref(), Some("First line\nsecond line"));
+        assert_eq!(module.procs[0].doc, None);
+    }
+
+    #[test]
+    fn assignments_to_variables_are_their_own_statement() {
+        let (module, diagnostics) = parse("decl main : () {\n    let x := 1;\n    x += 2;\n    <&x = 3;\n}\n");
+        assert!(!diagnostics.has_errors());
+
+        let stmts = &module.procs[0].body.as_ref().unwrap().stmts;
+        assert!(matches!(&stmts[1], ParsedStatement::VarAssign(name, BinaryOperator::AddAssign, _, _) if name == "x"));
+        assert!(matches!(&stmts[2], ParsedStatement::Expr(ParsedExpression::BinaryOperation(_, BinaryOperator::Assign, _, _))));
+    }
+
+    #[test]
+    fn deep_nesting_is_reported_instead_of_overflowing() {
+        let parens = format!("decl main : () -> i32 {{ return {}1{}; }}", "(".repeat(10_000), ")".repeat(10_000));
+        let blocks = format!("decl main : () {{ {}{} }}", "if true { ".repeat(10_000), "} ".repeat(10_000));
+        let pointers = format!("decl f : () -> {}i32;", "*".repeat(200_000));
+        let arrays = format!("decl f : () -> {}i32;", "[1]".repeat(200_000));
+        let shallow = format!("decl main : () -> {}i32 {{ return {}1{}; }}", "*".repeat(200), "(".repeat(200), ")".repeat(200));
+
+        // Test threads get less stack than the compiler's main thread
+        let messages = std::thread::Builder::new().stack_size(8 << 20).spawn(move || {
+            [parens, blocks, pointers, arrays, shallow].map(|source| {
+                let (_, diagnostics) = parse(&source);
+                diagnostics.take().into_iter().map(|diagnostic| diagnostic.message).collect::<Vec<_>>()
+            })
+        }).unwrap().join().unwrap();
+
+        assert_eq!(messages, [
+            vec!["this is nested too deeply"],
+            vec!["this is nested too deeply"],
+            vec!["this is nested too deeply"],
+            vec!["this is nested too deeply"],
+            vec![],
+        ]);
+    }
+
+    #[test]
+    fn long_chains_of_operators_arent_nesting() {
+        let chain = |terms: usize| format!("decl main : () -> i32 {{ return 1{}; }}", " + 1".repeat(terms));
+        // Each level of parentheses holds a chain of 100 operators, and the
+        // chain inside of it is under every one of them
+        let nested_chains = format!("decl main : () -> i32 {{ return {}; }}", (0..6).fold(String::from("1"), |expr, _| format!("({}{})", expr, " * 1".repeat(100))));
+
+        // Test threads get less stack than the compiler's main thread
+        let messages = std::thread::Builder::new().stack_size(8 << 20).spawn(move || {
+            [chain(300), chain(512), chain(513), nested_chains].map(|source| {
+                let (_, diagnostics) = parse(&source);
+                diagnostics.take().into_iter().map(|diagnostic| diagnostic.message).collect::<Vec<_>>()
+            })
+        }).unwrap().join().unwrap();
+
+        assert_eq!(messages, [
+            vec![],
+            vec![],
+            vec!["this chain of operators is too long"],
+            vec!["this chain of operators is too long"],
+        ]);
+    }
+
+    #[test]
+    fn truncated_input_is_reported() {
+        let (_, diagnostics) = parse("decl main : () { f(");
+        assert_eq!(diagnostics.take()[0].message, "expected an expression, but got end of file");
+
+        let (_, diagnostics) = parse("decl main : () { Vec3::new(x: 1.0, ");
+        assert_eq!(diagnostics.take()[0].message, "expected an expression, but got end of file");
+    }
+
+    #[test]
+    fn every_prefix_of_a_file_parses_without_panicking() {
+        let source = include_str!("../test_files/test.amds");
+
+        for end in (0..=source.len()).filter(|end| source.is_char_boundary(*end)) {
+            parse(&source[..end]);
+        }
+    }
+}
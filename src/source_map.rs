@@ -0,0 +1,108 @@
+use std::fs;
+use std::io;
+use std::path::{ Path, PathBuf };
+
+use crate::token::Span;
+
+/// A line and column in a source file. Both start at 1, and the
+/// column counts characters rather than bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub contents: String,
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    fn new(path: PathBuf, contents: String) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(contents.bytes()
+                                   .enumerate()
+                                   .filter(|(_, b)| *b == b'\n')
+                                   .map(|(idx, _)| idx + 1));
+
+        Self {
+            path,
+            contents,
+            line_starts,
+        }
+    }
+
+    /// The 0-based index of the line containing `offset`.
+    fn line_index(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        }
+    }
+
+    pub fn location(&self, offset: usize) -> Location {
+        let offset = self.clamp_offset(offset);
+        let line = self.line_index(offset);
+        let line_start = self.line_starts[line];
+
+        Location {
+            line: line + 1,
+            column: self.contents[line_start..offset].chars().count() + 1,
+        }
+    }
+
+    /// The text of the given 1-based line, without its line ending.
+    pub fn line_text(&self, line: usize) -> &str {
+        let start = self.line_starts[line - 1];
+        let end = self.line_starts.get(line)
+                                  .copied()
+                                  .unwrap_or(self.contents.len());
+
+        self.contents[start..end].trim_end_matches(['\n', '\r'])
+    }
+
+    /// Keeps offsets inside the file and on a char boundary, so a
+    /// slightly-off span can't make us slice through a UTF-8 sequence.
+    fn clamp_offset(&self, offset: usize) -> usize {
+        let mut offset = offset.min(self.contents.len());
+
+        while !self.contents.is_char_boundary(offset) {
+            offset -= 1;
+        }
+
+        offset
+    }
+}
+
+/// Every file loaded during a compilation. A `Span`'s `file_id`
+/// is an index into this map.
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_file(&mut self, path: &Path, contents: String) -> u64 {
+        let id = self.files.len() as u64;
+        self.files.push(SourceFile::new(path.to_path_buf(), contents));
+        id
+    }
+
+    pub fn load_file(&mut self, path: &Path) -> io::Result<u64> {
+        let contents = fs::read_to_string(path)?;
+        Ok(self.add_file(path, contents))
+    }
+
+    pub fn file(&self, file_id: u64) -> &SourceFile {
+        &self.files[file_id as usize]
+    }
+
+    pub fn location(&self, span: Span) -> Location {
+        self.file(span.file_id).location(span.start)
+    }
+}
@@ -1,10 +1,23 @@
-#[derive(PartialEq, Debug)]
+use std::fmt;
+
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
 pub struct Span {
     pub file_id: u64,
     pub start: usize,
     pub end: usize,
 }
 
+impl Span {
+    /// A span covering everything from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            file_id: self.file_id,
+            start: self.start,
+            end: other.end,
+        }
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub enum IntegerLiteralFormat {
     Binary,
@@ -19,13 +32,25 @@ pub enum FloatingPointLiteralFormat {
     ENotation
 }
 
+/// A number as it's written, with the separators left out. The type is
+/// only filled in when the literal has a suffix like `u8` or `f`, and is
+/// up to the surrounding code otherwise.
 #[derive(PartialEq, Debug, Clone)]
 pub enum NumericConstant {
-    Integer(String, IntegerLiteralFormat),
-    FloatingPoint(String, FloatingPointLiteralFormat),
+    Integer(String, IntegerLiteralFormat, Option<PrimitiveType>),
+    FloatingPoint(String, FloatingPointLiteralFormat, Option<PrimitiveType>),
 }
 
-#[derive(PartialEq, Debug)]
+impl NumericConstant {
+    pub fn suffix(&self) -> Option<&PrimitiveType> {
+        match self {
+            NumericConstant::Integer(_, _, suffix) |
+            NumericConstant::FloatingPoint(_, _, suffix) => suffix.as_ref(),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
 pub enum PrimitiveType {
     Nothing,
     Bool,
@@ -62,17 +87,67 @@ impl PrimitiveType {
             PrimitiveType::F64 => "f64"
         }
     }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, PrimitiveType::U8  | PrimitiveType::I8  |
+                       PrimitiveType::U16 | PrimitiveType::I16 |
+                       PrimitiveType::U32 | PrimitiveType::I32 |
+                       PrimitiveType::U64 | PrimitiveType::I64)
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(self, PrimitiveType::I8  | PrimitiveType::I16 |
+                       PrimitiveType::I32 | PrimitiveType::I64 |
+                       PrimitiveType::F32 | PrimitiveType::F64)
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, PrimitiveType::F32 | PrimitiveType::F64)
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || self.is_float()
+    }
+
+    /// How many bits a value of this type takes up once compiled.
+    pub fn bit_width(&self) -> u32 {
+        match self {
+            PrimitiveType::Nothing => 0,
+            PrimitiveType::Bool => 1,
+            PrimitiveType::U8 | PrimitiveType::I8 => 8,
+            PrimitiveType::U16 | PrimitiveType::I16 => 16,
+            PrimitiveType::U32 | PrimitiveType::I32 | PrimitiveType::F32 | PrimitiveType::Char => 32,
+            PrimitiveType::U64 | PrimitiveType::I64 | PrimitiveType::F64 | PrimitiveType::String => 64,
+        }
+    }
+
+    /// Cuts `bits` down to the width of this integer type.
+    pub fn truncate(&self, bits: u64) -> u64 {
+        match self.bit_width() {
+            64 => bits,
+            width => bits & ((1 << width) - 1),
+        }
+    }
+
+    /// Reads `bits` as a signed integer of this type's width.
+    pub fn sign_extend(&self, bits: u64) -> i64 {
+        let shift = 64 - self.bit_width();
+        ((bits << shift) as i64) >> shift
+    }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum Token {
     EOL(Span), // End-of-line
-    EOF,       // End-of-file
+    EOF(Span), // End-of-file
 
     IdentName(Span, String),
 
     StringLiteral(Span, String), // ""
-    CharLiteral(Span, u8),       // ''
+    CharLiteral(Span, char),     // ''
+    DocComment(Span, String),       // ///
+    ModuleDocComment(Span, String), // //!
     Number(Span, NumericConstant),
     BooleanLiteral(Span, bool),
 
@@ -150,6 +225,188 @@ pub enum Token {
     KeywordReturn(Span),
 
     KeywordAs(Span),
+    KeywordNull(Span),
 
     BuiltinType(Span, PrimitiveType),
 }
+
+impl Token {
+    pub fn span(&self) -> Span {
+        match self {
+            Token::IdentName(span, _) |
+            Token::StringLiteral(span, _) |
+            Token::CharLiteral(span, _) |
+            Token::DocComment(span, _) |
+            Token::ModuleDocComment(span, _) |
+            Token::Number(span, _) |
+            Token::BooleanLiteral(span, _) |
+            Token::BuiltinType(span, _) => *span,
+
+            Token::EOL(span) |
+            Token::EOF(span) |
+            Token::LParen(span) |
+            Token::RParen(span) |
+            Token::LCurly(span) |
+            Token::RCurly(span) |
+            Token::LSquare(span) |
+            Token::RSquare(span) |
+            Token::LAngle(span) |
+            Token::RAngle(span) |
+            Token::Assign(span) |
+            Token::Colon(span) |
+            Token::ColonAssign(span) |
+            Token::DoubleColon(span) |
+            Token::Semicolon(span) |
+            Token::ThinArrow(span) |
+            Token::ThiccArrow(span) |
+            Token::Dollar(span) |
+            Token::Comma(span) |
+            Token::Dot(span) |
+            Token::DotDot(span) |
+            Token::Hash(span) |
+            Token::Bang(span) |
+            Token::QuestionMark(span) |
+            Token::Tilde(span) |
+            Token::TildeAssign(span) |
+            Token::Ampersand(span) |
+            Token::AmpersandAssign(span) |
+            Token::DoubleAmpersand(span) |
+            Token::Pipe(span) |
+            Token::PipeAssign(span) |
+            Token::DoublePipe(span) |
+            Token::Caret(span) |
+            Token::CaretAssign(span) |
+            Token::DoubleCaret(span) |
+            Token::RShift(span) |
+            Token::RShiftAssign(span) |
+            Token::LShift(span) |
+            Token::LShiftAssign(span) |
+            Token::LEQ(span) |
+            Token::GEQ(span) |
+            Token::EQ(span) |
+            Token::NEQ(span) |
+            Token::Minus(span) |
+            Token::MinusMinus(span) |
+            Token::MinusAssign(span) |
+            Token::Plus(span) |
+            Token::PlusPlus(span) |
+            Token::PlusAssign(span) |
+            Token::Star(span) |
+            Token::StarAssign(span) |
+            Token::Slash(span) |
+            Token::SlashAssign(span) |
+            Token::Percent(span) |
+            Token::PercentAssign(span) |
+            Token::KeywordDecl(span) |
+            Token::KeywordLet(span) |
+            Token::KeywordStruct(span) |
+            Token::KeywordEnum(span) |
+            Token::KeywordMatch(span) |
+            Token::KeywordIf(span) |
+            Token::KeywordElse(span) |
+            Token::KeywordFor(span) |
+            Token::KeywordWhile(span) |
+            Token::KeywordLoop(span) |
+            Token::KeywordIn(span) |
+            Token::KeywordContinue(span) |
+            Token::KeywordBreak(span) |
+            Token::KeywordReturn(span) |
+            Token::KeywordAs(span) |
+            Token::KeywordNull(span) => *span,
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::EOL(_span) => write!(f, "end of line"),
+            Token::EOF(_span) => write!(f, "end of file"),
+
+            Token::IdentName(_span, name) => write!(f, "identifier `{}`", name),
+            Token::StringLiteral(_span, lit) => write!(f, "string literal {:?}", lit),
+            Token::CharLiteral(_span, lit) => write!(f, "char literal {:?}", lit),
+            Token::DocComment(_span, _text) => write!(f, "doc comment"),
+            Token::ModuleDocComment(_span, _text) => write!(f, "module doc comment"),
+            Token::Number(_span, NumericConstant::Integer(num, _fmt, _suffix)) => write!(f, "number `{}`", num),
+            Token::Number(_span, NumericConstant::FloatingPoint(num, _fmt, _suffix)) => write!(f, "number `{}`", num),
+            Token::BooleanLiteral(_span, val) => write!(f, "`{}`", val),
+
+            Token::LParen(_span) => write!(f, "`(`"),
+            Token::RParen(_span) => write!(f, "`)`"),
+            Token::LCurly(_span) => write!(f, "`{{`"),
+            Token::RCurly(_span) => write!(f, "`}}`"),
+            Token::LSquare(_span) => write!(f, "`[`"),
+            Token::RSquare(_span) => write!(f, "`]`"),
+            Token::LAngle(_span) => write!(f, "`<`"),
+            Token::RAngle(_span) => write!(f, "`>`"),
+            Token::Assign(_span) => write!(f, "`=`"),
+            Token::Colon(_span) => write!(f, "`:`"),
+            Token::ColonAssign(_span) => write!(f, "`:=`"),
+            Token::DoubleColon(_span) => write!(f, "`::`"),
+            Token::Semicolon(_span) => write!(f, "`;`"),
+            Token::ThinArrow(_span) => write!(f, "`->`"),
+            Token::ThiccArrow(_span) => write!(f, "`=>`"),
+            Token::Dollar(_span) => write!(f, "`$`"),
+            Token::Comma(_span) => write!(f, "`,`"),
+            Token::Dot(_span) => write!(f, "`.`"),
+            Token::DotDot(_span) => write!(f, "`..`"),
+            Token::Hash(_span) => write!(f, "`#`"),
+            Token::Bang(_span) => write!(f, "`!`"),
+            Token::QuestionMark(_span) => write!(f, "`?`"),
+            Token::Tilde(_span) => write!(f, "`~`"),
+            Token::TildeAssign(_span) => write!(f, "`~=`"),
+            Token::Ampersand(_span) => write!(f, "`&`"),
+            Token::AmpersandAssign(_span) => write!(f, "`&=`"),
+            Token::DoubleAmpersand(_span) => write!(f, "`&&`"),
+            Token::Pipe(_span) => write!(f, "`|`"),
+            Token::PipeAssign(_span) => write!(f, "`|=`"),
+            Token::DoublePipe(_span) => write!(f, "`||`"),
+            Token::Caret(_span) => write!(f, "`^`"),
+            Token::CaretAssign(_span) => write!(f, "`^=`"),
+            Token::DoubleCaret(_span) => write!(f, "`^^`"),
+            Token::RShift(_span) => write!(f, "`>>`"),
+            Token::RShiftAssign(_span) => write!(f, "`>>=`"),
+            Token::LShift(_span) => write!(f, "`<<`"),
+            Token::LShiftAssign(_span) => write!(f, "`<<=`"),
+            Token::LEQ(_span) => write!(f, "`<=`"),
+            Token::GEQ(_span) => write!(f, "`>=`"),
+            Token::EQ(_span) => write!(f, "`==`"),
+            Token::NEQ(_span) => write!(f, "`!=`"),
+            Token::Minus(_span) => write!(f, "`-`"),
+            Token::MinusMinus(_span) => write!(f, "`--`"),
+            Token::MinusAssign(_span) => write!(f, "`-=`"),
+            Token::Plus(_span) => write!(f, "`+`"),
+            Token::PlusPlus(_span) => write!(f, "`++`"),
+            Token::PlusAssign(_span) => write!(f, "`+=`"),
+            Token::Star(_span) => write!(f, "`*`"),
+            Token::StarAssign(_span) => write!(f, "`*=`"),
+            Token::Slash(_span) => write!(f, "`/`"),
+            Token::SlashAssign(_span) => write!(f, "`/=`"),
+            Token::Percent(_span) => write!(f, "`%`"),
+            Token::PercentAssign(_span) => write!(f, "`%=`"),
+
+            Token::KeywordDecl(_span) => write!(f, "`decl`"),
+            Token::KeywordLet(_span) => write!(f, "`let`"),
+
+            Token::KeywordStruct(_span) => write!(f, "`struct`"),
+            Token::KeywordEnum(_span) => write!(f, "`enum`"),
+
+            Token::KeywordMatch(_span) => write!(f, "`match`"),
+            Token::KeywordIf(_span) => write!(f, "`if`"),
+            Token::KeywordElse(_span) => write!(f, "`else`"),
+            Token::KeywordFor(_span) => write!(f, "`for`"),
+            Token::KeywordWhile(_span) => write!(f, "`while`"),
+            Token::KeywordLoop(_span) => write!(f, "`loop`"),
+            Token::KeywordIn(_span) => write!(f, "`in`"),
+            Token::KeywordContinue(_span) => write!(f, "`continue`"),
+            Token::KeywordBreak(_span) => write!(f, "`break`"),
+            Token::KeywordReturn(_span) => write!(f, "`return`"),
+
+            Token::KeywordAs(_span) => write!(f, "`as`"),
+            Token::KeywordNull(_span) => write!(f, "`null`"),
+
+            Token::BuiltinType(_span, primitive) => write!(f, "`{}`", primitive.as_str()),
+        }
+    }
+}
@@ -1,6 +1,4 @@
-use std::fs::File;
-use std::io::Read;
-
+use crate::diagnostic::{ Diagnostic, DiagnosticSink };
 use crate::token::{
     Span,
     IntegerLiteralFormat,
@@ -13,47 +11,98 @@ use crate::token::{
 const NUMBER_LITERAL_SEPERATOR: u8 = b'\'';
 
 pub struct Tokenizer {
+    diagnostics: DiagnosticSink,
+    file_id: u64,
     current_file_contents: String,
     current_file_size: usize,
     cursor: usize,
     line: usize,
-    in_block_comment: bool
+    /// Where each block comment the cursor is in was opened, innermost
+    /// last, since they nest
+    open_block_comments: Vec<usize>
 }
 
 fn is_valid_identifier_char(c: u8) -> bool {
-    (c >= b'A' && c <= b'Z') || (c >= b'a' && c <= b'z')
-                             || (c >= b'0' && c <= b'9')
-                             || (c == b'_')
+    c.is_ascii_alphanumeric() || (c == b'_')
 }
 
-fn is_valid_number_literal_char(c: u8) -> bool {
-    (c >= b'0' && c <= b'9') || (c >= b'a' && c <= b'f')
-                             || (c >= b'A' && c <= b'F')
-                             || (c == NUMBER_LITERAL_SEPERATOR)
-                             || (c == b'.')
+fn is_digit_in_base(c: u8, format: &IntegerLiteralFormat) -> bool {
+    match format {
+        IntegerLiteralFormat::Binary => matches!(c, b'0'..=b'1'),
+        IntegerLiteralFormat::Octal => matches!(c, b'0'..=b'7'),
+        IntegerLiteralFormat::Decimal => c.is_ascii_digit(),
+        IntegerLiteralFormat::Hexadecimal => c.is_ascii_hexdigit(),
+    }
 }
 
-impl Tokenizer {
-    pub fn new(main_file_path: String) -> Self {
-        let mut file = File::open(&main_file_path).expect("Could't open main file!!");
-        let mut file_contents = String::new();
-        file.read_to_string(&mut file_contents).expect("Couldn't read main file!!");
+/// The type a number literal's suffix stands for. `f` is short for `f32`.
+fn literal_suffix_type(suffix: &str) -> Option<PrimitiveType> {
+    if let Some(primitive) = pointer_sized_type(suffix) {
+        return Some(primitive);
+    }
 
+    let primitive = match suffix {
+        "u8" => PrimitiveType::U8,
+        "i8" => PrimitiveType::I8,
+        "u16" => PrimitiveType::U16,
+        "i16" => PrimitiveType::I16,
+        "u32" => PrimitiveType::U32,
+        "i32" => PrimitiveType::I32,
+        "u64" => PrimitiveType::U64,
+        "i64" => PrimitiveType::I64,
+        "f" | "f32" => PrimitiveType::F32,
+        "f64" => PrimitiveType::F64,
+        _ => return None,
+    };
+
+    Some(primitive)
+}
+
+/// `usize` and `isize`, which are as wide as a pointer. Only targets with
+/// 64-bit pointers are supported, so they're other names for `u64` and
+/// `i64`.
+fn pointer_sized_type(name: &str) -> Option<PrimitiveType> {
+    match name {
+        "usize" => Some(PrimitiveType::U64),
+        "isize" => Some(PrimitiveType::I64),
+        _ => None,
+    }
+}
+
+impl Tokenizer {
+    pub fn new(file_contents: String, file_id: u64, diagnostics: DiagnosticSink) -> Self {
         Self {
+            diagnostics,
+            file_id,
+            current_file_size: file_contents.len(),
             current_file_contents: file_contents,
-            current_file_size: file.metadata().expect("Couldn't get the main file's meta").len() as usize,
             cursor: 0,
             line: 0,
-            in_block_comment: false
+            open_block_comments: Vec::new()
         }
     }
 
-    pub fn dump_file_contents(&self) {
-        println!("***** START SOURCE DUMP *****\n");
-        print!("\x1b[1;36m");
-        println!("{}", self.current_file_contents);
-        print!("\x1b[0;0m");
-        println!("***** END SOURCE DUMP *****");
+    /// Reads the whole file into a token stream that always ends in
+    /// `Token::EOF`. Malformed tokens are reported to the diagnostic
+    /// sink and left out of the stream.
+    pub fn tokenize(&mut self) -> Vec<Token> {
+        let mut token_stream = Vec::new();
+
+        loop {
+            match self.read_next_token() {
+                Ok(token) => {
+                    let at_eof = matches!(token, Token::EOF(_span));
+                    token_stream.push(token);
+
+                    if at_eof {
+                        break;
+                    }
+                },
+                Err(diagnostic) => self.diagnostics.emit(diagnostic),
+            }
+        }
+
+        token_stream
     }
 
     fn byte_at(&self, idx: usize) -> u8 {
@@ -74,54 +123,95 @@ impl Tokenizer {
                        || self.byte_at(self.cursor) == b'\r')
     }
 
-    fn consume_useless_bytes(&mut self) {
-        while self.is_current_whitespace() || self.byte_at(self.cursor) == b'/' || self.in_block_comment {
-            if self.in_block_comment || (!self.at_eof() && self.byte_at(self.cursor) == b'/') {
-                self.cursor += 1;
+    fn char_at(&self, idx: usize) -> char {
+        self.current_file_contents[idx..].chars().next().unwrap_or('\0')
+    }
 
-                if !self.at_eof() && self.byte_at(self.cursor) == b'/' {
-                    while !self.at_eof() && self.byte_at(self.cursor) != b'\n' {
-                        self.cursor += 1;
-                    }
-                } else if self.in_block_comment || (!self.at_eof() && self.byte_at(self.cursor) == b'*') {
-                    self.cursor += 1;
-                    self.in_block_comment = true;
+    fn starts_with(&self, text: &[u8]) -> bool {
+        self.current_file_contents.as_bytes()[self.cursor..].starts_with(text)
+    }
 
-                    loop {
-                        if self.at_eof() { panic!("Unterminated block comment!"); }
-                        if self.byte_at(self.cursor) == b'\n' { break; }
+    /// Whether the cursor is at a `///` or `//!` doc comment. Four or more
+    /// slashes make a normal comment again, so lines of them can be used
+    /// as separators.
+    fn doc_comment_follows(&self) -> bool {
+        self.starts_with(b"//!") || (self.starts_with(b"///") && !self.starts_with(b"////"))
+    }
 
-                        if self.byte_at(self.cursor) == b'/' && self.byte_at(self.cursor - 1) == b'*' {
-                            self.cursor += 1;
-                            self.in_block_comment = false;
-                            break;
-                        }
+    /// Skips whitespace and comments, stopping at line breaks so they
+    /// still make it into the token stream, even inside of a block comment.
+    fn consume_useless_bytes(&mut self) -> Result<(), Diagnostic> {
+        loop {
+            if !self.open_block_comments.is_empty() {
+                self.skip_block_comment()?;
 
-                        self.cursor += 1;
-                    }
-                } else {
-                    // We're looking at a `/` or `/=`
-                    self.cursor -= 1;
-                    break;
+                if !self.open_block_comments.is_empty() {
+                    return Ok(());
+                }
+            } else if self.is_current_whitespace() {
+                self.cursor += 1;
+            } else if self.starts_with(b"/*") {
+                self.open_block_comments.push(self.cursor);
+                self.cursor += 2;
+            } else if self.starts_with(b"//") && !self.doc_comment_follows() {
+                while !self.at_eof() && self.byte_at(self.cursor) != b'\n' {
+                    self.cursor += 1;
                 }
+            } else {
+                return Ok(());
             }
+        }
+    }
 
-            self.cursor += 1;
+    /// Moves through the block comments that are open, up to the line
+    /// break or the `*/` that closes the outermost one.
+    fn skip_block_comment(&mut self) -> Result<(), Diagnostic> {
+        loop {
+            if self.at_eof() {
+                let starts = std::mem::take(&mut self.open_block_comments);
+                let outermost = Span { file_id: self.file_id, start: starts[0], end: starts[0] + 2 };
+                let mut diagnostic = Diagnostic::error("unterminated block comment", outermost);
+
+                if let [_, .., innermost] = starts[..] {
+                    diagnostic = diagnostic.with_label(Span { file_id: self.file_id, start: innermost, end: innermost + 2 }, "this one is still open inside of it")
+                                           .with_note("block comments nest, so every `/*` needs its own `*/`");
+                }
+
+                return Err(diagnostic);
+            }
+
+            if self.byte_at(self.cursor) == b'\n' {
+                return Ok(());
+            }
+
+            if self.starts_with(b"/*") {
+                self.open_block_comments.push(self.cursor);
+                self.cursor += 2;
+            } else if self.starts_with(b"*/") {
+                self.open_block_comments.pop();
+                self.cursor += 2;
+
+                if self.open_block_comments.is_empty() {
+                    return Ok(());
+                }
+            } else {
+                self.cursor += 1;
+            }
         }
     }
 
-    pub fn read_next_token(&mut self) -> Token {
+    pub fn read_next_token(&mut self) -> Result<Token, Diagnostic> {
+        self.consume_useless_bytes()?;
+
         if self.at_eof() {
-            return Token::EOF;
+            return Ok(Token::EOF(Span { file_id: self.file_id, start: self.current_file_size, end: self.current_file_size }));
         }
 
-        self.consume_useless_bytes();
-
-        match self.byte_at(self.cursor) {
+        let token = match self.byte_at(self.cursor) {
             b'\n' => {
                 self.cursor += 1;
                 self.line += 1;
-                Token::EOL(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+                Token::EOL(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
             },
 
             b'0' | b'1' | b'2' |
@@ -135,12 +225,12 @@ impl Tokenizer {
 
             b'#' => {
                 self.cursor += 1;
-                Token::Hash(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+                Token::Hash(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
             },
 
             b'$' => {
                 self.cursor += 1;
-                Token::Dollar(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+                Token::Dollar(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
             },
 
             b'%' => self.tokenize_percent_variations(),
@@ -153,12 +243,12 @@ impl Tokenizer {
 
             b'(' => {
                 self.cursor += 1;
-                Token::LParen(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+                Token::LParen(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
             },
 
             b')' => {
                 self.cursor += 1;
-                Token::RParen(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+                Token::RParen(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
             },
 
             b'-' => self.tokenize_dash_variations(),
@@ -168,43 +258,44 @@ impl Tokenizer {
 
             b'[' => {
                 self.cursor += 1;
-                Token::LSquare(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+                Token::LSquare(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
             },
 
             b']' => {
                 self.cursor += 1;
-                Token::RSquare(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+                Token::RSquare(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
             },
 
             b'|' => self.tokenize_pipe_variations(),
 
             b'{' => {
                 self.cursor += 1;
-                Token::LCurly(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+                Token::LCurly(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
             },
 
             b'}' => {
                 self.cursor += 1;
-                Token::RCurly(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+                Token::RCurly(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
             },
 
             b';' => {
                 self.cursor += 1;
-                Token::Semicolon(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+                Token::Semicolon(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
             },
 
             b':' => self.tokenize_colon_variations(),
 
-            b'\'' => self.tokenize_char_literal(),
-            b'"' => self.tokenize_string_literal(),
+            b'\'' => self.tokenize_char_literal()?,
+            b'"' => self.tokenize_string_literal()?,
 
             b',' => {
                 self.cursor += 1;
-                Token::Comma(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+                Token::Comma(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
             },
 
             b'.' => self.tokenize_dot_variations(),
 
+            b'/' if self.doc_comment_follows() => self.tokenize_doc_comment(),
             b'/' => self.tokenize_slash_variations(),
 
             b'<' => self.tokenize_left_angle_variations(),
@@ -212,323 +303,538 @@ impl Tokenizer {
 
             b'?' => {
                 self.cursor += 1;
-                Token::QuestionMark(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+                Token::QuestionMark(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
             },
 
-            _ => self.tokenize_names(),
-        }
+            b'r' if self.raw_string_follows() => self.tokenize_raw_string_literal()?,
+
+            _ => self.tokenize_names()?,
+        };
+
+        Ok(token)
     }
 
     fn tokenize_tilde_variations(&mut self) -> Token {
         if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'=' {
             self.cursor += 2;
 
-            return Token::TildeAssign(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::TildeAssign(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         }
 
         self.cursor += 1;
 
-        Token::Tilde(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+        Token::Tilde(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
     }
 
     fn tokenize_bang_variations(&mut self) -> Token {
         if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'=' {
             self.cursor += 2;
 
-            return Token::NEQ(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::NEQ(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         }
 
         self.cursor += 1;
 
-        Token::Bang(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+        Token::Bang(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
     }
 
     fn tokenize_percent_variations(&mut self) -> Token {
         if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'=' {
             self.cursor += 2;
 
-            return Token::PercentAssign(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::PercentAssign(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         }
 
         self.cursor += 1;
 
-        Token::Percent(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+        Token::Percent(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
     }
 
     fn tokenize_caret_variations(&mut self) -> Token {
         if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'=' {
             self.cursor += 2;
 
-            return Token::CaretAssign(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::CaretAssign(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         } else if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'^' {
             self.cursor += 2;
 
-            return Token::DoubleCaret(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::DoubleCaret(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         }
 
         self.cursor += 1;
 
-        Token::Caret(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+        Token::Caret(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
     }
 
     fn tokenize_ampersand_variations(&mut self) -> Token {
         if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'=' {
             self.cursor += 2;
 
-            return Token::AmpersandAssign(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::AmpersandAssign(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         } else if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'&' {
             self.cursor += 2;
 
-            return Token::DoubleAmpersand(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::DoubleAmpersand(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         }
 
         self.cursor += 1;
 
-        Token::Ampersand(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+        Token::Ampersand(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
     }
 
     fn tokenize_star_variations(&mut self) -> Token {
         if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'=' {
             self.cursor += 2;
 
-            return Token::StarAssign(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::StarAssign(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         }
 
         self.cursor += 1;
 
-        Token::Star(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+        Token::Star(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
     }
 
     fn tokenize_dash_variations(&mut self) -> Token {
         if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'=' {
             self.cursor += 2;
 
-            return Token::MinusAssign(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::MinusAssign(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         } else if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'-' {
             self.cursor += 2;
 
-            return Token::MinusMinus(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::MinusMinus(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         } else if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'>' {
             self.cursor += 2;
 
-            return Token::ThinArrow(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::ThinArrow(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         }
 
         self.cursor += 1;
 
-        Token::Minus(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+        Token::Minus(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
     }
 
     fn tokenize_plus_variations(&mut self) -> Token {
         if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'=' {
             self.cursor += 2;
 
-            return Token::PlusAssign(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::PlusAssign(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         } else if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'+' {
             self.cursor += 2;
 
-            return Token::PlusPlus(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::PlusPlus(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         }
 
         self.cursor += 1;
 
-        Token::Plus(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+        Token::Plus(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
     }
 
     fn tokenize_equals_variations(&mut self) -> Token {
         if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'=' {
             self.cursor += 2;
 
-            return Token::EQ(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::EQ(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         } else if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'>' {
             self.cursor += 2;
 
-            return Token::ThiccArrow(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::ThiccArrow(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         }
 
         self.cursor += 1;
 
-        Token::Assign(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+        Token::Assign(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
     }
 
     fn tokenize_pipe_variations(&mut self) -> Token {
         if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'=' {
             self.cursor += 2;
 
-            return Token::PipeAssign(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::PipeAssign(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         } else if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'|' {
             self.cursor += 2;
 
-            return Token::DoublePipe(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::DoublePipe(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         }
 
         self.cursor += 1;
 
-        Token::Pipe(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+        Token::Pipe(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
     }
 
     fn tokenize_colon_variations(&mut self) -> Token {
         if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'=' {
             self.cursor += 2;
 
-            return Token::ColonAssign(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::ColonAssign(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         } else if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b':' {
             self.cursor += 2;
 
-            return Token::DoubleColon(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::DoubleColon(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         }
 
         self.cursor += 1;
 
-        Token::Colon(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+        Token::Colon(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
     }
 
-    fn tokenize_char_literal(&mut self) -> Token {
+    fn tokenize_char_literal(&mut self) -> Result<Token, Diagnostic> {
         let start = self.cursor;
-        let end: usize;
-        let mut chr: u8;
+        self.cursor += 1;
+
+        if self.at_eof() || self.byte_at(self.cursor) == b'\n' {
+            return Err(Diagnostic::error("unterminated char literal", Span { file_id: self.file_id, start, end: self.cursor }));
+        }
 
-        if !self.is_eof(self.cursor + 1) {
+        if self.byte_at(self.cursor) == b'\'' {
             self.cursor += 1;
-            chr = self.byte_at(self.cursor);
+            let span = Span { file_id: self.file_id, start, end: self.cursor };
+            self.diagnostics.emit(Diagnostic::error("empty char literal", span));
+            return Ok(Token::CharLiteral(span, char::REPLACEMENT_CHARACTER));
+        }
 
-            if !self.is_eof(self.cursor + 1) && chr == b'\\' {
-                self.cursor += 1;
-                chr = self.byte_at(self.cursor);
+        let chr = match self.byte_at(self.cursor) {
+            // The literal is still read to the end after a bad escape, so
+            // the rest of it isn't taken for more tokens
+            b'\\' => self.read_escape().unwrap_or_else(|diagnostic| {
+                self.diagnostics.emit(diagnostic);
+                char::REPLACEMENT_CHARACTER
+            }),
+            _ => {
+                let chr = self.char_at(self.cursor);
+                self.cursor += chr.len_utf8();
+                chr
             }
+        };
 
-            if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'\'' {
-                self.cursor += 1;
-                end = self.cursor;
-            } else {
-                // FIXME: Propagate error rather that just freaking out
-                panic!("Unterminated char literal at cursor: {}!", self.cursor);
+        if self.at_eof() || self.byte_at(self.cursor) != b'\'' {
+            return self.skip_bad_char_literal(start);
+        }
+
+        self.cursor += 1;
+
+        Ok(Token::CharLiteral(Span { file_id: self.file_id, start, end: self.cursor }, chr))
+    }
+
+    /// Skips the rest of a char literal that didn't close after one
+    /// character, so the leftovers aren't read as more tokens. A literal
+    /// that does close still gives a token, to keep the parser going.
+    fn skip_bad_char_literal(&mut self, start: usize) -> Result<Token, Diagnostic> {
+        while !self.at_eof() && self.byte_at(self.cursor) != b'\'' && self.byte_at(self.cursor) != b'\n' {
+            self.cursor += 1;
+        }
+
+        if !self.at_eof() && self.byte_at(self.cursor) == b'\'' {
+            self.cursor += 1;
+            let span = Span { file_id: self.file_id, start, end: self.cursor };
+
+            self.diagnostics.emit(
+                Diagnostic::error("char literal must hold exactly one character", span)
+                    .with_note("use double quotes for string literals")
+            );
+
+            return Ok(Token::CharLiteral(span, char::REPLACEMENT_CHARACTER));
+        }
+
+        Err(Diagnostic::error("unterminated char literal", Span { file_id: self.file_id, start, end: self.cursor }))
+    }
+
+    /// Reads the escape sequence that starts with the `\` at the cursor.
+    /// All of it is skipped, even when it turns out to be invalid.
+    fn read_escape(&mut self) -> Result<char, Diagnostic> {
+        let start = self.cursor;
+        self.cursor += 1;
+
+        if self.at_eof() {
+            return Err(Diagnostic::error("expected an escape sequence after `\\`", Span { file_id: self.file_id, start, end: self.cursor }));
+        }
+
+        let escaped = self.char_at(self.cursor);
+        self.cursor += escaped.len_utf8();
+
+        let chr = match escaped {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' => '\\',
+            '\'' => '\'',
+            '"' => '"',
+            'x' => {
+                let digits = self.read_hex_digits(2);
+                let span = Span { file_id: self.file_id, start, end: self.cursor };
+
+                if digits.len() != 2 {
+                    return Err(Diagnostic::error("`\\x` has to be followed by two hex digits", span)
+                                          .with_note("like `\\x7f`"));
+                }
+
+                let value = u8::from_str_radix(&digits, 16).unwrap();
+
+                if !value.is_ascii() {
+                    return Err(Diagnostic::error("`\\x` escapes only go up to `\\x7f`", span)
+                                          .with_note("use `\\u{...}` for characters past ASCII"));
+                }
+
+                value as char
+            },
+            'u' => return self.read_unicode_escape(start),
+            _ => {
+                return Err(Diagnostic::error(format!("unknown escape sequence `\\{}`", escaped), Span { file_id: self.file_id, start, end: self.cursor })
+                                      .with_note("the escapes are `\\n`, `\\t`, `\\r`, `\\0`, `\\\\`, `\\'`, `\\\"`, `\\xNN` and `\\u{...}`"));
             }
-        } else {
-            // FIXME: Propagate error rather that just freaking out
-            panic!("Unterminated char literal at cursor: {}!", self.cursor);
+        };
+
+        Ok(chr)
+    }
+
+    /// Reads the `{...}` of a `\u{...}` escape, which is a Unicode scalar
+    /// value written in hex.
+    fn read_unicode_escape(&mut self, start: usize) -> Result<char, Diagnostic> {
+        if self.at_eof() || self.byte_at(self.cursor) != b'{' {
+            return Err(Diagnostic::error("expected `{` after `\\u`", Span { file_id: self.file_id, start, end: self.cursor })
+                                  .with_note("Unicode escapes look like `\\u{1F600}`"));
+        }
+
+        self.cursor += 1;
+        let digits = self.read_hex_digits(usize::MAX);
+
+        if self.at_eof() || self.byte_at(self.cursor) != b'}' {
+            return Err(Diagnostic::error("expected `}` to close the Unicode escape", Span { file_id: self.file_id, start, end: self.cursor }));
+        }
+
+        self.cursor += 1;
+        let span = Span { file_id: self.file_id, start, end: self.cursor };
+
+        if digits.is_empty() || digits.len() > 6 {
+            return Err(Diagnostic::error("a Unicode escape has to have from 1 to 6 hex digits", span));
         }
 
-        Token::CharLiteral(Span { file_id: 0, start, end }, chr)
+        let value = u32::from_str_radix(&digits, 16).unwrap();
+
+        char::from_u32(value).ok_or_else(|| {
+            Diagnostic::error(format!("`{}` isn't a Unicode scalar value", digits), span)
+                       .with_note("surrogates, from `D800` to `DFFF`, and anything past `10FFFF` can't be characters")
+        })
+    }
+
+    /// Reads up to `max` hex digits from the cursor.
+    fn read_hex_digits(&mut self, max: usize) -> String {
+        let mut digits = String::new();
+
+        while digits.len() < max && !self.at_eof() && self.byte_at(self.cursor).is_ascii_hexdigit() {
+            digits.push(self.byte_at(self.cursor) as char);
+            self.cursor += 1;
+        }
+
+        digits
     }
 
-    fn tokenize_string_literal(&mut self) -> Token {
+    fn tokenize_string_literal(&mut self) -> Result<Token, Diagnostic> {
         let mut res_str = String::new();
 
+        let quote = self.cursor;
         let start = self.cursor + 1;
-        let end: usize;
 
-        if !self.is_eof(self.cursor + 1) {
-            self.cursor += 1;
+        self.cursor += 1;
+
+        loop {
+            if self.at_eof() {
+                return Err(Diagnostic::error(
+                    "unterminated string literal",
+                    Span { file_id: self.file_id, start: quote, end: quote + 1 }
+                ).with_note("the string starts here and runs to the end of the file"));
+            }
 
-            while self.byte_at(self.cursor) != b'"' {
-                if self.byte_at(self.cursor) == b'\\' {
+            match self.byte_at(self.cursor) {
+                b'"' => break,
+                // A `\` at the end of a line joins it to the next one,
+                // leaving out the line break and the indentation after it
+                b'\\' if self.line_break_follows(self.cursor + 1) => {
                     self.cursor += 1;
 
-                    if !self.at_eof() && self.byte_at(self.cursor) == b'"' {
+                    while !self.at_eof() && self.byte_at(self.cursor).is_ascii_whitespace() {
                         self.cursor += 1;
-
-                        res_str.push('"');
                     }
-
-                    continue;
+                },
+                b'\\' => {
+                    let escape_start = self.cursor;
+
+                    match self.read_escape() {
+                        // Strings are handed to C, where they'd end early
+                        Ok('\0') => self.diagnostics.emit(
+                            Diagnostic::error("a string can't hold a null character", Span { file_id: self.file_id, start: escape_start, end: self.cursor })
+                                       .with_note("strings end at their first null character once they're compiled")
+                        ),
+                        Ok(chr) => res_str.push(chr),
+                        Err(diagnostic) => self.diagnostics.emit(diagnostic),
+                    }
+                },
+                _ => {
+                    let chr = self.char_at(self.cursor);
+                    res_str.push(chr);
+                    self.cursor += chr.len_utf8();
                 }
+            }
+        }
 
-                res_str.push(self.byte_at(self.cursor) as char);
-                self.cursor += 1;
+        let end = self.cursor;
+        self.cursor += 1;
 
-                if self.at_eof() {
-                    // FIXME: Propagate error rather that just freaking out
-                    panic!("Unterminated string literal at cursor: {}!", self.cursor);
-                }
-            }
+        Ok(Token::StringLiteral(Span { file_id: self.file_id, start, end }, res_str))
+    }
 
-            end = self.cursor;
+    fn line_break_follows(&self, idx: usize) -> bool {
+        match self.current_file_contents.as_bytes().get(idx) {
+            Some(b'\n') => true,
+            Some(b'\r') => self.current_file_contents.as_bytes().get(idx + 1) == Some(&b'\n'),
+            _ => false,
+        }
+    }
+
+    /// Whether the `r` at the cursor starts a raw string, like `r"..."` or
+    /// `r#"..."#`, rather than a name.
+    fn raw_string_follows(&self) -> bool {
+        let bytes = &self.current_file_contents.as_bytes()[self.cursor + 1..];
+        let hashes = bytes.iter().take_while(|byte| **byte == b'#').count();
+
+        bytes.get(hashes) == Some(&b'"')
+    }
+
+    /// Tokenizes a raw string, where `\` is just a `\` and nothing is
+    /// escaped. It ends at a `"` followed by as many `#`s as there were
+    /// between the `r` and the opening `"`, so a raw string can hold `"`s
+    /// by starting with `r#"`.
+    fn tokenize_raw_string_literal(&mut self) -> Result<Token, Diagnostic> {
+        let quote = self.cursor;
+        self.cursor += 1;
+
+        let mut hashes = 0;
+        while self.byte_at(self.cursor) == b'#' {
+            hashes += 1;
             self.cursor += 1;
-        } else {
-            // FIXME: Propagate error rather that just freaking out
-            panic!("Unterminated string literal at cursor: {}!", self.cursor);
         }
 
-        Token::StringLiteral(Span { file_id: 0, start, end }, res_str)
+        self.cursor += 1;
+        let start = self.cursor;
+        let terminator = format!("\"{}", "#".repeat(hashes));
+
+        while !self.current_file_contents[self.cursor..].starts_with(&terminator) {
+            if self.at_eof() {
+                return Err(Diagnostic::error(
+                    "unterminated raw string literal",
+                    Span { file_id: self.file_id, start: quote, end: start }
+                ).with_note(format!("the string starts here and runs to the end of the file, since nothing closes it with `{}`", terminator)));
+            }
+
+            self.cursor += self.char_at(self.cursor).len_utf8();
+        }
+
+        let end = self.cursor;
+        self.cursor += 1 + hashes;
+
+        let contents = String::from(&self.current_file_contents[start..end]);
+        Ok(Token::StringLiteral(Span { file_id: self.file_id, start, end }, contents))
     }
 
     fn tokenize_dot_variations(&mut self) -> Token {
         if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'.' {
             self.cursor += 2;
 
-            return Token::DotDot(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::DotDot(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         }
 
         self.cursor += 1;
 
-        Token::Dot(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+        Token::Dot(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
+    }
+
+    /// Reads a `///` comment, which documents the declaration after it, or
+    /// a `//!` comment, which documents the module it's in. The text is
+    /// kept without the marker and the space after it.
+    fn tokenize_doc_comment(&mut self) -> Token {
+        let start = self.cursor;
+        let of_module = self.byte_at(self.cursor + 2) == b'!';
+        self.cursor += 3;
+
+        while !self.at_eof() && self.byte_at(self.cursor) != b'\n' {
+            self.cursor += 1;
+        }
+
+        let text = self.current_file_contents[start + 3..self.cursor].trim_end_matches('\r');
+        let text = String::from(text.strip_prefix(' ').unwrap_or(text));
+        let span = Span { file_id: self.file_id, start, end: self.cursor };
+
+        if of_module {
+            Token::ModuleDocComment(span, text)
+        } else {
+            Token::DocComment(span, text)
+        }
     }
 
     fn tokenize_slash_variations(&mut self) -> Token {
         if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'=' {
             self.cursor += 2;
 
-            return Token::SlashAssign(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::SlashAssign(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         }
 
         self.cursor += 1;
 
-        Token::Slash(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+        Token::Slash(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
     }
 
     fn tokenize_left_angle_variations(&mut self) -> Token {
         if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'=' {
             self.cursor += 2;
 
-            return Token::LEQ(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::LEQ(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         } else if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'<' {
             self.cursor += 2;
 
             if !self.at_eof() && self.byte_at(self.cursor) == b'=' {
                 self.cursor += 1;
 
-                return Token::LShiftAssign(Span { file_id: 0, start: self.cursor - 3, end: self.cursor });
+                return Token::LShiftAssign(Span { file_id: self.file_id, start: self.cursor - 3, end: self.cursor });
             }
 
-            return Token::LShift(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::LShift(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         }
 
         self.cursor += 1;
 
-        Token::LAngle(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+        Token::LAngle(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
     }
 
     fn tokenize_right_angle_variations(&mut self) -> Token {
         if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'=' {
             self.cursor += 2;
 
-            return Token::GEQ(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
-        } else if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'<' {
+            return Token::GEQ(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
+        } else if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'>' {
             self.cursor += 2;
 
             if !self.at_eof() && self.byte_at(self.cursor) == b'=' {
                 self.cursor += 1;
 
-                return Token::RShiftAssign(Span { file_id: 0, start: self.cursor - 3, end: self.cursor });
+                return Token::RShiftAssign(Span { file_id: self.file_id, start: self.cursor - 3, end: self.cursor });
             }
 
-            return Token::RShift(Span { file_id: 0, start: self.cursor - 2, end: self.cursor });
+            return Token::RShift(Span { file_id: self.file_id, start: self.cursor - 2, end: self.cursor });
         }
 
         self.cursor += 1;
 
-        Token::RAngle(Span { file_id: 0, start: self.cursor - 1, end: self.cursor })
+        Token::RAngle(Span { file_id: self.file_id, start: self.cursor - 1, end: self.cursor })
     }
 
     fn tokenize_number(&mut self) -> Token {
-        // TODO: Implement literal type suffixes
-
         let start = self.cursor;
         let mut int_fmt = IntegerLiteralFormat::Decimal;
         let mut is_float = false;
         let mut float_fmt = FloatingPointLiteralFormat::Standard;
         let mut num_str = String::new();
+        let mut error: Option<Diagnostic> = None;
 
         // Parse base prefix (if present)
         if self.byte_at(self.cursor) == b'0' {
@@ -541,188 +847,418 @@ impl Tokenizer {
             } else if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'b' {
                 self.cursor += 2;
                 int_fmt = IntegerLiteralFormat::Binary;
-            } else if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) != b'.' {
-                // FIXME: Propagate error rather that just freaking out
-                panic!("Invalid Syntax! Leading zero with no known base prefix at cursor: {}", self.cursor);
+            } else if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1).is_ascii_digit() {
+                error = Some(Diagnostic::error(
+                    "leading zero with no known base prefix",
+                    Span { file_id: self.file_id, start, end: start + 1 }
+                ).with_note("use `0x`, `0o` or `0b` for hexadecimal, octal or binary literals"));
             }
         }
 
-        while is_valid_number_literal_char(self.byte_at(self.cursor)) {
-            if self.byte_at(self.cursor) == NUMBER_LITERAL_SEPERATOR {
-                self.cursor += 1;
-                continue;
-            }
+        let base_name = match int_fmt {
+            IntegerLiteralFormat::Binary => "a binary",
+            IntegerLiteralFormat::Octal => "an octal",
+            IntegerLiteralFormat::Decimal => "a decimal",
+            IntegerLiteralFormat::Hexadecimal => "a hexadecimal",
+        };
+
+        // Whether the last character was a digit, which is the only thing
+        // a separator can come after
+        let mut after_digit = false;
+
+        while !self.at_eof() {
+            let c = self.byte_at(self.cursor);
+            let next = if self.is_eof(self.cursor + 1) { 0 } else { self.byte_at(self.cursor + 1) };
+            let here = Span { file_id: self.file_id, start: self.cursor, end: self.cursor + 1 };
+
+            if c == NUMBER_LITERAL_SEPERATOR {
+                if !after_digit || !is_digit_in_base(next, &int_fmt) {
+                    error.get_or_insert(Diagnostic::error("`'` can only be put between two digits", here));
+                }
 
-            if self.byte_at(self.cursor) == b'.' {
-                if !self.is_eof(self.cursor + 1) && self.byte_at(self.cursor + 1) == b'.' {
-                    break;
+                after_digit = false;
+            } else if is_digit_in_base(c, &int_fmt) {
+                num_str.push(c as char);
+                after_digit = true;
+            } else if c.is_ascii_digit() {
+                error.get_or_insert(Diagnostic::error(format!("invalid digit `{}` in {} literal", c as char, base_name), here));
+                after_digit = true;
+            } else if c == b'.' && next.is_ascii_digit() {
+                if int_fmt != IntegerLiteralFormat::Decimal {
+                    error.get_or_insert(Diagnostic::error(format!("{} literal can't have a decimal point", base_name), here));
+                } else if float_fmt == FloatingPointLiteralFormat::ENotation {
+                    error.get_or_insert(Diagnostic::error("decimal point not allowed in the exponent of e-notation", here));
+                } else if is_float {
+                    error.get_or_insert(Diagnostic::error("a number can only have one decimal point", here));
                 }
 
                 is_float = true;
+                num_str.push('.');
+                after_digit = false;
+            } else if (c == b'e' || c == b'E') && int_fmt == IntegerLiteralFormat::Decimal && self.exponent_follows() {
                 if float_fmt == FloatingPointLiteralFormat::ENotation {
-                    panic!("Invalid Syntax! Floating point not allowed after E in e-notation!");
+                    error.get_or_insert(Diagnostic::error("e-notation can only have one exponent", here));
                 }
-            } else if self.byte_at(self.cursor) == b'e' || self.byte_at(self.cursor) == b'E' {
+
                 is_float = true;
                 float_fmt = FloatingPointLiteralFormat::ENotation;
+                num_str.push('e');
+
+                if next == b'+' || next == b'-' {
+                    num_str.push(next as char);
+                    self.cursor += 1;
+                }
+
+                after_digit = false;
+            } else {
+                break;
             }
- 
-            num_str.push(self.byte_at(self.cursor) as char);
 
             self.cursor += 1;
         }
 
+        if num_str.is_empty() && error.is_none() {
+            error = Some(Diagnostic::error(
+                format!("missing digits after the base prefix of {} literal", base_name),
+                Span { file_id: self.file_id, start, end: self.cursor }
+            ));
+        }
+
+        let suffix = self.tokenize_number_suffix(is_float, &int_fmt, &mut error);
+
+        // The number is still handed to the parser, so a bad literal doesn't
+        // also show up as a missing expression
+        if let Some(error) = error {
+            self.diagnostics.emit(error);
+
+            if num_str.is_empty() {
+                num_str.push('0');
+            }
+        }
+
         Token::Number(
-            Span { file_id: 0, start, end: self.cursor },
-            if is_float { NumericConstant::FloatingPoint(num_str, float_fmt) } else { NumericConstant::Integer(num_str, int_fmt) }
+            Span { file_id: self.file_id, start, end: self.cursor },
+            if is_float { NumericConstant::FloatingPoint(num_str, float_fmt, suffix) } else { NumericConstant::Integer(num_str, int_fmt, suffix) }
         )
     }
 
-    fn tokenize_names(&mut self) -> Token {
+    /// Whether the `e` at the cursor starts the exponent of a number, like
+    /// in `1e9` or `2.5e-3`, rather than a suffix.
+    fn exponent_follows(&self) -> bool {
+        let mut idx = self.cursor + 1;
+
+        if !self.is_eof(idx) && matches!(self.byte_at(idx), b'+' | b'-') {
+            idx += 1;
+        }
+
+        !self.is_eof(idx) && self.byte_at(idx).is_ascii_digit()
+    }
+
+    /// Reads the suffix that gives a number literal its type, like the
+    /// `u8` in `255u8`, if there is one right after the digits.
+    fn tokenize_number_suffix(&mut self, is_float: bool, int_fmt: &IntegerLiteralFormat, error: &mut Option<Diagnostic>) -> Option<PrimitiveType> {
+        let start = self.cursor;
+
+        while !self.at_eof() && is_valid_identifier_char(self.byte_at(self.cursor)) {
+            self.cursor += 1;
+        }
+
+        if start == self.cursor {
+            return None;
+        }
+
+        let suffix = &self.current_file_contents[start..self.cursor];
+        let span = Span { file_id: self.file_id, start, end: self.cursor };
+
+        let Some(primitive) = literal_suffix_type(suffix) else {
+            error.get_or_insert(Diagnostic::error(format!("invalid suffix `{}` for a number literal", suffix), span)
+                                          .with_note("the suffix can be any integer or float type, like `u8` or `f64`, or `f` for `f32`"));
+            return None;
+        };
+
+        if is_float && !primitive.is_float() {
+            error.get_or_insert(Diagnostic::error(format!("a floating point literal can't have the integer suffix `{}`", suffix), span));
+            return None;
+        }
+
+        if *int_fmt != IntegerLiteralFormat::Decimal && primitive.is_float() {
+            error.get_or_insert(Diagnostic::error(format!("only decimal literals can have the float suffix `{}`", suffix), span));
+            return None;
+        }
+
+        Some(primitive)
+    }
+
+    fn tokenize_names(&mut self) -> Result<Token, Diagnostic> {
         let mut ident = String::new();
 
-        while is_valid_identifier_char(self.byte_at(self.cursor)) {
+        while !self.at_eof() && is_valid_identifier_char(self.byte_at(self.cursor)) {
             ident.push(self.byte_at(self.cursor) as char);
             self.cursor += 1;
         }
 
         if ident.is_empty() {
-            // FIXME: Propagate error rather that just freaking out
-            panic!("Unknown token \"{}\" at cursor: {}", self.byte_at(self.cursor) as char, self.cursor);
+            let start = self.cursor;
+            let unknown = self.char_at(self.cursor);
+            self.cursor += unknown.len_utf8();
+
+            return Err(Diagnostic::error(
+                format!("unknown character {:?}", unknown),
+                Span { file_id: self.file_id, start, end: self.cursor }
+            ));
         }
 
         let start = self.cursor - ident.len();
         let end = self.cursor;
 
-        return match ident.as_str() {
-            "decl" => Token::KeywordDecl(Span { file_id: 0, start, end }),
-            "let" => Token::KeywordLet(Span { file_id: 0, start, end }),
-            "struct" => Token::KeywordStruct(Span { file_id: 0, start, end }),
-            "enum" => Token::KeywordEnum(Span { file_id: 0, start, end }),
+        let token = match ident.as_str() {
+            "decl" => Token::KeywordDecl(Span { file_id: self.file_id, start, end }),
+            "let" => Token::KeywordLet(Span { file_id: self.file_id, start, end }),
+            "struct" => Token::KeywordStruct(Span { file_id: self.file_id, start, end }),
+            "enum" => Token::KeywordEnum(Span { file_id: self.file_id, start, end }),
 
-            "match" => Token::KeywordMatch(Span { file_id: 0, start, end }),
-            "if" => Token::KeywordIf(Span { file_id: 0, start, end }),
-            "else" => Token::KeywordElse(Span { file_id: 0, start, end }),
+            "match" => Token::KeywordMatch(Span { file_id: self.file_id, start, end }),
+            "if" => Token::KeywordIf(Span { file_id: self.file_id, start, end }),
+            "else" => Token::KeywordElse(Span { file_id: self.file_id, start, end }),
 
-            "for" => Token::KeywordFor(Span { file_id: 0, start, end }),
-            "while" => Token::KeywordWhile(Span { file_id: 0, start, end }),
-            "loop" => Token::KeywordLoop(Span { file_id: 0, start, end }),
+            "for" => Token::KeywordFor(Span { file_id: self.file_id, start, end }),
+            "while" => Token::KeywordWhile(Span { file_id: self.file_id, start, end }),
+            "loop" => Token::KeywordLoop(Span { file_id: self.file_id, start, end }),
 
-            "in" => Token::KeywordIn(Span { file_id: 0, start, end }),
+            "in" => Token::KeywordIn(Span { file_id: self.file_id, start, end }),
 
-            "continue" => Token::KeywordContinue(Span { file_id: 0, start, end }),
-            "break" => Token::KeywordBreak(Span { file_id: 0, start, end }),
-            "return" => Token::KeywordReturn(Span { file_id: 0, start, end }),
+            "continue" => Token::KeywordContinue(Span { file_id: self.file_id, start, end }),
+            "break" => Token::KeywordBreak(Span { file_id: self.file_id, start, end }),
+            "return" => Token::KeywordReturn(Span { file_id: self.file_id, start, end }),
 
-            "true" => Token::BooleanLiteral(Span { file_id: 0, start, end }, true),
-            "false" => Token::BooleanLiteral(Span { file_id: 0, start, end }, false),
+            "true" => Token::BooleanLiteral(Span { file_id: self.file_id, start, end }, true),
+            "false" => Token::BooleanLiteral(Span { file_id: self.file_id, start, end }, false),
 
-            "as" => Token::KeywordAs(Span { file_id: 0, start, end }),
+            "as" => Token::KeywordAs(Span { file_id: self.file_id, start, end }),
+            "null" => Token::KeywordNull(Span { file_id: self.file_id, start, end }),
 
             "nothing" => {
                 Token::BuiltinType(
-                    Span { file_id: 0, start, end },
+                    Span { file_id: self.file_id, start, end },
                     PrimitiveType::Nothing
                 )
             },
 
             "bool" => {
                 Token::BuiltinType(
-                    Span { file_id: 0, start, end },
+                    Span { file_id: self.file_id, start, end },
                     PrimitiveType::Bool
                 )
             },
 
             "char" => {
                 Token::BuiltinType(
-                    Span { file_id: 0, start, end },
+                    Span { file_id: self.file_id, start, end },
                     PrimitiveType::Char
                 )
             },
 
             "string" => {
                 Token::BuiltinType(
-                    Span { file_id: 0, start, end },
+                    Span { file_id: self.file_id, start, end },
                     PrimitiveType::String
                 )
             },
 
             "u8" => {
                 Token::BuiltinType(
-                    Span { file_id: 0, start, end },
+                    Span { file_id: self.file_id, start, end },
                     PrimitiveType::U8
                 )
             },
 
             "i8" => {
                 Token::BuiltinType(
-                    Span { file_id: 0, start, end },
+                    Span { file_id: self.file_id, start, end },
                     PrimitiveType::I8
                 )
             },
 
             "u16" => {
                 Token::BuiltinType(
-                    Span { file_id: 0, start, end },
+                    Span { file_id: self.file_id, start, end },
                     PrimitiveType::U16
                 )
             },
 
             "i16" => {
                 Token::BuiltinType(
-                    Span { file_id: 0, start, end },
+                    Span { file_id: self.file_id, start, end },
                     PrimitiveType::I16
                 )
             },
 
             "u32" => {
                 Token::BuiltinType(
-                    Span { file_id: 0, start, end },
+                    Span { file_id: self.file_id, start, end },
                     PrimitiveType::U32
                 )
             },
 
             "i32" => {
                 Token::BuiltinType(
-                    Span { file_id: 0, start, end },
+                    Span { file_id: self.file_id, start, end },
                     PrimitiveType::I32
                 )
             },
 
             "u64" => {
                 Token::BuiltinType(
-                    Span { file_id: 0, start, end},
+                    Span { file_id: self.file_id, start, end},
                     PrimitiveType::U64
                 )
             },
 
             "i64" => {
                 Token::BuiltinType(
-                    Span { file_id: 0, start, end },
+                    Span { file_id: self.file_id, start, end },
                     PrimitiveType::I64
                 )
             },
 
             "f32" => {
                 Token::BuiltinType(
-                    Span { file_id: 0, start, end },
+                    Span { file_id: self.file_id, start, end },
                     PrimitiveType::F32
                 )
             },
 
             "f64" => {
                 Token::BuiltinType(
-                    Span { file_id: 0, start, end },
+                    Span { file_id: self.file_id, start, end },
                     PrimitiveType::F64
                 )
             },
 
-            _ => {
-                Token::IdentName(
-                    Span { file_id: 0, start, end },
-                    ident
-                )
+            _ => match pointer_sized_type(&ident) {
+                Some(primitive) => Token::BuiltinType(Span { file_id: self.file_id, start, end }, primitive),
+                None => Token::IdentName(Span { file_id: self.file_id, start, end }, ident),
             }
+        };
+
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The tokens of `source` without their spans, and the messages of
+    /// whatever got reported along the way.
+    fn tokenize(source: &str) -> (Vec<Token>, Vec<String>) {
+        let diagnostics = DiagnosticSink::new();
+        let tokens = Tokenizer::new(String::from(source), 0, diagnostics.clone()).tokenize();
+        let messages = diagnostics.take().into_iter().map(|diag| diag.message).collect();
+
+        (tokens, messages)
+    }
+
+    fn number(source: &str) -> NumericConstant {
+        match tokenize(source) {
+            (tokens, messages) if messages.is_empty() => match &tokens[0] {
+                Token::Number(_span, constant) => constant.clone(),
+                token => panic!("expected a number, got {:?}", token),
+            },
+            (_, messages) => panic!("{:?}", messages),
         }
     }
+
+    #[test]
+    fn literal_suffixes() {
+        assert_eq!(number("255u8"), NumericConstant::Integer(String::from("255"), IntegerLiteralFormat::Decimal, Some(PrimitiveType::U8)));
+        assert_eq!(number("2.0f"), NumericConstant::FloatingPoint(String::from("2.0"), FloatingPointLiteralFormat::Standard, Some(PrimitiveType::F32)));
+        assert_eq!(number("1.5f64"), NumericConstant::FloatingPoint(String::from("1.5"), FloatingPointLiteralFormat::Standard, Some(PrimitiveType::F64)));
+        assert_eq!(number("300"), NumericConstant::Integer(String::from("300"), IntegerLiteralFormat::Decimal, None));
+    }
+
+    #[test]
+    fn literal_formats_and_separators() {
+        assert_eq!(number("1'000'000"), NumericConstant::Integer(String::from("1000000"), IntegerLiteralFormat::Decimal, None));
+        assert_eq!(number("0xff"), NumericConstant::Integer(String::from("ff"), IntegerLiteralFormat::Hexadecimal, None));
+        assert_eq!(number("0b1010"), NumericConstant::Integer(String::from("1010"), IntegerLiteralFormat::Binary, None));
+
+        let (_, messages) = tokenize("0b102");
+        assert_eq!(messages, ["invalid digit `2` in a binary literal"]);
+    }
+
+    fn string(source: &str) -> String {
+        match tokenize(source) {
+            (tokens, messages) if messages.is_empty() => match &tokens[0] {
+                Token::StringLiteral(_span, contents) => contents.clone(),
+                token => panic!("expected a string, got {:?}", token),
+            },
+            (_, messages) => panic!("{:?}", messages),
+        }
+    }
+
+    fn char_literal(source: &str) -> char {
+        match tokenize(source) {
+            (tokens, messages) if messages.is_empty() => match &tokens[0] {
+                Token::CharLiteral(_span, c) => *c,
+                token => panic!("expected a char, got {:?}", token),
+            },
+            (_, messages) => panic!("{:?}", messages),
+        }
+    }
+
+    #[test]
+    fn string_escapes() {
+        assert_eq!(string(r#""a\tb\n\"c\" \\ \x41 \u{3bb}""#), "a\tb\n\"c\" \\ A \u{3bb}");
+        assert_eq!(string("\"caf\u{e9}\""), "caf\u{e9}");
+        assert_eq!(string("\"one \\\n      two\""), "one two");
+        assert_eq!(string(r#"r"C:\path""#), r"C:\path");
+        assert_eq!(string(r##"r#"say "hi""#"##), r#"say "hi""#);
+    }
+
+    #[test]
+    fn char_escapes() {
+        assert_eq!(char_literal(r"'\n'"), '\n');
+        assert_eq!(char_literal(r"'\''"), '\'');
+        assert_eq!(char_literal(r"'\0'"), '\0');
+        assert_eq!(char_literal("'\u{e9}'"), '\u{e9}');
+        assert_eq!(char_literal(r"'\u{1F600}'"), '\u{1F600}');
+    }
+
+    #[test]
+    fn bad_escapes_are_reported() {
+        for source in [r#""\q""#, r#""\xff""#, r#""\u{110000}""#, r"'\q'"] {
+            let (_, messages) = tokenize(source);
+            assert_eq!(messages.len(), 1, "{}: {:?}", source, messages);
+        }
+    }
+
+    #[test]
+    fn nested_block_comments() {
+        let (tokens, messages) = tokenize("a /* one /* two */ still a comment */ b");
+        assert!(messages.is_empty(), "{:?}", messages);
+        assert!(matches!(tokens[..], [Token::IdentName(_, ref a), Token::IdentName(_, ref b), Token::EOF(_)] if a == "a" && b == "b"));
+
+        let (_, messages) = tokenize("/* one /* two */");
+        assert_eq!(messages, ["unterminated block comment"]);
+    }
+
+    #[test]
+    fn doc_comments() {
+        let (tokens, _) = tokenize("//! module\n/// item\n// plain\nx");
+        assert!(matches!(&tokens[..], [Token::ModuleDocComment(_, module), Token::EOL(_),
+                                       Token::DocComment(_, item), Token::EOL(_),
+                                       Token::EOL(_),
+                                       Token::IdentName(_, _), Token::EOF(_)] if module == "module" && item == "item"), "{:?}", tokens);
+    }
+
+    #[test]
+    fn pointer_sized_types() {
+        // Codegen rejects targets whose pointers aren't 64-bit
+        let (tokens, _) = tokenize("usize isize");
+        assert!(matches!(tokens[..], [Token::BuiltinType(_, PrimitiveType::U64), Token::BuiltinType(_, PrimitiveType::I64), Token::EOF(_)]), "{:?}", tokens);
+
+        assert_eq!(number("10usize"), NumericConstant::Integer(String::from("10"), IntegerLiteralFormat::Decimal, Some(PrimitiveType::U64)));
+        assert_eq!(number("3isize"), NumericConstant::Integer(String::from("3"), IntegerLiteralFormat::Decimal, Some(PrimitiveType::I64)));
+    }
 }
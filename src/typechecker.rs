@@ -1,96 +1,2737 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{ HashMap, HashSet };
+use std::num::IntErrorKind;
+use std::path::PathBuf;
 
 use crate::ast::{
-    ParsedType,
+    BinaryOperator,
+    DirectiveKind,
+    MatchExprBody,
+    MatchExprCase,
+    ParsedBlock,
+    ParsedConstDecl,
+    ParsedDirective,
+    ParsedEnumVariant,
+    ParsedExpression,
     ParsedModule,
+    ParsedProcCall,
+    ParsedProcDecl,
+    ParsedStatement,
+    ParsedType,
+    ParsedVarDecl,
+    ParsedVariantPattern,
+    UnaryOperator,
 };
-use crate::token::PrimitiveType;
+use crate::checked_ast::*;
+use crate::consteval;
+use crate::diagnostic::{ Diagnostic, DiagnosticSink };
+use crate::intrinsics::{ self, FormatPiece, Intrinsic };
+use crate::layout::{ self, LayoutError };
+use crate::token::{ IntegerLiteralFormat, NumericConstant, PrimitiveType, Span };
 
 pub type TypeID = usize;
 
+/// Everything the typechecker knows about a registered type. Members
+/// and payloads refer to other types by `TypeID`.
+#[derive(Debug, Clone)]
 pub enum TypeInfo {
+    /// The type of anything that failed to typecheck. It's compatible
+    /// with every other type so one mistake isn't reported over and over.
+    Unknown,
     Primitive(PrimitiveType, usize),
-    Struct(String, HashMap<String, Box<TypeInfo>>),
-    Enum(String, Vec<Box<TypeInfo>>),
-    EnumVariant(String, HashMap<String, Box<TypeInfo>>),
+    Struct(String, Vec<(String, TypeID)>),
+    Enum(String, Vec<TypeID>),
+    EnumVariant(String, Vec<(String, TypeID)>),
+    /// The name, like `[4]i32`, the type of the elements and how many
+    /// there are
+    Array(String, TypeID, u64),
+    /// The name, like `*i32`, and the type that's pointed at
+    Pointer(String, TypeID),
 }
 
 impl TypeInfo {
-    pub fn name(&self) -> &String {
-        todo!()
+    pub fn primitive(&self) -> Option<&PrimitiveType> {
+        match self {
+            TypeInfo::Primitive(primitive, _size) => Some(primitive),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            TypeInfo::Unknown => "<unknown>",
+            TypeInfo::Primitive(primitive, _size) => primitive.as_str(),
+            TypeInfo::Struct(name, _members) => name,
+            TypeInfo::Enum(name, _variants) => name,
+            TypeInfo::EnumVariant(name, _payload) => name,
+            TypeInfo::Array(name, _elem_type, _len) => name,
+            TypeInfo::Pointer(name, _pointee) => name,
+        }
+    }
+}
+
+/// The parameters and return type of a procedure, which is all
+/// a call needs to be checked.
+struct ProcSignature {
+    params: Vec<(String, TypeID)>,
+    /// Whether it takes extra arguments after `params`, which only
+    /// `#foreign` procs can
+    variadic: bool,
+    return_type: TypeID,
+    /// Which intrinsic it is, if it's a valid `#intrinsic`
+    intrinsic: Option<Intrinsic>,
+    span: Span,
+}
+
+/// Constants always have their type written out, so they can be used
+/// before their value has been checked.
+struct ConstSignature {
+    id: ConstID,
+    type_id: TypeID,
+    span: Span,
+}
+
+/// What's in scope while checking the body of one procedure.
+struct ProcContext {
+    locals: Vec<CheckedVarDecl>,
+    /// Where each parameter is declared. They're the first locals, so
+    /// this is indexed by their `VarID`s too.
+    params: Vec<Span>,
+    scopes: Vec<HashMap<String, VarID>>,
+    loop_depth: usize,
+    return_type: TypeID,
+}
+
+impl ProcContext {
+    fn declare(&mut self, name: &str, type_id: TypeID) -> VarID {
+        let id = self.locals.len();
+        self.locals.push(CheckedVarDecl { name: String::from(name), type_id });
+        self.scopes.last_mut().unwrap().insert(String::from(name), id);
+        id
+    }
+
+    fn find_var(&self, name: &str) -> Option<VarID> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name).copied())
+    }
+}
+
+/// The module whose declarations are being checked, which decides what
+/// the names used in them refer to.
+#[derive(Default, Clone)]
+struct ModuleScope {
+    /// What the module's own declarations are qualified with, if it was
+    /// imported
+    namespace: Option<String>,
+    /// The file it was loaded from, if it was imported
+    import_path: Option<PathBuf>,
+    /// The names of the modules it imports
+    imports: HashSet<String>,
+}
+
+impl ModuleScope {
+    fn new(module: &ParsedModule) -> Self {
+        Self {
+            namespace: module.namespace.clone(),
+            import_path: module.import_path.clone(),
+            imports: module.imports.iter().map(|import| import.name()).collect(),
+        }
+    }
+
+    /// The full name of the declaration `path` refers to. Paths starting
+    /// with an imported module already are one, anything else is declared
+    /// in this module.
+    fn qualify(&self, path: &[&str]) -> String {
+        let path = path.join("::");
+
+        match &self.namespace {
+            Some(namespace) if !self.imports.contains(path.split("::").next().unwrap()) => format!("{}::{}", namespace, path),
+            _ => path,
+        }
+    }
+
+    fn is_import(&self, name: &str) -> bool {
+        self.imports.contains(name)
     }
 }
 
 pub struct Typechecker {
-    registry: Vec<TypeInfo>
+    /// Every type, by ID. Array types are added as they're used, which can
+    /// be while checking an expression.
+    registry: RefCell<Vec<TypeInfo>>,
+    type_ids: RefCell<HashMap<String, TypeID>>,
+    procs: HashMap<String, ProcSignature>,
+    consts: HashMap<String, ConstSignature>,
+    /// The value of every constant and the module it's declared in, for
+    /// working out array sizes before any constant is checked
+    const_values: HashMap<String, (ParsedExpression, ModuleScope)>,
+    builtins: Builtins,
+    scope: ModuleScope,
+    diagnostics: DiagnosticSink,
 }
 
 impl Typechecker {
-    pub fn new() -> Self {
+    pub fn new(diagnostics: DiagnosticSink) -> Self {
         Self {
-            registry: Vec::new()
+            registry: RefCell::new(Vec::new()),
+            type_ids: RefCell::new(HashMap::new()),
+            procs: HashMap::new(),
+            consts: HashMap::new(),
+            const_values: HashMap::new(),
+            builtins: Builtins::default(),
+            scope: ModuleScope::default(),
+            diagnostics,
         }
     }
 
-    pub fn primitive(ty: PrimitiveType) -> ParsedType {
+    fn primitive_size(ty: &PrimitiveType) -> usize {
         match ty {
-            PrimitiveType::Nothing => ParsedType::Name(Vec::new(), String::from("nothing")),
-            PrimitiveType::Bool => ParsedType::Name(Vec::new(), String::from("bool")),
-            PrimitiveType::I8 => ParsedType::Name(Vec::new(), String::from("i8")),
-            PrimitiveType::U8 => ParsedType::Name(Vec::new(), String::from("u8")),
-            PrimitiveType::I16 => ParsedType::Name(Vec::new(), String::from("i16")),
-            PrimitiveType::U16 => ParsedType::Name(Vec::new(), String::from("u16")),
-            PrimitiveType::I32 => ParsedType::Name(Vec::new(), String::from("i32")),
-            PrimitiveType::U32 => ParsedType::Name(Vec::new(), String::from("u32")),
-            PrimitiveType::I64 => ParsedType::Name(Vec::new(), String::from("i64")),
-            PrimitiveType::U64 => ParsedType::Name(Vec::new(), String::from("u64")),
-            PrimitiveType::F32 => ParsedType::Name(Vec::new(), String::from("f32")),
-            PrimitiveType::F64 => ParsedType::Name(Vec::new(), String::from("f64")),
-            PrimitiveType::Char => ParsedType::Name(Vec::new(), String::from("char")),
-            PrimitiveType::String => ParsedType::Name(Vec::new(), String::from("string")),
+            PrimitiveType::Nothing => 0,
+            PrimitiveType::Bool => 1,
+            PrimitiveType::I8 | PrimitiveType::U8 => 1,
+            PrimitiveType::I16 | PrimitiveType::U16 => 2,
+            PrimitiveType::I32 | PrimitiveType::U32 | PrimitiveType::F32 | PrimitiveType::Char => 4,
+            PrimitiveType::I64 | PrimitiveType::U64 | PrimitiveType::F64 => 8,
+            // Strings are passed around as a pointer to their bytes
+            PrimitiveType::String => 8,
         }
     }
 
     pub fn preregister_primitives(&mut self) {
-        let _ = self.register_type(&Typechecker::primitive(PrimitiveType::Nothing)).unwrap();
-        let _ = self.register_type(&Typechecker::primitive(PrimitiveType::Bool)).unwrap();
-        let _ = self.register_type(&Typechecker::primitive(PrimitiveType::I8)).unwrap();
-        let _ = self.register_type(&Typechecker::primitive(PrimitiveType::U8)).unwrap();
-        let _ = self.register_type(&Typechecker::primitive(PrimitiveType::I16)).unwrap();
-        let _ = self.register_type(&Typechecker::primitive(PrimitiveType::U16)).unwrap();
-        let _ = self.register_type(&Typechecker::primitive(PrimitiveType::I32)).unwrap();
-        let _ = self.register_type(&Typechecker::primitive(PrimitiveType::U32)).unwrap();
-        let _ = self.register_type(&Typechecker::primitive(PrimitiveType::I64)).unwrap();
-        let _ = self.register_type(&Typechecker::primitive(PrimitiveType::U64)).unwrap();
-        let _ = self.register_type(&Typechecker::primitive(PrimitiveType::F32)).unwrap();
-        let _ = self.register_type(&Typechecker::primitive(PrimitiveType::F64)).unwrap();
-        let _ = self.register_type(&Typechecker::primitive(PrimitiveType::Char)).unwrap();
-        let _ = self.register_type(&Typechecker::primitive(PrimitiveType::String)).unwrap();
-    }
-
-    pub fn register_type(
-        &mut self,
-        ty: &ParsedType
-    ) -> Result<TypeID, &'static str> {
-        todo!()
-    }
-
-    pub fn get_type_id(
-        &self,
-        ty_name: &String
-    ) -> Result<TypeID, &'static str> {
-        todo!()
+        let primitives = [
+            PrimitiveType::Nothing,
+            PrimitiveType::Bool,
+            PrimitiveType::I8,
+            PrimitiveType::U8,
+            PrimitiveType::I16,
+            PrimitiveType::U16,
+            PrimitiveType::I32,
+            PrimitiveType::U32,
+            PrimitiveType::I64,
+            PrimitiveType::U64,
+            PrimitiveType::F32,
+            PrimitiveType::F64,
+            PrimitiveType::Char,
+            PrimitiveType::String,
+        ];
+
+        let unknown = self.register_type(TypeInfo::Unknown).unwrap();
+
+        for primitive in primitives {
+            let size = Typechecker::primitive_size(&primitive);
+            let _ = self.register_type(TypeInfo::Primitive(primitive, size)).unwrap();
+        }
+
+        self.builtins = Builtins {
+            unknown,
+            nothing: self.get_type_id("nothing").unwrap(),
+            bool: self.get_type_id("bool").unwrap(),
+            char: self.get_type_id("char").unwrap(),
+            string: self.get_type_id("string").unwrap(),
+            i32: self.get_type_id("i32").unwrap(),
+            i64: self.get_type_id("i64").unwrap(),
+            f64: self.get_type_id("f64").unwrap(),
+        };
+    }
+
+    /// Adds a type to the registry. Enum variants aren't reachable by name
+    /// on their own, so only the other kinds of type have to be unique.
+    pub fn register_type(&self, info: TypeInfo) -> Result<TypeID, &'static str> {
+        let id = self.registry.borrow().len();
+
+        if !matches!(info, TypeInfo::EnumVariant(_, _)) {
+            if self.is_type_registered(info.name()) {
+                return Err("a type with this name is already registered");
+            }
+
+            self.type_ids.borrow_mut().insert(String::from(info.name()), id);
+        }
+
+        self.registry.borrow_mut().push(info);
+        Ok(id)
+    }
+
+    pub fn get_type_id(&self, ty_name: &str) -> Result<TypeID, &'static str> {
+        self.type_ids.borrow().get(ty_name).copied().ok_or("no type with this name is registered")
+    }
+
+    pub fn is_type_registered(&self, ty_name: &str) -> bool {
+        self.type_ids.borrow().contains_key(ty_name)
+    }
+
+    /// A copy of the type's info, since array types can be registered while
+    /// it's being looked at.
+    pub fn type_info(&self, id: TypeID) -> TypeInfo {
+        self.registry.borrow()[id].clone()
+    }
+
+    /// Replaces the info of a type that was registered before its
+    /// members could be resolved.
+    fn add_type_info(&mut self, id: TypeID, info: TypeInfo) {
+        self.registry.borrow_mut()[id] = info;
+    }
+
+    fn error(&self, diagnostic: Diagnostic) {
+        self.diagnostics.emit(diagnostic);
+    }
+
+    /// Registers every type declared in `modules`, resolves all the type
+    /// names in them to `ParsedType::CheckedType` and checks the body of
+    /// every procedure. Modules have to come after the ones they import,
+    /// with the module being compiled last. Anything wrong is reported to
+    /// the diagnostic sink; the returned module is only meant to be used if
+    /// nothing was.
+    pub fn verify_modules(&mut self, modules: &mut [ParsedModule]) -> CheckedModule {
+        // Types are registered before anything is resolved, so declarations
+        // can refer to types declared further down the file.
+        let mut declared: HashMap<String, Span> = HashMap::new();
+        let mut struct_ids: Vec<Vec<Option<TypeID>>> = Vec::new();
+        let mut enum_ids: Vec<Vec<Option<TypeID>>> = Vec::new();
+
+        for module in modules.iter() {
+            let scope = ModuleScope::new(module);
+            for decl in &module.consts {
+                self.const_values.entry(decl.name.clone()).or_insert_with(|| (decl.value.clone(), scope.clone()));
+            }
+
+            struct_ids.push(module.structs.iter().map(|decl| {
+                self.register_named(&mut declared, &decl.name, decl.span, TypeInfo::Struct(decl.name.clone(), Vec::new()))
+            }).collect());
+
+            enum_ids.push(module.enums.iter().map(|decl| {
+                self.register_named(&mut declared, &decl.name, decl.span, TypeInfo::Enum(decl.name.clone(), Vec::new()))
+            }).collect());
+        }
+
+        for (module, (struct_ids, enum_ids)) in modules.iter_mut().zip(struct_ids.iter().zip(&enum_ids)) {
+            self.scope = ModuleScope::new(module);
+            self.resolve_type_decls(module, struct_ids, enum_ids);
+            self.check_recursive_types(module, struct_ids, enum_ids);
+            self.check_type_sizes(module, struct_ids, enum_ids);
+        }
+
+        // Likewise, every signature is known before any body is checked so
+        // procs can call the ones declared after them. The same goes for
+        // constants, which can be used anywhere, including the values of
+        // other constants.
+        let mut is_duplicate = Vec::new();
+        let mut is_duplicate_const = Vec::new();
+
+        for module in modules.iter_mut() {
+            self.scope = ModuleScope::new(module);
+            is_duplicate.push(self.register_procs(module));
+            is_duplicate_const.push(self.register_consts(module));
+        }
+
+        let mut consts = Vec::new();
+        let mut procs = Vec::new();
+        let mut directives = Vec::new();
+
+        for ((module, is_duplicate), is_duplicate_const) in modules.iter_mut().zip(is_duplicate).zip(is_duplicate_const) {
+            self.scope = ModuleScope::new(module);
+
+            for (decl, is_duplicate) in module.consts.iter_mut().zip(is_duplicate_const) {
+                if !is_duplicate {
+                    consts.push(self.check_const(decl));
+                }
+            }
+
+            for (proc, is_duplicate) in module.procs.iter_mut().zip(is_duplicate) {
+                if !is_duplicate {
+                    procs.push(self.check_proc(proc));
+                }
+            }
+
+            for directive in &mut module.directives {
+                directives.push(self.check_directive(directive));
+            }
+        }
+
+        let mut checked_module = CheckedModule {
+            name: modules.last().map(|module| module.name.clone()).unwrap_or_default(),
+            types: self.registry.borrow().clone(),
+            builtins: self.builtins,
+            procs,
+            consts,
+            directives,
+        };
+
+        // Evaluating a constant runs the procs it calls, which only makes
+        // sense once all of them have checked out.
+        if !self.diagnostics.has_errors() {
+            consteval::evaluate_consts(&mut checked_module, &self.diagnostics);
+        }
+
+        // Directives can use constants, so they go last
+        if !self.diagnostics.has_errors() {
+            consteval::run_directives(&mut checked_module, &self.diagnostics);
+        }
+
+        checked_module
+    }
+
+    /// Fills in the members of the structs and the variants of the enums
+    /// declared in `module`, which were registered without them.
+    fn resolve_type_decls(&mut self, module: &mut ParsedModule, struct_ids: &[Option<TypeID>], enum_ids: &[Option<TypeID>]) {
+        for (decl, id) in module.structs.iter_mut().zip(struct_ids) {
+            let mut members = Vec::new();
+
+            for member in &mut decl.data_members {
+                if let Some(member_id) = self.resolve_type(&mut member.parsed_type) {
+                    members.push((member.name.clone(), member_id));
+                }
+            }
+
+            if let Some(id) = id {
+                self.add_type_info(*id, TypeInfo::Struct(decl.name.clone(), members));
+            }
+        }
+
+        for (decl, id) in module.enums.iter_mut().zip(enum_ids) {
+            let mut variant_names: HashSet<String> = HashSet::new();
+            let mut variants = Vec::new();
+
+            for variant in &mut decl.variants {
+                let (name, payload) = match variant {
+                    ParsedEnumVariant::Untyped(name) => (name.clone(), Vec::new()),
+                    ParsedEnumVariant::UnlabeledTypes(name, types) => {
+                        let payload = types.iter_mut()
+                                           .enumerate()
+                                           .filter_map(|(idx, ty)| Some((idx.to_string(), self.resolve_type(ty)?)))
+                                           .collect();
+                        (name.clone(), payload)
+                    },
+                    ParsedEnumVariant::LabeledTypes(name, fields) => {
+                        let payload = fields.iter_mut()
+                                            .filter_map(|field| Some((field.name.clone(), self.resolve_type(&mut field.parsed_type)?)))
+                                            .collect();
+                        (name.clone(), payload)
+                    },
+                };
+
+                if !variant_names.insert(name.clone()) {
+                    self.error(Diagnostic::error(format!("duplicate variant `{}` in enum `{}`", name, decl.name), decl.span));
+                    continue;
+                }
+
+                let variant_name = format!("{}::{}", decl.name, name);
+                variants.push(self.register_type(TypeInfo::EnumVariant(variant_name, payload)).unwrap());
+            }
+
+            if let Some(id) = id {
+                self.add_type_info(*id, TypeInfo::Enum(decl.name.clone(), variants));
+            }
+        }
+    }
+
+    /// Registers the signature of every proc in `module`, and gives back
+    /// which of them are defined more than once.
+    fn register_procs(&mut self, module: &mut ParsedModule) -> Vec<bool> {
+        let mut is_duplicate = Vec::new();
+
+        for proc in &mut module.procs {
+            let params = proc.parameters.iter_mut().map(|param| {
+                let type_id = self.resolve_type(&mut param.parsed_type).unwrap_or(self.builtins.unknown);
+                (param.name.clone(), type_id)
+            }).collect();
+            let return_type = self.resolve_type(&mut proc.parsed_return_type).unwrap_or(self.builtins.unknown);
+
+            if let Some(first) = self.procs.get(&proc.name) {
+                self.error(Diagnostic::error(format!("the proc `{}` is defined multiple times", proc.name), proc.span)
+                                     .with_label(first.span, "first defined here"));
+                is_duplicate.push(true);
+                continue;
+            }
+
+            let mut signature = ProcSignature {
+                params,
+                variadic: proc.variadic.is_some(),
+                return_type,
+                intrinsic: None,
+                span: proc.span,
+            };
+            signature.intrinsic = self.check_intrinsic(proc, &signature);
+
+            self.procs.insert(proc.name.clone(), signature);
+            is_duplicate.push(false);
+        }
+
+        is_duplicate
+    }
+
+    /// Works out which intrinsic an `#intrinsic` proc is, and checks that
+    /// it's declared the way the compiler expects it to be.
+    fn check_intrinsic(&self, proc: &ParsedProcDecl, signature: &ProcSignature) -> Option<Intrinsic> {
+        let span = proc.attributes.intrinsic?;
+
+        if let Some((_, foreign_span)) = &proc.attributes.foreign {
+            self.error(Diagnostic::error(format!("`{}` can't be both `#foreign` and `#intrinsic`", proc.name), span)
+                                 .with_label(*foreign_span, "declared `#foreign` here"));
+            return None;
+        }
+
+        let name = proc.name.rsplit("::").next().unwrap_or(&proc.name);
+        let Some(intrinsic) = Intrinsic::from_name(name) else {
+            let names = Intrinsic::ALL.iter().map(|intrinsic| format!("`{}`", intrinsic.name())).collect::<Vec<_>>().join(", ");
+            self.error(Diagnostic::error(format!("there's no intrinsic called `{}`", name), proc.span)
+                                 .with_label(span, "declared `#intrinsic` here")
+                                 .with_note(format!("the intrinsics are {}", names)));
+            return None;
+        };
+
+        let mut params: Vec<String> = signature.params.iter().map(|(name, type_id)| format!("{}: {}", name, self.type_name(*type_id))).collect();
+        if signature.variadic {
+            params.push(String::from(".."));
+        }
+
+        let mut declared = format!("({})", params.join(", "));
+        if signature.return_type != self.builtins.nothing {
+            declared += &format!(" -> {}", self.type_name(signature.return_type));
+        }
+
+        if declared != intrinsic.signature() {
+            self.error(Diagnostic::error(format!("the intrinsic `{}` has to be declared as `decl {} : {}`", name, name, intrinsic.signature()), proc.span)
+                                 .with_note(format!("it's declared as `decl {} : {}`", name, declared)));
+            return None;
+        }
+
+        Some(intrinsic)
+    }
+
+    /// Registers the type of every constant in `module`, and gives back
+    /// which of them are defined more than once.
+    fn register_consts(&mut self, module: &mut ParsedModule) -> Vec<bool> {
+        let mut is_duplicate_const = Vec::new();
+
+        for decl in &mut module.consts {
+            let type_id = self.resolve_type(&mut decl.parsed_type).unwrap_or(self.builtins.unknown);
+
+            if let Some(first) = self.consts.get(&decl.name) {
+                self.error(Diagnostic::error(format!("the constant `{}` is defined multiple times", decl.name), decl.span)
+                                     .with_label(first.span, "first defined here"));
+                is_duplicate_const.push(true);
+                continue;
+            }
+
+            self.consts.insert(decl.name.clone(), ConstSignature { id: self.consts.len(), type_id, span: decl.span });
+            is_duplicate_const.push(false);
+        }
+
+        is_duplicate_const
+    }
+
+    /// Registers a struct or enum, reporting it if the name is taken.
+    fn register_named(&mut self, declared: &mut HashMap<String, Span>, name: &str, span: Span, info: TypeInfo) -> Option<TypeID> {
+        match self.register_type(info) {
+            Ok(id) => {
+                declared.insert(String::from(name), span);
+                Some(id)
+            },
+            Err(_) => {
+                let mut diagnostic = Diagnostic::error(format!("the type `{}` is defined multiple times", name), span);
+                if let Some(first) = declared.get(name) {
+                    diagnostic = diagnostic.with_label(*first, "first defined here");
+                }
+
+                self.error(diagnostic);
+                None
+            }
+        }
+    }
+
+    /// Turns a `ParsedType::Name` into a `ParsedType::CheckedType`. Names
+    /// left empty by the parser are types that still have to be inferred,
+    /// so they are skipped.
+    fn resolve_type(&self, ty: &mut ParsedType) -> Option<TypeID> {
+        let id = match ty {
+            ParsedType::CheckedType(id) => return Some(*id),
+            ParsedType::Name(path, name, span) => {
+                if name.is_empty() {
+                    return None;
+                }
+
+                let full_path: Vec<&str> = path.iter().map(String::as_str).chain(std::iter::once(name.as_str())).collect();
+                let qualified = self.scope.qualify(&full_path);
+
+                // Primitives are the only types every module can name without
+                // importing anything
+                let primitive = || self.get_type_id(name).ok().filter(|id| path.is_empty() && self.primitive_of(*id).is_some());
+
+                match self.get_type_id(&qualified).ok().or_else(primitive) {
+                    Some(id) => id,
+                    None => {
+                        let mut diagnostic = Diagnostic::error(format!("cannot find type `{}`", full_path.join("::")), *span);
+                        if let [module] = path.as_slice() {
+                            if !self.scope.is_import(module) {
+                                diagnostic = diagnostic.with_note(format!("there's no module called `{}` imported here", module));
+                            }
+                        }
+
+                        self.error(diagnostic);
+                        return None;
+                    }
+                }
+            },
+            ParsedType::Array(elem_type, size) => {
+                let elem_id = self.resolve_type(elem_type);
+                let len = self.array_size(size)?;
+                self.array_of(elem_id?, len, size.span())?
+            },
+            ParsedType::Pointer(pointee) => {
+                let pointee_id = self.resolve_type(pointee)?;
+                self.pointer_to(pointee_id)
+            },
+        };
+
+        *ty = ParsedType::CheckedType(id);
+        Some(id)
+    }
+
+    /// The array type of `len` elements of `elem_id`, which is registered
+    /// the first time it's used.
+    fn array_of(&self, elem_id: TypeID, len: u64, span: Span) -> Option<TypeID> {
+        if elem_id == self.builtins.unknown {
+            return None;
+        }
+
+        if elem_id == self.builtins.nothing {
+            self.error(Diagnostic::error("can't make an array of `nothing`", span));
+            return None;
+        }
+
+        let name = format!("[{}]{}", len, self.type_name(elem_id));
+        let id = match self.get_type_id(&name) {
+            Ok(id) => id,
+            Err(_) => self.register_type(TypeInfo::Array(name, elem_id, len)).unwrap(),
+        };
+
+        // An element that's too big on its own has been reported already
+        let elem_fits = layout::layout_of(&self.registry.borrow(), elem_id).is_ok();
+        if elem_fits && layout::layout_of(&self.registry.borrow(), id) == Err(LayoutError::TooBig) {
+            self.error(Self::too_big(&self.type_name(id), span));
+            return None;
+        }
+
+        Some(id)
+    }
+
+    fn too_big(name: &str, span: Span) -> Diagnostic {
+        Diagnostic::error(format!("the type `{}` is too big", name), span)
+                   .with_note(format!("types can take up at most {} bytes", layout::MAX_SIZE))
+    }
+
+    /// Reports the structs and enums declared in `module` that take up
+    /// more memory than there is. Ones that are only too big because a
+    /// member is have already been reported, and so have recursive ones.
+    fn check_type_sizes(&self, module: &ParsedModule, struct_ids: &[Option<TypeID>], enum_ids: &[Option<TypeID>]) {
+        let spans = module.structs.iter().map(|decl| decl.span).zip(struct_ids)
+                          .chain(module.enums.iter().map(|decl| decl.span).zip(enum_ids));
+
+        for (span, id) in spans {
+            let Some(id) = id else { continue };
+
+            let members: Vec<TypeID> = match self.type_info(*id) {
+                TypeInfo::Struct(_, members) => members.iter().map(|(_, member)| *member).collect(),
+                TypeInfo::Enum(_, variants) => variants.iter().flat_map(|variant| match self.type_info(*variant) {
+                    TypeInfo::EnumVariant(_, payload) => payload.iter().map(|(_, field)| *field).collect(),
+                    _ => Vec::new(),
+                }).collect(),
+                _ => continue,
+            };
+
+            let types = self.registry.borrow();
+            if members.iter().all(|member| layout::layout_of(&types, *member).is_ok()) && layout::layout_of(&types, *id) == Err(LayoutError::TooBig) {
+                self.error(Self::too_big(types[*id].name(), span));
+            }
+        }
+    }
+
+    /// The pointer type to `pointee_id`, which is registered the first
+    /// time it's used.
+    fn pointer_to(&self, pointee_id: TypeID) -> TypeID {
+        let name = format!("*{}", self.type_name(pointee_id));
+        match self.get_type_id(&name) {
+            Ok(id) => id,
+            Err(_) => self.register_type(TypeInfo::Pointer(name, pointee_id)).unwrap(),
+        }
+    }
+
+    /// What a pointer type points at.
+    fn pointee_of(&self, type_id: TypeID) -> Option<TypeID> {
+        match self.type_info(type_id) {
+            TypeInfo::Pointer(_, pointee_id) => Some(pointee_id),
+            _ => None,
+        }
+    }
+
+    /// Whether the type is a pointer that can be offset and dereferenced,
+    /// which `*nothing` can't since what it points at has no size.
+    fn is_sized_pointer(&self, type_id: TypeID) -> bool {
+        self.pointee_of(type_id).is_some_and(|pointee_id| pointee_id != self.builtins.nothing)
+    }
+
+    /// The element type and length of an array type.
+    fn array_info(&self, type_id: TypeID) -> Option<(TypeID, u64)> {
+        match self.type_info(type_id) {
+            TypeInfo::Array(_, elem_id, len) => Some((elem_id, len)),
+            _ => None,
+        }
+    }
+
+    /// Works out the size of an array, which is needed as soon as the type
+    /// is resolved. That's before any constant is checked, so the size is
+    /// folded straight from the parsed expression.
+    fn array_size(&self, size: &ParsedExpression) -> Option<u64> {
+        let value = match self.fold_size(size, &self.scope, &mut Vec::new()) {
+            Ok(value) => value,
+            Err(diagnostic) => {
+                self.error(diagnostic);
+                return None;
+            }
+        };
+
+        match u64::try_from(value) {
+            Ok(len) => Some(len),
+            Err(_) => {
+                self.error(Diagnostic::error(format!("the size of an array can't be negative, but this is {}", value), size.span()));
+                None
+            }
+        }
+    }
+
+    /// `visiting` holds the constants whose values are being folded, to
+    /// catch the ones that depend on themselves.
+    fn fold_size(&self, expr: &ParsedExpression, scope: &ModuleScope, visiting: &mut Vec<String>) -> Result<i128, Diagnostic> {
+        let span = expr.span();
+        let too_large = || Diagnostic::error("the size of this array is too large", span);
+
+        let qualified = match expr {
+            ParsedExpression::NumericConstant(NumericConstant::Integer(digits, format, _), _) => {
+                return Self::parse_integer(digits, format, span).map(i128::from);
+            },
+            ParsedExpression::UnaryOperation(operand, UnaryOperator::Negate, _) => {
+                return Ok(-self.fold_size(operand, scope, visiting)?);
+            },
+            ParsedExpression::BinaryOperation(lhs, op, rhs, _) => {
+                let lhs = self.fold_size(lhs, scope, visiting)?;
+                let rhs = self.fold_size(rhs, scope, visiting)?;
+
+                let value = match op {
+                    BinaryOperator::Add => lhs.checked_add(rhs),
+                    BinaryOperator::Subtract => lhs.checked_sub(rhs),
+                    BinaryOperator::Multiply => lhs.checked_mul(rhs),
+                    BinaryOperator::Divide | BinaryOperator::Modulo if rhs == 0 => {
+                        return Err(Diagnostic::error("division by zero in the size of an array", span));
+                    },
+                    BinaryOperator::Divide => lhs.checked_div(rhs),
+                    BinaryOperator::Modulo => lhs.checked_rem(rhs),
+                    BinaryOperator::BitwiseAnd => Some(lhs & rhs),
+                    BinaryOperator::BitwiseOr => Some(lhs | rhs),
+                    BinaryOperator::BitwiseXOR => Some(lhs ^ rhs),
+                    BinaryOperator::BitwiseLeftShift => u32::try_from(rhs).ok().and_then(|rhs| lhs.checked_shl(rhs)).filter(|value| value >> rhs == lhs),
+                    BinaryOperator::BitwiseRightShift => u32::try_from(rhs).ok().and_then(|rhs| lhs.checked_shr(rhs)),
+                    _ => return Err(Self::not_constant_size(span)),
+                };
+
+                return value.ok_or_else(too_large);
+            },
+            ParsedExpression::Var(name, _) => scope.qualify(&[name]),
+            ParsedExpression::NamespacedVar(namespace, name, _) if matches!(namespace.as_slice(), [module] if scope.is_import(module)) => {
+                format!("{}::{}", namespace[0], name)
+            },
+            _ => return Err(Self::not_constant_size(span)),
+        };
+
+        let Some((value, const_scope)) = self.const_values.get(&qualified) else {
+            return Err(Diagnostic::error(format!("cannot find the constant `{}`", qualified), span));
+        };
+
+        if visiting.contains(&qualified) {
+            return Err(Diagnostic::error(format!("the value of `{}` depends on itself", qualified), span));
+        }
+
+        visiting.push(qualified);
+        let folded = self.fold_size(value, const_scope, visiting);
+        visiting.pop();
+
+        folded.map_err(|diagnostic| diagnostic.with_label(span, "used in the size of an array here"))
+    }
+
+    fn not_constant_size(span: Span) -> Diagnostic {
+        Diagnostic::error("the size of an array has to be a constant integer expression", span)
+            .with_note("it can only use integer literals, constants and arithmetic on them")
+    }
+
+    fn parse_integer(digits: &str, format: &IntegerLiteralFormat, span: Span) -> Result<u64, Diagnostic> {
+        let radix = match format {
+            IntegerLiteralFormat::Binary => 2,
+            IntegerLiteralFormat::Octal => 8,
+            IntegerLiteralFormat::Decimal => 10,
+            IntegerLiteralFormat::Hexadecimal => 16,
+        };
+
+        u64::from_str_radix(digits, radix).map_err(|err| {
+            let message = match err.kind() {
+                IntErrorKind::Empty => String::from("missing digits after the base prefix"),
+                IntErrorKind::PosOverflow => String::from("integer literal is too large"),
+                _ => format!("invalid digit in a base {} literal", radix),
+            };
+
+            Diagnostic::error(message, span)
+        })
+    }
+
+    /// Reports structs and enums that contain themselves by value, directly
+    /// or through other types, since they would need an infinite amount of
+    /// memory.
+    fn check_recursive_types(&self, module: &ParsedModule, struct_ids: &[Option<TypeID>], enum_ids: &[Option<TypeID>]) {
+        // For every struct and enum, the structs and enums it holds by value
+        // and where it holds them.
+        let mut edges: HashMap<TypeID, Vec<(TypeID, Span)>> = HashMap::new();
+        let mut decl_spans: HashMap<TypeID, Span> = HashMap::new();
+
+        // Arrays hold their elements by value too
+        let aggregate_of = |mut type_id: TypeID| {
+            while let TypeInfo::Array(_, elem_id, _) = self.type_info(type_id) {
+                type_id = elem_id;
+            }
+
+            matches!(self.type_info(type_id), TypeInfo::Struct(_, _) | TypeInfo::Enum(_, _)).then_some(type_id)
+        };
+
+        for (decl, id) in module.structs.iter().zip(struct_ids) {
+            let Some(id) = id else { continue };
+            decl_spans.insert(*id, decl.span);
+
+            let members = decl.data_members.iter().filter_map(|member| match member.parsed_type {
+                ParsedType::CheckedType(member_id) => aggregate_of(member_id).map(|member_id| (member_id, member.span)),
+                _ => None
+            });
+            edges.insert(*id, members.collect());
+        }
+
+        for (decl, id) in module.enums.iter().zip(enum_ids) {
+            let Some(id) = id else { continue };
+            decl_spans.insert(*id, decl.span);
+
+            // Payload types don't keep their spans, so the enum itself is
+            // pointed at
+            let TypeInfo::Enum(_, variants) = &self.type_info(*id) else { continue };
+            let payloads = variants.iter().flat_map(|variant| match &self.type_info(*variant) {
+                TypeInfo::EnumVariant(_, payload) => payload.clone(),
+                _ => Vec::new(),
+            });
+            edges.insert(*id, payloads.filter_map(|(_, field_id)| aggregate_of(field_id)).map(|field_id| (field_id, decl.span)).collect());
+        }
+
+        #[derive(Clone, Copy, PartialEq)]
+        enum Visit { Unvisited, InProgress, Done }
+
+        let mut visits: HashMap<TypeID, Visit> = HashMap::new();
+
+        fn visit(
+            checker: &Typechecker,
+            id: TypeID,
+            edges: &HashMap<TypeID, Vec<(TypeID, Span)>>,
+            decl_spans: &HashMap<TypeID, Span>,
+            visits: &mut HashMap<TypeID, Visit>,
+            path: &mut Vec<(TypeID, Span)>,
+        ) {
+            visits.insert(id, Visit::InProgress);
+
+            for (member_id, member_span) in edges.get(&id).into_iter().flatten() {
+                path.push((id, *member_span));
+
+                match visits.get(member_id).copied().unwrap_or(Visit::Unvisited) {
+                    Visit::Unvisited => visit(checker, *member_id, edges, decl_spans, visits, path),
+                    Visit::InProgress => {
+                        // Everything on the path from `member_id` onwards is the cycle
+                        let cycle_start = path.iter().position(|(on_path, _)| on_path == member_id).unwrap();
+                        let (kind, article) = match checker.type_info(*member_id) {
+                            TypeInfo::Enum(_, _) => ("enum", "an"),
+                            _ => ("struct", "a"),
+                        };
+                        let name = checker.type_name(*member_id);
+
+                        let decl_span = decl_spans[member_id];
+                        let mut diagnostic = Diagnostic::error(format!("recursive {} `{}` has infinite size", kind, name), decl_span);
+                        // An enum's own span is already underlined
+                        for (_, span) in path[cycle_start..].iter().filter(|(_, span)| *span != decl_span) {
+                            diagnostic = diagnostic.with_label(*span, "recursive without indirection");
+                        }
+
+                        checker.error(diagnostic.with_note(format!("{} {} can't contain itself by value, directly or through other types", article, kind)));
+                    },
+                    Visit::Done => { },
+                }
+
+                path.pop();
+            }
+
+            visits.insert(id, Visit::Done);
+        }
+
+        for id in struct_ids.iter().chain(enum_ids).flatten() {
+            if visits.get(id).copied().unwrap_or(Visit::Unvisited) == Visit::Unvisited {
+                visit(self, *id, &edges, &decl_spans, &mut visits, &mut Vec::new());
+            }
+        }
+    }
+
+    fn type_name(&self, type_id: TypeID) -> String {
+        String::from(self.registry.borrow()[type_id].name())
+    }
+
+    fn primitive_of(&self, type_id: TypeID) -> Option<PrimitiveType> {
+        self.registry.borrow()[type_id].primitive().cloned()
+    }
+
+    fn is_integer(&self, type_id: TypeID) -> bool {
+        self.primitive_of(type_id).is_some_and(|primitive| primitive.is_integer())
+    }
+
+    fn is_float(&self, type_id: TypeID) -> bool {
+        self.primitive_of(type_id).is_some_and(|primitive| primitive.is_float())
+    }
+
+    fn is_numeric(&self, type_id: TypeID) -> bool {
+        self.primitive_of(type_id).is_some_and(|primitive| primitive.is_numeric())
+    }
+
+    /// Types that can be compared with `==` and converted with `as`.
+    fn is_scalar(&self, type_id: TypeID) -> bool {
+        self.is_numeric(type_id) || type_id == self.builtins.bool || type_id == self.builtins.char
+    }
+
+    fn type_of(&self, expr: &CheckedExpression) -> TypeID {
+        expr.type_id(&self.builtins)
+    }
+
+    /// Reports `expr` if its type isn't `expected`. Expressions that
+    /// already failed to check are let through.
+    fn expect_type(&self, expr: &CheckedExpression, expected: TypeID) {
+        let found = self.type_of(expr);
+
+        if found == expected || found == self.builtins.unknown || expected == self.builtins.unknown {
+            return;
+        }
+
+        self.error(Diagnostic::error("mismatched types", expr.span())
+                             .with_primary_label(format!("expected `{}`, found `{}`", self.type_name(expected), self.type_name(found))));
+    }
+
+    fn check_proc(&self, proc: &mut ParsedProcDecl) -> CheckedProcDecl {
+        let signature = &self.procs[&proc.name];
+
+        let mut ctx = ProcContext {
+            locals: Vec::new(),
+            params: Vec::new(),
+            scopes: vec![HashMap::new()],
+            loop_depth: 0,
+            return_type: signature.return_type,
+        };
+
+        for (param, (_, type_id)) in proc.parameters.iter().zip(&signature.params) {
+            ctx.declare(&param.name, *type_id);
+            ctx.params.push(param.span);
+        }
+
+        let foreign = self.check_foreign(proc);
+
+        // Foreign procs and intrinsics are defined outside of the program
+        let external = match (&proc.attributes.foreign, proc.attributes.intrinsic) {
+            (Some((_, span)), _) => Some(("#foreign", *span)),
+            (None, Some(span)) => Some(("#intrinsic", span)),
+            (None, None) => None,
+        };
+
+        let body = match (&mut proc.body, external) {
+            (Some(body), None) => self.check_block(body, &mut ctx),
+            (Some(_), Some((attribute, span))) => {
+                self.error(Diagnostic::error(format!("the `{}` proc `{}` can't have a body", attribute, proc.name), proc.span)
+                                     .with_label(span, format!("declared `{}` here", attribute))
+                                     .with_note("it's defined outside of the program, so write `;` in place of the body"));
+                CheckedBlock::new()
+            },
+            (None, Some(_)) => CheckedBlock::new(),
+            (None, None) => {
+                self.error(Diagnostic::error(format!("`{}` has no body", proc.name), proc.span)
+                                     .with_note("only `#foreign` and `#intrinsic` procs can be declared without one"));
+                CheckedBlock::new()
+            },
+        };
+
+        let returns_something = ctx.return_type != self.builtins.nothing && ctx.return_type != self.builtins.unknown;
+        if returns_something && proc.body.is_some() && external.is_none() && !body.always_returns() {
+            self.error(Diagnostic::error(format!("`{}` doesn't return a value on every path", proc.name), proc.span)
+                                 .with_note(format!("it's declared to return `{}`", self.type_name(ctx.return_type))));
+        }
+
+        CheckedProcDecl {
+            name: proc.name.clone(),
+            span: proc.span,
+            param_count: proc.parameters.len(),
+            return_type: ctx.return_type,
+            locals: ctx.locals,
+            body,
+            inline: proc.attributes.inline.is_some(),
+            foreign,
+            intrinsic: signature.intrinsic,
+            imported_from: self.scope.import_path.clone(),
+        }
     }
 
-    pub fn is_type_registered(&self, ty_name: &String) -> bool {
-        todo!()
+    /// Checks that a `#foreign` proc only uses types C has an equivalent
+    /// of, and that only foreign procs take `..`.
+    fn check_foreign(&self, proc: &ParsedProcDecl) -> Option<ForeignProc> {
+        let signature = &self.procs[&proc.name];
+
+        let Some((library, _)) = &proc.attributes.foreign else {
+            // Whether an intrinsic takes `..` is checked along with the rest
+            // of its signature
+            if let (Some(span), None) = (proc.variadic, proc.attributes.intrinsic) {
+                self.error(Diagnostic::error(format!("`{}` can't take `..` since it isn't `#foreign`", proc.name), span)
+                                     .with_note("only foreign procs can take extra arguments"));
+            }
+
+            return None;
+        };
+
+        for (param, (_, type_id)) in proc.parameters.iter().zip(&signature.params) {
+            if !self.is_c_compatible(*type_id) || *type_id == self.builtins.nothing {
+                self.error(Diagnostic::error(format!("a `{}` can't be passed to the foreign proc `{}`", self.type_name(*type_id), proc.name), param.span)
+                                     .with_note("only pointers and primitive types other than `nothing` have a C equivalent"));
+            }
+        }
+
+        if !self.is_c_compatible(signature.return_type) {
+            self.error(Diagnostic::error(format!("a `{}` can't be returned from the foreign proc `{}`", self.type_name(signature.return_type), proc.name), proc.span)
+                                 .with_note("only pointers and primitive types have a C equivalent"));
+        }
+
+        // Foreign procs are linked by the name they have in C, whatever
+        // module they're declared in
+        let symbol = proc.name.rsplit("::").next().unwrap_or(&proc.name);
+
+        Some(ForeignProc {
+            symbol: String::from(symbol),
+            library: library.clone(),
+            variadic: signature.variadic,
+        })
+    }
+
+    /// Whether values of the type can be passed to and returned from C,
+    /// which so far is only true of primitives and pointers.
+    fn is_c_compatible(&self, type_id: TypeID) -> bool {
+        type_id == self.builtins.unknown || self.primitive_of(type_id).is_some() || self.pointee_of(type_id).is_some()
+    }
+
+    /// Checks the expression of an `#assert` or `#run`, which is done like
+    /// the value of a constant.
+    fn check_directive(&self, directive: &mut ParsedDirective) -> CheckedDirective {
+        let hint = match directive.kind {
+            DirectiveKind::Assert(_) => Some(self.builtins.bool),
+            DirectiveKind::Run => None,
+        };
+
+        let mut ctx = ProcContext {
+            locals: Vec::new(),
+            params: Vec::new(),
+            scopes: vec![HashMap::new()],
+            loop_depth: 0,
+            return_type: hint.unwrap_or(self.builtins.unknown),
+        };
+
+        let value = self.check_expression(&mut directive.value, hint, &mut ctx);
+
+        if let Some(bool_type) = hint {
+            self.expect_type(&value, bool_type);
+        }
+
+        CheckedDirective {
+            kind: directive.kind.clone(),
+            span: directive.span,
+            type_id: self.type_of(&value),
+            locals: ctx.locals,
+            value,
+        }
+    }
+
+    fn check_const(&self, decl: &mut ParsedConstDecl) -> CheckedConstDecl {
+        let type_id = self.consts[&decl.name].type_id;
+
+        // A constant's value is checked like the body of a proc without
+        // any parameters
+        let mut ctx = ProcContext {
+            locals: Vec::new(),
+            params: Vec::new(),
+            scopes: vec![HashMap::new()],
+            loop_depth: 0,
+            return_type: type_id,
+        };
+
+        let value = self.check_expression(&mut decl.value, Some(type_id), &mut ctx);
+        self.expect_type(&value, type_id);
+
+        if type_id == self.builtins.nothing {
+            self.error(Diagnostic::error(format!("the constant `{}` can't be of type `nothing`", decl.name), decl.span));
+        } else if type_id != self.builtins.unknown && self.primitive_of(type_id).is_none() {
+            self.error(Diagnostic::error(format!("constants of type `{}` are not supported yet", self.type_name(type_id)), decl.span));
+        }
+
+        CheckedConstDecl {
+            name: decl.name.clone(),
+            span: decl.span,
+            type_id,
+            locals: ctx.locals,
+            value,
+        }
     }
 
-    fn add_type_info(&self, ty: &ParsedType) {
-        todo!()
+    fn check_block(&self, block: &mut ParsedBlock, ctx: &mut ProcContext) -> CheckedBlock {
+        let mut checked_block = CheckedBlock::new();
+
+        ctx.scopes.push(HashMap::new());
+
+        for stmt in &mut block.stmts {
+            if let Some(checked_stmt) = self.check_statement(stmt, ctx) {
+                checked_block.stmts.push(checked_stmt);
+            }
+        }
+
+        ctx.scopes.pop();
+
+        checked_block
     }
 
-    pub fn verify_module(&mut self, module: &ParsedModule) -> Result<(), &'static str>{
-        todo!()
+    fn check_loop_body(&self, block: &mut ParsedBlock, ctx: &mut ProcContext) -> CheckedBlock {
+        ctx.loop_depth += 1;
+        let body = self.check_block(block, ctx);
+        ctx.loop_depth -= 1;
+
+        body
+    }
+
+    fn check_condition(&self, cond: &mut ParsedExpression, ctx: &mut ProcContext) -> CheckedExpression {
+        let cond = self.check_expression(cond, Some(self.builtins.bool), ctx);
+        self.expect_type(&cond, self.builtins.bool);
+        cond
+    }
+
+    fn check_statement(&self, stmt: &mut ParsedStatement, ctx: &mut ProcContext) -> Option<CheckedStatement> {
+        let checked_stmt = match stmt {
+            ParsedStatement::Expr(expr) => CheckedStatement::Expr(self.check_expression(expr, None, ctx)),
+            ParsedStatement::VarAssign(name, op, value, span) => {
+                let mut target = ParsedExpression::Var(name.clone(), *span);
+                let span = span.to(value.span());
+                CheckedStatement::Expr(self.check_binary_operation(&mut target, op, value, span, None, ctx))
+            },
+            ParsedStatement::VarDecl(var_decl) => {
+                let declared_type = match &var_decl.parsed_type {
+                    // `let x := ...` leaves the type name empty
+                    ParsedType::Name(_, name, _) if name.is_empty() => None,
+                    _ => Some(self.resolve_type(&mut var_decl.parsed_type).unwrap_or(self.builtins.unknown)),
+                };
+
+                let init = self.check_expression(&mut var_decl.defualt_value, declared_type, ctx);
+
+                let type_id = match declared_type {
+                    Some(type_id) => {
+                        self.expect_type(&init, type_id);
+                        type_id
+                    },
+                    None => {
+                        let type_id = self.type_of(&init);
+                        if type_id == self.builtins.nothing {
+                            self.error(Diagnostic::error(format!("can't infer the type of `{}` from a value of type `nothing`", var_decl.name), init.span())
+                                                 .with_label(var_decl.span, "declared here"));
+                        }
+                        type_id
+                    },
+                };
+
+                // Declared after the initializer is checked, so `let x := x`
+                // refers to an `x` from an outer scope.
+                let var_id = ctx.declare(&var_decl.name, type_id);
+
+                CheckedStatement::VarDecl(var_id, init)
+            },
+            ParsedStatement::If(cond, then_block, else_stmt) => {
+                let cond = self.check_condition(cond, ctx);
+                let then_block = self.check_block(then_block, ctx);
+                let else_stmt = match else_stmt {
+                    Some(else_stmt) => self.check_statement(else_stmt, ctx).map(Box::new),
+                    None => None
+                };
+
+                CheckedStatement::If(cond, then_block, else_stmt)
+            },
+            ParsedStatement::Block(block) => CheckedStatement::Block(self.check_block(block, ctx)),
+            ParsedStatement::ForLoop(it_decl, range, body) => {
+                let range = self.check_expression(range, None, ctx);
+                let range_type = self.type_of(&range);
+
+                let it_type = match (self.array_info(range_type), &range) {
+                    (Some((elem_id, _)), _) => elem_id,
+                    (None, CheckedExpression::Range(..)) if self.is_integer(range_type) => range_type,
+                    _ if range_type == self.builtins.unknown => range_type,
+                    _ => {
+                        self.error(Diagnostic::error(format!("can only loop over a range of integers or an array, not `{}`", self.type_name(range_type)), range.span()));
+                        self.builtins.unknown
+                    }
+                };
+
+                // The iterator only lives as long as the loop's body
+                ctx.scopes.push(HashMap::new());
+                let it_id = ctx.declare(&it_decl.name, it_type);
+                let body = self.check_loop_body(body, ctx);
+                ctx.scopes.pop();
+
+                CheckedStatement::ForLoop(it_id, range, body)
+            },
+            ParsedStatement::WhileLoop(cond, body) => {
+                let cond = self.check_condition(cond, ctx);
+                let body = self.check_loop_body(body, ctx);
+
+                CheckedStatement::WhileLoop(cond, body)
+            },
+            ParsedStatement::InfiniteLoop(body) => CheckedStatement::InfiniteLoop(self.check_loop_body(body, ctx)),
+            ParsedStatement::Continue(span) => {
+                if ctx.loop_depth == 0 {
+                    self.error(Diagnostic::error("`continue` outside of a loop", *span));
+                }
+
+                CheckedStatement::Continue
+            },
+            ParsedStatement::Break(span) => {
+                if ctx.loop_depth == 0 {
+                    self.error(Diagnostic::error("`break` outside of a loop", *span));
+                }
+
+                CheckedStatement::Break
+            },
+            ParsedStatement::Return(expr, span) => {
+                let expr = expr.as_mut().map(|expr| self.check_expression(expr, Some(ctx.return_type), ctx));
+
+                match &expr {
+                    Some(expr) => self.expect_type(expr, ctx.return_type),
+                    None if ctx.return_type != self.builtins.nothing && ctx.return_type != self.builtins.unknown => {
+                        self.error(Diagnostic::error(format!("missing a value of type `{}` to return", self.type_name(ctx.return_type)), *span));
+                    },
+                    None => { }
+                }
+
+                CheckedStatement::Return(expr)
+            },
+            // Already reported by the parser
+            ParsedStatement::Invalid(_) => return None,
+        };
+
+        Some(checked_stmt)
+    }
+
+    /// Checks `expr` and works out its type. `hint` is the type the
+    /// surrounding code expects, which untyped literals adopt if they can.
+    fn check_expression(&self, expr: &mut ParsedExpression, hint: Option<TypeID>, ctx: &mut ProcContext) -> CheckedExpression {
+        match expr {
+            ParsedExpression::Bool(value, span) => CheckedExpression::Bool(*value, *span),
+            ParsedExpression::Null(span) => match hint {
+                Some(hint) if self.pointee_of(hint).is_some() => CheckedExpression::Null(hint, *span),
+                // The type it was given is already wrong
+                Some(hint) if hint == self.builtins.unknown => CheckedExpression::Invalid(*span),
+                _ => {
+                    self.error(Diagnostic::error("can't infer the type of `null`", *span)
+                                         .with_note("it can only be used where a pointer is expected, like `let ptr: *i32 = null`"));
+                    CheckedExpression::Invalid(*span)
+                }
+            },
+            ParsedExpression::NumericConstant(constant, span) => self.check_numeric_constant(constant, *span, hint, false),
+            ParsedExpression::StringLiteral(value, span) => CheckedExpression::StringLiteral(value.clone(), *span),
+            ParsedExpression::CharLiteral(value, span) => CheckedExpression::CharLiteral(*value as u32, *span),
+            ParsedExpression::Var(name, span) => match ctx.find_var(name) {
+                Some(var_id) => CheckedExpression::Var(var_id, ctx.locals[var_id].type_id, *span),
+                None => {
+                    // Constants go by their full name from here on
+                    let qualified = self.scope.qualify(&[name]);
+                    let checked = self.check_const_use(&qualified, name, *span);
+                    *name = qualified;
+                    checked
+                }
+            },
+            ParsedExpression::NamespacedVar(namespace, name, span) => {
+                if let [module] = namespace.as_slice() {
+                    if self.scope.is_import(module) {
+                        let qualified = format!("{}::{}", module, name);
+                        let checked = self.check_const_use(&qualified, &qualified, *span);
+                        *expr = ParsedExpression::Var(qualified, *span);
+                        return checked;
+                    }
+                }
+
+                match self.namespaced_enum(namespace) {
+                    Some(enum_id) => self.check_enum_value(enum_id, name, None, *span, ctx),
+                    None => {
+                        self.error(Self::unknown_namespace(namespace, *span));
+                        CheckedExpression::Invalid(*span)
+                    }
+                }
+            },
+            ParsedExpression::Range(start_bound, start, end, end_bound, span) => {
+                let (start, end) = self.check_operands(start, end, hint, ctx);
+                let type_id = self.type_of(&start);
+
+                self.expect_type(&end, type_id);
+
+                CheckedExpression::Range(start_bound.clone(), Box::new(start), Box::new(end), end_bound.clone(), type_id, *span)
+            },
+            ParsedExpression::Match(matched, cases, result_type, span) => {
+                let checked = self.check_match(matched, cases, *span, hint, ctx);
+                **result_type = ParsedType::CheckedType(self.type_of(&checked));
+                checked
+            },
+            ParsedExpression::If(cond, then_body, else_body, result_type, span) => {
+                let checked = self.check_if_expression(cond, then_body, else_body, *span, hint, ctx);
+                **result_type = ParsedType::CheckedType(self.type_of(&checked));
+                checked
+            },
+            ParsedExpression::ArrayLiteral(elems, array_type, span) => {
+                let checked = self.check_array_literal(elems, *span, hint, ctx);
+                **array_type = ParsedType::CheckedType(self.type_of(&checked));
+                checked
+            },
+            ParsedExpression::ArrayRepeat(value, count, array_type, span) => {
+                let elem_hint = hint.and_then(|hint| self.array_info(hint)).map(|(elem_id, _)| elem_id);
+                let value = self.check_expression(value, elem_hint, ctx);
+
+                let type_id = self.array_size(count)
+                                  .and_then(|len| self.array_of(self.type_of(&value), len, *span))
+                                  .unwrap_or(self.builtins.unknown);
+                **array_type = ParsedType::CheckedType(type_id);
+
+                match self.array_info(type_id) {
+                    Some((_, len)) => CheckedExpression::ArrayRepeat(Box::new(value), len, type_id, *span),
+                    None => CheckedExpression::Invalid(*span),
+                }
+            },
+            ParsedExpression::Index(base, index, span) => self.check_index(base, index, *span, ctx),
+            ParsedExpression::StructLiteral(struct_type, members, span) => self.check_struct_literal(struct_type, members, *span, ctx),
+            ParsedExpression::Member(base, name, span) => self.check_member(base, name, *span, ctx),
+            ParsedExpression::MethodCall(receiver, call) => {
+                let checked = self.check_method_call(receiver, call, ctx);
+
+                // It's a plain call from here on, with the receiver
+                // passed first
+                if let ParsedExpression::MethodCall(_, call) = std::mem::replace(expr, ParsedExpression::Invalid) {
+                    *expr = ParsedExpression::ProcCall(call);
+                }
+
+                checked
+            },
+            ParsedExpression::UnaryOperation(operand, op, span) => self.check_unary_operation(operand, op, *span, hint, ctx),
+            ParsedExpression::BinaryOperation(lhs, op, rhs, span) => self.check_binary_operation(lhs, op, rhs, *span, hint, ctx),
+            ParsedExpression::ProcCall(call) => self.check_proc_call(call, ctx),
+            ParsedExpression::Operator(_, span) => unreachable!("Stray operator at {:?}", span),
+            ParsedExpression::Invalid => CheckedExpression::Invalid(Span::default()),
+        }
+    }
+
+    /// Checks `[a, b, c]`. The elements all have to be of the same type,
+    /// which comes from `hint` for an empty array.
+    fn check_array_literal(&self, elems: &mut [ParsedExpression], span: Span, hint: Option<TypeID>, ctx: &mut ProcContext) -> CheckedExpression {
+        let mut elem_type = hint.and_then(|hint| self.array_info(hint)).map(|(elem_id, _)| elem_id);
+
+        if elems.is_empty() && elem_type.is_none() {
+            // Unless the type it was given is what's wrong
+            if hint != Some(self.builtins.unknown) {
+                self.error(Diagnostic::error("can't infer the type of an empty array", span)
+                                     .with_note("give it a type, like `let values: [0]i32 = []`"));
+            }
+
+            return CheckedExpression::Invalid(span);
+        }
+
+        // Untyped literals are checked last, so `[1, x]` takes on the type of `x`
+        let mut order: Vec<usize> = (0..elems.len()).collect();
+        order.sort_by_key(|i| elems[*i].is_untyped_literal());
+
+        let mut checked: Vec<Option<CheckedExpression>> = elems.iter().map(|_| None).collect();
+        for i in order {
+            let value = self.check_expression(&mut elems[i], elem_type, ctx);
+
+            match elem_type {
+                Some(elem_id) => self.expect_type(&value, elem_id),
+                None => elem_type = Some(self.type_of(&value)),
+            }
+
+            checked[i] = Some(value);
+        }
+
+        let elem_type = elem_type.unwrap_or(self.builtins.unknown);
+        match self.array_of(elem_type, elems.len() as u64, span) {
+            Some(type_id) => CheckedExpression::Array(checked.into_iter().flatten().collect(), type_id, span),
+            None => CheckedExpression::Invalid(span),
+        }
+    }
+
+    /// Checks `base[index]`. Indices that are known at compile time are
+    /// checked against the length of the array here, the rest at runtime.
+    fn check_index(&self, base: &mut ParsedExpression, index: &mut ParsedExpression, span: Span, ctx: &mut ProcContext) -> CheckedExpression {
+        let base = self.check_expression(base, None, ctx);
+        let index = self.check_expression(index, Some(self.builtins.i64), ctx);
+        let base_type = self.type_of(&base);
+        let index_type = self.type_of(&index);
+
+        if index_type != self.builtins.unknown && !self.is_integer(index_type) {
+            self.error(Diagnostic::error(format!("arrays can only be indexed by integers, not `{}`", self.type_name(index_type)), index.span()));
+        }
+
+        let elem_type = match self.array_info(base_type) {
+            Some((elem_id, len)) => {
+                if let CheckedExpression::Integer(value, _, index_span) = &index {
+                    if *value >= len {
+                        self.error(Diagnostic::error(format!("index {} is out of bounds for an array of length {}", value, len), *index_span)
+                                             .with_label(base.span(), format!("this is `{}`", self.type_name(base_type))));
+                    }
+                }
+
+                elem_id
+            },
+            None => {
+                if base_type != self.builtins.unknown {
+                    self.error(Diagnostic::error(format!("can't index into a value of type `{}`", self.type_name(base_type)), base.span()));
+                }
+
+                self.builtins.unknown
+            }
+        };
+
+        CheckedExpression::Index(Box::new(base), Box::new(index), elem_type, span)
+    }
+
+    /// Checks `Name{member: value, ...}`, which has to give every member of
+    /// the struct a value, in any order.
+    fn check_struct_literal(&self, struct_type: &mut ParsedType, members: &mut [ParsedVarDecl], span: Span, ctx: &mut ProcContext) -> CheckedExpression {
+        let struct_info = self.resolve_type(struct_type).map(|type_id| (type_id, self.type_info(type_id)));
+
+        let (type_id, name, fields) = match struct_info {
+            Some((type_id, TypeInfo::Struct(name, fields))) => (type_id, name, fields),
+            _ => {
+                if let Some((type_id, _)) = struct_info {
+                    self.error(Diagnostic::error(format!("`{}` isn't a struct", self.type_name(type_id)), span)
+                                         .with_note("only structs can be made with `Name{member: value}`"));
+                }
+
+                // Still check the values, they might have mistakes of their own
+                for member in members {
+                    self.check_expression(&mut member.defualt_value, None, ctx);
+                }
+
+                return CheckedExpression::Invalid(span);
+            }
+        };
+
+        let mut values: Vec<Option<CheckedExpression>> = vec![None; fields.len()];
+
+        for member in members {
+            let Some(idx) = fields.iter().position(|(field_name, _)| *field_name == member.name) else {
+                self.error(Diagnostic::error(format!("`{}` has no member named `{}`", name, member.name), member.span));
+                self.check_expression(&mut member.defualt_value, None, ctx);
+                continue;
+            };
+
+            let field_type = fields[idx].1;
+            let value = self.check_expression(&mut member.defualt_value, Some(field_type), ctx);
+            self.expect_type(&value, field_type);
+
+            if let Some(previous) = &values[idx] {
+                self.error(Diagnostic::error(format!("`{}` is given more than once", member.name), member.span)
+                                     .with_label(previous.span(), "first given here"));
+                continue;
+            }
+
+            values[idx] = Some(value);
+        }
+
+        let missing: Vec<String> = fields.iter()
+                                         .zip(&values)
+                                         .filter(|(_, value)| value.is_none())
+                                         .map(|((field_name, _), _)| format!("`{}`", field_name))
+                                         .collect();
+
+        if !missing.is_empty() {
+            let plural = if missing.len() == 1 { "" } else { "s" };
+            self.error(Diagnostic::error(format!("missing member{} {} in `{}`", plural, missing.join(", "), name), span));
+        }
+
+        let values = values.into_iter()
+                           .map(|value| value.unwrap_or(CheckedExpression::Invalid(span)))
+                           .collect();
+        CheckedExpression::Struct(values, type_id, span)
+    }
+
+    /// The struct `type_id` is, or points at. Members and methods can be
+    /// reached through a pointer without dereferencing it first.
+    fn struct_behind(&self, type_id: TypeID) -> Option<(TypeID, bool)> {
+        let is_struct = |type_id| matches!(self.type_info(type_id), TypeInfo::Struct(_, _));
+
+        match self.pointee_of(type_id) {
+            Some(pointee_id) if is_struct(pointee_id) => Some((pointee_id, true)),
+            _ if is_struct(type_id) => Some((type_id, false)),
+            _ => None,
+        }
+    }
+
+    /// Checks `base.name`, where `base` is a struct or a pointer to one.
+    fn check_member(&self, base: &mut ParsedExpression, name: &str, span: Span, ctx: &mut ProcContext) -> CheckedExpression {
+        let base = self.check_expression(base, None, ctx);
+        let base_type = self.type_of(&base);
+
+        let Some((struct_id, through_pointer)) = self.struct_behind(base_type) else {
+            if base_type != self.builtins.unknown {
+                self.error(Diagnostic::error(format!("can't access the member `{}` of a value of type `{}`", name, self.type_name(base_type)), base.span())
+                                     .with_note("only structs, and pointers to them, have members"));
+            }
+
+            return CheckedExpression::Invalid(span);
+        };
+
+        let TypeInfo::Struct(struct_name, fields) = self.type_info(struct_id) else {
+            unreachable!("`struct_behind` only gives back structs");
+        };
+
+        let Some(idx) = fields.iter().position(|(field_name, _)| field_name == name) else {
+            self.error(Diagnostic::error(format!("`{}` has no member named `{}`", struct_name, name), span));
+            return CheckedExpression::Invalid(span);
+        };
+
+        let base = match through_pointer {
+            true => CheckedExpression::UnaryOperation(Box::new(base), UnaryOperator::Dereference, struct_id, span),
+            false => base,
+        };
+
+        CheckedExpression::Member(Box::new(base), idx, fields[idx].1, span)
+    }
+
+    /// Checks `receiver.name(...)`, which calls the proc `name` declared in
+    /// the struct the receiver is or points at. The receiver is passed as
+    /// the proc's first parameter, so that has to be the struct or a
+    /// pointer to it. Its address is taken or it's dereferenced to match.
+    fn check_method_call(&self, receiver: &mut Box<ParsedExpression>, call: &mut ParsedProcCall, ctx: &mut ProcContext) -> CheckedExpression {
+        let checked_receiver = self.check_expression(receiver, None, ctx);
+        let receiver_type = self.type_of(&checked_receiver);
+
+        let method = self.struct_behind(receiver_type).and_then(|(struct_id, through_pointer)| {
+            let qualified = format!("{}::{}", self.type_name(struct_id), call.name);
+            Some((struct_id, through_pointer, qualified.clone(), self.procs.get(&qualified)?))
+        });
+
+        let Some((struct_id, through_pointer, qualified, signature)) = method else {
+            match self.struct_behind(receiver_type) {
+                _ if receiver_type == self.builtins.unknown => { },
+                Some((struct_id, _)) => self.error(Diagnostic::error(format!("`{}` has no method named `{}`", self.type_name(struct_id), call.name), call.span)),
+                None => self.error(Diagnostic::error(format!("can't call a method on a value of type `{}`", self.type_name(receiver_type)), checked_receiver.span())
+                                               .with_note("only structs, and pointers to them, have methods")),
+            }
+
+            for arg in &mut call.passed_parameters {
+                self.check_expression(&mut arg.defualt_value, None, ctx);
+            }
+
+            return CheckedExpression::Invalid(call.span);
+        };
+
+        let takes_pointer = match signature.params.first() {
+            Some((_, param_type)) if *param_type == struct_id => false,
+            Some((_, param_type)) if self.pointee_of(*param_type) == Some(struct_id) => true,
+            _ => {
+                let struct_name = self.type_name(struct_id);
+                self.error(Diagnostic::error(format!("`{}` can't be called as a method", qualified), call.span)
+                                     .with_label(signature.span, "declared here")
+                                     .with_note(format!("methods take a `{}` or a `*{}` as their first parameter", struct_name, struct_name)));
+
+                for arg in &mut call.passed_parameters {
+                    self.check_expression(&mut arg.defualt_value, None, ctx);
+                }
+
+                return CheckedExpression::Invalid(call.span);
+            }
+        };
+
+        let receiver_span = checked_receiver.span();
+        let (checked_receiver, op) = match (takes_pointer, through_pointer) {
+            (true, false) if !Self::is_place(&checked_receiver) => {
+                self.error(Diagnostic::error("can't take the address of a temporary value", receiver_span)
+                                     .with_note(format!("`{}` takes a pointer, so it can only be called on something that has an address", qualified)));
+                (checked_receiver, None)
+            },
+            (true, false) => (CheckedExpression::UnaryOperation(Box::new(checked_receiver), UnaryOperator::AddressOf, self.pointer_to(struct_id), receiver_span), Some(UnaryOperator::AddressOf)),
+            (false, true) => (CheckedExpression::UnaryOperation(Box::new(checked_receiver), UnaryOperator::Dereference, struct_id, receiver_span), Some(UnaryOperator::Dereference)),
+            _ => (checked_receiver, None),
+        };
+
+        let self_name = signature.params[0].0.clone();
+        let checked = self.check_call(call, qualified, signature, Some(checked_receiver), ctx);
+
+        let mut receiver = std::mem::replace(&mut **receiver, ParsedExpression::Invalid);
+        if let Some(op) = op {
+            receiver = ParsedExpression::UnaryOperation(Box::new(receiver), op, receiver_span);
+        }
+
+        call.passed_parameters.insert(0, ParsedVarDecl {
+            parsed_type: ParsedType::Name(Vec::new(), String::new(), Span::default()),
+            name: self_name,
+            span: receiver_span,
+            defualt_value: receiver,
+        });
+
+        checked
+    }
+
+    /// Whether a value can be stored in `expr`, which is a variable, an
+    /// element or member of one or what a pointer points at. These are
+    /// also what `&` can take the address of.
+    fn is_place(expr: &CheckedExpression) -> bool {
+        match expr {
+            CheckedExpression::Var(_, _, _) => true,
+            CheckedExpression::Index(base, _, _, _) |
+            CheckedExpression::Member(base, _, _, _) => Self::is_place(base),
+            CheckedExpression::UnaryOperation(_, UnaryOperator::Dereference, _, _) => true,
+            _ => false,
+        }
+    }
+
+    /// Reports changing a parameter, or an element or member of one, since
+    /// parameters are immutable. What a pointer parameter points at can
+    /// still be changed.
+    fn check_not_param(&self, place: &CheckedExpression, ctx: &ProcContext) {
+        match place {
+            CheckedExpression::Var(var_id, _, span) if *var_id < ctx.params.len() => {
+                let name = &ctx.locals[*var_id].name;
+                self.error(Diagnostic::error(format!("cannot assign to the parameter `{}`", name), *span)
+                                     .with_label(ctx.params[*var_id], "parameters are immutable")
+                                     .with_note(format!("copy it into a local first, like `let {0} := {0};`", name)));
+            },
+            CheckedExpression::Index(base, _, _, _) |
+            CheckedExpression::Member(base, _, _, _) => self.check_not_param(base, ctx),
+            _ => {},
+        }
+    }
+
+    /// Checks a name that isn't a local, which has to be a constant then.
+    /// `qualified` is the constant's full name, `name` is how it was written.
+    fn check_const_use(&self, qualified: &str, name: &str, span: Span) -> CheckedExpression {
+        match self.consts.get(qualified) {
+            Some(signature) => CheckedExpression::Const(signature.id, signature.type_id, span),
+            None => {
+                self.error(Diagnostic::error(format!("cannot find `{}` in this scope", name), span));
+                CheckedExpression::Invalid(span)
+            }
+        }
+    }
+
+    /// Checks a number literal, which takes on its suffix's type, or the
+    /// type the surrounding code expects. The value has to fit in that
+    /// type, and `negated` lets a signed integer go one further, so the
+    /// smallest value, like `-128` for `i8`, can be written.
+    fn check_numeric_constant(&self, constant: &NumericConstant, span: Span, hint: Option<TypeID>, negated: bool) -> CheckedExpression {
+        let suffix_type = constant.suffix().map(|suffix| self.get_type_id(suffix.as_str()).unwrap());
+
+        match constant {
+            NumericConstant::Integer(digits, format, _) => {
+                let value = match Self::parse_integer(digits, format, span) {
+                    Ok(value) => value,
+                    Err(diagnostic) => {
+                        self.error(diagnostic);
+                        return CheckedExpression::Invalid(span);
+                    }
+                };
+
+                // An integer literal can stand in for any number, e.g. `let x: f32 = 1;`
+                let type_id = match (suffix_type, hint) {
+                    (Some(suffix_type), _) => suffix_type,
+                    (None, Some(hint)) if self.is_numeric(hint) => hint,
+                    _ => self.builtins.i64,
+                };
+
+                if self.is_float(type_id) {
+                    return CheckedExpression::Float(value as f64, type_id, span);
+                }
+
+                let primitive = self.primitive_of(type_id).unwrap();
+                let bits = primitive.bit_width();
+                let (min, max) = match primitive.is_signed() {
+                    true => (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1),
+                    false => (0, (1i128 << bits) - 1),
+                };
+
+                // Negating an unsigned literal is reported on its own
+                let limit = if negated && primitive.is_signed() { -min } else { max };
+
+                if value as i128 > limit {
+                    self.error(Diagnostic::error(format!("literal out of range for `{}`", primitive.as_str()), span)
+                                         .with_note(format!("`{}` goes from {} to {}", primitive.as_str(), min, max)));
+                }
+
+                CheckedExpression::Integer(value, type_id, span)
+            },
+            NumericConstant::FloatingPoint(digits, _format, _) => {
+                let value: f64 = match digits.parse() {
+                    Ok(value) => value,
+                    Err(_) => {
+                        self.error(Diagnostic::error("invalid floating point literal", span));
+                        return CheckedExpression::Invalid(span);
+                    }
+                };
+
+                let type_id = match (suffix_type, hint) {
+                    (Some(suffix_type), _) => suffix_type,
+                    (None, Some(hint)) if self.is_float(hint) => hint,
+                    _ => self.builtins.f64,
+                };
+
+                let fits = match self.primitive_of(type_id) {
+                    Some(PrimitiveType::F32) => (value as f32).is_finite(),
+                    _ => value.is_finite(),
+                };
+
+                if !fits {
+                    let type_name = self.type_name(type_id);
+                    self.error(Diagnostic::error(format!("literal out of range for `{}`", type_name), span)
+                                         .with_note(format!("it's too large to be a finite `{}`", type_name)));
+                }
+
+                CheckedExpression::Float(value, type_id, span)
+            },
+        }
+    }
+
+    /// Checks the two operands of a binary operation. When only one of
+    /// them is an untyped literal, the other one is checked first so the
+    /// literal can take on its type.
+    fn check_operands(
+        &self,
+        lhs: &mut ParsedExpression,
+        rhs: &mut ParsedExpression,
+        hint: Option<TypeID>,
+        ctx: &mut ProcContext
+    ) -> (CheckedExpression, CheckedExpression) {
+        if lhs.is_untyped_literal() && !rhs.is_untyped_literal() {
+            let rhs = self.check_expression(rhs, hint, ctx);
+            let rhs_type = self.type_of(&rhs);
+            let lhs = self.check_expression(lhs, Some(rhs_type), ctx);
+            (lhs, rhs)
+        } else {
+            let lhs = self.check_expression(lhs, hint, ctx);
+            let lhs_type = self.type_of(&lhs);
+            let rhs = self.check_expression(rhs, Some(lhs_type), ctx);
+            (lhs, rhs)
+        }
+    }
+
+    fn check_unary_operation(
+        &self,
+        operand: &mut ParsedExpression,
+        op: &mut UnaryOperator,
+        span: Span,
+        hint: Option<TypeID>,
+        ctx: &mut ProcContext
+    ) -> CheckedExpression {
+        if let UnaryOperator::TypeCast(to_type) = op {
+            let to_type_id = self.resolve_type(to_type).unwrap_or(self.builtins.unknown);
+            let operand = self.check_expression(operand, None, ctx);
+            let from_type_id = self.type_of(&operand);
+
+            let is_unknown = to_type_id == self.builtins.unknown || from_type_id == self.builtins.unknown;
+            if !is_unknown && from_type_id != to_type_id && !self.can_cast(from_type_id, to_type_id) {
+                self.error(Diagnostic::error(format!("can't cast `{}` to `{}`", self.type_name(from_type_id), self.type_name(to_type_id)), span));
+            }
+
+            return CheckedExpression::UnaryOperation(Box::new(operand), op.clone(), to_type_id, span);
+        }
+
+        if let UnaryOperator::AddressOf | UnaryOperator::Dereference = op {
+            return self.check_pointer_operation(operand, op, span, ctx);
+        }
+
+        let (symbol, operand_hint) = match op {
+            UnaryOperator::PreIncrement | UnaryOperator::PostIncrement => ("++", None),
+            UnaryOperator::PreDecrement | UnaryOperator::PostDecrement => ("--", None),
+            UnaryOperator::Negate => ("-", hint),
+            UnaryOperator::LogicalNot => ("!", Some(self.builtins.bool)),
+            UnaryOperator::BitwiseNot => ("~", hint),
+            UnaryOperator::AddressOf | UnaryOperator::Dereference | UnaryOperator::TypeCast(_) => unreachable!(),
+        };
+
+        let operand = match (&*op, &*operand) {
+            (UnaryOperator::Negate, ParsedExpression::NumericConstant(constant, literal_span)) => self.check_numeric_constant(constant, *literal_span, operand_hint, true),
+            _ => self.check_expression(operand, operand_hint, ctx),
+        };
+        let type_id = self.type_of(&operand);
+
+        if type_id == self.builtins.unknown {
+            return CheckedExpression::UnaryOperation(Box::new(operand), op.clone(), type_id, span);
+        }
+
+        let is_valid = match op {
+            UnaryOperator::PreIncrement  |
+            UnaryOperator::PostIncrement |
+            UnaryOperator::PreDecrement  |
+            UnaryOperator::PostDecrement => {
+                if !Self::is_place(&operand) {
+                    self.error(Diagnostic::error(format!("`{}` can only be applied to a variable or an element of one", symbol), span));
+                }
+                self.check_not_param(&operand, ctx);
+                self.is_numeric(type_id) || self.is_sized_pointer(type_id)
+            },
+            UnaryOperator::Negate => self.primitive_of(type_id).is_some_and(|primitive| primitive.is_numeric() && primitive.is_signed()),
+            UnaryOperator::LogicalNot => type_id == self.builtins.bool,
+            UnaryOperator::BitwiseNot => self.is_integer(type_id),
+            _ => unreachable!(),
+        };
+
+        if !is_valid {
+            self.error(Diagnostic::error(format!("cannot apply unary operator `{}` to type `{}`", symbol, self.type_name(type_id)), span));
+        }
+
+        CheckedExpression::UnaryOperation(Box::new(operand), op.clone(), type_id, span)
+    }
+
+    /// Checks `&place` and `<pointer`.
+    fn check_pointer_operation(&self, operand: &mut ParsedExpression, op: &UnaryOperator, span: Span, ctx: &mut ProcContext) -> CheckedExpression {
+        let operand = self.check_expression(operand, None, ctx);
+        let operand_type = self.type_of(&operand);
+
+        let type_id = match op {
+            _ if operand_type == self.builtins.unknown => operand_type,
+            UnaryOperator::AddressOf if Self::is_place(&operand) => self.pointer_to(operand_type),
+            UnaryOperator::AddressOf => {
+                self.error(Diagnostic::error("can't take the address of a temporary value", operand.span())
+                                     .with_note("only variables, elements of arrays, members of structs and what pointers point at have an address"));
+                self.builtins.unknown
+            },
+            _ => match self.pointee_of(operand_type) {
+                Some(pointee_id) if pointee_id == self.builtins.nothing => {
+                    self.error(Diagnostic::error("can't dereference a `*nothing`", operand.span())
+                                         .with_note("cast it to a pointer to what it points at first, like `ptr as *u8`"));
+                    self.builtins.unknown
+                },
+                Some(pointee_id) => pointee_id,
+                None => {
+                    self.error(Diagnostic::error(format!("can't dereference a value of type `{}`", self.type_name(operand_type)), operand.span())
+                                         .with_note("only pointers can be dereferenced"));
+                    self.builtins.unknown
+                },
+            },
+        };
+
+        CheckedExpression::UnaryOperation(Box::new(operand), op.clone(), type_id, span)
+    }
+
+    /// Scalars convert to each other, and pointers convert to other
+    /// pointers and to and from integers.
+    fn can_cast(&self, from: TypeID, to: TypeID) -> bool {
+        let is_pointer = |type_id| self.pointee_of(type_id).is_some();
+        let is_address = |type_id| is_pointer(type_id) || self.is_integer(type_id);
+
+        (self.is_scalar(from) && self.is_scalar(to)) || ((is_pointer(from) || is_pointer(to)) && is_address(from) && is_address(to))
+    }
+
+    fn check_binary_operation(
+        &self,
+        lhs: &mut ParsedExpression,
+        op: &BinaryOperator,
+        rhs: &mut ParsedExpression,
+        span: Span,
+        hint: Option<TypeID>,
+        ctx: &mut ProcContext
+    ) -> CheckedExpression {
+        if op.is_assignment() {
+            let lhs = self.check_expression(lhs, None, ctx);
+            let lhs_type = self.type_of(&lhs);
+            let mut checked_rhs = self.check_expression(rhs, Some(lhs_type), ctx);
+
+            if op.without_assignment().is_some_and(|bin_op| bin_op.is_arithmetic()) && self.is_float(lhs_type) && self.is_integer(self.type_of(&checked_rhs)) {
+                checked_rhs = Self::convert(rhs, checked_rhs, lhs_type);
+            }
+            let rhs = checked_rhs;
+
+            if !Self::is_place(&lhs) && !matches!(lhs, CheckedExpression::Invalid(_)) {
+                self.error(Diagnostic::error("can't assign to this expression", lhs.span())
+                                     .with_label(span, "in this assignment"));
+            }
+            self.check_not_param(&lhs, ctx);
+
+            match op.without_assignment() {
+                Some(bin_op) => {
+                    // Only matters for pointers, where `a - b` gives an `i64`
+                    let result_type = self.binary_operation_type(&lhs, &bin_op, &rhs, span);
+                    if result_type != lhs_type && result_type != self.builtins.unknown && lhs_type != self.builtins.unknown {
+                        self.error(Diagnostic::error("mismatched types", span)
+                                             .with_primary_label(format!("this gives a `{}`, which can't be stored in a `{}`", self.type_name(result_type), self.type_name(lhs_type))));
+                    }
+                },
+                None => self.expect_type(&rhs, lhs_type),
+            }
+
+            return CheckedExpression::BinaryOperation(Box::new(lhs), op.clone(), Box::new(rhs), self.builtins.nothing, span);
+        }
+
+        let operand_hint = match op {
+            BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr | BinaryOperator::LogicalXOR => Some(self.builtins.bool),
+            BinaryOperator::NEQ | BinaryOperator::EQ |
+            BinaryOperator::LT  | BinaryOperator::GT |
+            BinaryOperator::LEQ | BinaryOperator::GEQ => None,
+            _ => hint,
+        };
+
+        let (mut checked_lhs, mut checked_rhs) = self.check_operands(lhs, rhs, operand_hint, ctx);
+
+        if op.is_arithmetic() {
+            let (lhs_type, rhs_type) = (self.type_of(&checked_lhs), self.type_of(&checked_rhs));
+
+            if self.is_integer(lhs_type) && self.is_float(rhs_type) {
+                checked_lhs = Self::convert(lhs, checked_lhs, rhs_type);
+            } else if self.is_float(lhs_type) && self.is_integer(rhs_type) {
+                checked_rhs = Self::convert(rhs, checked_rhs, lhs_type);
+            }
+        }
+
+        let type_id = self.binary_operation_type(&checked_lhs, op, &checked_rhs, span);
+
+        CheckedExpression::BinaryOperation(Box::new(checked_lhs), op.clone(), Box::new(checked_rhs), type_id, span)
+    }
+
+    /// Converts an integer operand of arithmetic to the float type `to` of
+    /// the other operand, as if it had been written `operand as to`. The
+    /// parsed operand is rewritten too, for the interpreter.
+    fn convert(parsed: &mut ParsedExpression, checked: CheckedExpression, to: TypeID) -> CheckedExpression {
+        let span = checked.span();
+        let cast = UnaryOperator::TypeCast(Box::new(ParsedType::CheckedType(to)));
+
+        let operand = std::mem::replace(parsed, ParsedExpression::Invalid);
+        *parsed = ParsedExpression::UnaryOperation(Box::new(operand), cast.clone(), span);
+
+        CheckedExpression::UnaryOperation(Box::new(checked), cast, to, span)
+    }
+
+    /// Works out what `lhs op rhs` evaluates to, reporting the operation
+    /// if it can't be applied to the operands.
+    fn binary_operation_type(&self, lhs: &CheckedExpression, op: &BinaryOperator, rhs: &CheckedExpression, span: Span) -> TypeID {
+        let lhs_type = self.type_of(lhs);
+        let rhs_type = self.type_of(rhs);
+
+        if self.pointee_of(lhs_type).is_some() {
+            return self.pointer_operation_type(lhs, op, rhs, span);
+        }
+
+        let (result_type, is_valid) = match op {
+            BinaryOperator::Add      |
+            BinaryOperator::Subtract |
+            BinaryOperator::Multiply |
+            BinaryOperator::Divide   |
+            BinaryOperator::Modulo => (lhs_type, self.is_numeric(lhs_type)),
+
+            BinaryOperator::BitwiseAnd       |
+            BinaryOperator::BitwiseOr        |
+            BinaryOperator::BitwiseXOR       |
+            BinaryOperator::BitwiseLeftShift |
+            BinaryOperator::BitwiseRightShift => (lhs_type, self.is_integer(lhs_type)),
+
+            BinaryOperator::LogicalAnd |
+            BinaryOperator::LogicalOr  |
+            BinaryOperator::LogicalXOR => (self.builtins.bool, lhs_type == self.builtins.bool),
+
+            BinaryOperator::EQ |
+            BinaryOperator::NEQ => (self.builtins.bool, self.is_scalar(lhs_type)),
+
+            BinaryOperator::LT  |
+            BinaryOperator::GT  |
+            BinaryOperator::LEQ |
+            BinaryOperator::GEQ => (self.builtins.bool, self.is_numeric(lhs_type) || lhs_type == self.builtins.char),
+
+            _ => unreachable!("{:?} is not a binary operation", op),
+        };
+
+        if lhs_type == self.builtins.unknown || rhs_type == self.builtins.unknown {
+            return result_type;
+        }
+
+        if lhs_type != rhs_type {
+            self.error(Diagnostic::error(format!("mismatched types for `{}`", op.as_str()), span)
+                                 .with_label(lhs.span(), format!("this is `{}`", self.type_name(lhs_type)))
+                                 .with_label(rhs.span(), format!("this is `{}`", self.type_name(rhs_type)))
+                                 .with_note("use `as` to convert one of them"));
+        } else if !is_valid {
+            self.error(Diagnostic::error(format!("cannot apply `{}` to `{}`", op.as_str(), self.type_name(lhs_type)), span));
+        }
+
+        result_type
+    }
+
+    /// Pointers can be offset by an integer, subtracted from each other to
+    /// get how many elements apart they are, and compared.
+    fn pointer_operation_type(&self, lhs: &CheckedExpression, op: &BinaryOperator, rhs: &CheckedExpression, span: Span) -> TypeID {
+        let lhs_type = self.type_of(lhs);
+        let rhs_type = self.type_of(rhs);
+
+        let (result_type, is_valid) = match op {
+            BinaryOperator::Add | BinaryOperator::Subtract if self.is_integer(rhs_type) => (lhs_type, true),
+            BinaryOperator::Subtract => (self.builtins.i64, rhs_type == lhs_type),
+            BinaryOperator::EQ  | BinaryOperator::NEQ |
+            BinaryOperator::LT  | BinaryOperator::GT  |
+            BinaryOperator::LEQ | BinaryOperator::GEQ => (self.builtins.bool, rhs_type == lhs_type),
+            _ => (self.builtins.unknown, false),
+        };
+
+        if rhs_type == self.builtins.unknown {
+            return result_type;
+        }
+
+        if !is_valid {
+            let mut diagnostic = Diagnostic::error(format!("cannot apply `{}` to `{}` and `{}`", op.as_str(), self.type_name(lhs_type), self.type_name(rhs_type)), span);
+            if matches!(op, BinaryOperator::Add | BinaryOperator::Subtract) {
+                diagnostic = diagnostic.with_note("a pointer can be offset by an integer, or subtracted from a pointer of the same type");
+            }
+
+            self.error(diagnostic);
+        } else if matches!(op, BinaryOperator::Add | BinaryOperator::Subtract) && !self.is_sized_pointer(lhs_type) {
+            self.error(Diagnostic::error(format!("cannot apply `{}` to `*nothing`", op.as_str()), span)
+                                 .with_note("what it points at has no size, so cast it to a pointer to that first"));
+        }
+
+        result_type
+    }
+
+    fn check_proc_call(&self, call: &mut ParsedProcCall, ctx: &mut ProcContext) -> CheckedExpression {
+        let qualified = match call.namespace.as_slice() {
+            [] => self.scope.qualify(&[&call.name]),
+            [module] if self.scope.is_import(module) => format!("{}::{}", module, call.name),
+            _ => {
+                if let Some(enum_id) = self.namespaced_enum(&mut call.namespace) {
+                    return self.check_enum_value(enum_id, &call.name, Some(&mut call.passed_parameters), call.span, ctx);
+                }
+
+                // Procs declared inside of a struct are named after it
+                match self.namespaced_struct(&call.namespace) {
+                    Some(struct_name) => format!("{}::{}", struct_name, call.name),
+                    None => {
+                        self.error(Self::unknown_namespace(&call.namespace, call.span));
+
+                        for arg in &mut call.passed_parameters {
+                            self.check_expression(&mut arg.defualt_value, None, ctx);
+                        }
+
+                        return CheckedExpression::Invalid(call.span);
+                    }
+                }
+            }
+        };
+
+        let Some(signature) = self.procs.get(&qualified) else {
+            let written = call.namespace.iter().chain(std::iter::once(&call.name)).cloned().collect::<Vec<_>>().join("::");
+            let mut diagnostic = Diagnostic::error(format!("cannot find proc `{}`", written), call.span);
+
+            if call.namespace.is_empty() && Intrinsic::from_name(&call.name).is_some() {
+                diagnostic = diagnostic.with_note(format!("`{}` is in the standard library, `#import \"core/io\"` and call it as `io::{}`", call.name, call.name));
+            }
+
+            self.error(diagnostic);
+
+            // Still check the arguments, they might have mistakes of their own
+            for arg in &mut call.passed_parameters {
+                self.check_expression(&mut arg.defualt_value, None, ctx);
+            }
+
+            return CheckedExpression::Invalid(call.span);
+        };
+
+        self.check_call(call, qualified, signature, None, ctx)
+    }
+
+    /// Checks the arguments of a call to the proc `qualified`. A method's
+    /// receiver has already been checked, and is passed as the first one.
+    fn check_call(
+        &self,
+        call: &mut ParsedProcCall,
+        qualified: String,
+        signature: &ProcSignature,
+        receiver: Option<CheckedExpression>,
+        ctx: &mut ProcContext
+    ) -> CheckedExpression {
+        // The call goes by the proc's full name from here on
+        call.namespace.clear();
+        call.name = qualified;
+
+        let mut args: Vec<Option<CheckedExpression>> = vec![None; signature.params.len()];
+        let mut extra_args = Vec::new();
+
+        if receiver.is_some() {
+            args[0] = receiver;
+        }
+
+        for arg in &mut call.passed_parameters {
+            if arg.name.is_empty() {
+                if signature.intrinsic.is_some() && signature.variadic {
+                    // What the format needs from them is checked once it's parsed
+                    extra_args.push(self.check_expression(&mut arg.defualt_value, None, ctx));
+                } else if signature.variadic {
+                    extra_args.push(self.check_variadic_arg(&mut arg.defualt_value, ctx));
+                } else {
+                    self.error(Diagnostic::error("arguments need a label, like `name: value`", arg.span)
+                                         .with_note(format!("only the extra arguments of a proc that takes `..` go without one, and `{}` doesn't", call.name)));
+                    self.check_expression(&mut arg.defualt_value, None, ctx);
+                }
+                continue;
+            }
+
+            let Some(idx) = signature.params.iter().position(|(name, _)| *name == arg.name) else {
+                self.error(Diagnostic::error(format!("`{}` has no parameter named `{}`", call.name, arg.name), arg.span)
+                                     .with_label(signature.span, "declared here"));
+                self.check_expression(&mut arg.defualt_value, None, ctx);
+                continue;
+            };
+
+            let param_type = signature.params[idx].1;
+            let value = self.check_expression(&mut arg.defualt_value, Some(param_type), ctx);
+            self.expect_type(&value, param_type);
+
+            if let Some(previous) = &args[idx] {
+                self.error(Diagnostic::error(format!("`{}` is passed more than once", arg.name), arg.span)
+                                     .with_label(previous.span(), "first passed here"));
+                continue;
+            }
+
+            args[idx] = Some(value);
+        }
+
+        let missing: Vec<&str> = signature.params.iter()
+                                                 .zip(&args)
+                                                 .filter(|(_, arg)| arg.is_none())
+                                                 .map(|((name, _), _)| name.as_str())
+                                                 .collect();
+
+        if !missing.is_empty() {
+            let plural = if missing.len() == 1 { "" } else { "s" };
+            let missing = missing.iter().map(|name| format!("`{}`", name)).collect::<Vec<_>>().join(", ");
+            self.error(Diagnostic::error(format!("missing argument{} for {} in call to `{}`", plural, missing, call.name), call.span)
+                                 .with_label(signature.span, "declared here"));
+        }
+
+        let args: Vec<CheckedExpression> = args.into_iter()
+                                               .map(|arg| arg.unwrap_or(CheckedExpression::Invalid(call.span)))
+                                               .chain(extra_args)
+                                               .collect();
+
+        if let Some(intrinsic) = signature.intrinsic {
+            return self.check_intrinsic_call(intrinsic, args, signature.return_type, call.span);
+        }
+
+        CheckedExpression::ProcCall(CheckedProcCall { name: call.name.clone(), args }, signature.return_type, call.span)
+    }
+
+    /// Checks the format given to `print` or `println` against the
+    /// arguments after it, if the intrinsic takes one.
+    fn check_intrinsic_call(&self, intrinsic: Intrinsic, mut args: Vec<CheckedExpression>, return_type: TypeID, span: Span) -> CheckedExpression {
+        if !intrinsic.takes_format() {
+            let call = CheckedIntrinsicCall { intrinsic, format: Vec::new(), args };
+            return CheckedExpression::IntrinsicCall(call, return_type, span);
+        }
+
+        let values = args.split_off(1);
+        let mut format = match &args[0] {
+            CheckedExpression::StringLiteral(format, format_span) => match intrinsics::parse_format(format) {
+                Ok(format) => {
+                    self.check_format_args(intrinsic, &format, &values, *format_span, span);
+                    format
+                },
+                Err(message) => {
+                    self.error(Diagnostic::error(message, *format_span)
+                                         .with_note("the verbs are `%v`, `%d`, `%x`, `%f`, `%.2f`, `%s`, `%c` and `%b`, and `%%` is a `%`"));
+                    Vec::new()
+                },
+            },
+            CheckedExpression::Invalid(_) => Vec::new(),
+            format => {
+                self.error(Diagnostic::error(format!("the format of `{}` has to be a string literal", intrinsic.name()), format.span())
+                                     .with_note("it's checked against the arguments while compiling"));
+                Vec::new()
+            },
+        };
+
+        if intrinsic == Intrinsic::Println {
+            match format.last_mut() {
+                Some(FormatPiece::Text(text)) => text.push('\n'),
+                _ => format.push(FormatPiece::Text(String::from("\n"))),
+            }
+        }
+
+        CheckedExpression::IntrinsicCall(CheckedIntrinsicCall { intrinsic, format, args: values }, return_type, span)
+    }
+
+    fn check_format_args(&self, intrinsic: Intrinsic, format: &[FormatPiece], values: &[CheckedExpression], format_span: Span, span: Span) {
+        let verbs: Vec<_> = format.iter()
+                                  .filter_map(|piece| match piece {
+                                      FormatPiece::Verb(verb) => Some(verb),
+                                      FormatPiece::Text(_) => None,
+                                  })
+                                  .collect();
+
+        if verbs.len() != values.len() {
+            let plural = |count: usize, word: &str| format!("{} {}{}", count, word, if count == 1 { "" } else { "s" });
+            self.error(Diagnostic::error(format!("the format has {}, but `{}` was given {}", plural(verbs.len(), "verb"), intrinsic.name(), plural(values.len(), "argument")), span)
+                                 .with_label(format_span, "the format is here"));
+        }
+
+        for (verb, value) in verbs.iter().zip(values) {
+            let type_id = self.type_of(value);
+            if type_id == self.builtins.unknown {
+                continue;
+            }
+
+            let accepted = self.primitive_of(type_id).is_some_and(|primitive| verb.accepts(&primitive));
+            if !accepted {
+                self.error(Diagnostic::error(format!("`{}` needs {}, but this is a `{}`", verb.as_str(), verb.expects(), self.type_name(type_id)), value.span())
+                                     .with_label(format_span, format!("`{}` is in this format", verb.as_str())));
+            }
+        }
+    }
+
+    /// Checks an extra argument to a variadic foreign proc, which C gets
+    /// no type for. Like in C, an integer literal is an `i32`, anything
+    /// smaller than an `i32` is promoted to one and an `f32` is promoted to
+    /// an `f64`.
+    fn check_variadic_arg(&self, arg: &mut ParsedExpression, ctx: &mut ProcContext) -> CheckedExpression {
+        let value = self.check_expression(arg, Some(self.builtins.i32), ctx);
+        let type_id = self.type_of(&value);
+        let span = value.span();
+
+        let Some(primitive) = self.primitive_of(type_id) else {
+            if type_id != self.builtins.unknown && self.pointee_of(type_id).is_none() {
+                self.error(Diagnostic::error(format!("a `{}` can't be passed to a foreign proc", self.type_name(type_id)), span)
+                                     .with_note("only pointers and primitive types other than `nothing` have a C equivalent"));
+            }
+            return value;
+        };
+
+        let promoted_type = match primitive {
+            PrimitiveType::Nothing => {
+                self.error(Diagnostic::error("a `nothing` can't be passed to a foreign proc", span));
+                return value;
+            },
+            PrimitiveType::Bool | PrimitiveType::I8 | PrimitiveType::U8 | PrimitiveType::I16 | PrimitiveType::U16 => "i32",
+            PrimitiveType::F32 => "f64",
+            _ => return value,
+        };
+
+        let to_type = ParsedType::Name(Vec::new(), String::from(promoted_type), span);
+        let promoted_id = self.get_type_id(promoted_type).unwrap();
+        CheckedExpression::UnaryOperation(Box::new(value), UnaryOperator::TypeCast(Box::new(to_type)), promoted_id, span)
+    }
+
+    /// The enum that `namespace` names, if it names one. An enum declared
+    /// in this module goes by its own name, one from an imported module by
+    /// `module::Enum`. The namespace is replaced with the enum's full name,
+    /// so it can be found again without knowing which module it was in.
+    fn namespaced_enum(&self, namespace: &mut Vec<String>) -> Option<TypeID> {
+        let path: Vec<&str> = namespace.iter().map(String::as_str).collect();
+        let type_id = self.get_type_id(&self.scope.qualify(&path)).ok()?;
+
+        if !matches!(self.type_info(type_id), TypeInfo::Enum(_, _)) {
+            return None;
+        }
+
+        *namespace = self.type_name(type_id).split("::").map(String::from).collect();
+        Some(type_id)
+    }
+
+    /// The full name of the struct `namespace` names, if it's one.
+    fn namespaced_struct(&self, namespace: &[String]) -> Option<String> {
+        let path: Vec<&str> = namespace.iter().map(String::as_str).collect();
+        let type_id = self.get_type_id(&self.scope.qualify(&path)).ok()?;
+
+        match self.type_info(type_id) {
+            TypeInfo::Struct(name, _) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn unknown_namespace(namespace: &[String], span: Span) -> Diagnostic {
+        Diagnostic::error(format!("`{}` isn't an enum, a struct or an imported module", namespace.join("::")), span)
+    }
+
+    fn variant_of(&self, enum_id: TypeID, name: &str) -> Option<TypeID> {
+        let TypeInfo::Enum(enum_name, variants) = &self.type_info(enum_id) else { return None };
+        let variant_name = format!("{}::{}", enum_name, name);
+
+        variants.iter().copied().find(|id| self.type_name(*id) == variant_name)
+    }
+
+    /// Checks `Enum::Variant`, or `Enum::Variant(...)` when there are `args`.
+    /// Unlabeled payloads are passed in order, labeled ones by name.
+    fn check_enum_value(
+        &self,
+        enum_id: TypeID,
+        name: &str,
+        args: Option<&mut Vec<ParsedVarDecl>>,
+        span: Span,
+        ctx: &mut ProcContext
+    ) -> CheckedExpression {
+        let Some(variant_id) = self.variant_of(enum_id, name) else {
+            self.error(Diagnostic::error(format!("`{}` has no variant named `{}`", self.type_name(enum_id), name), span));
+
+            for arg in args.into_iter().flatten() {
+                self.check_expression(&mut arg.defualt_value, None, ctx);
+            }
+
+            return CheckedExpression::Invalid(span);
+        };
+
+        let TypeInfo::EnumVariant(variant_name, payload) = &self.type_info(variant_id) else {
+            unreachable!("Enums only hold variants");
+        };
+
+        let Some(args) = args else {
+            if !payload.is_empty() {
+                let types = payload.iter().map(|(_, type_id)| format!("`{}`", self.type_name(*type_id))).collect::<Vec<_>>().join(", ");
+                self.error(Diagnostic::error(format!("`{}` needs a payload", variant_name), span)
+                                     .with_note(format!("it holds {}", types)));
+                return CheckedExpression::Invalid(span);
+            }
+
+            return CheckedExpression::EnumValue(variant_id, Vec::new(), enum_id, span);
+        };
+
+        // Unlabeled payload fields are named after their position
+        let is_labeled = payload.first().is_some_and(|(field_name, _)| field_name.parse::<usize>().is_err());
+
+        if payload.is_empty() && !args.is_empty() {
+            self.error(Diagnostic::error(format!("`{}` doesn't have a payload", variant_name), span));
+        } else if !is_labeled && args.len() != payload.len() {
+            let plural = if payload.len() == 1 { "" } else { "s" };
+            let given = if args.len() == 1 { "was" } else { "were" };
+            self.error(Diagnostic::error(format!("`{}` holds {} value{}, but {} {} given", variant_name, payload.len(), plural, args.len(), given), span));
+        }
+
+        let mut values: Vec<Option<CheckedExpression>> = vec![None; payload.len()];
+        // Labels that are missing or shouldn't be there are only reported
+        // for the first value, and leave the fields unaccounted for
+        let mut mislabeled = false;
+
+        for (position, arg) in args.iter_mut().enumerate() {
+            let idx = match (is_labeled, arg.name.is_empty()) {
+                (true, true) => {
+                    if !mislabeled {
+                        let fields = payload.iter().map(|(field_name, _)| format!("`{}`", field_name)).collect::<Vec<_>>().join(", ");
+                        self.error(Diagnostic::error(format!("the payload of `{}` needs labels, like `name: value`", variant_name), arg.span)
+                                             .with_note(format!("its fields are {}", fields)));
+                        mislabeled = true;
+                    }
+                    None
+                },
+                (true, false) => {
+                    let idx = payload.iter().position(|(field_name, _)| *field_name == arg.name);
+                    if idx.is_none() {
+                        self.error(Diagnostic::error(format!("`{}` has no field named `{}`", variant_name, arg.name), arg.span));
+                    }
+                    idx
+                },
+                (false, false) => {
+                    if !mislabeled {
+                        self.error(Diagnostic::error(format!("the payload of `{}` isn't labeled", variant_name), arg.span));
+                        mislabeled = true;
+                    }
+                    None
+                },
+                (false, true) => Some(position).filter(|idx| *idx < payload.len()),
+            };
+
+            let Some(idx) = idx else {
+                self.check_expression(&mut arg.defualt_value, None, ctx);
+                continue;
+            };
+
+            let field_type = payload[idx].1;
+            let value = self.check_expression(&mut arg.defualt_value, Some(field_type), ctx);
+            self.expect_type(&value, field_type);
+
+            if let Some(previous) = &values[idx] {
+                self.error(Diagnostic::error(format!("`{}` is passed more than once", arg.name), arg.span)
+                                     .with_label(previous.span(), "first passed here"));
+                continue;
+            }
+
+            values[idx] = Some(value);
+        }
+
+        if is_labeled && !mislabeled {
+            let missing: Vec<String> = payload.iter()
+                                              .zip(&values)
+                                              .filter(|(_, value)| value.is_none())
+                                              .map(|((field_name, _), _)| format!("`{}`", field_name))
+                                              .collect();
+
+            if !missing.is_empty() {
+                let plural = if missing.len() == 1 { "" } else { "s" };
+                self.error(Diagnostic::error(format!("missing field{} {} in payload of `{}`", plural, missing.join(", "), variant_name), span));
+            }
+        }
+
+        let values = values.into_iter()
+                           .map(|value| value.unwrap_or(CheckedExpression::Invalid(span)))
+                           .collect();
+
+        CheckedExpression::EnumValue(variant_id, values, enum_id, span)
+    }
+
+    /// Checks a `match`. Every arm has to yield the same type, apart from
+    /// blocks that always return. Enums need an arm for every variant and
+    /// anything else needs an `else` arm, unless it's a `bool` with both
+    /// values matched. Arms that can never run are warned about.
+    fn check_match(
+        &self,
+        matched: &mut ParsedExpression,
+        cases: &mut [MatchExprCase],
+        span: Span,
+        hint: Option<TypeID>,
+        ctx: &mut ProcContext
+    ) -> CheckedExpression {
+        let matched = self.check_expression(matched, None, ctx);
+        let matched_type = self.type_of(&matched);
+        let is_enum = matches!(self.type_info(matched_type), TypeInfo::Enum(_, _));
+
+        if !is_enum && !self.is_scalar(matched_type) && matched_type != self.builtins.unknown {
+            self.error(Diagnostic::error(format!("can't match on a value of type `{}`", self.type_name(matched_type)), matched.span())
+                                 .with_note("only enums, numbers, `bool` and `char` can be matched on"));
+        }
+
+        let mut result_type: Option<TypeID> = None;
+        let mut checked_cases = Vec::new();
+
+        // Where each variant, or literal value, was first matched
+        let mut covered_variants: HashMap<TypeID, Span> = HashMap::new();
+        let mut covered_values: HashMap<u64, Span> = HashMap::new();
+        let mut fallback: Option<Span> = None;
+
+        // Which case each checked arm came from, since arms that fail to
+        // check are left out
+        let mut checked_from = Vec::new();
+
+        for (case_idx, case) in cases.iter_mut().enumerate() {
+            let case_span = match case {
+                MatchExprCase::Expr(case_expr, _) => case_expr.span(),
+                MatchExprCase::EnumVariant(pattern, _) => pattern.span,
+                MatchExprCase::Fallback(span, _) => *span,
+            };
+
+            let mut already_covered = fallback.map(|fallback| (fallback, "everything left is matched by this arm"));
+
+            // Payload bindings only live as long as the arm
+            ctx.scopes.push(HashMap::new());
+
+            let checked_case = match case {
+                MatchExprCase::Expr(case_expr, body) => {
+                    let case_expr = self.check_expression(case_expr, Some(matched_type), ctx);
+
+                    if is_enum {
+                        self.error(Diagnostic::error(format!("values of `{}` can't be compared", self.type_name(matched_type)), case_expr.span())
+                                             .with_note(format!("match on its variants instead, like `{}::Variant`", self.type_name(matched_type))));
+                    } else {
+                        self.expect_type(&case_expr, matched_type);
+                    }
+
+                    if let Some(value) = Self::literal_bits(&case_expr) {
+                        if let Some(first) = covered_values.get(&value) {
+                            already_covered = already_covered.or(Some((*first, "already matched here")));
+                        } else {
+                            covered_values.insert(value, case_span);
+                        }
+                    }
+
+                    let body = self.check_match_body(body, case_span, &mut result_type, hint, ctx);
+                    Some(CheckedMatchCase::Expr(case_expr, body))
+                },
+                MatchExprCase::EnumVariant(pattern, body) => {
+                    let variant = self.check_variant_pattern(pattern, matched_type, ctx);
+
+                    if let Some((variant_id, _)) = &variant {
+                        if let Some(first) = covered_variants.get(variant_id) {
+                            already_covered = already_covered.or(Some((*first, "already matched here")));
+                        } else {
+                            covered_variants.insert(*variant_id, case_span);
+                        }
+                    }
+
+                    let body = self.check_match_body(body, case_span, &mut result_type, hint, ctx);
+                    variant.map(|(variant_id, bindings)| CheckedMatchCase::EnumVariant(variant_id, bindings, body))
+                },
+                MatchExprCase::Fallback(span, body) => {
+                    let every_variant_covered = match &self.type_info(matched_type) {
+                        TypeInfo::Enum(_, variants) => variants.iter().all(|variant| covered_variants.contains_key(variant)),
+                        _ => matched_type == self.builtins.bool && covered_values.len() == 2,
+                    };
+
+                    if fallback.is_none() && every_variant_covered {
+                        self.error(Diagnostic::warning("unreachable match arm", *span)
+                                             .with_note(format!("every value of `{}` is already matched", self.type_name(matched_type))));
+                    }
+
+                    fallback.get_or_insert(*span);
+
+                    let body = self.check_match_body(body, case_span, &mut result_type, hint, ctx);
+                    Some(CheckedMatchCase::Fallback(body))
+                },
+            };
+
+            ctx.scopes.pop();
+
+            if let Some((covered_at, label)) = already_covered {
+                self.error(Diagnostic::warning("unreachable match arm", case_span)
+                                     .with_label(covered_at, label));
+            }
+
+            if let Some(checked_case) = checked_case {
+                checked_cases.push(checked_case);
+                checked_from.push(case_idx);
+            }
+        }
+
+        // Arms that are just a number take on the type of the other arms,
+        // the same way literals do on either side of an operator
+        for (checked_case, case_idx) in checked_cases.iter_mut().zip(checked_from) {
+            let body = match &mut cases[case_idx] {
+                MatchExprCase::Expr(_, body)        |
+                MatchExprCase::EnumVariant(_, body) |
+                MatchExprCase::Fallback(_, body) => body,
+            };
+
+            self.check_untyped_body(body, checked_case.body_mut(), &mut result_type, ctx);
+        }
+
+        if fallback.is_none() {
+            match &self.type_info(matched_type) {
+                TypeInfo::Enum(_, variants) => {
+                    let missing: Vec<String> = variants.iter()
+                                                       .filter(|variant| !covered_variants.contains_key(variant))
+                                                       .map(|variant| format!("`{}`", self.type_name(*variant)))
+                                                       .collect();
+
+                    if !missing.is_empty() {
+                        self.error(Diagnostic::error(format!("`match` doesn't cover {}", missing.join(", ")), span)
+                                             .with_note("add an arm for each of them, or an `else` arm"));
+                    }
+                },
+                _ if matched_type == self.builtins.bool && covered_values.len() == 2 => { },
+                _ if self.is_scalar(matched_type) => {
+                    self.error(Diagnostic::error(format!("`match` on `{}` values needs an `else` arm", self.type_name(matched_type)), span)
+                                         .with_note("it doesn't cover every possible value otherwise"));
+                },
+                _ => { },
+            }
+        }
+
+        let result_type = result_type.unwrap_or(self.builtins.nothing);
+        CheckedExpression::Match(Box::new(matched), checked_cases, result_type, span)
+    }
+
+    /// Checks an `if` that's used as a value. Both branches have to yield
+    /// the same type, the same way the arms of a `match` do.
+    fn check_if_expression(
+        &self,
+        cond: &mut ParsedExpression,
+        then_body: &mut MatchExprBody,
+        else_body: &mut MatchExprBody,
+        span: Span,
+        hint: Option<TypeID>,
+        ctx: &mut ProcContext
+    ) -> CheckedExpression {
+        let cond = self.check_condition(cond, ctx);
+
+        let mut result_type: Option<TypeID> = None;
+        let mut checked_then = self.check_match_body(then_body, span, &mut result_type, hint, ctx);
+        let mut checked_else = self.check_match_body(else_body, span, &mut result_type, hint, ctx);
+
+        self.check_untyped_body(then_body, &mut checked_then, &mut result_type, ctx);
+        self.check_untyped_body(else_body, &mut checked_else, &mut result_type, ctx);
+
+        let result_type = result_type.unwrap_or(self.builtins.nothing);
+        CheckedExpression::If(Box::new(cond), Box::new(checked_then), Box::new(checked_else), result_type, span)
+    }
+
+    /// Checks a body that `check_match_body` left for last because it's
+    /// just a number, which takes on the type of the other bodies the same
+    /// way literals do on either side of an operator.
+    fn check_untyped_body(
+        &self,
+        body: &mut MatchExprBody,
+        checked_body: &mut CheckedMatchBody,
+        result_type: &mut Option<TypeID>,
+        ctx: &mut ProcContext
+    ) {
+        let (MatchExprBody::Expr(body_expr), CheckedMatchBody::Expr(checked_body @ CheckedExpression::Invalid(_))) = (body, checked_body) else {
+            return;
+        };
+
+        if !body_expr.is_untyped_literal() {
+            return;
+        }
+
+        *checked_body = self.check_expression(body_expr, *result_type, ctx);
+        self.merge_result_type(checked_body, result_type);
+    }
+
+    /// Makes the type of an arm's value the type of the whole `match` or
+    /// `if` if it's the first, or checks it against the one before. After
+    /// a mismatch the type is unknown, so it isn't reported again by
+    /// whatever uses the value.
+    fn merge_result_type(&self, body_expr: &CheckedExpression, result_type: &mut Option<TypeID>) {
+        let found = self.type_of(body_expr);
+
+        match *result_type {
+            Some(expected) => {
+                self.expect_type(body_expr, expected);
+
+                if found != expected && found != self.builtins.unknown {
+                    *result_type = Some(self.builtins.unknown);
+                }
+            },
+            None => *result_type = Some(found),
+        }
+    }
+
+    /// The bits of a literal, so arms matching the same value twice can be
+    /// spotted. Only arms of the same type are ever compared.
+    fn literal_bits(expr: &CheckedExpression) -> Option<u64> {
+        match expr {
+            CheckedExpression::Bool(value, _) => Some(*value as u64),
+            CheckedExpression::CharLiteral(value, _) => Some(*value as u64),
+            CheckedExpression::Integer(value, _, _) => Some(*value),
+            CheckedExpression::Float(value, _, _) => Some(value.to_bits()),
+            _ => None,
+        }
+    }
+
+    /// Checks the body of a match arm, or a branch of an `if`, against the
+    /// type the ones before it yielded, or makes it the type they all have
+    /// to yield if it's the first. Untyped literals are left to be checked
+    /// last by `check_untyped_body`.
+    fn check_match_body(
+        &self,
+        body: &mut MatchExprBody,
+        case_span: Span,
+        result_type: &mut Option<TypeID>,
+        hint: Option<TypeID>,
+        ctx: &mut ProcContext
+    ) -> CheckedMatchBody {
+        match body {
+            // Checked once the other arms have decided on a type
+            MatchExprBody::Expr(body_expr) if result_type.is_none() && hint.is_none() && body_expr.is_untyped_literal() => {
+                CheckedMatchBody::Expr(CheckedExpression::Invalid(body_expr.span()))
+            },
+            MatchExprBody::Expr(body_expr) => {
+                let body_expr = self.check_expression(body_expr, result_type.or(hint), ctx);
+                self.merge_result_type(&body_expr, result_type);
+
+                CheckedMatchBody::Expr(body_expr)
+            },
+            MatchExprBody::Block(block) => {
+                let block = self.check_block(block, ctx);
+
+                // A block that always returns, breaks or continues never
+                // yields anything, so it fits in with arms of any type
+                if !block.always_leaves() {
+                    match *result_type {
+                        Some(expected) if expected != self.builtins.nothing && expected != self.builtins.unknown => {
+                            self.error(Diagnostic::error("mismatched types", case_span)
+                                                 .with_primary_label(format!("expected `{}`, but this block yields nothing", self.type_name(expected))));
+                            *result_type = Some(self.builtins.unknown);
+                        },
+                        Some(_) => { },
+                        None => *result_type = Some(self.builtins.nothing),
+                    }
+                }
+
+                CheckedMatchBody::Block(block)
+            },
+        }
+    }
+
+    /// Checks `Enum::Variant(...)` in a match arm against the type being
+    /// matched, and declares the names its payload is bound to. Gives back
+    /// the variant and which payload field goes into which local.
+    fn check_variant_pattern(
+        &self,
+        pattern: &mut ParsedVariantPattern,
+        matched_type: TypeID,
+        ctx: &mut ProcContext
+    ) -> Option<(TypeID, Vec<(usize, VarID)>)> {
+        let Some(enum_id) = self.namespaced_enum(&mut pattern.namespace) else {
+            self.error(Diagnostic::error(format!("`{}` isn't an enum", pattern.namespace.join("::")), pattern.span));
+            return None;
+        };
+
+        if enum_id != matched_type && matched_type != self.builtins.unknown {
+            self.error(Diagnostic::error("mismatched types", pattern.span)
+                                 .with_primary_label(format!("expected `{}`, found `{}`", self.type_name(matched_type), self.type_name(enum_id))));
+            return None;
+        }
+
+        let Some(variant_id) = self.variant_of(enum_id, &pattern.name) else {
+            self.error(Diagnostic::error(format!("`{}` has no variant named `{}`", self.type_name(enum_id), pattern.name), pattern.span));
+            return None;
+        };
+
+        let TypeInfo::EnumVariant(variant_name, payload) = &self.type_info(variant_id) else {
+            unreachable!("Enums only hold variants");
+        };
+
+        let Some(bindings) = &pattern.bindings else {
+            return Some((variant_id, Vec::new()));
+        };
+
+        // Unlabeled payload fields are named after their position
+        let is_labeled = payload.first().is_some_and(|(field_name, _)| field_name.parse::<usize>().is_err());
+
+        if payload.is_empty() && !bindings.is_empty() {
+            self.error(Diagnostic::error(format!("`{}` doesn't have a payload", variant_name), pattern.span));
+        } else if !is_labeled && bindings.len() != payload.len() {
+            let plural = if payload.len() == 1 { "" } else { "s" };
+            let given = if bindings.len() == 1 { "name was" } else { "names were" };
+            self.error(Diagnostic::error(format!("`{}` holds {} value{}, but {} {} given", variant_name, payload.len(), plural, bindings.len(), given), pattern.span)
+                                 .with_note("use `_` for the ones you don't need"));
+        }
+
+        let mut bound: Vec<(usize, VarID)> = Vec::new();
+        let mut bound_at: HashMap<usize, Span> = HashMap::new();
+
+        for (position, binding) in bindings.iter().enumerate() {
+            let idx = match (is_labeled, binding.label.is_empty()) {
+                (true, _) => {
+                    let field = if binding.label.is_empty() { &binding.name } else { &binding.label };
+                    let idx = payload.iter().position(|(field_name, _)| field_name == field);
+                    if idx.is_none() {
+                        self.error(Diagnostic::error(format!("`{}` has no field named `{}`", variant_name, field), binding.span));
+                    }
+                    idx
+                },
+                (false, false) => {
+                    self.error(Diagnostic::error(format!("the payload of `{}` isn't labeled", variant_name), binding.span));
+                    None
+                },
+                (false, true) => Some(position).filter(|idx| *idx < payload.len()),
+            };
+
+            let Some(idx) = idx else { continue };
+
+            if let Some(first) = bound_at.insert(idx, binding.span) {
+                self.error(Diagnostic::error(format!("`{}` is bound more than once", payload[idx].0), binding.span)
+                                     .with_label(first, "first bound here"));
+                continue;
+            }
+
+            if binding.name != "_" {
+                bound.push((idx, ctx.declare(&binding.name, payload[idx].1)));
+            }
+        }
+
+        Some((variant_id, bound))
     }
 }
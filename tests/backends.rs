@@ -0,0 +1,306 @@
+//! Runs the programs in `tests/programs` every way `amds run` can and checks
+//! that the interpreter, the bytecode VM and the built executable agree on
+//! what they print and how they exit.
+
+use std::path::PathBuf;
+use std::process::{ Command, Output };
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Backend {
+    Interpreter,
+    Vm,
+    Executable,
+}
+
+const EVERY_BACKEND: &[Backend] = &[Backend::Interpreter, Backend::Vm, Backend::Executable];
+
+fn program(name: &str) -> PathBuf {
+    PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/programs")).join(name)
+}
+
+fn amds(args: &[&str], inputs: &[PathBuf]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_amds"))
+        .args(args)
+        .args(inputs)
+        .output()
+        .expect("couldn't start amds")
+}
+
+/// What the program printed and the code it exited with. Executables are
+/// built into Cargo's temporary directory and named after the first input.
+fn run(backend: Backend, inputs: &[PathBuf]) -> (String, Option<i32>) {
+    let exe_path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(inputs[0].file_stem().unwrap());
+
+    let args = match backend {
+        Backend::Interpreter => vec!["run", "--interpret"],
+        Backend::Vm => vec!["run", "--vm"],
+        Backend::Executable => vec!["run", "-o", exe_path.to_str().unwrap()],
+    };
+
+    let output = amds(&args, inputs);
+    (String::from_utf8_lossy(&output.stdout).into_owned(), output.status.code())
+}
+
+/// Runs the program with each of `backends` and gives back what they all
+/// printed. Without LLVM there's no executable to compare against.
+fn run_with(backends: &[Backend], name: &str) -> (String, Option<i32>) {
+    let inputs = [program(name)];
+    let mut backends = backends.iter().filter(|backend| cfg!(feature = "llvm") || **backend != Backend::Executable);
+
+    let first = backends.next().unwrap();
+    let expected = run(*first, &inputs);
+
+    for backend in backends {
+        assert_eq!(run(*backend, &inputs), expected, "{} printed something else with {:?} than with {:?}", name, backend, first);
+    }
+
+    expected
+}
+
+#[test]
+fn arithmetic_and_control_flow() {
+    assert_eq!(run_with(EVERY_BACKEND, "arithmetic.amds"), (String::new(), Some(109)));
+}
+
+#[test]
+fn truncated_files_dont_crash_the_compiler() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("truncated");
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let source = std::fs::read_to_string(program("arithmetic.amds")).unwrap();
+    let truncated = dir.join("arithmetic.amds");
+
+    for end in (0..source.len()).filter(|end| source.is_char_boundary(*end)) {
+        std::fs::write(&truncated, &source[..end]).unwrap();
+
+        let output = amds(&["check"], std::slice::from_ref(&truncated));
+        assert!(matches!(output.status.code(), Some(0 | 1)), "cut off after {} bytes: {}", end, String::from_utf8_lossy(&output.stderr));
+    }
+}
+
+#[test]
+fn ranges_stop_at_the_end_of_their_type() {
+    let (stdout, code) = run_with(EVERY_BACKEND, "ranges.amds");
+
+    assert_eq!(code, Some(0));
+    assert_eq!(stdout, "253 254 255 |\n\
+                        254 255 |\n\
+                        |\n\
+                        |\n\
+                        255 |\n\
+                        125 126 127 |\n\
+                        -128 -127 -126 |\n\
+                        |\n\
+                        0 1 3 4 |\n\
+                        1 2 |\n\
+                        |\n");
+}
+
+#[test]
+fn procs_can_have_the_names_of_libc_functions() {
+    assert_eq!(run_with(EVERY_BACKEND, "libc_names.amds"), (String::from("8 3 3 7\n"), Some(0)));
+}
+
+#[test]
+fn bounds_checks_dont_call_a_user_exit() {
+    assert_eq!(run_with(EVERY_BACKEND, "user_exit.amds"), (String::from("user exit\n"), Some(1)));
+}
+
+#[test]
+#[cfg(feature = "llvm")]
+fn foreign_procs_with_the_names_of_libc_functions() {
+    assert_eq!(run(Backend::Executable, &[program("foreign_libc_names.amds")]), (String::from("5\n2\n"), Some(4)));
+}
+
+#[test]
+#[cfg(feature = "llvm")]
+fn inputs_that_import_the_same_module_link_together() {
+    let inputs = [program("multi/main.amds"), program("multi/helper.amds")];
+    assert_eq!(run(Backend::Executable, &inputs), (String::from("3.000000\n"), Some(0)));
+}
+
+#[test]
+#[cfg(feature = "llvm")]
+fn inputs_that_import_different_modules_with_the_same_name() {
+    let inputs = [program("multi_clash/helper.amds"), program("multi_clash/main.amds")];
+    assert_eq!(run(Backend::Executable, &inputs), (String::from("main sees 1\n"), Some(0)));
+}
+
+#[test]
+#[cfg(feature = "llvm")]
+fn a_module_imported_by_different_paths_has_one_symbol() {
+    // The line that defines the imported `util::val`
+    let symbol = |input: &str| {
+        let output = amds(&["emit-llvm"], &[program(input)]);
+        let ir = String::from_utf8_lossy(&output.stdout).into_owned();
+        ir.lines().find(|line| line.starts_with("define linkonce_odr")).map(String::from).unwrap_or(ir)
+    };
+
+    // `x/util` from `main` and `../x/util` from `nested` are the same file
+    assert_eq!(symbol("multi_clash/main.amds"), symbol("multi_clash/nested/other_path.amds"));
+    assert_ne!(symbol("multi_clash/main.amds"), symbol("multi_clash/helper.amds"));
+}
+
+#[test]
+#[cfg(feature = "llvm")]
+fn run_doesnt_leave_the_executable_behind() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("run_in_place");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let input = dir.join("arithmetic.amds");
+    std::fs::copy(program("arithmetic.amds"), &input).unwrap();
+
+    assert_eq!(amds(&["run"], &[input]).status.code(), Some(109));
+
+    let files: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert_eq!(files, ["arithmetic.amds"]);
+}
+
+#[test]
+fn match_arms_that_leave_the_loop() {
+    let (stdout, code) = run_with(EVERY_BACKEND, "match_leaves.amds");
+
+    assert_eq!(code, Some(0));
+    assert_eq!(stdout, "n=1 v=2\nn=2 v=2\nn=3 v=2\ntotal 6\nw=10\nw=20\nw=30\n");
+}
+
+#[test]
+fn pointers() {
+    let (stdout, code) = run_with(EVERY_BACKEND, "pointers/pointers.amds");
+
+    assert_eq!(code, Some(0));
+    assert_eq!(stdout, "4 4\n\
+                        33 50 4\n\
+                        40 -1\n\
+                        20 36\n\
+                        7\n\
+                        1 6\n\
+                        true false true\n\
+                        true true\n");
+}
+
+#[test]
+fn the_interpreter_catches_bad_dereferences() {
+    for (name, message) in [
+        ("pointers/null.amds", "error: dereferenced a null pointer\n"),
+        ("pointers/dangling.amds", "error: dereferenced a pointer to `x`, which went out of scope\n"),
+        ("pointers/past_the_end.amds", "error: dereferenced a pointer to element 3 of an array of length 3\n"),
+    ] {
+        let output = amds(&["run", "--interpret"], &[program(name)]);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        assert_eq!(output.status.code(), Some(1), "{}", name);
+        assert!(stderr.starts_with(message), "{}", stderr);
+    }
+}
+
+#[test]
+fn the_vm_catches_pointers_off_the_stack() {
+    // A pointer past the end of an array still points at a slot on the
+    // stack, so only the interpreter catches that one
+    for (name, message) in [
+        ("pointers/null.amds", "error: dereferenced a null pointer\n"),
+        ("pointers/dangling.amds", "error: dereferenced a pointer to a slot that isn't on the stack\n"),
+    ] {
+        let output = amds(&["run", "--vm"], &[program(name)]);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        assert_eq!(output.status.code(), Some(1), "{}", name);
+        assert!(stderr.starts_with(message), "{}", stderr);
+    }
+}
+
+#[test]
+fn pointer_sized_literals() {
+    assert_eq!(run_with(EVERY_BACKEND, "usize.amds"), (String::from("10 10 9 18446744073709551615\n"), Some(0)));
+}
+
+#[test]
+fn float_remainders_link_against_libm() {
+    assert_eq!(run_with(EVERY_BACKEND, "float_modulo.amds"), (String::from("1.500000\n"), Some(0)));
+}
+
+#[test]
+fn floats_that_dont_fit_saturate() {
+    assert_eq!(run_with(EVERY_BACKEND, "float_casts.amds"), (String::from("255 2147483647 -9223372036854775808 0\n0 255 -3 -128\n"), Some(0)));
+}
+
+#[test]
+fn dividing_by_zero_stops_the_program() {
+    assert_eq!(run_with(EVERY_BACKEND, "divide_by_zero.amds"), (String::from("-2147483648 0\n-3 1\n"), Some(1)));
+}
+
+#[test]
+fn shifting_by_the_width_stops_the_program() {
+    assert_eq!(run_with(EVERY_BACKEND, "overwide_shift.amds"), (String::from("128\n"), Some(1)));
+}
+
+#[test]
+#[cfg(feature = "llvm")]
+fn targets_need_64_bit_pointers() {
+    let obj_path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("i686.o");
+    let output = amds(&["build", "--emit=obj", "--target", "i686-linux-gnu", "-o", obj_path.to_str().unwrap()], &[program("usize.amds")]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "error: the target 'i686-unknown-linux-gnu' has 32-bit pointers, but only targets with 64-bit pointers are supported\n");
+}
+
+#[test]
+fn if_chains_and_if_values() {
+    let (stdout, code) = run_with(EVERY_BACKEND, "if_chains.amds");
+
+    assert_eq!(code, Some(0));
+    assert_eq!(stdout, "negative\n\
+                        zero\n\
+                        small\n\
+                        big\n\
+                        -1 0 1\n\
+                        20\n\
+                        201\n\
+                        200 255\n\
+                        1.000000\n\
+                        4 -1\n");
+}
+
+#[test]
+fn every_kind_of_enum_variant() {
+    let (stdout, code) = run_with(EVERY_BACKEND, "enums.amds");
+
+    assert_eq!(code, Some(0));
+    assert_eq!(stdout, "0\n\
+                        200\n\
+                        1099511627776\n\
+                        -993\n\
+                        130\n\
+                        304\n\
+                        498\n\
+                        x=1 y=2\n");
+}
+
+#[test]
+fn methods_and_members_through_pointers() {
+    let (stdout, code) = run_with(EVERY_BACKEND, "methods.amds");
+
+    assert_eq!(code, Some(0));
+    assert_eq!(stdout, "1 2 3\n\
+                        14\n\
+                        2 4 6\n\
+                        120\n\
+                        -20 -120\n\
+                        5 0 7\n");
+}
+
+#[test]
+fn integers_are_converted_in_float_arithmetic() {
+    assert_eq!(run_with(EVERY_BACKEND, "int_to_float.amds"), (String::from("1.500000 -2.500000\n1.000000\n9.000000\n"), Some(0)));
+}
+
+#[test]
+fn the_reference_program_runs_as_written() {
+    let (stdout, code) = run_with(EVERY_BACKEND, "../../test_files/test.amds");
+
+    assert_eq!(code, Some(0));
+    assert_eq!(stdout, "The value we got from 'some_proc' with param: 10 is 1373346.625000\n\
+                        Using the same param at compile time, we got: 1373346.625000\n");
+}
@@ -0,0 +1,437 @@
+//! Checks what `amds check` reports about programs with mistakes in them,
+//! and how it's rendered.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Writes `source` to a file called `name` and gives back what `amds check`
+/// printed about it. It runs next to the file, so the paths in the output
+/// are just `name`.
+fn check(name: &str, source: &str) -> String {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("diagnostics");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join(name), source).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_amds"))
+        .args(["check", name])
+        .current_dir(&dir)
+        .output()
+        .expect("couldn't start amds");
+
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn errors_show_the_line_they_are_on() {
+    let stderr = check("dollar.amds", "decl main : () {\n\tlet x := 1 $ 2;\n}\n");
+
+    // The tab is drawn as spaces, and the caret has to line up with it
+    assert_eq!(stderr, [
+        "error: expected `;` at the end of variable declaration, but got `$`",
+        " --> dollar.amds:2:13",
+        "  |",
+        "2 |     let x := 1 $ 2;",
+        "  |                ^",
+        "",
+        "error: dollar.amds: could not compile due to a previous error",
+        "",
+    ].join("\n"));
+}
+
+#[test]
+fn notes_go_under_the_snippet() {
+    let stderr = check("unterminated.amds", "decl main : () {\n    let s := \"abc;\n}\n");
+
+    assert!(stderr.starts_with(&[
+        "error: unterminated string literal",
+        " --> unterminated.amds:2:14",
+        "  |",
+        "2 |     let s := \"abc;",
+        "  |              ^",
+        "  = note: the string starts here and runs to the end of the file",
+        "",
+    ].join("\n")), "{}", stderr);
+}
+
+#[test]
+fn labels_on_the_same_line_share_an_underline() {
+    let stderr = check("mismatch.amds", "decl main : () {\n    let a: i64 = 1;\n    let b: i32 = 2;\n    let c := a + b;\n}\n");
+
+    assert!(stderr.starts_with(&[
+        "error: mismatched types for `+`",
+        " --> mismatch.amds:4:14",
+        "  |",
+        "4 |     let c := a + b;",
+        "  |              -^^^- this is `i32`",
+        "  |              |",
+        "  |              this is `i64`",
+        "",
+    ].join("\n")), "{}", stderr);
+}
+
+#[test]
+fn wide_characters_take_two_columns() {
+    let stderr = check("wide.amds", "decl main : () {\n    let s := \"日本\" $;\n}\n");
+
+    assert!(stderr.starts_with(&[
+        "error: expected `;` at the end of variable declaration, but got `$`",
+        " --> wide.amds:2:19",
+        "  |",
+        "2 |     let s := \"日本\" $;",
+        "  |                     ^",
+        "",
+    ].join("\n")), "{}", stderr);
+}
+
+/// The `error:` and `warning:` lines of `stderr`, leaving out the one that
+/// sums up how many errors there were.
+fn headlines(stderr: &str) -> Vec<&str> {
+    stderr.lines()
+          .filter(|line| line.starts_with("error: ") || line.starts_with("warning: "))
+          .filter(|line| !line.contains("could not compile"))
+          .collect()
+}
+
+#[test]
+fn type_declarations() {
+    let stderr = check("types.amds", "\
+decl Point : struct {
+    x: i32,
+    y: Missing,
+}
+
+decl Point : struct {
+    z: i32,
+}
+
+decl Node : struct {
+    value: i32,
+    next: Node,
+}
+
+decl List : enum { Cons(i32, List), Nil }
+
+decl main : () {
+}
+");
+
+    assert_eq!(headlines(&stderr), [
+        "error: the type `Point` is defined multiple times",
+        "error: cannot find type `Missing`",
+        "error: recursive struct `Node` has infinite size",
+        "error: recursive enum `List` has infinite size",
+    ]);
+    assert!(stderr.contains("  |      ----- first defined here\n"), "{}", stderr);
+    assert!(stderr.contains("   |     ---- recursive without indirection\n"), "{}", stderr);
+    // The enum's name is the only thing to point at, and it's underlined once
+    assert!(stderr.contains("   |      ^^^^\n   = note: an enum can't contain itself"), "{}", stderr);
+}
+
+#[test]
+fn match_exhaustiveness() {
+    let stderr = check("match.amds", "\
+decl Shape : enum { Circle(f64), Square(f64), Empty }
+
+decl area : (shape: Shape) -> f64 {
+    return match shape {
+        Shape::Circle(r) => r * r * 3.0,
+        Shape::Empty => 0.0,
+    };
+}
+
+decl sides : (shape: Shape) -> i32 {
+    return match shape {
+        Shape::Square(_) => 4,
+        Shape::Square(_) => 5,
+        else => 0,
+        Shape::Circle(_) => 1,
+    };
+}
+
+decl digit : (n: i32) -> i32 {
+    return match n {
+        0 => 1,
+        1 => 2,
+    };
+}
+
+decl main : () {
+}
+");
+
+    assert_eq!(headlines(&stderr), [
+        "error: `match` doesn't cover `Shape::Square`",
+        "warning: unreachable match arm",
+        "warning: unreachable match arm",
+        "error: `match` on `i32` values needs an `else` arm",
+    ]);
+    assert!(stderr.contains("   |         ---------------- already matched here\n"), "{}", stderr);
+    assert!(stderr.contains("   |         ---- everything left is matched by this arm\n"), "{}", stderr);
+}
+
+#[test]
+fn payload_labels_are_reported_once() {
+    let stderr = check("labels.amds", "\
+decl E : enum { C(x: i32, y: i32), D(i32, i32) }
+
+decl main : () {
+    let a := E::C(7, 2);
+    let b := E::D(x: 1, y: 2);
+    let c := E::C(x: 1);
+}
+");
+
+    assert_eq!(headlines(&stderr), [
+        "error: the payload of `E::C` needs labels, like `name: value`",
+        "error: the payload of `E::D` isn't labeled",
+        "error: missing field `y` in payload of `E::C`",
+    ]);
+    assert!(stderr.contains("  = note: its fields are `x`, `y`\n"), "{}", stderr);
+}
+
+#[test]
+fn literals_have_to_fit_their_type() {
+    let stderr = check("literals.amds", "\
+decl main : () {
+    let a: u8 = 300;
+    let b: i8 = -129;
+    let c := 256u8;
+    let d: i8 = -128;
+    let e: u16 = 0xffff;
+    let f: u16 = 0x1'0000;
+}
+");
+
+    assert_eq!(headlines(&stderr), [
+        "error: literal out of range for `u8`",
+        "error: literal out of range for `i8`",
+        "error: literal out of range for `u8`",
+        "error: literal out of range for `u16`",
+    ]);
+}
+
+#[test]
+fn constants_have_to_fit_their_type() {
+    let stderr = check("consts.amds", "\
+decl W : u8 = 200 + 100;
+decl A : i8 = 100;
+decl B : i8 = -A - A;
+decl C : u8 = 3 - 4;
+decl D : u8 = (200 + 100) / 2;
+decl E : u8 = ~0;
+decl F : u8 = 300 as u8;
+decl G : u8 = 0xf0 << 2;
+decl H : i32 = -2147483647 - 1;
+
+decl main : () {
+}
+");
+
+    assert_eq!(headlines(&stderr), [
+        "error: the value of `W` overflows `u8`",
+        "error: the value of `B` overflows `i8`",
+        "error: the value of `C` overflows `u8`",
+        "error: the value of `D` overflows `u8`",
+    ]);
+    assert!(stderr.contains("1 | decl W : u8 = 200 + 100;\n  |               ^^^^^^^^^ this is 300\n"), "{}", stderr);
+    assert!(stderr.contains("  |                ^^^^^^^^^ this is 300\n"), "{}", stderr);
+    assert!(stderr.contains("  = note: `i8` goes from -128 to 127\n"), "{}", stderr);
+}
+
+#[test]
+fn the_primary_span_can_have_a_message() {
+    let stderr = check("mismatch.amds", "\
+decl half : (x: f64) -> f64 {
+    return x / 2.0;
+}
+
+decl main : () {
+    let n: i32 = half(x: 3.0);
+}
+");
+
+    assert_eq!(stderr, [
+        "error: mismatched types",
+        " --> mismatch.amds:6:18",
+        "  |",
+        "6 |     let n: i32 = half(x: 3.0);",
+        "  |                  ^^^^^^^^^^^^ expected `i32`, found `f64`",
+        "",
+        "error: mismatch.amds: could not compile due to a previous error",
+        "",
+    ].join("\n"));
+}
+
+#[test]
+fn parameters_are_immutable() {
+    let stderr = check("params.amds", "\
+decl Point : struct { x: i32 }
+
+decl bump : (n: i32, p: *Point, q: Point) -> i32 {
+    p.x = 1;
+    n += 1;
+    q.x++;
+    let n := n;
+    n = 2;
+    return n;
+}
+
+decl main : () {
+}
+");
+
+    assert_eq!(headlines(&stderr), [
+        "error: cannot assign to the parameter `n`",
+        "error: cannot assign to the parameter `q`",
+    ]);
+    assert!(stderr.contains("3 | decl bump : (n: i32, p: *Point, q: Point) -> i32 {\n  |              - parameters are immutable\n"), "{}", stderr);
+}
+
+#[test]
+fn types_have_to_fit_in_memory() {
+    let stderr = check("sizes.amds", "\
+decl S : struct { a: [576460752303423488]u64, b: [576460752303423488]u64, c: u8 }
+decl T : struct { s: S }
+decl E : enum { A([1152921504606846975]u64), B }
+decl Fits : struct { a: [1152921504606846975]u64 }
+
+decl main : () {
+    let a: [4611686018427387904]u64 = [0; 4611686018427387904];
+}
+");
+
+    assert_eq!(headlines(&stderr), [
+        "error: the type `S` is too big",
+        "error: the type `E` is too big",
+        "error: the type `[4611686018427387904]u64` is too big",
+        "error: the type `[4611686018427387904]i64` is too big",
+    ]);
+    assert!(stderr.contains("  = note: types can take up at most 9223372036854775807 bytes\n"), "{}", stderr);
+}
+
+#[test]
+fn only_places_have_addresses_and_only_pointers_are_dereferenced() {
+    let stderr = check("pointers.amds", "\
+decl five : () -> i32 {
+    return 5;
+}
+
+decl main : () {
+    let x: i32 = 1;
+    let a := &five();
+    let b := &(x + 1);
+    let c := <x;
+    let d: *nothing = null;
+    let e := <d;
+}
+");
+
+    assert_eq!(headlines(&stderr), [
+        "error: can't take the address of a temporary value",
+        "error: can't take the address of a temporary value",
+        "error: can't dereference a value of type `i32`",
+        "error: can't dereference a `*nothing`",
+    ]);
+    assert!(stderr.contains("  = note: only pointers can be dereferenced\n"), "{}", stderr);
+}
+
+#[test]
+fn if_values_need_an_else_and_matching_branches() {
+    let stderr = check("if_no_else.amds", "\
+decl main : () {
+    let n := 3;
+    let a := if n > 2 { 1 };
+}
+");
+
+    assert_eq!(headlines(&stderr), ["error: an `if` that's used as a value needs an `else`"]);
+    assert!(stderr.contains("3 |     let a := if n > 2 { 1 };\n  |              ^^^^^^^^^^^^^^\n"), "{}", stderr);
+
+    let stderr = check("if_mismatch.amds", "\
+decl main : () {
+    let n := 3;
+    let b := if n > 2 { 1 } else { true };
+    let c: i32 = if n > 2 { 1 } else if n > 1 { 2.5 } else { 3 };
+    let d: u8 = if n > 2 { 200 } else { 300 };
+}
+");
+
+    assert_eq!(headlines(&stderr), [
+        "error: mismatched types",
+        "error: mismatched types",
+        "error: literal out of range for `u8`",
+    ]);
+    assert!(stderr.contains("expected `bool`, found `i64`"), "{}", stderr);
+    assert!(stderr.contains("expected `i32`, found `f64`"), "{}", stderr);
+}
+
+#[test]
+fn struct_members_and_methods() {
+    let stderr = check("duplicate_members.amds", "\
+decl Point : struct {
+    x: i32,
+    y: i32,
+    y: i64,
+
+    decl origin : () -> Point {
+        return Point{ x: 0, y: 0 };
+    }
+}
+");
+
+    // The rest of the struct is still parsed
+    assert_eq!(headlines(&stderr), ["error: duplicate member `y`"]);
+
+    let stderr = check("members.amds", "\
+decl Point : struct {
+    x: i32,
+    y: i32,
+
+    decl new : () -> Point {
+        return Point{ x: 0, y: 0 };
+    }
+
+    decl nudge : (self: *Point) {
+        self.x += 1;
+    }
+}
+
+decl main : () {
+    let a := Point{ x: 1, y: 2, x: 3 };
+    let b := Point{ x: 1 };
+    let c := Point{ x: 1, y: 2, z: 3 };
+    let d := a.z;
+    Point::new().nudge();
+}
+");
+
+    assert_eq!(headlines(&stderr), [
+        "error: `x` is given more than once",
+        "error: missing member `y` in `Point`",
+        "error: `Point` has no member named `z`",
+        "error: `Point` has no member named `z`",
+        "error: can't take the address of a temporary value",
+    ]);
+    assert!(stderr.contains("  = note: `Point::nudge` takes a pointer, so it can only be called on something that has an address\n"), "{}", stderr);
+}
+
+#[test]
+fn only_arithmetic_converts_integers_to_floats() {
+    let stderr = check("int_to_float.amds", "\
+decl main : () {
+    let n := 3;
+    let f: f64 = 1.0;
+    let sum := f + n;
+    let same := n == f;
+    let bits := n & f;
+    f = n;
+}
+");
+
+    assert_eq!(headlines(&stderr), [
+        "error: mismatched types for `==`",
+        "error: mismatched types for `&`",
+        "error: mismatched types",
+    ]);
+}
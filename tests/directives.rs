@@ -0,0 +1,68 @@
+//! Checks the `#` directives that are evaluated while compiling.
+
+use std::path::PathBuf;
+use std::process::{ Command, Output };
+
+fn amds(args: &[&str], input: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_amds"))
+        .args(args)
+        .arg(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/programs/directives")).join(input))
+        .output()
+        .expect("couldn't start amds")
+}
+
+#[test]
+fn if_keeps_the_branch_that_holds() {
+    for (defines, code) in [(&[][..], 1), (&["-D", "FAST"][..], 2), (&["-D", "FAST", "-D", "DEBUG"][..], 12)] {
+        let args = [&["run", "--interpret"][..], defines].concat();
+        assert_eq!(amds(&args, "directives.amds").status.code(), Some(code), "{:?}", defines);
+    }
+}
+
+#[test]
+fn run_prints_while_compiling() {
+    let output = amds(&["check"], "directives.amds");
+
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "4\n");
+}
+
+#[test]
+fn failed_assertions_stop_the_compilation() {
+    let output = amds(&["check"], "failed_assert.amds");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr.matches("error: compile-time assertion failed\n").count(), 2, "{}", stderr);
+    assert!(stderr.contains("  = note: there has to be an even number of lanes\n"), "{}", stderr);
+}
+
+#[test]
+fn aggregates_run_on_the_vm() {
+    let output = amds(&["check"], "aggregates.amds");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "10 7\n");
+    assert_eq!(stderr.matches("error: compile-time assertion failed\n").count(), 1, "{}", stderr);
+    assert!(stderr.contains("  = note: that's not how distances work\n"), "{}", stderr);
+}
+
+#[test]
+fn infinite_loops_are_stopped() {
+    let output = amds(&["check"], "spin.amds");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.starts_with("error: ran for more than 100000000 instructions\n"), "{}", stderr);
+    assert!(stderr.contains("while evaluating `FOREVER`"), "{}", stderr);
+}
+
+#[test]
+fn huge_arrays_are_stopped() {
+    let output = amds(&["check"], "hoard.amds");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.starts_with("error: ran out of stack space (1048576 slots)\n"), "{}", stderr);
+    assert!(stderr.contains("while evaluating `X`"), "{}", stderr);
+}
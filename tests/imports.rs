@@ -0,0 +1,40 @@
+//! Checks how `#import`s are found and loaded.
+
+use std::path::PathBuf;
+use std::process::{ Command, Output };
+
+fn amds(args: &[&str], input: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_amds"))
+        .args(args)
+        .arg(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/programs/imports")).join(input))
+        .output()
+        .expect("couldn't start amds")
+}
+
+#[test]
+fn a_module_is_only_loaded_once() {
+    let output = amds(&["run", "--interpret"], "dedup/main.amds");
+    assert_eq!(output.status.code(), Some(12), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn import_cycles() {
+    let output = amds(&["check"], "cycle/a.amds");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("error: the module `a` imports itself through other modules\n"), "{}", stderr);
+    assert!(stderr.contains("  = note: the imports go a -> b -> c -> a\n"), "{}", stderr);
+
+    let output = amds(&["check"], "cycle/itself.amds");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("error: the module `itself` imports itself\n"), "{}", stderr);
+}
+
+#[test]
+fn modules_that_clash_or_are_missing() {
+    let output = amds(&["check"], "clash/main.amds");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.contains("error: there's already a different module called `util`\n"), "{}", stderr);
+    assert!(stderr.contains("error: cannot find module `missing`\n"), "{}", stderr);
+}